@@ -24,6 +24,7 @@ __all__ = [
     "convert_datascrubbing_config",
     "pii_strip_event",
     "pii_selector_suggestions_from_event",
+    "pii_explain",
     "VALID_PLATFORMS",
     "validate_sampling_condition",
     "validate_sampling_configuration",
@@ -215,6 +216,17 @@ def pii_selector_suggestions_from_event(event):
     return json.loads(decode_str(raw_rv, free=True))
 
 
+def pii_explain(config, event):
+    """
+    Explain which fields of the event would be scrubbed by a PII config, and
+    why. The event is not modified.
+    """
+    raw_config = encode_str(json.dumps(config))
+    raw_event = encode_str(json.dumps(event))
+    raw_rv = rustcall(lib.relay_pii_explain, raw_config, raw_event)
+    return json.loads(decode_str(raw_rv, free=True))
+
+
 def parse_release(release):
     """Parses a release string into a dictionary of its components."""
     return json.loads(
@@ -170,6 +170,20 @@ def test_convert_datascrubbing_config():
     )
 
 
+def test_convert_datascrubbing_config_allowlist():
+    cfg = sentry_relay.convert_datascrubbing_config(
+        {
+            "scrubData": False,
+            "scrubIpAddresses": False,
+            "allowlist": {"frameVars": ["user_id"]},
+        }
+    )
+
+    assert cfg["applications"] == {
+        "$frame.vars.* && !$frame.vars.user_id": ["@anything:remove"]
+    }
+
+
 def test_pii_strip_event():
     event = {"logentry": {"message": "hi"}}
     assert sentry_relay.pii_strip_event({}, event) == event
@@ -183,6 +197,22 @@ def test_pii_selector_suggestions_from_event():
     ]
 
 
+def test_pii_explain():
+    event = {"logentry": {"formatted": "hi foo@example.com"}}
+    config = {"applications": {"$message": ["@email:mask"]}}
+    assert sentry_relay.pii_explain(config, event) == [
+        {
+            "path": "logentry.formatted",
+            "selector": "$message",
+            "rule_id": "@email:mask",
+            "origin": "@email:mask",
+            "redaction": {"method": "mask"},
+            "before": "hi foo@example.com",
+            "after": "hi ***************",
+        }
+    ]
+
+
 def test_parse_release():
     parsed = sentry_relay.parse_release("org.example.FooApp@1.0rc1+20200101100")
     assert parsed == {
@@ -30,7 +30,7 @@ use ed25519_dalek::{Signer, Verifier};
 use hmac::{Hmac, Mac};
 use rand::rngs::OsRng;
 use rand::{thread_rng, RngCore};
-use relay_common::{UnixTimestamp, Uuid};
+use relay_common::{ProjectKey, UnixTimestamp, Uuid};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use sha2::Sha512;
@@ -152,6 +152,13 @@ pub struct SignatureHeader {
     /// The timestamp of when the data was packed and signed.
     #[serde(rename = "t", skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<DateTime<Utc>>,
+
+    /// The next public key of the signing relay during a key rotation.
+    ///
+    /// Since the header is covered by the signature, this announces the next key in a way that
+    /// can be verified with the current key.
+    #[serde(rename = "nk", default, skip_serializing_if = "Option::is_none")]
+    pub next_key: Option<PublicKey>,
 }
 
 impl SignatureHeader {
@@ -169,6 +176,7 @@ impl Default for SignatureHeader {
     fn default() -> SignatureHeader {
         SignatureHeader {
             timestamp: Some(Utc::now()),
+            next_key: None,
         }
     }
 }
@@ -399,6 +407,111 @@ impl fmt::Debug for PublicKey {
 
 relay_common::impl_str_serde!(PublicKey, "a public key");
 
+/// The JOSE header of an ingestion token.
+#[derive(Serialize, Deserialize)]
+struct IngestionTokenHeader {
+    alg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    typ: Option<String>,
+}
+
+/// The only signature algorithm supported in ingestion tokens.
+const INGESTION_TOKEN_ALG: &str = "EdDSA";
+
+/// Claims of a signed, short-lived ingestion token.
+///
+/// Ingestion tokens are JSON Web Tokens issued by the backend and signed with Ed25519 (`EdDSA`).
+/// Relay verifies them with a configured [`PublicKey`] to authenticate store requests in addition
+/// to the DSN's project key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestionClaims {
+    /// The project key that the token is valid for.
+    pub project_key: ProjectKey,
+    /// Item types that may be sent with this token. If empty, all item types are allowed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub item_types: Vec<String>,
+    /// The maximum size of an envelope in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+    /// The expiration time of the token.
+    pub exp: UnixTimestamp,
+}
+
+impl IngestionClaims {
+    /// Returns `true` if the token has expired.
+    pub fn expired(&self) -> bool {
+        self.exp < UnixTimestamp::now()
+    }
+
+    /// Returns `true` if items of the given type may be sent with this token.
+    pub fn allows_item_type(&self, item_type: &str) -> bool {
+        self.item_types.is_empty() || self.item_types.iter().any(|ty| ty == item_type)
+    }
+
+    /// Encodes the claims into a token signed with the given secret key.
+    pub fn pack(&self, secret_key: &SecretKey) -> String {
+        let header = IngestionTokenHeader {
+            alg: INGESTION_TOKEN_ALG.to_owned(),
+            typ: Some("JWT".to_owned()),
+        };
+
+        let header = serde_json::to_vec(&header).expect("attempted to pack non json safe header");
+        let claims = serde_json::to_vec(self).expect("attempted to pack non json safe claims");
+
+        let mut token = BASE64URL_NOPAD.encode(&header);
+        token.push('.');
+        token.push_str(&BASE64URL_NOPAD.encode(&claims));
+
+        let sig = secret_key.inner.sign(token.as_bytes());
+        token.push('.');
+        token.push_str(&BASE64URL_NOPAD.encode(&sig.to_bytes()));
+        token
+    }
+
+    /// Verifies the token with the given public key and returns its claims.
+    ///
+    /// Tokens with other algorithms than `EdDSA` are rejected. Expired tokens return
+    /// [`UnpackError::SignatureExpired`].
+    pub fn unpack(token: &str, public_key: &PublicKey) -> Result<Self, UnpackError> {
+        let Some((signed, sig)) = token.rsplit_once('.') else {
+            return Err(UnpackError::BadEncoding);
+        };
+        let Some((header, claims)) = signed.split_once('.') else {
+            return Err(UnpackError::BadEncoding);
+        };
+
+        let header = BASE64URL_NOPAD
+            .decode(header.as_bytes())
+            .map_err(|_| UnpackError::BadEncoding)?;
+        let header: IngestionTokenHeader =
+            serde_json::from_slice(&header).map_err(UnpackError::BadPayload)?;
+        if header.alg != INGESTION_TOKEN_ALG {
+            return Err(UnpackError::BadSignature);
+        }
+
+        let sig_bytes = BASE64URL_NOPAD
+            .decode(sig.as_bytes())
+            .map_err(|_| UnpackError::BadEncoding)?;
+        let sig = ed25519_dalek::Signature::from_slice(&sig_bytes)
+            .map_err(|_| UnpackError::BadEncoding)?;
+
+        if public_key.inner.verify(signed.as_bytes(), &sig).is_err() {
+            return Err(UnpackError::BadSignature);
+        }
+
+        let claims = BASE64URL_NOPAD
+            .decode(claims.as_bytes())
+            .map_err(|_| UnpackError::BadEncoding)?;
+        let claims: Self = serde_json::from_slice(&claims).map_err(UnpackError::BadPayload)?;
+
+        if claims.expired() {
+            return Err(UnpackError::SignatureExpired);
+        }
+
+        Ok(claims)
+    }
+}
+
 /// Generates an relay ID.
 pub fn generate_relay_id() -> RelayId {
     Uuid::new_v4()
@@ -756,6 +869,61 @@ mod tests {
         assert!(!pk.verify(data, bad_sig));
     }
 
+    #[test]
+    fn test_signature_next_key() {
+        let (sk, pk) = generate_key_pair();
+        let (_, next_pk) = generate_key_pair();
+        let data = b"Hello World!";
+
+        let header = SignatureHeader {
+            next_key: Some(next_pk.clone()),
+            ..Default::default()
+        };
+        let sig = sk.sign_with_header(data, &header);
+
+        let header = pk.verify_meta(data, &sig).unwrap();
+        assert_eq!(header.next_key, Some(next_pk));
+    }
+
+    #[test]
+    fn test_ingestion_token() {
+        let (sk, pk) = generate_key_pair();
+        let claims = IngestionClaims {
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            item_types: vec!["event".to_owned()],
+            max_size: Some(1024),
+            exp: UnixTimestamp::from_secs(UnixTimestamp::now().as_secs() + 60),
+        };
+
+        let token = claims.pack(&sk);
+        assert_eq!(token.split('.').count(), 3);
+        assert_eq!(IngestionClaims::unpack(&token, &pk).unwrap(), claims);
+        assert!(claims.allows_item_type("event"));
+        assert!(!claims.allows_item_type("attachment"));
+
+        // Signed by another key.
+        let (_, other_pk) = generate_key_pair();
+        assert!(matches!(
+            IngestionClaims::unpack(&token, &other_pk),
+            Err(UnpackError::BadSignature)
+        ));
+
+        // Expired token.
+        let expired = IngestionClaims {
+            exp: UnixTimestamp::from_secs(0),
+            ..claims
+        };
+        assert!(matches!(
+            IngestionClaims::unpack(&expired.pack(&sk), &pk),
+            Err(UnpackError::SignatureExpired)
+        ));
+
+        assert!(matches!(
+            IngestionClaims::unpack("garbage", &pk),
+            Err(UnpackError::BadEncoding)
+        ));
+    }
+
     #[test]
     fn test_registration() {
         let max_age = Duration::minutes(15);
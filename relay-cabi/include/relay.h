@@ -546,6 +546,14 @@ struct RelayStr relay_pii_strip_event(const struct RelayStr *config,
  */
 struct RelayStr relay_pii_selector_suggestions_from_event(const struct RelayStr *event);
 
+/**
+ * Explain which fields of the event would be scrubbed by a PII config, and why.
+ *
+ * The event is not modified. Returns a list of explanations with the matching selector, rule and
+ * redaction method, as well as the values before and after scrubbing.
+ */
+struct RelayStr relay_pii_explain(const struct RelayStr *config, const struct RelayStr *event);
+
 /**
  * A test function that always panics.
  */
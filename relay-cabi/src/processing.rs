@@ -13,7 +13,8 @@ use once_cell::sync::OnceCell;
 use relay_common::{codeowners_match_bytes, glob_match_bytes, GlobOptions};
 use relay_dynamic_config::{validate_json, ProjectConfig};
 use relay_general::pii::{
-    selector_suggestions_from_value, DataScrubbingConfig, PiiConfig, PiiConfigError, PiiProcessor,
+    explain_pii, selector_suggestions_from_value, DataScrubbingConfig, PiiConfig, PiiConfigError,
+    PiiProcessor,
 };
 use relay_general::processor::{process_value, split_chunks, ProcessingState};
 use relay_general::protocol::{Event, VALID_PLATFORMS};
@@ -135,6 +136,7 @@ pub unsafe extern "C" fn relay_store_normalizer_normalize_event(
         scrub_span_descriptions: false,
         light_normalize_spans: false,
         span_description_rules: None,
+        grouping_config: config.grouping_config.as_ref(),
     };
     light_normalize_event(&mut event, light_normalization_config)?;
     process_value(&mut event, &mut *processor, ProcessingState::root())?;
@@ -204,6 +206,22 @@ pub unsafe extern "C" fn relay_pii_selector_suggestions_from_event(
     RelayStr::from_string(serde_json::to_string(&rv)?)
 }
 
+/// Explain which fields of the event would be scrubbed by a PII config, and why.
+///
+/// The event is not modified. Returns a list of explanations with the matching selector, rule and
+/// redaction method, as well as the values before and after scrubbing.
+#[no_mangle]
+#[relay_ffi::catch_unwind]
+pub unsafe extern "C" fn relay_pii_explain(
+    config: *const RelayStr,
+    event: *const RelayStr,
+) -> RelayStr {
+    let config = serde_json::from_str::<PiiConfig>((*config).as_str())?;
+    let event = Annotated::<Event>::from_json((*event).as_str())?;
+    let rv = explain_pii(&event, &config)?;
+    RelayStr::from_string(serde_json::to_string(&rv)?)
+}
+
 /// A test function that always panics.
 #[no_mangle]
 #[relay_ffi::catch_unwind]
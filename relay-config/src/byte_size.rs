@@ -36,6 +36,7 @@ use serde::{de, Serialize};
 /// let size = ByteSize::kibibytes(42);
 /// assert_eq!("42KiB", size.to_string());
 /// ```
+#[derive(Clone, Copy)]
 pub struct ByteSize(Size);
 
 impl ByteSize {
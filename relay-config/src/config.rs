@@ -10,7 +10,7 @@ use std::{env, fmt, fs, io};
 
 use anyhow::Context;
 use relay_auth::{generate_key_pair, generate_relay_id, PublicKey, RelayId, SecretKey};
-use relay_common::{Dsn, Uuid};
+use relay_common::{DataCategory, Dsn, Uuid};
 use relay_kafka::{
     ConfigError as KafkaConfigError, KafkaConfig, KafkaConfigParam, KafkaTopic, TopicAssignments,
 };
@@ -20,7 +20,7 @@ use serde::de::{DeserializeOwned, Unexpected, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::byte_size::ByteSize;
-use crate::upstream::UpstreamDescriptor;
+use crate::upstream::{UpstreamDescriptor, UpstreamTarget};
 
 const DEFAULT_NETWORK_OUTAGE_GRACE_PERIOD: u64 = 10;
 
@@ -256,6 +256,12 @@ pub struct Credentials {
     pub public_key: PublicKey,
     /// The globally unique ID of the relay.
     pub id: RelayId,
+    /// The next secret key of the relay during a key rotation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_secret_key: Option<SecretKey>,
+    /// The next public key of the relay during a key rotation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_public_key: Option<PublicKey>,
 }
 
 impl Credentials {
@@ -267,6 +273,39 @@ impl Credentials {
             secret_key: sk,
             public_key: pk,
             id: generate_relay_id(),
+            next_secret_key: None,
+            next_public_key: None,
+        }
+    }
+
+    /// Returns `true` if a key rotation is in progress.
+    ///
+    /// During a rotation, requests are signed with both the current and the next key.
+    pub fn is_rotating(&self) -> bool {
+        self.next_secret_key.is_some() && self.next_public_key.is_some()
+    }
+
+    /// Generates a next key pair and starts a key rotation.
+    ///
+    /// An already started rotation is replaced with the new key pair.
+    pub fn rotate(&mut self) {
+        relay_log::info!("generating next relay key pair");
+        let (sk, pk) = generate_key_pair();
+        self.next_secret_key = Some(sk);
+        self.next_public_key = Some(pk);
+    }
+
+    /// Retires the current key pair and replaces it with the next key pair.
+    ///
+    /// Returns `false` if no key rotation is in progress.
+    pub fn finish_rotation(&mut self) -> bool {
+        match (self.next_secret_key.take(), self.next_public_key.take()) {
+            (Some(secret_key), Some(public_key)) => {
+                self.secret_key = secret_key;
+                self.public_key = public_key;
+                true
+            }
+            _ => false,
         }
     }
 
@@ -293,9 +332,23 @@ pub struct RelayInfo {
     /// The public key that this Relay uses to authenticate and sign requests.
     pub public_key: PublicKey,
 
+    /// The next public key of this Relay during a key rotation.
+    ///
+    /// Until the current key is retired, signatures of either key are accepted. This is also set
+    /// once the Relay announces its next key in a request signed with the current key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_public_key: Option<PublicKey>,
+
     /// Marks an internal relay that has privileged access to more project configuration.
     #[serde(default)]
     pub internal: bool,
+
+    /// Subject of the client certificate that authenticates this Relay.
+    ///
+    /// Requests with this certificate do not need to be signed. This is only configured for static
+    /// relays and never sent to other Relays.
+    #[serde(default, skip_serializing)]
+    pub certificate_subject: Option<String>,
 }
 
 impl RelayInfo {
@@ -303,9 +356,16 @@ impl RelayInfo {
     pub fn new(public_key: PublicKey) -> Self {
         Self {
             public_key,
+            next_public_key: None,
             internal: false,
+            certificate_subject: None,
         }
     }
+
+    /// Returns all public keys that are accepted for this Relay.
+    pub fn public_keys(&self) -> impl Iterator<Item = &PublicKey> {
+        std::iter::once(&self.public_key).chain(self.next_public_key.as_ref())
+    }
 }
 
 /// The operation mode of a relay.
@@ -435,6 +495,9 @@ pub struct Relay {
     pub mode: RelayMode,
     /// The upstream relay or sentry instance.
     pub upstream: UpstreamDescriptor<'static>,
+    /// Multiple upstreams to fail over between, replacing `upstream` if set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upstreams: Vec<UpstreamTarget>,
     /// The host the relay should bind to (network interface).
     pub host: IpAddr,
     /// The port to bind for the unencrypted relay HTTP server.
@@ -458,6 +521,7 @@ impl Default for Relay {
         Relay {
             mode: RelayMode::Managed,
             upstream: "https://sentry.io/".parse().unwrap(),
+            upstreams: Vec::new(),
             host: default_host(),
             port: 3000,
             tls_port: None,
@@ -717,6 +781,13 @@ struct Http {
     ///  - `gzip` (default): Compression using gzip.
     ///  - `br`: Compression using the brotli algorithm.
     encoding: HttpEncoding,
+    /// The path to the identity (DER-encoded PKCS12) to present as client certificate to the
+    /// upstream.
+    ///
+    /// Use this for mutual TLS with upstreams that verify client certificates.
+    client_identity_path: Option<PathBuf>,
+    /// Password for the PKCS12 archive of the client identity.
+    client_identity_password: Option<String>,
 }
 
 impl Default for Http {
@@ -729,6 +800,8 @@ impl Default for Http {
             auth_interval: Some(600), // 10 minutes
             outage_grace_period: DEFAULT_NETWORK_OUTAGE_GRACE_PERIOD,
             encoding: HttpEncoding::Gzip,
+            client_identity_path: None,
+            client_identity_password: None,
         }
     }
 }
@@ -874,6 +947,111 @@ fn default_max_rate_limit() -> Option<u32> {
     Some(300) // 5 minutes
 }
 
+fn default_kafka_fallback_max_size() -> ByteSize {
+    ByteSize::mebibytes(1024)
+}
+
+fn default_kafka_fallback_drain_batch_size() -> usize {
+    1000
+}
+
+/// Configuration for the queue on disk that stores messages Relay cannot deliver to Kafka.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KafkaFallbackConfig {
+    /// The directory to store messages in.
+    pub path: PathBuf,
+    /// Maximum total size of stored messages. Defaults to 1GiB.
+    ///
+    /// Messages that do not fit into the queue are dropped.
+    #[serde(default = "default_kafka_fallback_max_size")]
+    pub max_size: ByteSize,
+    /// Maximum number of stored messages that are sent to Kafka again per second. Defaults to
+    /// `1000`.
+    #[serde(default = "default_kafka_fallback_drain_batch_size")]
+    pub drain_batch_size: usize,
+}
+
+/// Name of the built-in sink that produces to the configured Kafka topics.
+pub const KAFKA_SINK: &str = "kafka";
+
+/// Controls how a batching store sink groups records into files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct SinkBatchConfig {
+    /// Maximum number of records written into a single file. Defaults to `1000`.
+    pub max_records: usize,
+    /// Maximum uncompressed size of a single file. Defaults to 10MiB.
+    pub max_size: ByteSize,
+    /// Maximum time in seconds that records are buffered before the file is written. Defaults
+    /// to `60`.
+    pub flush_interval: u64,
+}
+
+impl Default for SinkBatchConfig {
+    fn default() -> Self {
+        Self {
+            max_records: 1000,
+            max_size: ByteSize::mebibytes(10),
+            flush_interval: 60,
+        }
+    }
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_owned()
+}
+
+/// Configuration for a sink writing to an S3-compatible object storage.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct S3SinkConfig {
+    /// The endpoint URL of the object storage, for example `http://minio:9000`.
+    pub endpoint: String,
+    /// Name of the bucket to write into. The bucket is addressed path-style.
+    pub bucket: String,
+    /// Signing region of the object storage. Defaults to `us-east-1`.
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    /// Access key ID used to sign requests.
+    pub access_key_id: String,
+    /// Secret access key used to sign requests.
+    pub secret_access_key: String,
+    /// Optional key prefix prepended to all objects.
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Batching of records into objects.
+    #[serde(default)]
+    pub batch: SinkBatchConfig,
+}
+
+/// Configuration for an additional store sink.
+///
+/// Sinks write processed envelope items as gzip-compressed, newline-delimited JSON files, which
+/// are partitioned by project, data category and hour.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// Writes files into a local directory.
+    File {
+        /// The directory to write files into.
+        path: PathBuf,
+        /// Batching of records into files.
+        #[serde(default)]
+        batch: SinkBatchConfig,
+    },
+    /// Uploads files to an S3-compatible object storage.
+    S3(S3SinkConfig),
+}
+
+impl SinkConfig {
+    /// Returns the batching configuration of this sink.
+    pub fn batch(&self) -> &SinkBatchConfig {
+        match self {
+            Self::File { batch, .. } => batch,
+            Self::S3(config) => &config.batch,
+        }
+    }
+}
+
 /// Controls Sentry-internal event processing.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Processing {
@@ -917,6 +1095,12 @@ pub struct Processing {
     /// Kafka topic names.
     #[serde(default)]
     pub topics: TopicAssignments,
+    /// Queue on disk for messages that cannot be delivered to Kafka.
+    ///
+    /// Stored messages are sent to Kafka again once the brokers recover. Without this queue,
+    /// such messages are dropped and reported as internal errors in outcomes.
+    #[serde(default)]
+    pub kafka_fallback: Option<KafkaFallbackConfig>,
     /// Redis hosts to connect to for storing state for rate limits.
     #[serde(default)]
     pub redis: Option<RedisConfig>,
@@ -929,6 +1113,28 @@ pub struct Processing {
     /// Maximum rate limit to report to clients.
     #[serde(default = "default_max_rate_limit")]
     pub max_rate_limit: Option<u32>,
+    /// Additional sinks that the store can write to, by name.
+    ///
+    /// The name `kafka` is reserved for the built-in Kafka producer.
+    #[serde(default)]
+    pub sinks: BTreeMap<String, SinkConfig>,
+    /// Sinks that each topic is written to.
+    ///
+    /// Topics are referenced by their name in `topics`. Topics without a route are produced to
+    /// Kafka only. Kafka is not required if no topic is routed to it:
+    ///
+    /// ```yaml
+    /// sinks:
+    ///   archive:
+    ///     type: file
+    ///     path: /var/lib/relay/archive
+    ///
+    /// sink_routes:
+    ///   events: [kafka, archive]
+    ///   attachments: [archive]
+    /// ```
+    #[serde(default)]
+    pub sink_routes: BTreeMap<String, Vec<String>>,
 }
 
 impl Default for Processing {
@@ -943,10 +1149,13 @@ impl Default for Processing {
             kafka_config: Vec::new(),
             secondary_kafka_configs: BTreeMap::new(),
             topics: TopicAssignments::default(),
+            kafka_fallback: None,
             redis: None,
             attachment_chunk_size: default_chunk_size(),
             projectconfig_cache_prefix: default_projectconfig_cache_prefix(),
             max_rate_limit: default_max_rate_limit(),
+            sinks: BTreeMap::new(),
+            sink_routes: BTreeMap::new(),
         }
     }
 }
@@ -1045,6 +1254,62 @@ impl<'de> Deserialize<'de> for EmitOutcomes {
     }
 }
 
+fn default_export_max_file_size() -> ByteSize {
+    ByteSize::mebibytes(100)
+}
+
+fn default_export_max_file_age() -> u64 {
+    3600
+}
+
+fn default_export_max_retries() -> u32 {
+    5
+}
+
+fn default_export_max_concurrent_requests() -> usize {
+    10
+}
+
+/// Configuration for exporting outcomes to files or a webhook.
+///
+/// Exported outcomes have the same JSON format as outcomes produced to Kafka.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutcomeExportConfig {
+    /// Writes outcomes as newline-delimited JSON files into a local directory.
+    ///
+    /// Files are written with a `.tmp` extension and renamed to `.ndjson` once they are rotated.
+    File {
+        /// The directory to write files into.
+        path: PathBuf,
+        /// Size after which a file is rotated. Defaults to 100MiB.
+        #[serde(default = "default_export_max_file_size")]
+        max_file_size: ByteSize,
+        /// Time in seconds after which a file is rotated. Defaults to `3600`.
+        #[serde(default = "default_export_max_file_age")]
+        max_file_age: u64,
+    },
+    /// Sends batches of outcomes in `POST` requests to a URL.
+    ///
+    /// Batches are sent with the same body as outcomes sent to the upstream, and are batched
+    /// according to `outcomes.batch_size` and `outcomes.batch_interval`.
+    Webhook {
+        /// The URL to send outcomes to.
+        url: String,
+        /// Additional headers sent with every request, for example for authentication.
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        /// Number of times a failed request is retried before its outcomes are dropped. Defaults
+        /// to `5`.
+        #[serde(default = "default_export_max_retries")]
+        max_retries: u32,
+        /// Maximum number of batches sent or retried at the same time. Further batches wait until
+        /// a request completes. Defaults to `10`.
+        #[serde(default = "default_export_max_concurrent_requests")]
+        max_concurrent_requests: usize,
+    },
+}
+
 /// Outcome generation specific configuration values.
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(default)]
@@ -1055,17 +1320,29 @@ pub struct Outcomes {
     pub emit_outcomes: EmitOutcomes,
     /// Controls wheather client reported outcomes should be emitted.
     pub emit_client_outcomes: bool,
+    /// The maximum quantity accepted for a single entry in a client report.
+    ///
+    /// Larger quantities are clamped to this value and the client report is tracked as
+    /// implausible.
+    pub max_client_report_quantity: u32,
     /// The maximum number of outcomes that are batched before being sent
-    /// via http to the upstream (only applies to non processing relays).
+    /// via http to the upstream or the export webhook (only applies to non processing relays
+    /// and exports).
     pub batch_size: usize,
     /// The maximum time interval (in milliseconds) that an outcome may be batched
-    /// via http to the upstream (only applies to non processing relays).
+    /// via http to the upstream or the export webhook (only applies to non processing relays
+    /// and exports).
     pub batch_interval: u64,
     /// Defines the source string registered in the outcomes originating from
     /// this Relay (typically something like the region or the layer).
     pub source: Option<String>,
     /// Configures the outcome aggregator.
     pub aggregator: OutcomeAggregatorConfig,
+    /// Exports outcomes to files or a webhook instead of Kafka or the upstream.
+    ///
+    /// This only applies if outcomes are emitted as outcomes, either with `emit_outcomes: true`
+    /// or in processing mode.
+    pub export: Option<OutcomeExportConfig>,
 }
 
 impl Default for Outcomes {
@@ -1073,10 +1350,12 @@ impl Default for Outcomes {
         Outcomes {
             emit_outcomes: EmitOutcomes::AsClientReports,
             emit_client_outcomes: true,
+            max_client_report_quantity: 100_000,
             batch_size: 1000,
             batch_interval: 500,
             source: None,
             aggregator: OutcomeAggregatorConfig::default(),
+            export: None,
         }
     }
 }
@@ -1120,15 +1399,21 @@ mod config_relay_info {
     #[derive(Debug, Serialize, Deserialize, Clone)]
     struct RelayInfoConfig {
         public_key: PublicKey,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next_public_key: Option<PublicKey>,
         #[serde(default)]
         internal: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        certificate_subject: Option<String>,
     }
 
     impl From<RelayInfoConfig> for RelayInfo {
         fn from(v: RelayInfoConfig) -> Self {
             RelayInfo {
                 public_key: v.public_key,
+                next_public_key: v.next_public_key,
                 internal: v.internal,
+                certificate_subject: v.certificate_subject,
             }
         }
     }
@@ -1137,7 +1422,9 @@ mod config_relay_info {
         fn from(v: RelayInfo) -> Self {
             RelayInfoConfig {
                 public_key: v.public_key,
+                next_public_key: v.next_public_key,
                 internal: v.internal,
+                certificate_subject: v.certificate_subject,
             }
         }
     }
@@ -1174,6 +1461,23 @@ pub struct AuthConfig {
     /// Statically authenticated downstream relays.
     #[serde(default, with = "config_relay_info")]
     pub static_relays: HashMap<RelayId, RelayInfo>,
+
+    /// Request header containing the subject of a verified client certificate.
+    ///
+    /// Relay does not terminate TLS. To authenticate downstream Relays with mutual TLS, the
+    /// TLS-terminating proxy in front of Relay verifies client certificates and forwards the
+    /// certificate subject in this header. It must remove this header from all other requests.
+    /// Static relays with a `certificate_subject` can authenticate with a matching certificate
+    /// instead of signing their requests. This header is required if any static relay has a
+    /// `certificate_subject`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_certificate_header: Option<String>,
+    /// Public key to verify ingestion tokens issued by the backend.
+    ///
+    /// Ingestion tokens are sent as `sentry_token` next to `sentry_key`. Without this key, Relay
+    /// does not verify tokens and leaves verification to its upstream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingestion_token_key: Option<PublicKey>,
 }
 
 /// AWS extension config.
@@ -1186,6 +1490,117 @@ pub struct AwsConfig {
     pub runtime_api: Option<String>,
 }
 
+/// Configuration for quota enforcement in non-processing Relays.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct LocalQuotas {
+    /// Enforces quotas from project configs in memory of this Relay.
+    ///
+    /// Processing Relays always enforce quotas in Redis and ignore this option.
+    pub enabled: bool,
+    /// The interval in seconds at which consumed quota is reported to the upstream.
+    ///
+    /// Usage is sent to the `/api/0/relays/quotas/usage/` endpoint, which the upstream has to
+    /// implement. If not set, usage is only tracked locally.
+    pub sync_interval: Option<u64>,
+}
+
+/// Configuration for the deduplication of events with the same ID.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Deduplication {
+    /// Drops events whose ID has already been seen for the same project within the window.
+    pub enabled: bool,
+    /// The time in seconds for which event IDs are remembered.
+    pub window: u64,
+    /// The maximum number of event IDs remembered in memory.
+    ///
+    /// Processing Relays with Redis remember event IDs in Redis and ignore this option.
+    pub max_entries: usize,
+}
+
+impl Default for Deduplication {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: 300,
+            max_entries: 100_000,
+        }
+    }
+}
+
+/// Configuration for resolving JavaScript stack traces with source maps.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SourceMaps {
+    /// Directory with source maps of releases.
+    ///
+    /// Source maps are looked up at `<release>/<dist>/<path>.map`, where `<path>` is the URL path
+    /// of the minified file. For events without a `dist`, the directory is omitted. If not set,
+    /// stack traces are not resolved.
+    pub path: Option<PathBuf>,
+}
+
+/// Priority of envelopes when shedding load under backpressure.
+///
+/// Envelopes with a higher priority are admitted longer when the envelope buffer fills up, and they
+/// may evict queued envelopes of lower priority.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Data that can be dropped first, such as replays.
+    Low,
+    /// The default priority.
+    Normal,
+    /// Data that should be kept as long as possible, such as errors and crash reports.
+    High,
+}
+
+/// Default fraction of the envelope buffer that low priority envelopes may occupy.
+fn load_shedding_low_priority_limit() -> f64 {
+    0.8
+}
+
+/// Default fraction of the envelope buffer that normal priority envelopes may occupy.
+fn load_shedding_normal_priority_limit() -> f64 {
+    0.95
+}
+
+/// Controls which envelopes are dropped first when Relay is under backpressure.
+///
+/// The priority of an envelope is the highest priority of its items. An item's priority is taken
+/// from `items` by its item type, then from `categories` by its data category, and otherwise falls
+/// back to a built-in default.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct LoadShedding {
+    /// Priorities of envelope items by their item type, such as `replay_recording`.
+    pub items: BTreeMap<String, Priority>,
+    /// Priorities of envelope items by their data category, such as `error`.
+    pub categories: BTreeMap<DataCategory, Priority>,
+    /// The fraction of `cache.envelope_buffer_size` that low priority envelopes may occupy.
+    ///
+    /// Defaults to `0.8`.
+    #[serde(default = "load_shedding_low_priority_limit")]
+    pub low_priority_limit: f64,
+    /// The fraction of `cache.envelope_buffer_size` that normal priority envelopes may occupy.
+    ///
+    /// Defaults to `0.95`. High priority envelopes may always use the full buffer.
+    #[serde(default = "load_shedding_normal_priority_limit")]
+    pub normal_priority_limit: f64,
+}
+
+impl Default for LoadShedding {
+    fn default() -> Self {
+        Self {
+            items: BTreeMap::new(),
+            categories: BTreeMap::new(),
+            low_priority_limit: load_shedding_low_priority_limit(),
+            normal_priority_limit: load_shedding_normal_priority_limit(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 struct ConfigValues {
     #[serde(default)]
@@ -1216,6 +1631,14 @@ struct ConfigValues {
     auth: AuthConfig,
     #[serde(default)]
     aws: AwsConfig,
+    #[serde(default)]
+    quotas: LocalQuotas,
+    #[serde(default)]
+    load_shedding: LoadShedding,
+    #[serde(default)]
+    dedupe: Deduplication,
+    #[serde(default)]
+    source_maps: SourceMaps,
 }
 
 impl ConfigObject for ConfigValues {
@@ -1265,6 +1688,16 @@ impl Config {
             return Err(ConfigError::file(ConfigErrorKind::ProcessingNotAvailable, &path).into());
         }
 
+        // Client certificates of static relays cannot be verified without the header.
+        if config.client_certificate_header().is_none()
+            && config
+                .static_relays()
+                .values()
+                .any(|info| info.certificate_subject.is_some())
+        {
+            return Err(ConfigError::file(ConfigErrorKind::InvalidValue, &path).into());
+        }
+
         Ok(config)
     }
 
@@ -1394,6 +1827,8 @@ impl Config {
                         secret_key,
                         public_key,
                         id,
+                        next_secret_key: None,
+                        next_public_key: None,
                     })
                 }
                 (None, None, None) => {
@@ -1507,8 +1942,26 @@ impl Config {
     }
 
     /// Returns the upstream target as descriptor.
+    ///
+    /// With multiple upstreams, this is the preferred upstream with the lowest priority value.
     pub fn upstream_descriptor(&self) -> &UpstreamDescriptor<'_> {
-        &self.values.relay.upstream
+        self.values
+            .relay
+            .upstreams
+            .iter()
+            .min_by_key(|target| target.priority)
+            .map_or(&self.values.relay.upstream, |target| &target.url)
+    }
+
+    /// Returns all upstreams that Relay fails over between.
+    ///
+    /// If no list of upstreams is configured, this contains only the single `upstream`.
+    pub fn upstream_targets(&self) -> Vec<UpstreamTarget> {
+        if self.values.relay.upstreams.is_empty() {
+            vec![UpstreamTarget::new(self.values.relay.upstream.clone())]
+        } else {
+            self.values.relay.upstreams.clone()
+        }
     }
 
     /// Returns the custom HTTP "Host" header.
@@ -1541,6 +1994,16 @@ impl Config {
         self.values.relay.tls_identity_password.as_deref()
     }
 
+    /// Returns the path to the identity bundle presented as client certificate to the upstream.
+    pub fn http_client_identity_path(&self) -> Option<&Path> {
+        self.values.http.client_identity_path.as_deref()
+    }
+
+    /// Returns the password for the client identity bundle.
+    pub fn http_client_identity_password(&self) -> Option<&str> {
+        self.values.http.client_identity_password.as_deref()
+    }
+
     /// Returns `true` when project IDs should be overriden rather than validated.
     ///
     /// Defaults to `false`, which requires project ID validation.
@@ -1607,6 +2070,11 @@ impl Config {
         self.values.outcomes.emit_client_outcomes
     }
 
+    /// Returns the maximum quantity accepted for a single entry in a client report.
+    pub fn max_client_report_quantity(&self) -> u32 {
+        self.values.outcomes.max_client_report_quantity
+    }
+
     /// Returns the maximum number of outcomes that are batched before being sent
     pub fn outcome_batch_size(&self) -> usize {
         self.values.outcomes.batch_size
@@ -1627,6 +2095,11 @@ impl Config {
         &self.values.outcomes.aggregator
     }
 
+    /// Returns the configuration for exporting outcomes, if enabled.
+    pub fn outcome_export(&self) -> Option<&OutcomeExportConfig> {
+        self.values.outcomes.export.as_ref()
+    }
+
     /// Returns logging configuration.
     pub fn logging(&self) -> &relay_log::LogConfig {
         &self.values.logging
@@ -1924,6 +2397,27 @@ impl Config {
         )
     }
 
+    /// Queue on disk for messages that cannot be delivered to Kafka.
+    pub fn kafka_fallback(&self) -> Option<&KafkaFallbackConfig> {
+        self.values.processing.kafka_fallback.as_ref()
+    }
+
+    /// Additional store sinks by name.
+    pub fn store_sinks(&self) -> &BTreeMap<String, SinkConfig> {
+        &self.values.processing.sinks
+    }
+
+    /// Names of the sinks that messages for the given topic are written to.
+    ///
+    /// Defaults to the built-in [`KAFKA_SINK`] if there is no route for the topic.
+    pub fn sink_route(&self, topic: KafkaTopic) -> Vec<&str> {
+        let routes = &self.values.processing.sink_routes;
+        match routes.get(topic.as_str()) {
+            Some(sinks) => sinks.iter().map(String::as_str).collect(),
+            None => vec![KAFKA_SINK],
+        }
+    }
+
     /// Redis servers to connect to, for rate limiting.
     pub fn redis(&self) -> Option<&RedisConfig> {
         self.values.processing.redis.as_ref()
@@ -1945,6 +2439,56 @@ impl Config {
         self.values.processing.max_rate_limit.map(u32::into)
     }
 
+    /// Returns `true` if quotas should be enforced in memory of a non-processing Relay.
+    pub fn local_quotas_enabled(&self) -> bool {
+        self.values.quotas.enabled && !self.processing_enabled()
+    }
+
+    /// Returns the interval at which locally consumed quota is reported to the upstream.
+    pub fn local_quotas_sync_interval(&self) -> Option<Duration> {
+        self.values.quotas.sync_interval.map(Duration::from_secs)
+    }
+
+    /// Returns the configured priority of an envelope item type, if any.
+    pub fn item_priority(&self, item_type: &str) -> Option<Priority> {
+        self.values.load_shedding.items.get(item_type).copied()
+    }
+
+    /// Returns the configured priority of a data category, if any.
+    pub fn category_priority(&self, category: DataCategory) -> Option<Priority> {
+        self.values.load_shedding.categories.get(&category).copied()
+    }
+
+    /// Returns the fraction of the envelope buffer that envelopes of the given priority may use.
+    pub fn priority_limit(&self, priority: Priority) -> f64 {
+        let load_shedding = &self.values.load_shedding;
+        match priority {
+            Priority::Low => load_shedding.low_priority_limit,
+            Priority::Normal => load_shedding.normal_priority_limit,
+            Priority::High => 1.0,
+        }
+    }
+
+    /// Returns `true` if events with the same ID should be deduplicated.
+    pub fn dedupe_enabled(&self) -> bool {
+        self.values.dedupe.enabled
+    }
+
+    /// Returns the time for which event IDs are remembered for deduplication.
+    pub fn dedupe_window(&self) -> Duration {
+        Duration::from_secs(self.values.dedupe.window)
+    }
+
+    /// Returns the maximum number of event IDs remembered in memory for deduplication.
+    pub fn dedupe_max_entries(&self) -> usize {
+        self.values.dedupe.max_entries
+    }
+
+    /// Returns the directory with source maps for resolving JavaScript stack traces, if enabled.
+    pub fn source_maps_path(&self) -> Option<&Path> {
+        self.values.source_maps.path.as_deref()
+    }
+
     /// Returns configuration for the metrics [aggregator](relay_metrics::Aggregator).
     pub fn aggregator_config(&self) -> &AggregatorConfig {
         &self.values.aggregator
@@ -1955,6 +2499,16 @@ impl Config {
         &self.values.auth.static_relays
     }
 
+    /// Returns the name of the header containing the subject of verified client certificates.
+    pub fn client_certificate_header(&self) -> Option<&str> {
+        self.values.auth.client_certificate_header.as_deref()
+    }
+
+    /// Returns the public key to verify ingestion tokens, if configured.
+    pub fn ingestion_token_key(&self) -> Option<&PublicKey> {
+        self.values.auth.ingestion_token_key.as_ref()
+    }
+
     /// Returns `true` if unknown items should be accepted and forwarded.
     pub fn accept_unknown_items(&self) -> bool {
         let forward = self.values.routing.accept_unknown_items;
@@ -1995,6 +2549,194 @@ cache:
         assert_eq!(values.cache.envelope_expiry, 1800);
     }
 
+    #[test]
+    fn test_load_shedding() {
+        let yaml = r###"
+load_shedding:
+    items:
+        replay_recording: low
+    categories:
+        error: high
+        transaction: low
+    low_priority_limit: 0.5
+"###;
+
+        let values: ConfigValues = serde_yaml::from_str(yaml).unwrap();
+        let config = Config {
+            values,
+            credentials: None,
+            path: PathBuf::new(),
+        };
+
+        assert_eq!(
+            config.item_priority("replay_recording"),
+            Some(Priority::Low)
+        );
+        assert_eq!(config.item_priority("event"), None);
+        assert_eq!(
+            config.category_priority(DataCategory::Error),
+            Some(Priority::High)
+        );
+        assert_eq!(config.category_priority(DataCategory::Replay), None);
+        assert_eq!(config.priority_limit(Priority::Low), 0.5);
+        assert_eq!(config.priority_limit(Priority::Normal), 0.95);
+        assert_eq!(config.priority_limit(Priority::High), 1.0);
+    }
+
+    #[test]
+    fn test_credentials_rotation() {
+        let mut credentials = Credentials::generate();
+        let old_public_key = credentials.public_key.clone();
+        assert!(!credentials.is_rotating());
+        assert!(!credentials.finish_rotation());
+
+        credentials.rotate();
+        assert!(credentials.is_rotating());
+        let next_public_key = credentials.next_public_key.clone().unwrap();
+        assert_ne!(next_public_key, old_public_key);
+
+        assert!(credentials.finish_rotation());
+        assert!(!credentials.is_rotating());
+        assert_eq!(credentials.public_key, next_public_key);
+    }
+
+    #[test]
+    fn test_static_relay_next_public_key() {
+        let yaml = r###"
+auth:
+    static_relays:
+        33cbc2a1-da1c-4a83-b6ce-6a9d8c2ba73c:
+            public_key: "JOaR2bHZ31zYjFojC7UhPOidzfT3qOQgT9WEBw1JAKU"
+            next_public_key: "kMpGbydHZSvohzeMlghcWwHd8MkreKGzl_ncdkZSOMg"
+"###;
+
+        let values: ConfigValues = serde_yaml::from_str(yaml).unwrap();
+        let info = values.auth.static_relays.values().next().unwrap();
+        assert_eq!(info.public_keys().count(), 2);
+    }
+
+    #[test]
+    fn test_static_relay_certificate() {
+        let yaml = r###"
+auth:
+    client_certificate_header: x-ssl-client-s-dn
+    static_relays:
+        33cbc2a1-da1c-4a83-b6ce-6a9d8c2ba73c:
+            public_key: "JOaR2bHZ31zYjFojC7UhPOidzfT3qOQgT9WEBw1JAKU"
+            certificate_subject: "CN=relay-1,O=Example"
+"###;
+
+        let values: ConfigValues = serde_yaml::from_str(yaml).unwrap();
+        let config = Config {
+            values,
+            credentials: None,
+            path: PathBuf::new(),
+        };
+
+        assert_eq!(
+            config.client_certificate_header(),
+            Some("x-ssl-client-s-dn")
+        );
+        let relay_id = "33cbc2a1-da1c-4a83-b6ce-6a9d8c2ba73c".parse().unwrap();
+        let info = &config.static_relays()[&relay_id];
+        assert_eq!(
+            info.certificate_subject.as_deref(),
+            Some("CN=relay-1,O=Example")
+        );
+
+        // The subject is never exposed to other Relays.
+        assert!(!serde_json::to_string(info).unwrap().contains("relay-1"));
+    }
+
+    #[test]
+    fn test_static_relay_certificate_requires_header() {
+        let yaml = r###"
+auth:
+    static_relays:
+        33cbc2a1-da1c-4a83-b6ce-6a9d8c2ba73c:
+            public_key: "JOaR2bHZ31zYjFojC7UhPOidzfT3qOQgT9WEBw1JAKU"
+            certificate_subject: "CN=relay-1,O=Example"
+"###;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("config.yml"), yaml).unwrap();
+
+        let error = Config::from_path(dir.path()).unwrap_err();
+        let error = error.downcast_ref::<ConfigError>().unwrap();
+        assert_eq!(error.kind(), ConfigErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn test_upstream_targets() {
+        let config = Config::default();
+        assert_eq!(config.upstream_targets().len(), 1);
+
+        let yaml = r###"
+relay:
+    upstreams:
+        - url: "https://eu.sentry.io/"
+          priority: 1
+        - url: "https://us.sentry.io/"
+          weight: 3
+"###;
+
+        let values: ConfigValues = serde_yaml::from_str(yaml).unwrap();
+        let config = Config {
+            values,
+            credentials: None,
+            path: PathBuf::new(),
+        };
+
+        let targets = config.upstream_targets();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].weight, 1);
+        assert_eq!(targets[1].weight, 3);
+        assert_eq!(config.upstream_descriptor().host(), "us.sentry.io");
+    }
+
+    #[test]
+    fn test_sink_routes() {
+        let yaml = r###"
+processing:
+    enabled: true
+    kafka_config: []
+    sinks:
+        archive:
+            type: file
+            path: /tmp/archive
+        lake:
+            type: s3
+            endpoint: "http://minio:9000"
+            bucket: events
+            access_key_id: minio
+            secret_access_key: minio123
+            batch:
+                max_records: 10
+    sink_routes:
+        events: [kafka, archive]
+        replay_recordings: [lake]
+"###;
+
+        let values: ConfigValues = serde_yaml::from_str(yaml).unwrap();
+        let config = Config {
+            values,
+            credentials: None,
+            path: PathBuf::new(),
+        };
+
+        assert_eq!(config.sink_route(KafkaTopic::Events), ["kafka", "archive"]);
+        assert_eq!(config.sink_route(KafkaTopic::ReplayRecordings), ["lake"]);
+        assert_eq!(config.sink_route(KafkaTopic::Transactions), [KAFKA_SINK]);
+
+        let sinks = config.store_sinks();
+        assert!(matches!(sinks["archive"], SinkConfig::File { .. }));
+        assert_eq!(sinks["archive"].batch().max_records, 1000);
+
+        let SinkConfig::S3(ref lake) = sinks["lake"] else { panic!("expected s3 sink") };
+        assert_eq!(lake.region, "us-east-1");
+        assert_eq!(lake.batch.max_records, 10);
+    }
+
     #[test]
     fn test_emit_outcomes() {
         for (serialized, deserialized) in &[
@@ -2008,6 +2750,62 @@ cache:
         }
     }
 
+    #[test]
+    fn test_outcome_export() {
+        let yaml = r###"
+outcomes:
+    emit_outcomes: true
+    export:
+        type: webhook
+        url: "https://billing.example.com/outcomes"
+        headers:
+            Authorization: Bearer secret
+"###;
+
+        let values: ConfigValues = serde_yaml::from_str(yaml).unwrap();
+        let config = Config {
+            values,
+            credentials: None,
+            path: PathBuf::new(),
+        };
+
+        assert_eq!(config.emit_outcomes(), EmitOutcomes::AsOutcomes);
+        match config.outcome_export() {
+            Some(OutcomeExportConfig::Webhook {
+                url,
+                headers,
+                max_retries,
+                max_concurrent_requests,
+            }) => {
+                assert_eq!(url, "https://billing.example.com/outcomes");
+                assert_eq!(headers["Authorization"], "Bearer secret");
+                assert_eq!(*max_retries, 5);
+                assert_eq!(*max_concurrent_requests, 10);
+            }
+            other => panic!("expected webhook export, got {other:?}"),
+        }
+
+        let yaml = r###"
+type: file
+path: /var/lib/relay/outcomes
+max_file_size: 1MB
+"###;
+
+        let export: OutcomeExportConfig = serde_yaml::from_str(yaml).unwrap();
+        match export {
+            OutcomeExportConfig::File {
+                path,
+                max_file_size,
+                max_file_age,
+            } => {
+                assert_eq!(path, Path::new("/var/lib/relay/outcomes"));
+                assert_eq!(max_file_size.as_bytes(), 1_000_000);
+                assert_eq!(max_file_age, 3600);
+            }
+            other => panic!("expected file export, got {other:?}"),
+        }
+    }
+
     #[test]
     fn test_emit_outcomes_invalid() {
         assert!(matches!(
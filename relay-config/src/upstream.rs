@@ -4,6 +4,7 @@ use std::str::FromStr;
 use std::{fmt, io};
 
 use relay_common::{Dsn, Scheme};
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 /// Indicates failures in the upstream error api.
@@ -157,6 +158,38 @@ impl FromStr for UpstreamDescriptor<'static> {
 
 relay_common::impl_str_serde!(UpstreamDescriptor<'static>, "a sentry upstream URL");
 
+fn default_weight() -> u32 {
+    1
+}
+
+/// One of multiple upstreams that Relay fails over between.
+///
+/// Relay sends requests to the healthy upstreams with the lowest `priority` value and distributes
+/// requests between them according to their `weight`. Upstreams with higher priority values are
+/// only used if all preferred upstreams are unavailable.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamTarget {
+    /// The upstream relay or sentry instance.
+    pub url: UpstreamDescriptor<'static>,
+    /// Upstreams with lower values are preferred.
+    #[serde(default)]
+    pub priority: u32,
+    /// Relative share of requests among upstreams of the same priority.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+impl UpstreamTarget {
+    /// Creates a target with default priority and weight.
+    pub fn new(url: UpstreamDescriptor<'static>) -> Self {
+        Self {
+            url,
+            priority: 0,
+            weight: default_weight(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
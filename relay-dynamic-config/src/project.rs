@@ -1,20 +1,68 @@
 use std::collections::BTreeSet;
 
 use relay_auth::PublicKey;
+use relay_common::UnixTimestamp;
 use relay_filter::FiltersConfig;
 use relay_general::pii::{DataScrubbingConfig, PiiConfig};
+use relay_general::protocol::Event;
 use relay_general::store::{
     BreakdownsConfig, MeasurementsConfig, SpanDescriptionRule, TransactionNameRule,
 };
 use relay_general::types::SpanAttribute;
 use relay_quotas::Quota;
-use relay_sampling::SamplingConfig;
+use relay_sampling::{RuleCondition, SamplingConfig};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::feature::Feature;
 use crate::{ErrorBoundary, SessionMetricsConfig, TaggingRule, TransactionMetricsConfig};
 
+/// A PII config that only applies to events matching a condition.
+///
+/// This allows a single project to carry stricter scrubbing policies for a subset of its traffic,
+/// for instance events from users in a specific region.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionalPiiConfig {
+    /// Condition that defines when the PII config applies.
+    pub condition: RuleCondition,
+    /// PII config applied in addition to the project's PII configs.
+    pub pii_config: PiiConfig,
+}
+
+impl ConditionalPiiConfig {
+    /// Returns `true` if the condition is supported and matches the given event.
+    pub fn matches(&self, event: &Event) -> bool {
+        self.condition.supported() && self.condition.matches(event, None)
+    }
+}
+
+fn default_lifecycle_ttl() -> u64 {
+    3600
+}
+
+/// Enables recording the lifecycle of envelopes for debugging data loss.
+///
+/// While active, Relay records every stage that envelopes with an event ID pass through, such as
+/// processing, filtering, sampling, rate limiting, spooling and sending. The records can be
+/// queried by event ID through the internal `/api/relay/events/:event_id/lifecycle/` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LifecycleDebugConfig {
+    /// Time until which the lifecycle of envelopes is recorded.
+    pub until: UnixTimestamp,
+    /// Time in seconds that the records of an event are kept. Defaults to one hour.
+    #[serde(default = "default_lifecycle_ttl")]
+    pub ttl: u64,
+}
+
+impl LifecycleDebugConfig {
+    /// Returns `true` if lifecycles are recorded at the given time.
+    pub fn is_active(&self, now: UnixTimestamp) -> bool {
+        now < self.until
+    }
+}
+
 /// Dynamic, per-DSN configuration passed down from Sentry.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
@@ -23,8 +71,14 @@ pub struct ProjectConfig {
     pub allowed_domains: Vec<String>,
     /// List of relay public keys that are permitted to access this project.
     pub trusted_relays: Vec<PublicKey>,
+    /// Requires signed ingestion tokens for all store requests.
+    #[serde(skip_serializing_if = "is_false")]
+    pub require_ingestion_token: bool,
     /// Configuration for PII stripping.
     pub pii_config: Option<PiiConfig>,
+    /// Additional PII configs that only apply to events matching their condition.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conditional_pii_configs: Vec<ConditionalPiiConfig>,
     /// The grouping configuration.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub grouping_config: Option<Value>,
@@ -73,6 +127,9 @@ pub struct ProjectConfig {
     /// Span description renaming rules.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub span_description_rules: Option<Vec<SpanDescriptionRule>>,
+    /// Records the lifecycle of envelopes for debugging. Not passed to external Relays.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lifecycle_debug: Option<LifecycleDebugConfig>,
 }
 
 impl Default for ProjectConfig {
@@ -80,7 +137,9 @@ impl Default for ProjectConfig {
         ProjectConfig {
             allowed_domains: vec!["*".to_string()],
             trusted_relays: vec![],
+            require_ingestion_token: false,
             pii_config: None,
+            conditional_pii_configs: Vec::new(),
             grouping_config: None,
             filter_settings: FiltersConfig::default(),
             datascrubbing_settings: DataScrubbingConfig::default(),
@@ -97,6 +156,7 @@ impl Default for ProjectConfig {
             tx_name_rules: Vec::new(),
             tx_name_ready: false,
             span_description_rules: None,
+            lifecycle_debug: None,
         }
     }
 }
@@ -110,11 +170,19 @@ impl Default for ProjectConfig {
 pub struct LimitedProjectConfig {
     pub allowed_domains: Vec<String>,
     pub trusted_relays: Vec<PublicKey>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub require_ingestion_token: bool,
     pub pii_config: Option<PiiConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conditional_pii_configs: Vec<ConditionalPiiConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grouping_config: Option<Value>,
     #[serde(skip_serializing_if = "FiltersConfig::is_empty")]
     pub filter_settings: FiltersConfig,
     #[serde(skip_serializing_if = "DataScrubbingConfig::is_disabled")]
     pub datascrubbing_settings: DataScrubbingConfig,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quotas: Vec<Quota>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dynamic_sampling: Option<SamplingConfig>,
     #[serde(skip_serializing_if = "SessionMetricsConfig::is_disabled")]
@@ -3,17 +3,19 @@ use std::collections::BTreeMap;
 
 use once_cell::sync::Lazy;
 
+use crate::pii::sensitive_keys::builtin_sensitive_key_rules;
 use crate::pii::{
     AliasRule, MultipleRule, PatternRule, Redaction, ReplaceRedaction, RuleSpec, RuleType,
 };
 
 macro_rules! declare_builtin_rules {
     ($($rule_id:expr => $spec:expr;)*) => {
-        pub(crate) static BUILTIN_RULES_MAP: Lazy<BTreeMap<&str, RuleSpec>> = Lazy::new(|| {
+        pub(crate) static BUILTIN_RULES_MAP: Lazy<BTreeMap<String, RuleSpec>> = Lazy::new(|| {
             let mut map = BTreeMap::new();
             $(
-                map.insert($rule_id, $spec);
+                map.insert(($rule_id).to_owned(), $spec);
             )*
+            map.extend(builtin_sensitive_key_rules());
             map
         });
     }
@@ -1,10 +1,11 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use once_cell::sync::Lazy;
 
+use crate::pii::sensitive_keys::sensitive_key_rules;
 use crate::pii::{
-    DataScrubbingConfig, LazyPattern, PiiConfig, PiiConfigError, RedactPairRule, Redaction,
-    RuleSpec, RuleType, Vars,
+    DataScrubbingConfig, LazyPattern, PatternRule, PiiConfig, PiiConfigError, RedactPairRule,
+    Redaction, RuleSpec, RuleType, Vars,
 };
 use crate::processor::{SelectorPathItem, SelectorSpec, ValueType};
 
@@ -57,6 +58,44 @@ static SENSITIVE_COOKIES: Lazy<SelectorSpec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Fields that are scrubbed based on the catalog of sensitive keys.
+static SENSITIVE_KEY_FIELDS: Lazy<SelectorSpec> = Lazy::new(|| {
+    "($http.query_string.* | *.cookies.* | $http.headers.* | $http.data.** | $breadcrumb.data.url)"
+        .parse()
+        .unwrap()
+});
+
+/// The rule for keys added to the catalog of sensitive keys by the datascrubbing config.
+const CUSTOM_SENSITIVE_KEYS_RULE: &str = "@sensitivekey:custom";
+
+/// The rule truncating long strings kept by an allowlist.
+const ALLOWLIST_TRUNCATE_RULE: &str = "allowlist-truncate";
+
+/// Returns a selector for all children of `prefix`, except for the `allowed` keys.
+fn allowlist_selector(prefix: &[SelectorPathItem], allowed: &[String]) -> SelectorSpec {
+    let child = |item| {
+        let mut path = prefix.to_vec();
+        path.push(item);
+        SelectorSpec::Path(path)
+    };
+
+    let mut allowed: Vec<_> = allowed
+        .iter()
+        .map(|key| key.trim())
+        .filter(|key| !key.is_empty())
+        .map(|key| child(SelectorPathItem::Key(key.to_owned())))
+        .collect();
+
+    let all = child(SelectorPathItem::Wildcard);
+    let allowed = match allowed.len() {
+        0 => return all,
+        1 => allowed.remove(0),
+        _ => SelectorSpec::Or(allowed),
+    };
+
+    SelectorSpec::And(vec![all, SelectorSpec::Not(Box::new(allowed))])
+}
+
 pub fn to_pii_config(
     datascrubbing_config: &DataScrubbingConfig,
 ) -> Result<Option<PiiConfig>, PiiConfigError> {
@@ -72,6 +111,13 @@ pub fn to_pii_config(
         );
     }
 
+    if datascrubbing_config.scrub_data && datascrubbing_config.scrub_sensitive_keys {
+        applications.insert(
+            SENSITIVE_KEY_FIELDS.clone(),
+            vec!["@sensitivekey".to_owned()],
+        );
+    }
+
     if datascrubbing_config.scrub_ip_addresses {
         // legacy(?) scrubs all fields that are known to have IPs regardless of actual content
         applications.insert(KNOWN_IP_FIELDS.clone(), vec!["@anything:remove".to_owned()]);
@@ -81,6 +127,22 @@ pub fn to_pii_config(
     }
 
     if datascrubbing_config.scrub_data {
+        let sensitive_key_rules = sensitive_key_rules(
+            CUSTOM_SENSITIVE_KEYS_RULE,
+            datascrubbing_config
+                .sensitive_keys
+                .iter()
+                .map(String::as_str),
+        );
+
+        if !sensitive_key_rules.is_empty() {
+            custom_rules.extend(sensitive_key_rules);
+            applications
+                .entry(SENSITIVE_KEY_FIELDS.clone())
+                .or_insert_with(Vec::new)
+                .push(CUSTOM_SENSITIVE_KEYS_RULE.to_owned());
+        }
+
         let mut sensitive_fields = datascrubbing_config
             .sensitive_fields
             .iter()
@@ -121,6 +183,68 @@ pub fn to_pii_config(
         }
     }
 
+    let allowlist = &datascrubbing_config.allowlist;
+    let allowlist_sections = [
+        (
+            vec![
+                SelectorPathItem::Type(ValueType::Frame),
+                SelectorPathItem::Key("vars".to_owned()),
+            ],
+            &allowlist.frame_vars,
+        ),
+        (
+            vec![SelectorPathItem::Key("extra".to_owned())],
+            &allowlist.extra,
+        ),
+        (
+            vec![
+                SelectorPathItem::Key("contexts".to_owned()),
+                SelectorPathItem::Wildcard,
+            ],
+            // The context type is required to parse contexts and never contains PII.
+            &allowlist.contexts.as_ref().map(|allowed| {
+                let mut allowed = allowed.clone();
+                allowed.push("type".to_owned());
+                allowed
+            }),
+        ),
+    ];
+
+    let mut truncated_selectors = Vec::new();
+    for (prefix, allowed) in allowlist_sections {
+        if let Some(allowed) = allowed {
+            applications.insert(
+                allowlist_selector(&prefix, allowed),
+                vec!["@anything:remove".to_owned()],
+            );
+
+            let mut values = prefix;
+            values.push(SelectorPathItem::Wildcard);
+            truncated_selectors.push(SelectorSpec::Path(values.clone()));
+            values.push(SelectorPathItem::DeepWildcard);
+            truncated_selectors.push(SelectorSpec::Path(values));
+        }
+    }
+
+    if let Some(max_length) = allowlist.max_value_length {
+        if !truncated_selectors.is_empty() {
+            custom_rules.insert(
+                ALLOWLIST_TRUNCATE_RULE.to_owned(),
+                RuleSpec {
+                    ty: RuleType::Pattern(PatternRule {
+                        pattern: LazyPattern::new(format!(r"(?s)\A.{{{max_length}}}(.+)")),
+                        replace_groups: Some(BTreeSet::from([1])),
+                    }),
+                    redaction: Redaction::Remove,
+                },
+            );
+            applications.insert(
+                SelectorSpec::Or(truncated_selectors),
+                vec![ALLOWLIST_TRUNCATE_RULE.to_owned()],
+            );
+        }
+    }
+
     if applied_rules.is_empty() && applications.is_empty() {
         return Ok(None);
     }
@@ -168,7 +292,7 @@ mod tests {
     use super::to_pii_config as to_pii_config_impl;
     /// These tests are ported from Sentry's Python testsuite (test_data_scrubber). Each testcase
     /// has an equivalent testcase in Python.
-    use crate::pii::{DataScrubbingConfig, PiiConfig, PiiProcessor};
+    use crate::pii::{DataScrubbingAllowlist, DataScrubbingConfig, PiiConfig, PiiProcessor};
     use crate::processor::{process_value, ProcessingState};
     use crate::protocol::Event;
     use crate::store::{StoreConfig, StoreProcessor};
@@ -491,6 +615,179 @@ THd+9FBxiHLGXNKhG/FRSyREXEt+NyYIf/0cyByc9tNksat794ddUqnLOg0vwSkv
         assert_annotated_snapshot!(data);
     }
 
+    #[test]
+    fn test_sensitive_key_catalog() {
+        let scrub = |scrub_sensitive_keys| {
+            let mut data = Event::from_value(
+                serde_json::json!({
+                    "request": {
+                        "query_string": [["sig", "abc"], ["page", "2"]],
+                        "headers": [["X-Amz-Signature", "abc"]]
+                    }
+                })
+                .into(),
+            );
+
+            let pii_config = to_pii_config(&DataScrubbingConfig {
+                scrub_sensitive_keys,
+                ..simple_enabled_config()
+            })
+            .unwrap();
+
+            let mut pii_processor = PiiProcessor::new(pii_config.compiled());
+            process_value(&mut data, &mut pii_processor, ProcessingState::root()).unwrap();
+
+            let request = data.value().unwrap().request.value().unwrap();
+            let query = request.query_string.value().unwrap();
+            let headers = request.headers.value().unwrap();
+            [
+                query.get_value("sig").map(|v| v.as_str().to_owned()),
+                query.get_value("page").map(|v| v.as_str().to_owned()),
+                headers.get_header("X-Amz-Signature").map(str::to_owned),
+            ]
+        };
+
+        // The catalog is not applied unless it is enabled explicitly.
+        assert_eq!(
+            scrub(false),
+            [
+                Some("abc".to_owned()),
+                Some("2".to_owned()),
+                Some("abc".to_owned())
+            ]
+        );
+        assert_eq!(
+            scrub(true),
+            [
+                Some("[Filtered]".to_owned()),
+                Some("2".to_owned()),
+                Some("[Filtered]".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_sensitive_keys() {
+        let mut data = Event::from_value(
+            serde_json::json!({
+                "request": {
+                    "query_string": [["tenant", "acme"], ["page", "2"]],
+                    "headers": [["X-Tenant", "acme"]]
+                },
+                "breadcrumbs": {
+                    "values": [{
+                        "data": {"url": "https://example.com/?tenant=acme&page=2"}
+                    }]
+                }
+            })
+            .into(),
+        );
+
+        let pii_config = to_pii_config(&DataScrubbingConfig {
+            sensitive_keys: vec!["tenant".to_owned(), "x-tenant".to_owned()],
+            ..simple_enabled_config()
+        })
+        .unwrap();
+
+        let mut pii_processor = PiiProcessor::new(pii_config.compiled());
+        process_value(&mut data, &mut pii_processor, ProcessingState::root()).unwrap();
+        assert_annotated_snapshot!(data);
+    }
+
+    #[test]
+    fn test_convert_allowlist() {
+        let pii_config = to_pii_config(&DataScrubbingConfig {
+            allowlist: DataScrubbingAllowlist {
+                frame_vars: Some(vec!["user_id".to_owned(), "".to_owned()]),
+                extra: Some(vec![]),
+                contexts: None,
+                max_value_length: Some(10),
+            },
+            ..Default::default()
+        });
+
+        insta::assert_json_snapshot!(pii_config, @r###"
+        {
+          "rules": {
+            "allowlist-truncate": {
+              "type": "pattern",
+              "pattern": "(?s)\\A.{10}(.+)",
+              "replaceGroups": [
+                1
+              ],
+              "redaction": {
+                "method": "remove"
+              }
+            }
+          },
+          "applications": {
+            "$frame.vars.* && !$frame.vars.user_id": [
+              "@anything:remove"
+            ],
+            "$frame.vars.* || $frame.vars.*.** || extra.* || extra.*.**": [
+              "allowlist-truncate"
+            ],
+            "extra.*": [
+              "@anything:remove"
+            ]
+          }
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let mut data = Event::from_value(
+            serde_json::json!({
+                "exception": {
+                    "values": [{
+                        "stacktrace": {
+                            "frames": [{
+                                "vars": {
+                                    "user_id": "1234567890123",
+                                    "password": "hunter2",
+                                    "count": 42
+                                }
+                            }]
+                        }
+                    }]
+                },
+                "extra": {
+                    "Request_ID": {"id": "abcdefghijklmno"},
+                    "token": "secret"
+                },
+                "contexts": {
+                    "trace": {
+                        "type": "trace",
+                        "trace_id": "4c79f60c11214eb38604f4ae0781bfb2",
+                        "span_id": "fa90fdead5f74053"
+                    },
+                    "custom": {
+                        "type": "custom",
+                        "region": "eu",
+                        "account": "12345"
+                    }
+                }
+            })
+            .into(),
+        );
+
+        let pii_config = to_pii_config(&DataScrubbingConfig {
+            allowlist: DataScrubbingAllowlist {
+                frame_vars: Some(vec!["user_id".to_owned(), "count".to_owned()]),
+                extra: Some(vec!["request_id".to_owned()]),
+                contexts: Some(vec!["region".to_owned()]),
+                max_value_length: Some(10),
+            },
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut pii_processor = PiiProcessor::new(pii_config.compiled());
+        process_value(&mut data, &mut pii_processor, ProcessingState::root()).unwrap();
+        assert_annotated_snapshot!(data);
+    }
+
     #[test]
     fn test_http_remote_addr_stripped() {
         let mut data = Event::from_value(
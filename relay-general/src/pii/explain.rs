@@ -0,0 +1,123 @@
+//! Dry-run explanations of PII scrubbing.
+
+use serde::Serialize;
+
+use crate::pii::{PiiConfig, PiiProcessor, Redaction};
+use crate::processor::{process_value, ProcessValue, ProcessingState, SelectorSpec};
+use crate::types::{Annotated, ProcessingAction, Value};
+
+/// Describes a single modification made by a PII rule.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PiiExplanation {
+    /// The path of the modified field, for example `request.headers.Authorization`.
+    pub path: String,
+    /// The selector of the PII config that matched the field.
+    pub selector: SelectorSpec,
+    /// The ID of the rule that modified the value.
+    pub rule_id: String,
+    /// The ID of the rule as it appears in remarks.
+    ///
+    /// This differs from `rule_id` if the rule was applied through an alias or a `multiple` rule
+    /// that hides its inner rules.
+    pub origin: String,
+    /// The redaction method used to modify the value.
+    pub redaction: Redaction,
+    /// The value before the rule was applied.
+    pub before: Option<Value>,
+    /// The value after the rule was applied, or `None` if the value was removed.
+    pub after: Option<Value>,
+}
+
+/// Explains which parts of a value would be scrubbed by the given PII config.
+///
+/// This runs the [`PiiProcessor`] on a copy of the value and leaves the original untouched. For
+/// every field modified by a rule, it returns the matching selector, the rule and the values
+/// before and after applying the rule. If multiple rules modify the same field, there is one
+/// explanation per rule in the order they were applied.
+pub fn explain_pii<T: ProcessValue>(
+    value: &Annotated<T>,
+    config: &PiiConfig,
+) -> Result<Vec<PiiExplanation>, ProcessingAction> {
+    let mut value = value.clone();
+    let mut processor = PiiProcessor::explaining(config.compiled());
+    process_value(&mut value, &mut processor, ProcessingState::root())?;
+    Ok(processor.into_explanations())
+}
+
+#[cfg(test)]
+mod tests {
+    use similar_asserts::assert_eq;
+
+    use super::*;
+    use crate::pii::ReplaceRedaction;
+    use crate::protocol::Event;
+
+    #[test]
+    fn test_explain_string() {
+        let event = Annotated::<Event>::from_json(
+            r#"{"logentry": {"formatted": "Contact me at foo@example.com"}}"#,
+        )
+        .unwrap();
+        let config = PiiConfig::from_json(r#"{"applications": {"$message": ["@email"]}}"#).unwrap();
+
+        let explanations = explain_pii(&event, &config).unwrap();
+        assert_eq!(
+            explanations,
+            vec![PiiExplanation {
+                path: "logentry.formatted".to_owned(),
+                selector: "$message".parse().unwrap(),
+                rule_id: "@email:replace".to_owned(),
+                origin: "@email".to_owned(),
+                redaction: Redaction::Replace(ReplaceRedaction {
+                    text: "[email]".to_owned(),
+                }),
+                before: Some(Value::String("Contact me at foo@example.com".to_owned())),
+                after: Some(Value::String("Contact me at [email]".to_owned())),
+            }]
+        );
+
+        // The original event is not modified.
+        let formatted = event
+            .value()
+            .and_then(|event| event.logentry.value())
+            .and_then(|logentry| logentry.formatted.as_str());
+        assert_eq!(formatted, Some("Contact me at foo@example.com"));
+    }
+
+    #[test]
+    fn test_explain_removed_container() {
+        let event = Annotated::<Event>::from_json(
+            r#"{"extra": {"secrets": {"password": "hunter2"}, "other": 42}}"#,
+        )
+        .unwrap();
+        let config =
+            PiiConfig::from_json(r#"{"applications": {"extra.secrets": ["@anything:remove"]}}"#)
+                .unwrap();
+
+        let explanations = explain_pii(&event, &config).unwrap();
+        insta::assert_json_snapshot!(explanations, @r###"
+        [
+          {
+            "path": "extra.secrets",
+            "selector": "extra.secrets",
+            "rule_id": "@anything:remove",
+            "origin": "@anything:remove",
+            "redaction": {
+              "method": "remove"
+            },
+            "before": {
+              "password": "hunter2"
+            },
+            "after": null
+          }
+        ]
+        "###);
+    }
+
+    #[test]
+    fn test_explain_no_match() {
+        let event = Annotated::<Event>::from_json(r#"{"logentry": {"formatted": "hi"}}"#).unwrap();
+        let config = PiiConfig::from_json(r#"{"applications": {"$message": ["@email"]}}"#).unwrap();
+        assert!(explain_pii(&event, &config).unwrap().is_empty());
+    }
+}
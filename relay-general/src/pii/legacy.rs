@@ -9,6 +9,39 @@ use serde::{Deserialize, Serialize};
 use super::config::PiiConfigError;
 use crate::pii::{convert, is_flag_default, PiiConfig};
 
+/// Allowlists for Sentry's datascrubbing.
+///
+/// For every section that has an allowlist, all values are removed except for those with an
+/// allowed key. Sections without an allowlist are not affected.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DataScrubbingAllowlist {
+    /// Names of local variables to keep in stack frames.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_vars: Option<Vec<String>>,
+    /// Keys to keep in `extra`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extra: Option<Vec<String>>,
+    /// Fields to keep in contexts.
+    ///
+    /// Fields that can never contain PII, such as trace IDs, are always kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contexts: Option<Vec<String>>,
+    /// Maximum number of characters of strings kept by an allowlist.
+    ///
+    /// Longer strings, including strings nested in objects and arrays, are truncated. Numbers and
+    /// booleans are kept as they are.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_value_length: Option<usize>,
+}
+
+impl DataScrubbingAllowlist {
+    /// Returns true if no section has an allowlist.
+    pub fn is_empty(&self) -> bool {
+        self.frame_vars.is_none() && self.extra.is_none() && self.contexts.is_none()
+    }
+}
+
 /// Configuration for Sentry's datascrubbing
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
@@ -26,6 +59,22 @@ pub struct DataScrubbingConfig {
     /// Controls whether default fields will be scrubbed.
     #[serde(skip_serializing_if = "is_flag_default")]
     pub scrub_defaults: bool,
+    /// Controls whether the catalog of sensitive keys is applied.
+    ///
+    /// If enabled, values of well-known sensitive keys are scrubbed from query strings, cookies,
+    /// headers, form bodies and breadcrumb URLs. See
+    /// [`DEFAULT_SENSITIVE_KEYS`](crate::pii::DEFAULT_SENSITIVE_KEYS).
+    #[serde(skip_serializing_if = "is_flag_default")]
+    pub scrub_sensitive_keys: bool,
+    /// Additional sensitive keys to scrub.
+    ///
+    /// Values of these keys are scrubbed from query strings, cookies, headers, form bodies and
+    /// breadcrumb URLs, regardless of whether the default catalog is applied.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sensitive_keys: Vec<String>,
+    /// Allowlists for frame variables, `extra` and contexts.
+    #[serde(skip_serializing_if = "DataScrubbingAllowlist::is_empty")]
+    pub allowlist: DataScrubbingAllowlist,
 
     /// PII config derived from datascrubbing settings.
     ///
@@ -47,13 +96,16 @@ impl DataScrubbingConfig {
             scrub_ip_addresses: false,
             sensitive_fields: vec![],
             scrub_defaults: false,
+            scrub_sensitive_keys: false,
+            sensitive_keys: vec![],
+            allowlist: DataScrubbingAllowlist::default(),
             pii_config: OnceCell::with_value(Ok(None)),
         }
     }
 
     /// Returns true if datascrubbing is disabled.
     pub fn is_disabled(&self) -> bool {
-        !self.scrub_data && !self.scrub_ip_addresses
+        !self.scrub_data && !self.scrub_ip_addresses && self.allowlist.is_empty()
     }
 
     /// Get the PII config derived from datascrubbing settings.
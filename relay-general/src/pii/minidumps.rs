@@ -1,13 +1,17 @@
 //! Minidump scrubbing.
 
 use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
 use std::num::TryFromIntError;
 use std::ops::Range;
 use std::str::Utf8Error;
 
 use minidump::format::{
-    CvSignature, MINIDUMP_LOCATION_DESCRIPTOR, MINIDUMP_STREAM_TYPE as StreamType,
+    CvSignature, MINIDUMP_ANNOTATION, MINIDUMP_LOCATION_DESCRIPTOR,
+    MINIDUMP_STREAM_TYPE as StreamType,
 };
 use minidump::{
     Endian, Error as MinidumpError, Minidump, MinidumpMemoryList, MinidumpModuleList,
@@ -75,6 +79,71 @@ enum MinidumpItem {
     CodeModuleName(Range<usize>),
     /// This is a UTF-16LE encoded pathname of a debug file.
     DebugModuleName(Range<usize>),
+    /// This is a UTF-16LE encoded name of a thread.
+    ThreadName(Range<usize>),
+    /// A fixed-size UTF-16LE string from the misc info stream.
+    ///
+    /// These are the time zone names and the build strings, truncated at the first NULL
+    /// character.
+    MiscInfoString(Range<usize>),
+    /// The additional parameters of the exception record.
+    ///
+    /// These are raw 64-bit values defined by the exception code.
+    ExceptionParameters(Range<usize>),
+    /// The UTF-8 encoded value of a crashpad annotation.
+    ///
+    /// This covers simple annotations of the process and modules, list annotations and
+    /// string annotation objects.
+    CrashpadAnnotation(Range<usize>),
+}
+
+/// An area of a minidump that can be modified by scrubbing.
+///
+/// Returned by [`PiiAttachmentsProcessor::scrub_minidump_streams`] to report which parts of
+/// the minidump have been touched.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MinidumpStreamArea {
+    /// Memory regions referenced by a thread's stack.
+    StackMemory,
+    /// Memory regions not associated with a thread's stack.
+    HeapMemory,
+    /// The Linux environ and cmdline streams.
+    LinuxProcessInfo,
+    /// Code file paths in the module list.
+    CodeFile,
+    /// Debug file paths in the module list.
+    DebugFile,
+    /// The thread names stream.
+    ThreadName,
+    /// Strings in the misc info stream.
+    MiscInfo,
+    /// Parameters of the exception record.
+    ExceptionRecord,
+    /// Crashpad annotations.
+    Annotations,
+}
+
+impl MinidumpStreamArea {
+    /// Returns the name of this area as used in PII selectors.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StackMemory => "stack_memory",
+            Self::HeapMemory => "heap_memory",
+            Self::LinuxProcessInfo => "linux_process_info",
+            Self::CodeFile => "code_file",
+            Self::DebugFile => "debug_file",
+            Self::ThreadName => "thread_name",
+            Self::MiscInfo => "misc_info",
+            Self::ExceptionRecord => "exception_record",
+            Self::Annotations => "annotations",
+        }
+    }
+}
+
+impl fmt::Display for MinidumpStreamArea {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Internal struct to keep a minidump and it's raw data together.
@@ -128,6 +197,86 @@ impl<'a> MinidumpData<'a> {
         Ok(start..start + len)
     }
 
+    /// Reads a `u16` at the given offset in the raw minidump data.
+    fn u16_at(&self, offset: usize) -> Result<u16, ScrubMinidumpError> {
+        let bytes = self
+            .data
+            .get(offset..)
+            .ok_or(ScrubMinidumpError::InvalidAddress)?;
+        u16_from_bytes(bytes, self.minidump.endian)
+    }
+
+    /// Reads a `u32` at the given offset in the raw minidump data.
+    fn u32_at(&self, offset: usize) -> Result<u32, ScrubMinidumpError> {
+        let bytes = self
+            .data
+            .get(offset..)
+            .ok_or(ScrubMinidumpError::InvalidAddress)?;
+        u32_from_bytes(bytes, self.minidump.endian)
+    }
+
+    /// Reads a `u64` at the given offset in the raw minidump data.
+    fn u64_at(&self, offset: usize) -> Result<u64, ScrubMinidumpError> {
+        let bytes = self
+            .data
+            .get(offset..)
+            .ok_or(ScrubMinidumpError::InvalidAddress)?;
+        u64_from_bytes(bytes, self.minidump.endian)
+    }
+
+    /// Reads a `MINIDUMP_LOCATION_DESCRIPTOR` at the given offset and returns its `Range`.
+    fn location_range_at(&self, offset: usize) -> Result<Range<usize>, ScrubMinidumpError> {
+        let location = MINIDUMP_LOCATION_DESCRIPTOR {
+            data_size: self.u32_at(offset)?,
+            rva: self.u32_at(offset + 4)?,
+        };
+        self.location_range(location)
+    }
+
+    /// Returns the `Range` of the contents of a length-prefixed string at `rva`.
+    ///
+    /// Both `MINIDUMP_STRING` and crashpad's `MinidumpUTF8String` and `MinidumpByteArray` are
+    /// stored as a `u32` byte length followed by the data.
+    fn prefixed_range(&self, rva: usize) -> Result<Range<usize>, ScrubMinidumpError> {
+        let len: usize = self.u32_at(rva)?.try_into()?;
+        let start = rva + 4;
+        let end = start + len;
+        if end > self.data.len() {
+            return Err(ScrubMinidumpError::InvalidAddress);
+        }
+        Ok(start..end)
+    }
+
+    /// Returns the `Range` of a fixed-size UTF-16LE buffer up to its first NULL character.
+    fn wstr_buffer_range(&self, range: Range<usize>) -> Result<Range<usize>, ScrubMinidumpError> {
+        let buffer = self
+            .data
+            .get(range.clone())
+            .ok_or(ScrubMinidumpError::InvalidAddress)?;
+        let len = buffer
+            .chunks_exact(2)
+            .position(|unit| unit == [0, 0])
+            .unwrap_or(buffer.len() / 2);
+        Ok(range.start..range.start + len * 2)
+    }
+
+    /// Returns the `Range` of the `index`-th entry of `size` bytes in a list at `range`.
+    ///
+    /// Lists in minidumps and crashpad info start with a `u32` count which is followed by the
+    /// entries.
+    fn list_entry(
+        &self,
+        range: &Range<usize>,
+        index: usize,
+        size: usize,
+    ) -> Result<usize, ScrubMinidumpError> {
+        let offset = range.start + 4 + index * size;
+        if offset + size > range.end {
+            return Err(ScrubMinidumpError::InvalidAddress);
+        }
+        Ok(offset)
+    }
+
     /// Returns the range of a raw stream, if the stream is preset.
     fn raw_stream_range(
         &self,
@@ -217,8 +366,202 @@ impl<'a> MinidumpData<'a> {
             }
         }
 
+        // These streams are not required for processing. A malformed stream is skipped instead
+        // of failing the entire minidump, keeping the items extracted before the error.
+        skip_malformed("thread names", self.thread_name_items(&mut items));
+        skip_malformed("misc info", self.misc_info_items(&mut items));
+        skip_malformed("exception", self.exception_items(&mut items));
+        skip_malformed("crashpad info", self.crashpad_items(&mut items));
+
         Ok(items)
     }
+
+    /// Extracts the names from the thread names stream.
+    fn thread_name_items(&self, items: &mut Vec<MinidumpItem>) -> Result<(), ScrubMinidumpError> {
+        let range = match self.raw_stream_range(StreamType::ThreadNamesStream)? {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
+        let count: usize = self.u32_at(range.start)?.try_into()?;
+        for index in 0..count {
+            // MINIDUMP_THREAD_NAME: thread_id (u32) + thread_name_rva (RVA64)
+            let entry = self.list_entry(&range, index, 4 + 8)?;
+            let rva: usize = self.u64_at(entry + 4)?.try_into()?;
+            items.push(MinidumpItem::ThreadName(self.prefixed_range(rva)?));
+        }
+
+        Ok(())
+    }
+
+    /// Extracts the time zone names and build strings from the misc info stream.
+    ///
+    /// These fields are only present in `MINIDUMP_MISC_INFO_3` and `MINIDUMP_MISC_INFO_4`
+    /// respectively, which is determined by the size of the stream.
+    fn misc_info_items(&self, items: &mut Vec<MinidumpItem>) -> Result<(), ScrubMinidumpError> {
+        const TIME_ZONE_OFFSET: usize = 60;
+        const STANDARD_NAME: Range<usize> = TIME_ZONE_OFFSET + 4..TIME_ZONE_OFFSET + 68;
+        const DAYLIGHT_NAME: Range<usize> = TIME_ZONE_OFFSET + 88..TIME_ZONE_OFFSET + 152;
+        const BUILD_STRING: Range<usize> = 232..752;
+        const DBG_BLD_STR: Range<usize> = 752..832;
+
+        let range = match self.raw_stream_range(StreamType::MiscInfoStream)? {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
+        let size_of_info: usize = self.u32_at(range.start)?.try_into()?;
+        let len = size_of_info.min(range.len());
+        for field in [STANDARD_NAME, DAYLIGHT_NAME, BUILD_STRING, DBG_BLD_STR] {
+            if field.end <= len {
+                let field_range = range.start + field.start..range.start + field.end;
+                items.push(MinidumpItem::MiscInfoString(
+                    self.wstr_buffer_range(field_range)?,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Extracts the parameters of the exception record.
+    fn exception_items(&self, items: &mut Vec<MinidumpItem>) -> Result<(), ScrubMinidumpError> {
+        // thread_id + __align + exception_code + exception_flags + exception_record +
+        // exception_address
+        const NUMBER_PARAMETERS_OFFSET: usize = 4 + 4 + 4 + 4 + 8 + 8;
+        const INFORMATION_OFFSET: usize = NUMBER_PARAMETERS_OFFSET + 4 + 4;
+        const MAXIMUM_PARAMETERS: usize = 15;
+
+        let range = match self.raw_stream_range(StreamType::ExceptionStream)? {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
+        let number_parameters: usize = self
+            .u32_at(range.start + NUMBER_PARAMETERS_OFFSET)?
+            .try_into()?;
+        let start = range.start + INFORMATION_OFFSET;
+        let end = start + number_parameters.min(MAXIMUM_PARAMETERS) * 8;
+        if end > range.end {
+            return Err(ScrubMinidumpError::InvalidAddress);
+        }
+        if end > start {
+            items.push(MinidumpItem::ExceptionParameters(start..end));
+        }
+
+        Ok(())
+    }
+
+    /// Extracts the values of all crashpad annotations.
+    ///
+    /// Annotation keys are left intact, only the values are considered for scrubbing.
+    fn crashpad_items(&self, items: &mut Vec<MinidumpItem>) -> Result<(), ScrubMinidumpError> {
+        // version + report_id + client_id
+        const SIMPLE_ANNOTATIONS_OFFSET: usize = 4 + 16 + 16;
+        const MODULE_LIST_OFFSET: usize = SIMPLE_ANNOTATIONS_OFFSET + 8;
+        // version + list_annotations + simple_annotations + annotation_objects
+        const MODULE_INFO_SIZE: usize = 4 + 8 + 8 + 8;
+
+        let range = match self.raw_stream_range(StreamType::CrashpadInfoStream)? {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        if range.len() < MODULE_LIST_OFFSET + 8 {
+            return Err(ScrubMinidumpError::InvalidAddress);
+        }
+
+        let simple_annotations = self.location_range_at(range.start + SIMPLE_ANNOTATIONS_OFFSET)?;
+        self.simple_annotation_items(simple_annotations, items)?;
+
+        let module_list = self.location_range_at(range.start + MODULE_LIST_OFFSET)?;
+        if module_list.is_empty() {
+            return Ok(());
+        }
+
+        let count: usize = self.u32_at(module_list.start)?.try_into()?;
+        for index in 0..count {
+            // MINIDUMP_MODULE_CRASHPAD_INFO_LINK: index (u32) + location
+            let link = self.list_entry(&module_list, index, 4 + 8)?;
+            let info = self.location_range_at(link + 4)?;
+            if info.len() < MODULE_INFO_SIZE {
+                return Err(ScrubMinidumpError::InvalidAddress);
+            }
+
+            // MINIDUMP_MODULE_CRASHPAD_INFO: version + three location descriptors
+            let list_annotations = self.location_range_at(info.start + 4)?;
+            if !list_annotations.is_empty() {
+                let count: usize = self.u32_at(list_annotations.start)?.try_into()?;
+                for index in 0..count {
+                    let entry = self.list_entry(&list_annotations, index, 4)?;
+                    let rva: usize = self.u32_at(entry)?.try_into()?;
+                    items.push(MinidumpItem::CrashpadAnnotation(self.prefixed_range(rva)?));
+                }
+            }
+
+            let simple_annotations = self.location_range_at(info.start + 4 + 8)?;
+            self.simple_annotation_items(simple_annotations, items)?;
+
+            let annotation_objects = self.location_range_at(info.start + 4 + 8 + 8)?;
+            if !annotation_objects.is_empty() {
+                let count: usize = self.u32_at(annotation_objects.start)?.try_into()?;
+                for index in 0..count {
+                    // MINIDUMP_ANNOTATION: name (RVA) + ty (u16) + reserved (u16) + value (RVA)
+                    let entry = self.list_entry(&annotation_objects, index, 4 + 2 + 2 + 4)?;
+                    let ty = self.u16_at(entry + 4)?;
+                    if ty == MINIDUMP_ANNOTATION::TYPE_STRING {
+                        let rva: usize = self.u32_at(entry + 8)?.try_into()?;
+                        items.push(MinidumpItem::CrashpadAnnotation(self.prefixed_range(rva)?));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Extracts the values of a crashpad `MinidumpSimpleStringDictionary`.
+    fn simple_annotation_items(
+        &self,
+        range: Range<usize>,
+        items: &mut Vec<MinidumpItem>,
+    ) -> Result<(), ScrubMinidumpError> {
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        let count: usize = self.u32_at(range.start)?.try_into()?;
+        for index in 0..count {
+            // MINIDUMP_SIMPLE_STRING_DICTIONARY_ENTRY: key (RVA) + value (RVA)
+            let entry = self.list_entry(&range, index, 4 + 4)?;
+            let rva: usize = self.u32_at(entry + 4)?.try_into()?;
+            items.push(MinidumpItem::CrashpadAnnotation(self.prefixed_range(rva)?));
+        }
+
+        Ok(())
+    }
+}
+
+/// Logs the error of a malformed minidump stream that is skipped during scrubbing.
+fn skip_malformed(stream: &str, result: Result<(), ScrubMinidumpError>) {
+    if let Err(error) = result {
+        relay_log::warn!(
+            error = &error as &dyn Error,
+            "skipping malformed {} stream in minidump",
+            stream
+        );
+    }
+}
+
+/// Read a u16 from the start of a byte-slice.
+///
+/// See [`u32_from_bytes`].
+fn u16_from_bytes(bytes: &[u8], endian: Endian) -> Result<u16, ScrubMinidumpError> {
+    let mut buf = [0u8; 2];
+    buf.copy_from_slice(bytes.get(..2).ok_or(ScrubMinidumpError::InvalidAddress)?);
+    match endian {
+        Endian::Little => Ok(u16::from_le_bytes(buf)),
+        Endian::Big => Ok(u16::from_be_bytes(buf)),
+    }
 }
 
 /// Read a u32 from the start of a byte-slice.
@@ -234,6 +577,18 @@ fn u32_from_bytes(bytes: &[u8], endian: Endian) -> Result<u32, ScrubMinidumpErro
     }
 }
 
+/// Read a u64 from the start of a byte-slice.
+///
+/// See [`u32_from_bytes`].
+fn u64_from_bytes(bytes: &[u8], endian: Endian) -> Result<u64, ScrubMinidumpError> {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes.get(..8).ok_or(ScrubMinidumpError::InvalidAddress)?);
+    match endian {
+        Endian::Little => Ok(u64::from_le_bytes(buf)),
+        Endian::Big => Ok(u64::from_be_bytes(buf)),
+    }
+}
+
 impl PiiAttachmentsProcessor<'_> {
     /// Applies PII rules to the given minidump.
     ///
@@ -243,6 +598,9 @@ impl PiiAttachmentsProcessor<'_> {
     ///  1. All stack memory regions with `ValueType::StackMemory`
     ///  2. All other memory regions with `ValueType::HeapMemory`
     ///  3. Linux auxiliary streams with `ValueType::Binary`
+    ///  4. Module paths, thread names and misc info strings with `ValueType::String`
+    ///  5. Crashpad annotation values with `ValueType::Annotations`
+    ///  6. Exception record parameters with `ValueType::Binary`
     ///
     /// Returns `true`, if the minidump was modified.
     pub fn scrub_minidump(
@@ -250,12 +608,25 @@ impl PiiAttachmentsProcessor<'_> {
         filename: &str,
         data: &mut [u8],
     ) -> Result<bool, ScrubMinidumpError> {
+        let areas = self.scrub_minidump_streams(filename, data)?;
+        Ok(!areas.is_empty())
+    }
+
+    /// Applies PII rules to the given minidump and reports the modified areas.
+    ///
+    /// This behaves like [`scrub_minidump`](Self::scrub_minidump), but returns the set of
+    /// areas in the minidump that have been modified. The set is empty if nothing changed.
+    pub fn scrub_minidump_streams(
+        &self,
+        filename: &str,
+        data: &mut [u8],
+    ) -> Result<BTreeSet<MinidumpStreamArea>, ScrubMinidumpError> {
         let file_state = self.state(filename, ValueType::Minidump);
         let items = MinidumpData::parse(data)?.items()?;
-        let mut changed = false;
+        let mut areas = BTreeSet::new();
 
         for item in items {
-            match item {
+            let (area, changed) = match item {
                 MinidumpItem::StackMemory(range) => {
                     // IMPORTANT: The stack is PII::Maybe to avoid accidentally scrubbing it
                     // with highly generic selectors.
@@ -269,7 +640,8 @@ impl PiiAttachmentsProcessor<'_> {
                         Some(attrs),
                         ValueType::Binary | ValueType::StackMemory,
                     );
-                    changed |= self.scrub_bytes(slice, &state, ScrubEncodings::All);
+                    let changed = self.scrub_bytes(slice, &state, ScrubEncodings::All);
+                    (MinidumpStreamArea::StackMemory, changed)
                 }
                 MinidumpItem::NonStackMemory(range) => {
                     let slice = data
@@ -281,7 +653,8 @@ impl PiiAttachmentsProcessor<'_> {
                         Some(attrs),
                         ValueType::Binary | ValueType::HeapMemory,
                     );
-                    changed |= self.scrub_bytes(slice, &state, ScrubEncodings::All);
+                    let changed = self.scrub_bytes(slice, &state, ScrubEncodings::All);
+                    (MinidumpStreamArea::HeapMemory, changed)
                 }
                 MinidumpItem::LinuxEnviron(range) | MinidumpItem::LinuxCmdLine(range) => {
                     let slice = data
@@ -289,7 +662,8 @@ impl PiiAttachmentsProcessor<'_> {
                         .ok_or(ScrubMinidumpError::InvalidAddress)?;
                     let attrs = Cow::Owned(FieldAttrs::new().pii(Pii::True));
                     let state = file_state.enter_static("", Some(attrs), Some(ValueType::Binary));
-                    changed |= self.scrub_bytes(slice, &state, ScrubEncodings::All);
+                    let changed = self.scrub_bytes(slice, &state, ScrubEncodings::All);
+                    (MinidumpStreamArea::LinuxProcessInfo, changed)
                 }
                 MinidumpItem::CodeModuleName(range) => {
                     let slice = data
@@ -300,7 +674,8 @@ impl PiiAttachmentsProcessor<'_> {
                     let state =
                         file_state.enter_static("code_file", Some(attrs), Some(ValueType::String));
                     let wstr = WStr::from_utf16le_mut(slice)?; // TODO: Consider making this lossy?
-                    changed |= self.scrub_utf16_filepath(wstr, &state);
+                    let changed = self.scrub_utf16_filepath(wstr, &state);
+                    (MinidumpStreamArea::CodeFile, changed)
                 }
                 MinidumpItem::DebugModuleName(range) => {
                     let slice = data
@@ -311,12 +686,68 @@ impl PiiAttachmentsProcessor<'_> {
                     let state =
                         file_state.enter_static("debug_file", Some(attrs), Some(ValueType::String));
                     let s = std::str::from_utf8_mut(slice)?;
-                    changed |= self.scrub_utf8_filepath(s, &state);
+                    let changed = self.scrub_utf8_filepath(s, &state);
+                    (MinidumpStreamArea::DebugFile, changed)
+                }
+                MinidumpItem::ThreadName(range) => {
+                    let slice = data
+                        .get_mut(range)
+                        .ok_or(ScrubMinidumpError::InvalidAddress)?;
+                    let attrs = Cow::Owned(FieldAttrs::new().pii(Pii::True));
+                    let state = file_state.enter_static(
+                        "thread_name",
+                        Some(attrs),
+                        ValueType::String | ValueType::ThreadName,
+                    );
+                    let changed = self.scrub_bytes(slice, &state, ScrubEncodings::Utf16Le);
+                    (MinidumpStreamArea::ThreadName, changed)
+                }
+                MinidumpItem::MiscInfoString(range) => {
+                    let slice = data
+                        .get_mut(range)
+                        .ok_or(ScrubMinidumpError::InvalidAddress)?;
+                    let attrs = Cow::Owned(FieldAttrs::new().pii(Pii::True));
+                    let state =
+                        file_state.enter_static("misc_info", Some(attrs), Some(ValueType::String));
+                    let changed = self.scrub_bytes(slice, &state, ScrubEncodings::Utf16Le);
+                    (MinidumpStreamArea::MiscInfo, changed)
+                }
+                MinidumpItem::ExceptionParameters(range) => {
+                    // IMPORTANT: Exception parameters are PII::Maybe like the stack, since they
+                    // carry addresses required for processing access violations.
+                    let slice = data
+                        .get_mut(range)
+                        .ok_or(ScrubMinidumpError::InvalidAddress)?;
+                    let attrs = Cow::Owned(FieldAttrs::new().pii(Pii::Maybe));
+                    let state = file_state.enter_static(
+                        "exception_record",
+                        Some(attrs),
+                        Some(ValueType::Binary),
+                    );
+                    let changed = self.scrub_bytes(slice, &state, ScrubEncodings::All);
+                    (MinidumpStreamArea::ExceptionRecord, changed)
+                }
+                MinidumpItem::CrashpadAnnotation(range) => {
+                    let slice = data
+                        .get_mut(range)
+                        .ok_or(ScrubMinidumpError::InvalidAddress)?;
+                    let attrs = Cow::Owned(FieldAttrs::new().pii(Pii::True));
+                    let state = file_state.enter_static(
+                        "annotations",
+                        Some(attrs),
+                        ValueType::String | ValueType::Annotations,
+                    );
+                    let changed = self.scrub_bytes(slice, &state, ScrubEncodings::Utf8);
+                    (MinidumpStreamArea::Annotations, changed)
                 }
             };
+
+            if changed {
+                areas.insert(area);
+            }
         }
 
-        Ok(changed)
+        Ok(areas)
     }
 }
 
@@ -441,6 +872,112 @@ mod tests {
             dump.get_raw_stream(StreamType::LinuxEnviron.into())
                 .unwrap()
         }
+
+        /// Returns the raw bytes of the given stream.
+        ///
+        /// Panics if there is no such stream.
+        fn raw_stream(&self, which: Which, stream_type: StreamType) -> &[u8] {
+            let dump = match which {
+                Which::Original => &self.orig_dump,
+                Which::Scrubbed => &self.scrubbed_dump,
+            };
+            dump.get_raw_stream(stream_type.into()).unwrap()
+        }
+    }
+
+    /// Decodes a fixed-size UTF-16LE buffer up to the first NULL character.
+    fn decode_wstr_buffer(buffer: &[u8]) -> String {
+        let units: Vec<u16> = buffer
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .take_while(|unit| *unit != 0)
+            .collect();
+        String::from_utf16(&units).unwrap()
+    }
+
+    /// Adds a stream to a minidump by replacing an unused entry in its stream directory.
+    ///
+    /// The stream is appended to the data. `build` receives the stream's RVA to compute RVAs
+    /// within the stream.
+    fn add_stream(data: &mut Vec<u8>, stream_type: StreamType, build: impl FnOnce(u32) -> Vec<u8>) {
+        let read_u32 = |data: &[u8], offset: usize| {
+            u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+        };
+
+        let count = read_u32(data, 8);
+        let directory = read_u32(data, 12);
+        let entry = (0..count)
+            .map(|index| directory + index * 12)
+            .find(|&entry| read_u32(data, entry) == 0)
+            .expect("no unused stream in minidump");
+
+        let rva = data.len() as u32;
+        let stream = build(rva);
+        data[entry..entry + 4].copy_from_slice(&u32::from(stream_type).to_le_bytes());
+        data[entry + 4..entry + 8].copy_from_slice(&(stream.len() as u32).to_le_bytes());
+        data[entry + 8..entry + 12].copy_from_slice(&rva.to_le_bytes());
+        data.extend(stream);
+    }
+
+    fn put_u32(stream: &mut Vec<u8>, values: &[u32]) {
+        for value in values {
+            stream.extend(value.to_le_bytes());
+        }
+    }
+
+    /// Builds a thread names stream with a single thread.
+    fn thread_names_stream(rva: u32, name: &str) -> Vec<u8> {
+        let name: Vec<u8> = name.encode_utf16().flat_map(u16::to_le_bytes).collect();
+
+        let mut stream = Vec::new();
+        put_u32(&mut stream, &[1, 42]); // count + thread_id
+        stream.extend(u64::from(rva + 16).to_le_bytes()); // thread_name_rva
+        put_u32(&mut stream, &[name.len() as u32]);
+        stream.extend(name);
+        stream
+    }
+
+    /// Builds a crashpad info stream with a simple annotation of the process and a list
+    /// annotation and annotation object of a module.
+    fn crashpad_info_stream(rva: u32, module_info_size: u32) -> Vec<u8> {
+        const STRINGS_OFFSET: u32 = 132;
+
+        let mut strings = Vec::new();
+        let mut string = |value: &str| {
+            let offset = rva + STRINGS_OFFSET + strings.len() as u32;
+            put_u32(&mut strings, &[value.len() as u32]);
+            strings.extend(value.as_bytes());
+            strings.push(0);
+            offset
+        };
+
+        let simple_key = string("user");
+        let simple_value = string("alice@example.com");
+        let list_value = string("bob@example.com");
+        let object_name = string("email");
+        let object_value = string("carol@example.com");
+        let object_type = u32::from(MINIDUMP_ANNOTATION::TYPE_STRING);
+
+        let mut stream = Vec::new();
+        put_u32(&mut stream, &[1]); // version
+        put_u32(&mut stream, &[0; 8]); // report_id + client_id
+        put_u32(&mut stream, &[12, rva + 52]); // simple_annotations
+        put_u32(&mut stream, &[16, rva + 64]); // module_list
+        put_u32(&mut stream, &[1, simple_key, simple_value]);
+        put_u32(&mut stream, &[1, 0, module_info_size, rva + 80]);
+        put_u32(&mut stream, &[1, 8, rva + 108, 0, 0, 16, rva + 116]); // module info
+        put_u32(&mut stream, &[1, list_value]);
+        put_u32(&mut stream, &[1, object_name, object_type, object_value]);
+        assert_eq!(stream.len(), STRINGS_OFFSET as usize);
+
+        stream.extend(strings);
+        stream
+    }
+
+    fn contains(haystack: &[u8], needle: &str) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle.as_bytes())
     }
 
     #[test]
@@ -798,4 +1335,214 @@ mod tests {
         let environ = scrubber.environ(Which::Scrubbed);
         assert!(environ.iter().all(|b| *b == b'*'));
     }
+
+    #[test]
+    fn test_misc_info_strings() {
+        let scrubber = TestScrubber::new(
+            "windows.dmp",
+            include_bytes!("../../../tests/fixtures/windows.dmp"),
+            serde_json::json!(
+                {
+                    "applications": {
+                        "$minidump.misc_info": ["@anything:mask"],
+                    }
+                }
+            ),
+        );
+
+        let original = scrubber.raw_stream(Which::Original, StreamType::MiscInfoStream);
+        assert_eq!(
+            decode_wstr_buffer(&original[64..128]),
+            "Pacific Standard Time"
+        );
+        assert_eq!(
+            decode_wstr_buffer(&original[232..752]),
+            "10.0.14393.1715 (rs1_release_inmarket.170906-1810)"
+        );
+
+        let scrubbed = scrubber.raw_stream(Which::Scrubbed, StreamType::MiscInfoStream);
+        assert_eq!(decode_wstr_buffer(&scrubbed[64..128]), "*".repeat(21));
+        assert_eq!(decode_wstr_buffer(&scrubbed[148..212]), "*".repeat(21));
+        assert_eq!(decode_wstr_buffer(&scrubbed[232..752]), "*".repeat(50));
+
+        // Data outside of the strings must remain untouched.
+        assert_eq!(original[..64], scrubbed[..64]);
+        assert_eq!(original[832..], scrubbed[832..]);
+    }
+
+    #[test]
+    fn test_exception_record_specific_selector() {
+        let scrubber = TestScrubber::new(
+            "windows.dmp",
+            include_bytes!("../../../tests/fixtures/windows.dmp"),
+            serde_json::json!(
+                {
+                    "applications": {
+                        "$minidump.exception_record": ["@anything:mask"],
+                    }
+                }
+            ),
+        );
+
+        let original = scrubber.raw_stream(Which::Original, StreamType::ExceptionStream);
+        let scrubbed = scrubber.raw_stream(Which::Scrubbed, StreamType::ExceptionStream);
+
+        // Two parameters of 8 bytes each are scrubbed, everything else remains intact.
+        assert!(scrubbed[40..56].iter().all(|b| *b == b'*'));
+        assert_eq!(original[..40], scrubbed[..40]);
+        assert_eq!(original[56..], scrubbed[56..]);
+    }
+
+    #[test]
+    fn test_exception_record_valuetype_not_fully_qualified() {
+        // Like the stack, exception parameters are required for processing.
+        let scrubber = TestScrubber::new(
+            "windows.dmp",
+            include_bytes!("../../../tests/fixtures/windows.dmp"),
+            serde_json::json!(
+                {
+                    "applications": {
+                        "$binary": ["@anything:mask"],
+                    }
+                }
+            ),
+        );
+
+        assert_eq!(
+            scrubber.raw_stream(Which::Original, StreamType::ExceptionStream),
+            scrubber.raw_stream(Which::Scrubbed, StreamType::ExceptionStream)
+        );
+    }
+
+    #[test]
+    fn test_thread_name() {
+        let mut data = include_bytes!("../../../tests/fixtures/windows.dmp").to_vec();
+        add_stream(&mut data, StreamType::ThreadNamesStream, |rva| {
+            thread_names_stream(rva, "worker for alice@example.com")
+        });
+
+        let scrubber = TestScrubber::new(
+            "windows.dmp",
+            Box::leak(data.into_boxed_slice()),
+            serde_json::json!(
+                {
+                    "applications": {
+                        "$minidump.thread_name": ["@email:mask"],
+                    }
+                }
+            ),
+        );
+
+        let original = scrubber.raw_stream(Which::Original, StreamType::ThreadNamesStream);
+        assert_eq!(
+            decode_wstr_buffer(&original[20..]),
+            "worker for alice@example.com"
+        );
+
+        let scrubbed = scrubber.raw_stream(Which::Scrubbed, StreamType::ThreadNamesStream);
+        assert_eq!(
+            decode_wstr_buffer(&scrubbed[20..]),
+            format!("worker for {}", "*".repeat(17))
+        );
+        assert_eq!(original[..20], scrubbed[..20]);
+    }
+
+    #[test]
+    fn test_crashpad_annotations() {
+        let mut data = include_bytes!("../../../tests/fixtures/windows.dmp").to_vec();
+        add_stream(&mut data, StreamType::CrashpadInfoStream, |rva| {
+            crashpad_info_stream(rva, 28)
+        });
+
+        let scrubber = TestScrubber::new(
+            "windows.dmp",
+            Box::leak(data.into_boxed_slice()),
+            serde_json::json!(
+                {
+                    "applications": {
+                        "$minidump.annotations": ["@email:mask"],
+                    }
+                }
+            ),
+        );
+
+        let original = scrubber.raw_stream(Which::Original, StreamType::CrashpadInfoStream);
+        let scrubbed = scrubber.raw_stream(Which::Scrubbed, StreamType::CrashpadInfoStream);
+        for value in ["alice@example.com", "bob@example.com", "carol@example.com"] {
+            assert!(contains(original, value));
+            assert!(!contains(scrubbed, value));
+        }
+
+        // Annotation keys and the structure of the stream remain intact.
+        assert!(contains(scrubbed, "user"));
+        assert!(contains(scrubbed, "email"));
+        assert_eq!(original[..132], scrubbed[..132]);
+    }
+
+    #[test]
+    fn test_malformed_stream_skipped() {
+        let mut data = include_bytes!("../../../tests/fixtures/windows.dmp").to_vec();
+        add_stream(&mut data, StreamType::ThreadNamesStream, |rva| {
+            let mut stream = thread_names_stream(rva, "alice@example.com");
+            stream[8..16].copy_from_slice(&u64::MAX.to_le_bytes()); // thread_name_rva
+            stream
+        });
+        // The module info is too small to hold its annotation locations.
+        add_stream(&mut data, StreamType::CrashpadInfoStream, |rva| {
+            crashpad_info_stream(rva, 4)
+        });
+
+        let config = serde_json::from_value::<PiiConfig>(serde_json::json!(
+            {
+                "applications": {
+                    "$minidump.misc_info": ["@anything:mask"],
+                    "$minidump.thread_name": ["@anything:mask"],
+                    "$minidump.annotations": ["@anything:mask"],
+                }
+            }
+        ))
+        .unwrap();
+
+        let processor = PiiAttachmentsProcessor::new(config.compiled());
+        let areas = processor
+            .scrub_minidump_streams("windows.dmp", &mut data)
+            .unwrap();
+
+        // The process annotations are read before the malformed module info.
+        assert_eq!(
+            areas.into_iter().collect::<Vec<_>>(),
+            vec![
+                MinidumpStreamArea::MiscInfo,
+                MinidumpStreamArea::Annotations
+            ]
+        );
+        assert!(!contains(&data, "alice@example.com"));
+        assert!(contains(&data, "bob@example.com"));
+    }
+
+    #[test]
+    fn test_scrub_minidump_streams() {
+        let config = serde_json::from_value::<PiiConfig>(serde_json::json!(
+            {
+                "applications": {
+                    "$minidump.misc_info": ["@anything:mask"],
+                    "$minidump.code_file": ["@anything:mask"],
+                    "$thread_name": ["@anything:mask"],
+                }
+            }
+        ))
+        .unwrap();
+
+        let processor = PiiAttachmentsProcessor::new(config.compiled());
+        let mut data = include_bytes!("../../../tests/fixtures/windows.dmp").to_vec();
+        let areas = processor
+            .scrub_minidump_streams("windows.dmp", &mut data)
+            .unwrap();
+
+        // The fixture does not contain a thread names stream.
+        assert_eq!(
+            areas.into_iter().collect::<Vec<_>>(),
+            vec![MinidumpStreamArea::CodeFile, MinidumpStreamArea::MiscInfo]
+        );
+    }
 }
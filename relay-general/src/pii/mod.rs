@@ -5,19 +5,23 @@ mod builtin;
 mod compiledconfig;
 mod config;
 mod convert;
+mod explain;
 mod generate_selectors;
 mod legacy;
 mod minidumps;
 mod processor;
 mod redactions;
 mod regexes;
+mod sensitive_keys;
 mod utils;
 
 pub use self::attachments::*;
 pub use self::compiledconfig::*;
 pub use self::config::*;
+pub use self::explain::*;
 pub use self::generate_selectors::selector_suggestions_from_value;
 pub use self::legacy::*;
 pub use self::minidumps::*;
 pub use self::processor::*;
 pub use self::redactions::*;
+pub use self::sensitive_keys::{SensitiveKeys, DEFAULT_SENSITIVE_KEYS};
@@ -7,7 +7,7 @@ use regex::Regex;
 use crate::pii::compiledconfig::RuleRef;
 use crate::pii::regexes::{get_regex_for_rule_type, PatternType, ReplaceBehavior, ANYTHING_REGEX};
 use crate::pii::utils::{hash_value, process_pairlist};
-use crate::pii::{CompiledPiiConfig, Redaction, RuleType};
+use crate::pii::{CompiledPiiConfig, PiiExplanation, Redaction, RuleType};
 use crate::processor::{
     process_chunked_value, Chunk, Pii, ProcessValue, ProcessingState, Processor, ValueType,
 };
@@ -17,6 +17,7 @@ use crate::types::{Meta, ProcessingAction, ProcessingResult, Remark, RemarkType,
 /// A processor that performs PII stripping.
 pub struct PiiProcessor<'a> {
     compiled_config: &'a CompiledPiiConfig,
+    explanations: Option<Vec<PiiExplanation>>,
 }
 
 impl<'a> PiiProcessor<'a> {
@@ -24,26 +25,90 @@ impl<'a> PiiProcessor<'a> {
     pub fn new(compiled_config: &'a CompiledPiiConfig) -> PiiProcessor<'a> {
         // this constructor needs to be cheap... a new PiiProcessor is created for each event. Move
         // any init logic into CompiledPiiConfig::new.
-        PiiProcessor { compiled_config }
+        PiiProcessor {
+            compiled_config,
+            explanations: None,
+        }
+    }
+
+    /// Creates a new processor that records an explanation for every applied rule.
+    ///
+    /// The processor still modifies the values it processes. Use
+    /// [`explain_pii`](crate::pii::explain_pii) to run it on a copy of a value without modifying
+    /// the original.
+    pub fn explaining(compiled_config: &'a CompiledPiiConfig) -> PiiProcessor<'a> {
+        PiiProcessor {
+            compiled_config,
+            explanations: Some(Vec::new()),
+        }
+    }
+
+    /// Returns the explanations recorded by this processor.
+    ///
+    /// This is always empty unless the processor was created with
+    /// [`explaining`](Self::explaining).
+    pub fn into_explanations(self) -> Vec<PiiExplanation> {
+        self.explanations.unwrap_or_default()
     }
 
     fn apply_all_rules(
-        &self,
+        &mut self,
         meta: &mut Meta,
         state: &ProcessingState<'_>,
         mut value: Option<&mut String>,
+        container: Option<&dyn Fn() -> Value>,
     ) -> ProcessingResult {
         let pii = state.attrs().pii;
         if pii == Pii::False {
             return Ok(());
         }
 
-        for (selector, rules) in self.compiled_config.applications.iter() {
+        let compiled_config = self.compiled_config;
+        for (selector, rules) in compiled_config.applications.iter() {
             if state.path().matches_selector(selector) {
                 #[allow(clippy::needless_option_as_deref)]
                 for rule in rules {
                     let reborrowed_value = value.as_deref_mut();
-                    apply_rule_to_value(meta, rule, state.path().key(), reborrowed_value)?;
+                    match self.explanations {
+                        Some(ref mut explanations) => {
+                            let before = match reborrowed_value {
+                                Some(ref string) => Some(Value::String(string.to_string())),
+                                None => container.map(|f| f()),
+                            };
+
+                            let result = apply_rule_to_value(
+                                meta,
+                                rule,
+                                state.path().key(),
+                                reborrowed_value,
+                            );
+
+                            let after = match result {
+                                Ok(()) => match value.as_deref() {
+                                    Some(string) => Some(Value::String(string.clone())),
+                                    None => before.clone(),
+                                },
+                                Err(_) => None,
+                            };
+
+                            if before != after {
+                                explanations.push(PiiExplanation {
+                                    path: state.path().to_string(),
+                                    selector: selector.clone(),
+                                    rule_id: rule.id.clone(),
+                                    origin: rule.origin.clone(),
+                                    redaction: rule.redaction.clone(),
+                                    before,
+                                    after,
+                                });
+                            }
+
+                            result?;
+                        }
+                        None => {
+                            apply_rule_to_value(meta, rule, state.path().key(), reborrowed_value)?
+                        }
+                    }
                 }
             }
         }
@@ -71,10 +136,17 @@ impl<'a> Processor for PiiProcessor<'a> {
                     enumset::enum_set!(ValueType::String),
                 );
 
-                if self
-                    .apply_all_rules(&mut Meta::default(), &new_state, Some(original_value))
-                    .is_err()
-                {
+                // Original values are not part of the explanation, they follow the value.
+                let explanations = self.explanations.take();
+                let result = self.apply_all_rules(
+                    &mut Meta::default(),
+                    &new_state,
+                    Some(original_value),
+                    None,
+                );
+                self.explanations = explanations;
+
+                if result.is_err() {
                     // `apply_all_rules` returned `DeleteValueHard` or `DeleteValueSoft`, so delete the original as well.
                     meta.set_original_value(Option::<String>::None);
                 }
@@ -93,7 +165,13 @@ impl<'a> Processor for PiiProcessor<'a> {
         }
 
         // apply rules based on key/path
-        self.apply_all_rules(meta, state, None)
+        match value {
+            Some(value) if self.explanations.is_some() => {
+                let container = || value.clone().into_value();
+                self.apply_all_rules(meta, state, None, Some(&container))
+            }
+            _ => self.apply_all_rules(meta, state, None, None),
+        }
     }
 
     fn process_string(
@@ -108,7 +186,7 @@ impl<'a> Processor for PiiProcessor<'a> {
 
         // same as before_process. duplicated here because we can only check for "true",
         // "false" etc in process_string.
-        self.apply_all_rules(meta, state, Some(value))
+        self.apply_all_rules(meta, state, Some(value), None)
     }
 
     fn process_native_image_path(
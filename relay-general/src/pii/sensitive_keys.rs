@@ -0,0 +1,288 @@
+//! Catalog of well-known keys that carry sensitive values.
+//!
+//! Every entry of the catalog is exposed as a builtin rule `@sensitivekey:<name>`, and
+//! `@sensitivekey` applies all of them. These rules remove values whose key matches one of the
+//! entry's keys, for instance in query strings, cookies, headers and form bodies. In URLs, only
+//! the values of matching query parameters are removed.
+
+use std::collections::BTreeSet;
+
+use crate::pii::{
+    LazyPattern, MultipleRule, PatternRule, RedactPairRule, Redaction, ReplaceRedaction, RuleSpec,
+    RuleType,
+};
+
+/// A named group of keys that are known to carry sensitive values.
+#[derive(Clone, Copy, Debug)]
+pub struct SensitiveKeys {
+    /// The name of the catalog entry.
+    ///
+    /// Redactions by this entry are reported with the rule `@sensitivekey:<name>`.
+    pub name: &'static str,
+    /// Keys matched case-insensitively against query parameters, cookies, headers and fields.
+    pub keys: &'static [&'static str],
+}
+
+/// The default catalog of sensitive keys.
+pub static DEFAULT_SENSITIVE_KEYS: &[SensitiveKeys] = &[
+    SensitiveKeys {
+        name: "session",
+        keys: &[
+            // Common session cookie names for popular web frameworks
+            "sentrysid", // Sentry default session cookie name
+            "sudo",      // Sentry default sudo cookie name
+            "su",        // Sentry superuser cookie name
+            "session",
+            "__session",
+            "_session_id",
+            "session_id",
+            "sessionid",
+            "user_session",
+            "symfony",
+            "phpsessid",
+            "jsessionid",
+            "asp.net_sessionid",
+            "connect.sid",
+            "laravel_session",
+            "fasthttpsessionid",
+            "mysession",
+            "irissessionid",
+        ],
+    },
+    SensitiveKeys {
+        name: "csrf",
+        keys: &[
+            // Common CSRF/XSRF cookie, header and field names for popular web frameworks
+            "csrf",
+            "xsrf",
+            "_xsrf",
+            "_csrf",
+            "csrf-token",
+            "csrf_token",
+            "xsrf-token",
+            "xsrf_token",
+            "x-csrf-token",
+            "x-xsrf-token",
+            "csrftoken",
+            "csrfmiddlewaretoken",
+            "authenticity_token",
+            "fastcsrf",
+            "_iris_csrf",
+        ],
+    },
+    SensitiveKeys {
+        name: "auth",
+        keys: &[
+            "authorization",
+            "proxy-authorization",
+            "x-api-key",
+            "x-auth-token",
+            "api_key",
+            "apikey",
+        ],
+    },
+    SensitiveKeys {
+        name: "oauth",
+        keys: &[
+            "access_token",
+            "refresh_token",
+            "id_token",
+            "client_secret",
+            "oauth_token",
+            "oauth_verifier",
+        ],
+    },
+    SensitiveKeys {
+        name: "signature",
+        keys: &[
+            "signature",
+            "sig",
+            "x-amz-signature",
+            "x-amz-security-token",
+            "x-goog-signature",
+        ],
+    },
+];
+
+/// Returns the PII rules that redact the values of the given keys.
+///
+/// The first returned rule is `rule_id` itself, which applies the others and hides them in
+/// remarks. Returns no rules if all keys are empty.
+pub(crate) fn sensitive_key_rules<'a>(
+    rule_id: &str,
+    keys: impl IntoIterator<Item = &'a str>,
+) -> Vec<(String, RuleSpec)> {
+    let alternation = keys
+        .into_iter()
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join("|");
+
+    if alternation.is_empty() {
+        return Vec::new();
+    }
+
+    let pair_id = format!("{rule_id}:pair");
+    let url_id = format!("{rule_id}:url");
+
+    let pair = RuleSpec {
+        ty: RuleType::RedactPair(RedactPairRule {
+            key_pattern: LazyPattern::new(format!("(?i)^(?:{alternation})$")),
+        }),
+        redaction: Redaction::Default,
+    };
+
+    let url = RuleSpec {
+        ty: RuleType::Pattern(PatternRule {
+            pattern: LazyPattern::new(format!("(?i)[?&;](?:{alternation})=([^&#;]+)")),
+            replace_groups: Some(BTreeSet::from([1])),
+        }),
+        redaction: Redaction::Default,
+    };
+
+    let outer = RuleSpec {
+        ty: RuleType::Multiple(MultipleRule {
+            rules: vec![pair_id.clone(), url_id.clone()],
+            hide_inner: true,
+        }),
+        redaction: Redaction::Replace(ReplaceRedaction {
+            text: "[Filtered]".into(),
+        }),
+    };
+
+    vec![(rule_id.to_owned(), outer), (pair_id, pair), (url_id, url)]
+}
+
+/// Returns the builtin rules for the default catalog.
+pub(crate) fn builtin_sensitive_key_rules() -> Vec<(String, RuleSpec)> {
+    let mut rules = Vec::new();
+    let mut entry_ids = Vec::new();
+
+    for entry in DEFAULT_SENSITIVE_KEYS {
+        let rule_id = format!("@sensitivekey:{}", entry.name);
+        rules.extend(sensitive_key_rules(&rule_id, entry.keys.iter().copied()));
+        entry_ids.push(rule_id);
+    }
+
+    rules.push((
+        "@sensitivekey".to_owned(),
+        RuleSpec {
+            ty: RuleType::Multiple(MultipleRule {
+                rules: entry_ids,
+                hide_inner: false,
+            }),
+            redaction: Redaction::Default,
+        },
+    ));
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use similar_asserts::assert_eq;
+
+    use super::*;
+    use crate::pii::{PiiConfig, PiiProcessor};
+    use crate::processor::{process_value, ProcessingState};
+    use crate::protocol::Event;
+    use crate::testutils::assert_annotated_snapshot;
+    use crate::types::{Annotated, FromValue};
+
+    fn sensitive_key_config() -> PiiConfig {
+        PiiConfig::from_json(
+            r#"{
+                "applications": {
+                    "$http.query_string.* || $http.cookies.* || $http.headers.* || $http.data.** || $breadcrumb.data.url": ["@sensitivekey"]
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sensitive_keys_request() {
+        let mut data = Event::from_value(
+            serde_json::json!({
+                "request": {
+                    "query_string": [["session_id", "abc"], ["page", "2"]],
+                    "cookies": [["JSESSIONID", "abc"], ["theme", "dark"]],
+                    "headers": [["X-CSRF-Token", "abc"], ["Accept", "*/*"]],
+                    "data": {"access_token": "abc", "name": "foo"}
+                }
+            })
+            .into(),
+        );
+
+        let config = sensitive_key_config();
+        let mut processor = PiiProcessor::new(config.compiled());
+        process_value(&mut data, &mut processor, ProcessingState::root()).unwrap();
+        assert_annotated_snapshot!(data);
+    }
+
+    #[test]
+    fn test_sensitive_keys_breadcrumb_url() {
+        let mut data = Event::from_value(
+            serde_json::json!({
+                "breadcrumbs": {
+                    "values": [{
+                        "type": "http",
+                        "data": {
+                            "url": "https://example.com/cb?state=1&Signature=abc&page=2#top"
+                        }
+                    }]
+                }
+            })
+            .into(),
+        );
+
+        let config = sensitive_key_config();
+        let mut processor = PiiProcessor::new(config.compiled());
+        process_value(&mut data, &mut processor, ProcessingState::root()).unwrap();
+        assert_annotated_snapshot!(data);
+    }
+
+    #[test]
+    fn test_sensitive_key_rules_empty() {
+        assert!(sensitive_key_rules("custom", ["", " "]).is_empty());
+    }
+
+    #[test]
+    fn test_sensitive_key_rules_escaped() {
+        let rules: BTreeMap<_, _> = sensitive_key_rules("custom", ["a.b"]).into_iter().collect();
+        let config = PiiConfig {
+            rules,
+            applications: BTreeMap::from([(
+                "$http.query_string.*".parse().unwrap(),
+                vec!["custom".to_owned()],
+            )]),
+            ..Default::default()
+        };
+
+        let mut data = Annotated::<Event>::from_json(
+            r#"{"request": {"query_string": [["a.b", "1"], ["axb", "2"]]}}"#,
+        )
+        .unwrap();
+
+        let mut processor = PiiProcessor::new(config.compiled());
+        process_value(&mut data, &mut processor, ProcessingState::root()).unwrap();
+
+        let query = data
+            .value()
+            .and_then(|event| event.request.value())
+            .and_then(|request| request.query_string.value())
+            .unwrap();
+
+        let values: Vec<_> = query
+            .iter()
+            .filter_map(|pair| pair.value())
+            .map(|(_, value)| value.as_str())
+            .collect();
+
+        assert_eq!(values, vec![Some("[Filtered]"), Some("2")]);
+    }
+}
@@ -55,6 +55,8 @@ pub enum ValueType {
     Minidump,
     HeapMemory,
     StackMemory,
+    ThreadName,
+    Annotations,
 }
 
 impl ValueType {
@@ -91,6 +93,8 @@ derive_fromstr_and_display!(ValueType, UnknownValueTypeError, {
     ValueType::Minidump => "minidump",
     ValueType::HeapMemory => "heap_memory",
     ValueType::StackMemory => "stack_memory",
+    ValueType::ThreadName => "thread_name",
+    ValueType::Annotations => "annotations",
 });
 
 /// The maximum length of a field.
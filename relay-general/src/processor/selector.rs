@@ -119,6 +119,8 @@ impl SelectorPathItem {
                         | ValueType::Minidump
                         | ValueType::HeapMemory
                         | ValueType::StackMemory
+                        | ValueType::ThreadName
+                        | ValueType::Annotations
                         | ValueType::ClientSdkInfo => i == 0,
                     }
             }
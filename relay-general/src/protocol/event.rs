@@ -484,6 +484,11 @@ pub struct Event {
     #[metastructure(omit_from_schema)] // not part of external schema
     pub grouping_config: Annotated<Object<Value>>,
 
+    /// Hash approximating the issue this event is grouped into, computed by Relay.
+    #[metastructure(max_chars = "hash")]
+    #[metastructure(omit_from_schema)] // not part of external schema
+    pub grouping_hint: Annotated<String>,
+
     /// Legacy checksum used for grouping before fingerprint hashes.
     #[metastructure(max_chars = "hash")]
     #[metastructure(omit_from_schema)] // deprecated
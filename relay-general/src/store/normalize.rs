@@ -31,6 +31,7 @@ use crate::user_agent::RawUserAgentInfo;
 
 pub mod breakdowns;
 mod contexts;
+mod grouping;
 mod logentry;
 mod mechanism;
 mod request;
@@ -737,6 +738,7 @@ pub struct LightNormalizationConfig<'a> {
     pub scrub_span_descriptions: bool,
     pub light_normalize_spans: bool,
     pub span_description_rules: Option<&'a Vec<SpanDescriptionRule>>,
+    pub grouping_config: Option<&'a serde_json::Value>,
 }
 
 pub fn light_normalize_event(
@@ -817,6 +819,14 @@ pub fn light_normalize_event(
         ); // Measurements are part of the metric extraction
         normalize_breakdowns(event, config.breakdowns_config); // Breakdowns are part of the metric extraction too
 
+        // The grouping hint is internal and must not be taken from the payload.
+        event.grouping_hint = match config.grouping_config {
+            Some(grouping_config) if event.ty.value() != Some(&EventType::Transaction) => {
+                grouping::grouping_hint(event, grouping_config).into()
+            }
+            _ => Annotated::empty(),
+        };
+
         if config.light_normalize_spans && event.ty.value() == Some(&EventType::Transaction) {
             // XXX(iker): span normalization runs in the store processor, but
             // the exclusive time is required for span metrics. Most of
@@ -2114,6 +2124,34 @@ mod tests {
         "###);
     }
 
+    #[test]
+    fn test_grouping_hint() {
+        let event = || {
+            Annotated::new(Event {
+                logentry: Annotated::from(LogEntry {
+                    message: Annotated::new("Hello World!".to_string().into()),
+                    ..Default::default()
+                }),
+                grouping_hint: Annotated::new("from the payload".to_owned()),
+                ..Default::default()
+            })
+        };
+
+        let mut event_without_config = event();
+        light_normalize_event(&mut event_without_config, Default::default()).unwrap();
+        assert_eq!(get_value!(event_without_config.grouping_hint), None);
+
+        let grouping_config = json!({"id": "newstyle:2023-01-11"});
+        let mut event_with_config = event();
+        let config = LightNormalizationConfig {
+            grouping_config: Some(&grouping_config),
+            ..Default::default()
+        };
+        light_normalize_event(&mut event_with_config, config).unwrap();
+        let grouping_hint = get_value!(event_with_config.grouping_hint!);
+        assert_eq!(grouping_hint.len(), 32);
+    }
+
     #[test]
     fn test_logentry_error() {
         let json = r###"
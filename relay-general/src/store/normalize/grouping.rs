@@ -0,0 +1,362 @@
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+
+use crate::protocol::{Event, Exception, Frame};
+
+/// Length of the grouping hint, which matches the length of grouping hashes in Sentry.
+const GROUPING_HINT_LENGTH: usize = 32;
+
+/// Strips generic parameters, arguments and surrounding whitespace from type or function names.
+fn normalize_name(name: &str) -> &str {
+    let end = name.find(|c| c == '<' || c == '(').unwrap_or(name.len());
+    name[..end].trim()
+}
+
+/// Returns the name of the variable if the fingerprint entry is a variable, such as `{{ type }}`.
+///
+/// Variables must span the entire entry. Other entries are matched literally.
+fn fingerprint_variable(entry: &str) -> Option<&str> {
+    let variable = entry.strip_prefix("{{")?.strip_suffix("}}")?.trim();
+    if variable.is_empty() || variable.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(variable)
+}
+
+/// Adds the module and function of an in-app frame.
+fn frame_components(frame: &Frame, components: &mut Vec<String>) {
+    if frame.in_app.value() != Some(&true) {
+        return;
+    }
+
+    let module = frame.module.as_str().map(normalize_name).unwrap_or("");
+    let function = frame.function.as_str().map(normalize_name).unwrap_or("");
+    if !module.is_empty() || !function.is_empty() {
+        components.push(format!("frame:{module}:{function}"));
+    }
+}
+
+/// Adds the exception type and in-app frames of an exception.
+fn exception_components(exception: &Exception, components: &mut Vec<String>) {
+    if let Some(ty) = exception.ty.as_str().map(normalize_name) {
+        if !ty.is_empty() {
+            components.push(format!("type:{ty}"));
+        }
+    }
+
+    let frames = exception
+        .stacktrace
+        .value()
+        .and_then(|stacktrace| stacktrace.frames.value());
+    for frame in frames.into_iter().flatten() {
+        if let Some(frame) = frame.value() {
+            frame_components(frame, components);
+        }
+    }
+}
+
+/// Returns the last exception of the event, which is the one that was raised last.
+fn last_exception(event: &Event) -> Option<&Exception> {
+    let exceptions = event
+        .exceptions
+        .value()
+        .and_then(|exceptions| exceptions.values.value())?;
+    exceptions
+        .iter()
+        .rev()
+        .find_map(|exception| exception.value())
+}
+
+/// Returns the frame in which the event occurred.
+///
+/// This is the most recent in-app frame of the last exception or the event's stacktrace, or the
+/// most recent frame if there are no in-app frames.
+fn crash_frame(event: &Event) -> Option<&Frame> {
+    let stacktrace = last_exception(event)
+        .and_then(|exception| exception.stacktrace.value())
+        .or_else(|| event.stacktrace.value())?;
+
+    let frames: Vec<&Frame> = stacktrace
+        .frames
+        .value()?
+        .iter()
+        .filter_map(|frame| frame.value())
+        .collect();
+
+    frames
+        .iter()
+        .rev()
+        .find(|frame| frame.in_app.value() == Some(&true))
+        .or_else(|| frames.last())
+        .copied()
+}
+
+/// Returns the log message of the event.
+fn message(event: &Event) -> Option<&str> {
+    let logentry = event.logentry.value()?;
+    let message = logentry
+        .formatted
+        .value()
+        .or_else(|| logentry.message.value())?;
+    Some(message.as_ref())
+}
+
+/// Resolves the components of a fingerprint variable.
+///
+/// Returns `None` for variables that are not supported. Variables without a value in the event
+/// resolve to an empty value.
+fn variable_components(event: &Event, variable: &str) -> Option<Vec<String>> {
+    let value = match variable {
+        "default" => return Some(default_components(event)),
+        "transaction" => event.transaction.as_str().map(str::to_owned),
+        "message" => message(event).map(str::to_owned),
+        "type" | "error.type" => last_exception(event)
+            .and_then(|exception| exception.ty.as_str())
+            .map(|ty| normalize_name(ty).to_owned()),
+        "value" | "error.value" => last_exception(event)
+            .and_then(|exception| exception.value.as_str())
+            .map(str::to_owned),
+        "function" | "stack.function" => crash_frame(event)
+            .and_then(|frame| frame.function.as_str())
+            .map(|function| normalize_name(function).to_owned()),
+        "module" | "stack.module" => crash_frame(event)
+            .and_then(|frame| frame.module.as_str())
+            .map(|module| normalize_name(module).to_owned()),
+        "level" => event.level.value().map(ToString::to_string),
+        "logger" => event.logger.as_str().map(str::to_owned),
+        _ => return None,
+    };
+
+    Some(vec![format!("{variable}:{}", value.unwrap_or_default())])
+}
+
+/// Returns the components of the default grouping.
+///
+/// These are the exception types and in-app frames of all exceptions, or the in-app frames of the
+/// event's stacktrace. Events without either are grouped by their log message.
+fn default_components(event: &Event) -> Vec<String> {
+    let mut components = Vec::new();
+
+    let exceptions = event
+        .exceptions
+        .value()
+        .and_then(|exceptions| exceptions.values.value());
+    for exception in exceptions.into_iter().flatten() {
+        if let Some(exception) = exception.value() {
+            exception_components(exception, &mut components);
+        }
+    }
+
+    if components.is_empty() {
+        let frames = event
+            .stacktrace
+            .value()
+            .and_then(|stacktrace| stacktrace.frames.value());
+        for frame in frames.into_iter().flatten() {
+            if let Some(frame) = frame.value() {
+                frame_components(frame, &mut components);
+            }
+        }
+    }
+
+    if components.is_empty() {
+        let message = event.logentry.value().and_then(|logentry| {
+            logentry
+                .message
+                .value()
+                .or_else(|| logentry.formatted.value())
+        });
+        if let Some(message) = message {
+            components.push(format!("message:{}", message.as_ref()));
+        }
+    }
+
+    components
+}
+
+/// Computes a stable hash that approximates the issue an event is grouped into.
+///
+/// The hint is based on the normalized exception types and in-app frames of the event, or on its
+/// fingerprint if the SDK overrides it. Fingerprints may contain the variables `{{ default }}`,
+/// `{{ transaction }}`, `{{ message }}`, `{{ type }}`, `{{ value }}`, `{{ function }}`,
+/// `{{ module }}`, `{{ level }}` and `{{ logger }}`. Returns `None` if the event has no information
+/// to group by, or if its fingerprint contains other variables.
+///
+/// The grouping config `id` is part of the hash, so that hints change along with the project's
+/// grouping config. Other than that, the grouping config is not interpreted. In particular, stack
+/// trace rules, server-side fingerprinting rules and strategy options of the project are not
+/// applied, so the hint may differ from the issue that Sentry assigns.
+pub fn grouping_hint(event: &Event, grouping_config: &Value) -> Option<String> {
+    let mut components = Vec::new();
+
+    match event.fingerprint.value() {
+        Some(fingerprint) if !fingerprint.is_empty() => {
+            for entry in fingerprint.iter() {
+                match fingerprint_variable(entry) {
+                    Some(variable) => components.extend(variable_components(event, variable)?),
+                    None => components.push(format!("fingerprint:{entry}")),
+                }
+            }
+        }
+        _ => components = default_components(event),
+    }
+
+    if components.is_empty() {
+        return None;
+    }
+
+    let config_id = grouping_config
+        .get("id")
+        .and_then(Value::as_str)
+        .unwrap_or("");
+
+    let mut hasher = Sha1::new();
+    hasher.update(config_id.as_bytes());
+    for component in components {
+        hasher.update(b"\0");
+        hasher.update(component.as_bytes());
+    }
+
+    let mut hint = format!("{:x}", hasher.finalize());
+    hint.truncate(GROUPING_HINT_LENGTH);
+    Some(hint)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use similar_asserts::assert_eq;
+
+    use crate::types::Annotated;
+
+    use super::*;
+
+    fn event(json: &str) -> Event {
+        Annotated::<Event>::from_json(json)
+            .unwrap()
+            .into_value()
+            .unwrap()
+    }
+
+    fn hint(json: &str) -> Option<String> {
+        grouping_hint(&event(json), &json!({"id": "newstyle:2023-01-11"}))
+    }
+
+    const EXCEPTION: &str = r#"{
+        "exception": {
+            "values": [{
+                "type": "ValueError",
+                "value": "invalid literal for int(): 'foo'",
+                "stacktrace": {
+                    "frames": [
+                        {"module": "threading", "function": "run", "in_app": false},
+                        {"module": "app.views", "function": "index", "lineno": 10, "in_app": true}
+                    ]
+                }
+            }]
+        }
+    }"#;
+
+    #[test]
+    fn test_grouping_hint_exception() {
+        let expected = hint(EXCEPTION).unwrap();
+        assert_eq!(expected.len(), 32);
+
+        // Exception values, line numbers and system frames do not affect the hint.
+        let other = r#"{
+            "exception": {
+                "values": [{
+                    "type": "ValueError",
+                    "value": "invalid literal for int(): 'bar'",
+                    "stacktrace": {
+                        "frames": [
+                            {"module": "app.views", "function": "index", "lineno": 12, "in_app": true}
+                        ]
+                    }
+                }]
+            }
+        }"#;
+        assert_eq!(hint(other), Some(expected.clone()));
+
+        let other_type = EXCEPTION.replace("ValueError", "TypeError");
+        assert_ne!(hint(&other_type), Some(expected));
+    }
+
+    #[test]
+    fn test_grouping_hint_normalized_names() {
+        let generic = EXCEPTION.replace("ValueError", "ValueError<T> ");
+        assert_eq!(hint(&generic), hint(EXCEPTION));
+    }
+
+    #[test]
+    fn test_grouping_hint_grouping_config() {
+        let event = event(EXCEPTION);
+        assert_ne!(
+            grouping_hint(&event, &json!({"id": "newstyle:2023-01-11"})),
+            grouping_hint(&event, &json!({"id": "mobile:2021-02-12"}))
+        );
+    }
+
+    #[test]
+    fn test_grouping_hint_fingerprint() {
+        let fingerprinted = r#"{
+            "fingerprint": ["database-unavailable"],
+            "exception": {"values": [{"type": "ConnectionError"}]}
+        }"#;
+        let other = r#"{
+            "fingerprint": ["database-unavailable"],
+            "exception": {"values": [{"type": "TimeoutError"}]}
+        }"#;
+        assert_eq!(hint(fingerprinted), hint(other));
+
+        // The default fingerprint falls back to the default grouping.
+        let default = r#"{
+            "fingerprint": ["{{ default }}"],
+            "exception": {"values": [{"type": "ConnectionError"}]}
+        }"#;
+        let plain = r#"{"exception": {"values": [{"type": "ConnectionError"}]}}"#;
+        assert_eq!(hint(default), hint(plain));
+        assert_ne!(hint(default), hint(fingerprinted));
+    }
+
+    #[test]
+    fn test_grouping_hint_fingerprint_variables() {
+        let template = r#"{
+            "fingerprint": ["{{ type }}", "{{function}}"],
+            "exception": {"values": [{
+                "type": "TYPE",
+                "stacktrace": {"frames": [
+                    {"function": "main", "in_app": true},
+                    {"function": "FUNCTION", "in_app": true},
+                    {"function": "lib_call", "in_app": false}
+                ]}
+            }]}
+        }"#;
+        let fingerprinted = |ty: &str, function: &str| {
+            hint(&template.replace("TYPE", ty).replace("FUNCTION", function))
+        };
+
+        let expected = fingerprinted("ValueError", "load");
+        assert!(expected.is_some());
+        assert_eq!(fingerprinted("ValueError", "load"), expected);
+        assert_ne!(fingerprinted("TypeError", "load"), expected);
+        assert_ne!(fingerprinted("ValueError", "save"), expected);
+
+        // Variables are only expanded if they span the entire entry.
+        let literal = r#"{"fingerprint": ["type: {{ type }}"]}"#;
+        assert!(hint(literal).is_some());
+
+        let unsupported = r#"{
+            "fingerprint": ["{{ tags.server_name }}"],
+            "exception": {"values": [{"type": "ConnectionError"}]}
+        }"#;
+        assert_eq!(hint(unsupported), None);
+    }
+
+    #[test]
+    fn test_grouping_hint_message() {
+        let message = r#"{"logentry": {"message": "Failed to load %s"}}"#;
+        assert!(hint(message).is_some());
+        assert_eq!(hint("{}"), None);
+    }
+}
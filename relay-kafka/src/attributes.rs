@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+/// Determines the partitioning key of messages in a topic.
+///
+/// Messages with the same key are written to the same partition, which preserves their order.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionKey {
+    /// The key chosen by the message, usually its event ID or a random key.
+    #[default]
+    Message,
+    /// The project ID of the message.
+    Project,
+    /// The trace ID of the message.
+    ///
+    /// Messages that do not belong to a trace fall back to their own key.
+    Trace,
+}
+
+impl PartitionKey {
+    /// Returns `true` if this is the default partitioning strategy.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the partitioning key for a message.
+    ///
+    /// `message_key` is the key chosen by the message, which is used if the message does not have
+    /// the attribute required by this strategy.
+    pub fn key(&self, message_key: [u8; 16], attributes: &MessageAttributes) -> [u8; 16] {
+        let key = match self {
+            Self::Message => None,
+            Self::Project => attributes
+                .project_id
+                .map(|project_id| u128::from(project_id).to_be_bytes()),
+            Self::Trace => attributes.trace_id,
+        };
+
+        key.unwrap_or(message_key)
+    }
+}
+
+/// A standard header that can be added to messages in a topic.
+///
+/// Headers allow consumers to route messages without deserializing their payload. Headers are
+/// omitted for messages that do not have the respective attribute.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageHeader {
+    /// The project ID of the message.
+    ProjectId,
+    /// The organization ID of the message.
+    OrganizationId,
+    /// The type of the envelope item the message was created from.
+    ItemType,
+    /// Whether the trace of the message was sampled, either `"true"` or `"false"`.
+    Sampled,
+}
+
+impl MessageHeader {
+    /// Returns the name of the Kafka header.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ProjectId => "project_id",
+            Self::OrganizationId => "organization_id",
+            Self::ItemType => "item_type",
+            Self::Sampled => "sampled",
+        }
+    }
+
+    /// Returns the value of this header for a message, if available.
+    pub fn value(&self, organization_id: u64, attributes: &MessageAttributes) -> Option<String> {
+        match self {
+            Self::ProjectId => attributes.project_id.map(|id| id.to_string()),
+            Self::OrganizationId => Some(organization_id.to_string()),
+            Self::ItemType => attributes.item_type.map(str::to_owned),
+            Self::Sampled => attributes.sampled.map(|sampled| sampled.to_string()),
+        }
+    }
+}
+
+/// Attributes of a message used for partitioning and headers.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MessageAttributes {
+    /// The project ID of the message.
+    pub project_id: Option<u64>,
+    /// The type of the envelope item the message was created from.
+    pub item_type: Option<&'static str>,
+    /// The ID of the trace the message belongs to.
+    pub trace_id: Option<[u8; 16]>,
+    /// Whether the trace of the message was sampled.
+    pub sampled: Option<bool>,
+}
+
+impl MessageAttributes {
+    /// Returns the names and values of the given headers for a message.
+    pub fn headers(
+        &self,
+        organization_id: u64,
+        headers: &[MessageHeader],
+    ) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .filter_map(|header| {
+                let value = header.value(organization_id, self)?;
+                Some((header.name().to_owned(), value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_key() {
+        let message_key = [1; 16];
+        let attributes = MessageAttributes {
+            project_id: Some(42),
+            trace_id: Some([2; 16]),
+            ..Default::default()
+        };
+
+        assert_eq!(PartitionKey::Message.key(message_key, &attributes), [1; 16]);
+        assert_eq!(
+            PartitionKey::Project.key(message_key, &attributes),
+            42u128.to_be_bytes()
+        );
+        assert_eq!(PartitionKey::Trace.key(message_key, &attributes), [2; 16]);
+
+        let empty = MessageAttributes::default();
+        assert_eq!(PartitionKey::Project.key(message_key, &empty), [1; 16]);
+        assert_eq!(PartitionKey::Trace.key(message_key, &empty), [1; 16]);
+    }
+
+    #[test]
+    fn test_headers() {
+        let attributes = MessageAttributes {
+            project_id: Some(42),
+            item_type: Some("transaction"),
+            sampled: Some(false),
+            ..Default::default()
+        };
+
+        let all = [
+            MessageHeader::ProjectId,
+            MessageHeader::OrganizationId,
+            MessageHeader::ItemType,
+            MessageHeader::Sampled,
+        ];
+
+        let headers = attributes.headers(7, &all);
+        assert_eq!(
+            headers,
+            [
+                ("project_id".to_owned(), "42".to_owned()),
+                ("organization_id".to_owned(), "7".to_owned()),
+                ("item_type".to_owned(), "transaction".to_owned()),
+                ("sampled".to_owned(), "false".to_owned()),
+            ]
+        );
+
+        let headers = MessageAttributes::default().headers(7, &all);
+        assert_eq!(headers, [("organization_id".to_owned(), "7".to_owned())]);
+    }
+}
@@ -3,6 +3,9 @@ use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::attributes::{MessageHeader, PartitionKey};
+use crate::schema::TopicEncoding;
+
 /// Kafka configuration errors.
 #[derive(Error, Debug)]
 pub enum ConfigError {
@@ -64,6 +67,24 @@ impl KafkaTopic {
         ];
         TOPICS.iter()
     }
+
+    /// Returns the name of this topic in [`TopicAssignments`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Events => "events",
+            Self::Attachments => "attachments",
+            Self::Transactions => "transactions",
+            Self::Outcomes => "outcomes",
+            Self::OutcomesBilling => "outcomes_billing",
+            Self::Sessions => "sessions",
+            Self::MetricsSessions => "metrics_sessions",
+            Self::MetricsTransactions => "metrics_transactions",
+            Self::Profiles => "profiles",
+            Self::ReplayEvents => "replay_events",
+            Self::ReplayRecordings => "replay_recordings",
+            Self::Monitors => "monitors",
+        }
+    }
 }
 
 /// Configuration for topics.
@@ -172,8 +193,56 @@ pub struct KafkaTopicConfig {
     #[serde(rename = "name")]
     topic_name: String,
     /// The Kafka config name will be used to produce data to the given topic.
-    #[serde(rename = "config")]
-    kafka_config_name: String,
+    ///
+    /// Defaults to the primary `kafka_config`.
+    #[serde(rename = "config", default, skip_serializing_if = "Option::is_none")]
+    kafka_config_name: Option<String>,
+    /// Enables the idempotent producer for this topic.
+    ///
+    /// An idempotent producer retries messages without introducing duplicates or reordering
+    /// them within a partition.
+    #[serde(default, skip_serializing_if = "is_false")]
+    idempotent: bool,
+    /// Encodes messages with a registered Avro or Protobuf schema instead of the default format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<TopicEncoding>,
+    /// The strategy for partitioning keys of messages in this topic.
+    ///
+    /// Defaults to the key chosen by every message.
+    #[serde(default, skip_serializing_if = "PartitionKey::is_default")]
+    partition_key: PartitionKey,
+    /// Standard headers added to all messages in this topic.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<MessageHeader>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl KafkaTopicConfig {
+    fn kafka_params<'a>(
+        &'a self,
+        default_config: &'a [KafkaConfigParam],
+        secondary_configs: &'a BTreeMap<String, Vec<KafkaConfigParam>>,
+    ) -> Result<KafkaParams<'a>, ConfigError> {
+        let params = match self.kafka_config_name {
+            Some(ref name) => secondary_configs
+                .get(name)
+                .ok_or(ConfigError::UnknownKafkaConfigName)?,
+            None => default_config,
+        };
+
+        Ok(KafkaParams {
+            topic_name: &self.topic_name,
+            config_name: self.kafka_config_name.as_deref(),
+            params,
+            idempotent: self.idempotent,
+            encoding: self.encoding,
+            partition_key: self.partition_key,
+            headers: &self.headers,
+        })
+    }
 }
 
 /// Configuration for logical shards -> kafka configuration mapping.
@@ -233,6 +302,14 @@ pub struct KafkaParams<'a> {
     pub config_name: Option<&'a str>,
     /// Parameters for the Kafka producer configuration.
     pub params: &'a [KafkaConfigParam],
+    /// Whether the producer for this topic is idempotent.
+    pub idempotent: bool,
+    /// The schema-based encoding of messages in this topic, if any.
+    pub encoding: Option<TopicEncoding>,
+    /// The strategy for partitioning keys of messages in this topic.
+    pub partition_key: PartitionKey,
+    /// Standard headers added to all messages in this topic.
+    pub headers: &'a [MessageHeader],
 }
 
 impl From<String> for TopicAssignment {
@@ -257,19 +334,14 @@ impl TopicAssignment {
                     topic_name,
                     config_name: None,
                     params: default_config.as_slice(),
+                    idempotent: false,
+                    encoding: None,
+                    partition_key: PartitionKey::Message,
+                    headers: &[],
                 },
             },
-            Self::Secondary(KafkaTopicConfig {
-                topic_name,
-                kafka_config_name,
-            }) => KafkaConfig::Single {
-                params: KafkaParams {
-                    config_name: Some(kafka_config_name),
-                    topic_name,
-                    params: secondary_configs
-                        .get(kafka_config_name)
-                        .ok_or(ConfigError::UnknownKafkaConfigName)?,
-                },
+            Self::Secondary(topic_config) => KafkaConfig::Single {
+                params: topic_config.kafka_params(default_config, secondary_configs)?,
             },
             Self::Sharded(Sharded { shards, mapping }) => {
                 // quick fail if the config does not contain shard 0
@@ -278,13 +350,7 @@ impl TopicAssignment {
                 }
                 let mut kafka_params = BTreeMap::new();
                 for (shard, kafka_config) in mapping {
-                    let config = KafkaParams {
-                        topic_name: kafka_config.topic_name.as_str(),
-                        config_name: Some(kafka_config.kafka_config_name.as_str()),
-                        params: secondary_configs
-                            .get(kafka_config.kafka_config_name.as_str())
-                            .ok_or(ConfigError::UnknownKafkaConfigName)?,
-                    };
+                    let config = kafka_config.kafka_params(default_config, secondary_configs)?;
                     kafka_params.insert(*shard, config);
                 }
                 KafkaConfig::Sharded {
@@ -311,6 +377,7 @@ pub struct KafkaConfigParam {
 mod tests {
 
     use super::*;
+    use crate::schema::SchemaFormat;
 
     #[test]
     fn test_kafka_config() {
@@ -394,4 +461,110 @@ metrics:
         assert_eq!(shards, 65000);
         assert_eq!(3, mapping.len());
     }
+
+    #[test]
+    fn test_kafka_config_idempotent() {
+        let yaml = r###"
+events:
+    name: "ingest-events"
+    idempotent: true
+profiles:
+    name: "ingest-profiles"
+    config: "profiles"
+"###;
+
+        let def_config = vec![KafkaConfigParam {
+            name: "test".to_string(),
+            value: "test-value".to_string(),
+        }];
+        let mut second_config = BTreeMap::new();
+        second_config.insert("profiles".to_string(), vec![]);
+
+        let topics: TopicAssignments = serde_yaml::from_str(yaml).unwrap();
+
+        let events_config = topics
+            .events
+            .kafka_config(&def_config, &second_config)
+            .unwrap();
+        let KafkaConfig::Single { params } = events_config else { unreachable!() };
+        assert_eq!(params.topic_name, "ingest-events");
+        assert_eq!(params.config_name, None);
+        assert_eq!(params.params.len(), 1);
+        assert!(params.idempotent);
+
+        let profiles_config = topics
+            .profiles
+            .kafka_config(&def_config, &second_config)
+            .unwrap();
+        let KafkaConfig::Single { params } = profiles_config else { unreachable!() };
+        assert_eq!(params.config_name, Some("profiles"));
+        assert!(params.params.is_empty());
+        assert!(!params.idempotent);
+    }
+
+    #[test]
+    fn test_kafka_config_encoding() {
+        let yaml = r###"
+events:
+    name: "ingest-events"
+    encoding:
+        format: protobuf
+        schema_id: 42
+"###;
+
+        let def_config = vec![];
+        let second_config = BTreeMap::new();
+
+        let topics: TopicAssignments = serde_yaml::from_str(yaml).unwrap();
+        let events_config = topics
+            .events
+            .kafka_config(&def_config, &second_config)
+            .unwrap();
+        let KafkaConfig::Single { params } = events_config else { unreachable!() };
+        assert_eq!(
+            params.encoding,
+            Some(TopicEncoding {
+                format: SchemaFormat::Protobuf,
+                schema_id: 42,
+            })
+        );
+    }
+
+    #[test]
+    fn test_kafka_config_headers() {
+        let yaml = r###"
+events:
+    name: "ingest-events"
+    partition_key: trace
+    headers: [project_id, item_type, sampled]
+transactions: "ingest-transactions"
+"###;
+
+        let def_config = vec![];
+        let second_config = BTreeMap::new();
+
+        let topics: TopicAssignments = serde_yaml::from_str(yaml).unwrap();
+        let events_config = topics
+            .events
+            .kafka_config(&def_config, &second_config)
+            .unwrap();
+        let KafkaConfig::Single { params } = events_config else { unreachable!() };
+        assert_eq!(params.partition_key, PartitionKey::Trace);
+        assert_eq!(
+            params.headers,
+            [
+                MessageHeader::ProjectId,
+                MessageHeader::ItemType,
+                MessageHeader::Sampled,
+            ]
+        );
+
+        let transactions_config = topics
+            .transactions
+            .kafka_config(&def_config, &second_config)
+            .unwrap();
+        let KafkaConfig::Single { params } = transactions_config else { unreachable!() };
+        assert_eq!(params.partition_key, PartitionKey::Message);
+        assert!(params.headers.is_empty());
+    }
 }
@@ -0,0 +1,345 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+use crate::config::KafkaTopic;
+
+/// File extension of messages in the fallback queue.
+const MESSAGE_EXTENSION: &str = "msg";
+
+/// File extension of messages that are still being written.
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Errors of the [`FallbackQueue`].
+#[derive(Error, Debug)]
+pub enum FallbackError {
+    /// Failed to read or write the queue directory.
+    #[error("failed to access the kafka fallback queue")]
+    Io(#[from] io::Error),
+
+    /// The queue exceeds its maximum size.
+    #[error("the kafka fallback queue is full")]
+    Full,
+
+    /// A file in the queue could not be parsed.
+    #[error("invalid message in the kafka fallback queue")]
+    InvalidMessage,
+}
+
+/// A Kafka message stored in the [`FallbackQueue`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FallbackMessage {
+    /// The logical topic of the message.
+    pub topic: KafkaTopic,
+    /// The organization used for sharded topics.
+    pub organization_id: u64,
+    /// The partitioning key of the message.
+    pub key: [u8; 16],
+    /// The type of the message.
+    pub variant: String,
+    /// Names and values of the Kafka headers of the message.
+    pub headers: Vec<(String, String)>,
+    /// The serialized message.
+    pub payload: Vec<u8>,
+}
+
+impl FallbackMessage {
+    /// Encodes the message as a header line followed by the raw payload.
+    ///
+    /// Kafka headers are appended to the header line as `name=value` pairs.
+    fn encode(&self) -> Vec<u8> {
+        let key: String = self.key.iter().map(|b| format!("{b:02x}")).collect();
+        let mut header = format!(
+            "{} {} {} {}",
+            self.topic.as_str(),
+            self.organization_id,
+            key,
+            self.variant
+        );
+        for (name, value) in &self.headers {
+            header.push_str(&format!(" {name}={value}"));
+        }
+        header.push('\n');
+
+        let mut encoded = Vec::with_capacity(header.len() + self.payload.len());
+        encoded.extend_from_slice(header.as_bytes());
+        encoded.extend_from_slice(&self.payload);
+        encoded
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let split = data.iter().position(|b| *b == b'\n')?;
+        let header = std::str::from_utf8(&data[..split]).ok()?;
+        let mut parts = header.split(' ');
+
+        let topic_name = parts.next()?;
+        let topic = *KafkaTopic::iter().find(|t| t.as_str() == topic_name)?;
+        let organization_id = parts.next()?.parse().ok()?;
+
+        let key_hex = parts.next()?;
+        if key_hex.len() != 32 {
+            return None;
+        }
+        let mut key = [0; 16];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(key_hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+
+        let variant = parts.next()?.to_owned();
+        let headers = parts
+            .map(|part| {
+                let (name, value) = part.split_once('=')?;
+                Some((name.to_owned(), value.to_owned()))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self {
+            topic,
+            organization_id,
+            key,
+            variant,
+            headers,
+            payload: data[split + 1..].to_vec(),
+        })
+    }
+}
+
+/// A message file in the [`FallbackQueue`].
+#[derive(Debug)]
+pub struct FallbackEntry {
+    path: PathBuf,
+    size: u64,
+}
+
+impl FallbackEntry {
+    /// Reads the message from disk.
+    pub fn read(&self) -> Result<FallbackMessage, FallbackError> {
+        let data = fs::read(&self.path)?;
+        FallbackMessage::decode(&data).ok_or(FallbackError::InvalidMessage)
+    }
+}
+
+/// A queue on disk for messages that could not be delivered to Kafka.
+///
+/// Every message is stored in its own file. Files are named by the time they were written, so
+/// that messages are returned in the order they were pushed.
+#[derive(Debug)]
+pub struct FallbackQueue {
+    path: PathBuf,
+    max_size: u64,
+    size: AtomicU64,
+    sequence: AtomicU64,
+}
+
+impl FallbackQueue {
+    /// Opens the queue in the given directory, creating it if it does not exist.
+    ///
+    /// Messages left over from a previous run remain in the queue.
+    pub fn open(path: impl Into<PathBuf>, max_size: u64) -> Result<Self, FallbackError> {
+        let path = path.into();
+        fs::create_dir_all(&path)?;
+
+        let mut size = 0;
+        for entry in fs::read_dir(&path)? {
+            let entry_path = entry?.path();
+            if has_extension(&entry_path, TEMP_EXTENSION) {
+                // Incomplete writes from a previous run.
+                fs::remove_file(&entry_path)?;
+            } else if has_extension(&entry_path, MESSAGE_EXTENSION) {
+                size += fs::metadata(&entry_path)?.len();
+            }
+        }
+
+        Ok(Self {
+            path,
+            max_size,
+            size: AtomicU64::new(size),
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the total size of all messages in the queue in bytes.
+    pub fn size(&self) -> u64 {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if there are no messages in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Appends a message to the queue.
+    ///
+    /// # Errors
+    /// Returns [`FallbackError::Full`] if the message would exceed the maximum size of the queue.
+    pub fn push(&self, message: &FallbackMessage) -> Result<(), FallbackError> {
+        let data = message.encode();
+        let len = data.len() as u64;
+
+        if self.size.fetch_add(len, Ordering::Relaxed) + len > self.max_size {
+            self.size.fetch_sub(len, Ordering::Relaxed);
+            return Err(FallbackError::Full);
+        }
+
+        let result = self.write(&data);
+        if result.is_err() {
+            self.size.fetch_sub(len, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), FallbackError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let name = format!("{timestamp:020}-{sequence:020}");
+
+        // Write to a temporary file first so that partial messages are never read.
+        let temp_path = self.path.join(&name).with_extension(TEMP_EXTENSION);
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(data)?;
+        file.sync_data()?;
+        drop(file);
+
+        fs::rename(
+            temp_path,
+            self.path.join(name).with_extension(MESSAGE_EXTENSION),
+        )?;
+        Ok(())
+    }
+
+    /// Returns up to `limit` of the oldest messages in the queue without removing them.
+    pub fn peek(&self, limit: usize) -> Result<Vec<FallbackEntry>, FallbackError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            if has_extension(&path, MESSAGE_EXTENSION) {
+                paths.push(path);
+            }
+        }
+
+        paths.sort_unstable();
+        paths.truncate(limit);
+
+        let mut entries = Vec::with_capacity(paths.len());
+        for path in paths {
+            let size = fs::metadata(&path)?.len();
+            entries.push(FallbackEntry { path, size });
+        }
+
+        Ok(entries)
+    }
+
+    /// Removes a message from the queue.
+    pub fn remove(&self, entry: FallbackEntry) -> Result<(), FallbackError> {
+        fs::remove_file(&entry.path)?;
+        self.size.fetch_sub(entry.size, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().map_or(false, |ext| ext == extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(variant: &str, payload: &[u8]) -> FallbackMessage {
+        FallbackMessage {
+            topic: KafkaTopic::Events,
+            organization_id: 42,
+            key: *b"0123456789abcdef",
+            variant: variant.to_owned(),
+            headers: vec![],
+            payload: payload.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut message = message("event", b"\x82\xa1a\x01\n\x00");
+        let decoded = FallbackMessage::decode(&message.encode()).unwrap();
+        assert_eq!(decoded, message);
+
+        message.headers = vec![
+            ("project_id".to_owned(), "42".to_owned()),
+            ("sampled".to_owned(), "true".to_owned()),
+        ];
+        let decoded = FallbackMessage::decode(&message.encode()).unwrap();
+        assert_eq!(decoded, message);
+
+        assert_eq!(FallbackMessage::decode(b"events 42 abc event\n"), None);
+        assert_eq!(FallbackMessage::decode(b"no header"), None);
+        assert_eq!(
+            FallbackMessage::decode(b"events 42 30313233343536373839616263646566 event x\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = FallbackQueue::open(dir.path(), 1024).unwrap();
+        assert!(queue.is_empty());
+
+        queue.push(&message("event", b"first")).unwrap();
+        queue.push(&message("event", b"second")).unwrap();
+        queue.push(&message("event", b"third")).unwrap();
+
+        let entries = queue.peek(2).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].read().unwrap().payload, b"first");
+        assert_eq!(entries[1].read().unwrap().payload, b"second");
+
+        for entry in entries {
+            queue.remove(entry).unwrap();
+        }
+
+        let entries = queue.peek(10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].read().unwrap().payload, b"third");
+    }
+
+    #[test]
+    fn test_queue_full() {
+        let dir = tempfile::tempdir().unwrap();
+        let message = message("event", &[0; 100]);
+        let len = message.encode().len() as u64;
+
+        let queue = FallbackQueue::open(dir.path(), len * 2).unwrap();
+        queue.push(&message).unwrap();
+        queue.push(&message).unwrap();
+        assert!(matches!(queue.push(&message), Err(FallbackError::Full)));
+        assert_eq!(queue.size(), len * 2);
+
+        let entry = queue.peek(1).unwrap().pop().unwrap();
+        queue.remove(entry).unwrap();
+        queue.push(&message).unwrap();
+    }
+
+    #[test]
+    fn test_queue_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let message = message("event", b"payload");
+
+        let queue = FallbackQueue::open(dir.path(), 1024).unwrap();
+        queue.push(&message).unwrap();
+        let size = queue.size();
+        drop(queue);
+
+        fs::write(dir.path().join("incomplete.tmp"), b"partial").unwrap();
+
+        let queue = FallbackQueue::open(dir.path(), 1024).unwrap();
+        assert_eq!(queue.size(), size);
+        assert!(!dir.path().join("incomplete.tmp").exists());
+        assert_eq!(queue.peek(10).unwrap()[0].read().unwrap(), message);
+    }
+}
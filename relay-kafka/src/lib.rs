@@ -27,6 +27,16 @@
 //!
 //! If the configuration for the [`KafkaTopic`] was not added, attemps to send the message to this
 //! topic will return the error.
+//!
+//! Messages that cannot be delivered can be stored in a [`FallbackQueue`] on disk, which is
+//! drained back into Kafka once the brokers recover.
+//!
+//! Topics can be configured with a [`TopicEncoding`] to produce messages in Avro or Protobuf
+//! format. The structure of such messages is described by a [`MessageSchema`], from which the
+//! schemas for the registry are generated.
+//!
+//! Per topic, messages can be partitioned by a [`PartitionKey`] and carry standard
+//! [`MessageHeader`]s, both derived from the [`MessageAttributes`] of a message.
 #![warn(missing_docs)]
 #![warn(missing_debug_implementations)]
 #![doc(
@@ -34,12 +44,18 @@
     html_favicon_url = "https://raw.githubusercontent.com/getsentry/relay/master/artwork/relay-icon.png"
 )]
 
+mod attributes;
 mod config;
+mod fallback;
 #[cfg(feature = "producer")]
 mod producer;
+mod schema;
 #[cfg(feature = "producer")]
 mod statsd;
 
+pub use attributes::*;
 pub use config::*;
+pub use fallback::*;
+pub use schema::*;
 #[cfg(feature = "producer")]
 pub use producer::*;
@@ -4,18 +4,23 @@ use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::sync::Arc;
 
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::BaseRecord;
 use rdkafka::ClientConfig;
 use relay_statsd::metric;
 use thiserror::Error;
 
+use crate::attributes::{MessageAttributes, MessageHeader, PartitionKey};
 use crate::config::{KafkaConfig, KafkaParams, KafkaTopic};
+use crate::fallback::{FallbackError, FallbackQueue};
 #[cfg(debug_assertions)]
 use crate::producer::schemas::Validator;
-use crate::statsd::KafkaHistograms;
+use crate::schema::{EncodingError, MessageSchema, TopicEncoding};
+use crate::statsd::{KafkaCounters, KafkaHistograms};
 
 mod utils;
-use utils::{CaptureErrorContext, ThreadedProducer};
+use utils::{CaptureErrorContext, Delivery, DeliveryHooks, ThreadedProducer};
+pub use utils::{DeliveryContext, DeliveryFailure, DeliveryFailureHandler};
 
 #[cfg(debug_assertions)]
 mod schemas;
@@ -51,6 +56,18 @@ pub enum ClientError {
     /// Configuration is wrong and it cannot be used to identify the number of a shard.
     #[error("invalid kafka shard")]
     InvalidShard,
+
+    /// Failed to access the fallback queue.
+    #[error("failed to access the kafka fallback queue")]
+    Fallback(#[source] FallbackError),
+
+    /// The topic has a schema-based encoding, but the message does not declare a schema.
+    #[error("kafka message has no schema for the topic encoding")]
+    MissingSchema,
+
+    /// Failed to encode the message with its schema.
+    #[error("failed to encode kafka message with its schema")]
+    InvalidEncoding(#[source] EncodingError),
 }
 
 /// Describes the type which can be sent using kafka producer provided by this crate.
@@ -67,6 +84,26 @@ pub trait Message {
     /// Returns the [`ClientError::InvalidMsgPack`] or [`ClientError::InvalidJson`] if the
     /// serialization failed.
     fn serialize(&self) -> Result<Vec<u8>, ClientError>;
+
+    /// Returns the schema of this message for topics with a [`TopicEncoding`].
+    ///
+    /// Messages without a schema cannot be sent to such topics.
+    fn schema(&self) -> Option<&'static MessageSchema> {
+        None
+    }
+
+    /// Returns the representation of this message that is encoded with its schema.
+    ///
+    /// # Errors
+    /// Returns [`ClientError::MissingSchema`] by default.
+    fn to_value(&self) -> Result<serde_json::Value, ClientError> {
+        Err(ClientError::MissingSchema)
+    }
+
+    /// Returns the attributes of this message used for partitioning keys and headers.
+    fn attributes(&self) -> MessageAttributes {
+        MessageAttributes::default()
+    }
 }
 
 /// Single kafka producer config with assigned topic.
@@ -75,6 +112,24 @@ struct SingleProducer {
     topic_name: String,
     /// Real kafka producer.
     producer: Arc<ThreadedProducer>,
+    /// Schema-based encoding of messages in this topic.
+    encoding: Option<TopicEncoding>,
+    /// The strategy for partitioning keys of messages in this topic.
+    partition_key: PartitionKey,
+    /// Standard headers added to messages in this topic.
+    headers: Vec<MessageHeader>,
+}
+
+impl SingleProducer {
+    fn new(params: &KafkaParams<'_>, producer: Arc<ThreadedProducer>) -> Self {
+        Self {
+            topic_name: params.topic_name.to_string(),
+            producer,
+            encoding: params.encoding,
+            partition_key: params.partition_key,
+            headers: params.headers.to_vec(),
+        }
+    }
 }
 
 impl fmt::Debug for SingleProducer {
@@ -82,6 +137,9 @@ impl fmt::Debug for SingleProducer {
         f.debug_struct("Single")
             .field("topic_name", &self.topic_name)
             .field("producer", &"<ThreadedProducer>")
+            .field("encoding", &self.encoding)
+            .field("partition_key", &self.partition_key)
+            .field("headers", &self.headers)
             .finish()
     }
 }
@@ -92,31 +150,25 @@ struct ShardedProducer {
     shards: u64,
     /// The actual Kafka producer assigned to the range of logical shards, where the `u64` in the map is
     /// the inclusive beginning of the range.
-    producers: BTreeMap<u64, (String, Arc<ThreadedProducer>)>,
+    producers: BTreeMap<u64, SingleProducer>,
 }
 
 impl ShardedProducer {
-    /// Returns topic name and the Kafka producer based on the provided sharding key.
+    /// Returns the producer with its topic name based on the provided sharding key.
     /// Returns error [`ClientError::InvalidShard`] if the shard range for the provided sharding
     /// key could not be found.
     ///
     /// # Errors
     /// Returns [`ClientError::InvalidShard`] error if the provided `sharding_key` could not be
     /// placed in any configured shard ranges.
-    pub fn get_producer(
-        &self,
-        sharding_key: u64,
-    ) -> Result<(&str, &ThreadedProducer), ClientError> {
+    pub fn get_producer(&self, sharding_key: u64) -> Result<&SingleProducer, ClientError> {
         let shard = sharding_key % self.shards;
-        let (topic_name, producer) = self
-            .producers
+        self.producers
             .iter()
             .take_while(|(k, _)| *k <= &shard)
             .last()
             .map(|(_, v)| v)
-            .ok_or(ClientError::InvalidShard)?;
-
-        Ok((topic_name, producer))
+            .ok_or(ClientError::InvalidShard)
     }
 }
 
@@ -125,7 +177,7 @@ impl fmt::Debug for ShardedProducer {
         let producers = &self
             .producers
             .iter()
-            .map(|(shard, (topic, _))| (shard, topic))
+            .map(|(shard, producer)| (shard, &producer.topic_name))
             .collect::<BTreeMap<_, _>>();
         f.debug_struct("ShardedProducer")
             .field("shards", &self.shards)
@@ -135,13 +187,22 @@ impl fmt::Debug for ShardedProducer {
 }
 
 /// Keeps all the configured kafka producers and responsible for the routing of the messages.
-#[derive(Debug)]
 pub struct KafkaClient {
     producers: HashMap<KafkaTopic, Producer>,
+    hooks: Arc<DeliveryHooks>,
     #[cfg(debug_assertions)]
     schema_validator: RefCell<schemas::Validator>,
 }
 
+impl fmt::Debug for KafkaClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaClient")
+            .field("producers", &self.producers)
+            .field("fallback", &self.hooks.fallback)
+            .finish()
+    }
+}
+
 impl KafkaClient {
     /// Returns the [`KafkaClientBuilder`]
     pub fn builder() -> KafkaClientBuilder {
@@ -155,14 +216,48 @@ impl KafkaClient {
         organization_id: u64,
         message: &impl Message,
     ) -> Result<(), ClientError> {
-        let serialized = message.serialize()?;
-        #[cfg(debug_assertions)]
-        self.schema_validator
-            .borrow_mut()
-            .validate_message_schema(topic, &serialized)
-            .map_err(ClientError::SchemaValidationFailed)?;
-        let key = message.key();
-        self.send(topic, organization_id, &key, message.variant(), &serialized)
+        self.send_message_with_context(topic, organization_id, message, None)
+    }
+
+    /// Sends message to the provided kafka topic.
+    ///
+    /// If the message cannot be delivered and is not stored in the fallback queue, the `context`
+    /// is passed to the [`DeliveryFailureHandler`].
+    pub fn send_message_with_context(
+        &self,
+        topic: KafkaTopic,
+        organization_id: u64,
+        message: &impl Message,
+        context: Option<DeliveryContext>,
+    ) -> Result<(), ClientError> {
+        let producer = self.producer(topic)?.get(organization_id)?;
+        let serialized = match producer.encoding {
+            Some(encoding) => {
+                let schema = message.schema().ok_or(ClientError::MissingSchema)?;
+                encoding
+                    .encode(schema, &message.to_value()?)
+                    .map_err(ClientError::InvalidEncoding)?
+            }
+            None => {
+                let serialized = message.serialize()?;
+                #[cfg(debug_assertions)]
+                self.schema_validator
+                    .borrow_mut()
+                    .validate_message_schema(topic, &serialized)
+                    .map_err(ClientError::SchemaValidationFailed)?;
+                serialized
+            }
+        };
+        let attributes = message.attributes();
+        let delivery = Delivery {
+            topic,
+            organization_id,
+            key: producer.partition_key.key(message.key(), &attributes),
+            variant: message.variant().to_owned(),
+            headers: attributes.headers(organization_id, &producer.headers),
+            context,
+        };
+        self.send_delivery(delivery, &serialized)
     }
 
     /// Sends the payload to the correct producer for the current topic.
@@ -174,21 +269,79 @@ impl KafkaClient {
         variant: &str,
         payload: &[u8],
     ) -> Result<(), ClientError> {
-        let producer = self.producers.get(&topic).ok_or_else(|| {
+        let delivery = Delivery {
+            topic,
+            organization_id,
+            key: *key,
+            variant: variant.to_owned(),
+            headers: Vec::new(),
+            context: None,
+        };
+        self.send_delivery(delivery, payload)
+    }
+
+    fn send_delivery(&self, delivery: Delivery, payload: &[u8]) -> Result<(), ClientError> {
+        self.producer(delivery.topic)?
+            .send(&self.hooks, delivery, payload)
+    }
+
+    /// Returns the producer configured for the given topic.
+    fn producer(&self, topic: KafkaTopic) -> Result<&Producer, ClientError> {
+        self.producers.get(&topic).ok_or_else(|| {
             relay_log::error!(
                 "attempted to send message to {topic:?} using an unconfigured kafka producer",
             );
             ClientError::InvalidTopicName
-        })?;
-        producer.send(organization_id, key, variant, payload)
+        })
+    }
+
+    /// Sends up to `limit` messages from the fallback queue to Kafka again.
+    ///
+    /// Messages are only resent after the last delivery to Kafka succeeded. Messages that fail
+    /// again are written back to the fallback queue. Returns the number of messages sent.
+    pub fn drain_fallback(&self, limit: usize) -> Result<usize, ClientError> {
+        let Some(ref fallback) = self.hooks.fallback else { return Ok(0) };
+        if fallback.is_empty() || !self.hooks.is_healthy() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        for entry in fallback.peek(limit).map_err(ClientError::Fallback)? {
+            match entry.read() {
+                Ok(message) => {
+                    let delivery = Delivery {
+                        topic: message.topic,
+                        organization_id: message.organization_id,
+                        key: message.key,
+                        variant: message.variant,
+                        headers: message.headers,
+                        context: None,
+                    };
+                    self.send_delivery(delivery, &message.payload)?;
+                    count += 1;
+                }
+                Err(error) => relay_log::error!(
+                    error = &error as &dyn std::error::Error,
+                    "dropping invalid message from kafka fallback queue"
+                ),
+            }
+
+            fallback.remove(entry).map_err(ClientError::Fallback)?;
+        }
+
+        metric!(counter(KafkaCounters::FallbackDrained) += count as i64);
+        Ok(count)
     }
 }
 
 /// Helper structure responsable for building the actual [`KafkaClient`].
 #[derive(Default)]
 pub struct KafkaClientBuilder {
-    reused_producers: BTreeMap<Option<String>, Arc<ThreadedProducer>>,
+    reused_producers: BTreeMap<(Option<String>, bool), Arc<ThreadedProducer>>,
     producers: HashMap<KafkaTopic, Producer>,
+    fallback: Option<Arc<FallbackQueue>>,
+    on_failure: Option<DeliveryFailureHandler>,
+    hooks: Option<Arc<DeliveryHooks>>,
 }
 
 impl KafkaClientBuilder {
@@ -197,6 +350,68 @@ impl KafkaClientBuilder {
         Self::default()
     }
 
+    /// Stores messages that cannot be delivered in the given queue on disk.
+    ///
+    /// Stored messages are sent again with [`KafkaClient::drain_fallback`]. This must be
+    /// configured before adding topics.
+    pub fn fallback(mut self, queue: FallbackQueue) -> Self {
+        self.fallback = Some(Arc::new(queue));
+        self
+    }
+
+    /// Registers a callback for messages that are lost because they cannot be delivered.
+    ///
+    /// This must be configured before adding topics.
+    pub fn on_delivery_failure(mut self, handler: DeliveryFailureHandler) -> Self {
+        self.on_failure = Some(handler);
+        self
+    }
+
+    /// Returns the delivery hooks shared by all producers of this client.
+    fn hooks(&mut self) -> Arc<DeliveryHooks> {
+        if let Some(ref hooks) = self.hooks {
+            return Arc::clone(hooks);
+        }
+
+        let hooks = Arc::new(DeliveryHooks {
+            fallback: self.fallback.take(),
+            on_failure: self.on_failure.take(),
+            ..Default::default()
+        });
+        self.hooks = Some(Arc::clone(&hooks));
+        hooks
+    }
+
+    /// Creates a producer for the given parameters or reuses an existing one.
+    ///
+    /// Every producer gets its own client configuration, so that settings of one producer do not
+    /// carry over to the next.
+    fn producer(&mut self, params: &KafkaParams<'_>) -> Result<Arc<ThreadedProducer>, ClientError> {
+        let cache_key = (params.config_name.map(str::to_string), params.idempotent);
+        if let Some(producer) = self.reused_producers.get(&cache_key) {
+            return Ok(Arc::clone(producer));
+        }
+
+        let mut client_config = ClientConfig::new();
+        for config_p in params.params {
+            client_config.set(config_p.name.as_str(), config_p.value.as_str());
+        }
+
+        if params.idempotent {
+            client_config.set("enable.idempotence", "true");
+        }
+
+        let producer = Arc::new(
+            client_config
+                .create_with_context(CaptureErrorContext::new(self.hooks()))
+                .map_err(ClientError::InvalidConfig)?,
+        );
+
+        self.reused_producers
+            .insert(cache_key, Arc::clone(&producer));
+        Ok(producer)
+    }
+
     /// Adds topic configuration to the current [`KafkaClientBuilder`], which in return assigns
     /// dedicates producer to the topic which can will be used to send the messages.
     ///
@@ -208,72 +423,20 @@ impl KafkaClientBuilder {
         topic: KafkaTopic,
         config: &KafkaConfig,
     ) -> Result<Self, ClientError> {
-        let mut client_config = ClientConfig::new();
         match config {
             KafkaConfig::Single { params } => {
-                let KafkaParams {
-                    topic_name,
-                    config_name,
-                    params,
-                } = params;
-
-                let config_name = config_name.map(str::to_string);
-
-                if let Some(producer) = self.reused_producers.get(&config_name) {
-                    self.producers.insert(
-                        topic,
-                        Producer::Single(SingleProducer {
-                            topic_name: (*topic_name).to_string(),
-                            producer: Arc::clone(producer),
-                        }),
-                    );
-                    return Ok(self);
-                }
-
-                for config_p in *params {
-                    client_config.set(config_p.name.as_str(), config_p.value.as_str());
-                }
-
-                let producer = Arc::new(
-                    client_config
-                        .create_with_context(CaptureErrorContext)
-                        .map_err(ClientError::InvalidConfig)?,
-                );
-
-                self.reused_producers
-                    .insert(config_name, Arc::clone(&producer));
+                let producer = self.producer(params)?;
                 self.producers.insert(
                     topic,
-                    Producer::Single(SingleProducer {
-                        topic_name: (*topic_name).to_string(),
-                        producer,
-                    }),
+                    Producer::Single(SingleProducer::new(params, producer)),
                 );
                 Ok(self)
             }
             KafkaConfig::Sharded { shards, configs } => {
                 let mut producers = BTreeMap::new();
                 for (shard, kafka_params) in configs {
-                    let config_name = kafka_params.config_name.map(str::to_string);
-                    if let Some(producer) = self.reused_producers.get(&config_name) {
-                        let cached_producer = Arc::clone(producer);
-                        producers.insert(
-                            *shard,
-                            (kafka_params.topic_name.to_string(), cached_producer),
-                        );
-                        continue;
-                    }
-                    for config_p in kafka_params.params {
-                        client_config.set(config_p.name.as_str(), config_p.value.as_str());
-                    }
-                    let producer = Arc::new(
-                        client_config
-                            .create_with_context(CaptureErrorContext)
-                            .map_err(ClientError::InvalidConfig)?,
-                    );
-                    self.reused_producers
-                        .insert(config_name, Arc::clone(&producer));
-                    producers.insert(*shard, (kafka_params.topic_name.to_string(), producer));
+                    let producer = self.producer(kafka_params)?;
+                    producers.insert(*shard, SingleProducer::new(kafka_params, producer));
                 }
                 self.producers.insert(
                     topic,
@@ -288,8 +451,9 @@ impl KafkaClientBuilder {
     }
 
     /// Consumes self and returns the built [`KafkaClient`].
-    pub fn build(self) -> KafkaClient {
+    pub fn build(mut self) -> KafkaClient {
         KafkaClient {
+            hooks: self.hooks(),
             producers: self.producers,
             #[cfg(debug_assertions)]
             schema_validator: Validator::default().into(),
@@ -317,35 +481,62 @@ enum Producer {
 }
 
 impl Producer {
+    /// Returns the producer for the given organization.
+    fn get(&self, organization_id: u64) -> Result<&SingleProducer, ClientError> {
+        match self {
+            Self::Single(single) => Ok(single),
+            Self::Sharded(sharded) => sharded.get_producer(organization_id),
+        }
+    }
+
     /// Sends the payload to the correct producer for the current topic.
+    ///
+    /// If the message cannot be enqueued, it is stored in the fallback queue if configured.
     fn send(
         &self,
-        organization_id: u64,
-        key: &[u8; 16],
-        variant: &str,
+        hooks: &DeliveryHooks,
+        delivery: Delivery,
         payload: &[u8],
     ) -> Result<(), ClientError> {
         metric!(
             histogram(KafkaHistograms::KafkaMessageSize) = payload.len() as u64,
-            variant = variant
+            variant = delivery.variant.as_str()
         );
-        let (topic_name, producer) = match self {
-            Self::Single(SingleProducer {
-                topic_name,
-                producer,
-            }) => (topic_name.as_str(), producer.as_ref()),
+        let SingleProducer {
+            topic_name,
+            producer,
+            ..
+        } = self.get(delivery.organization_id)?;
+        let key = delivery.key;
+        let headers = (!delivery.headers.is_empty()).then(|| {
+            let mut headers = OwnedHeaders::new_with_capacity(delivery.headers.len());
+            for (name, value) in &delivery.headers {
+                headers = headers.insert(Header {
+                    key: name,
+                    value: Some(value.as_str()),
+                });
+            }
+            headers
+        });
 
-            Self::Sharded(sharded) => sharded.get_producer(organization_id)?,
-        };
-        let record = BaseRecord::to(topic_name).key(key).payload(payload);
+        let mut record = BaseRecord::with_opaque_to(topic_name, Box::new(delivery))
+            .key(&key)
+            .payload(payload);
+        record.headers = headers;
 
-        producer.send(record).map_err(|(error, _message)| {
+        producer.send(record).or_else(|(error, record)| {
+            let delivery = record.delivery_opaque;
             relay_log::error!(
                 error = &error as &dyn std::error::Error,
-                tags.variant = variant,
+                tags.variant = delivery.variant.as_str(),
                 "error sending kafka message"
             );
-            ClientError::SendFailed(error)
+
+            if hooks.store(&delivery, payload) {
+                return Ok(());
+            }
+
+            Err(ClientError::SendFailed(error))
         })
     }
 }
@@ -1,32 +1,149 @@
+use std::any::Any;
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+use rdkafka::error::KafkaError;
 use rdkafka::producer::{DeliveryResult, ProducerContext};
 use rdkafka::{ClientContext, Message};
 use relay_statsd::metric;
 
+use crate::config::KafkaTopic;
+use crate::fallback::{FallbackMessage, FallbackQueue};
 use crate::statsd::KafkaCounters;
 
-/// Kafka producer context that logs producer errors.
+/// Arbitrary context attached to a message, which is passed to the [`DeliveryFailureHandler`].
+pub type DeliveryContext = Box<dyn Any + Send + Sync>;
+
+/// A message that could not be delivered to Kafka and was not stored in the fallback queue.
 #[derive(Debug)]
-pub struct CaptureErrorContext;
+pub struct DeliveryFailure<'a> {
+    /// The logical topic of the message.
+    pub topic: KafkaTopic,
+    /// The organization of the message.
+    pub organization_id: u64,
+    /// The type of the message.
+    pub variant: &'a str,
+    /// The error returned by the producer.
+    pub error: &'a KafkaError,
+    /// The context passed when sending the message, if any.
+    pub context: Option<&'a (dyn Any + Send + Sync)>,
+}
+
+/// Callback invoked for every message that is lost because it could not be delivered.
+pub type DeliveryFailureHandler = Arc<dyn Fn(DeliveryFailure<'_>) + Send + Sync>;
+
+/// Information about a message that is passed to the delivery callback.
+pub struct Delivery {
+    pub topic: KafkaTopic,
+    pub organization_id: u64,
+    pub key: [u8; 16],
+    pub variant: String,
+    pub headers: Vec<(String, String)>,
+    pub context: Option<DeliveryContext>,
+}
+
+/// Handling of failed deliveries shared by all producers of a client.
+#[derive(Default)]
+pub struct DeliveryHooks {
+    /// Queue on disk that failed messages are written to.
+    pub fallback: Option<Arc<FallbackQueue>>,
+    /// Callback for messages that are lost.
+    pub on_failure: Option<DeliveryFailureHandler>,
+    /// Set if the last delivery attempt failed.
+    pub unhealthy: AtomicBool,
+}
+
+impl DeliveryHooks {
+    /// Returns `true` if the last message was delivered successfully.
+    pub fn is_healthy(&self) -> bool {
+        !self.unhealthy.load(Ordering::Relaxed)
+    }
+
+    /// Writes the message to the fallback queue.
+    ///
+    /// Returns `true` if the message was stored and will be retried later.
+    pub fn store(&self, delivery: &Delivery, payload: &[u8]) -> bool {
+        let Some(ref fallback) = self.fallback else { return false };
+
+        let message = FallbackMessage {
+            topic: delivery.topic,
+            organization_id: delivery.organization_id,
+            key: delivery.key,
+            variant: delivery.variant.clone(),
+            headers: delivery.headers.clone(),
+            payload: payload.to_vec(),
+        };
+
+        match fallback.push(&message) {
+            Ok(()) => {
+                metric!(counter(KafkaCounters::FallbackStored) += 1);
+                true
+            }
+            Err(error) => {
+                relay_log::error!(
+                    error = &error as &dyn Error,
+                    tags.variant = delivery.variant.as_str(),
+                    "failed to store kafka message in fallback queue"
+                );
+                false
+            }
+        }
+    }
+
+    /// Stores a message that failed to be delivered, or reports it as lost.
+    fn handle_failure(&self, error: &KafkaError, delivery: Delivery, payload: &[u8]) {
+        if self.store(&delivery, payload) {
+            return;
+        }
+
+        if let Some(ref on_failure) = self.on_failure {
+            on_failure(DeliveryFailure {
+                topic: delivery.topic,
+                organization_id: delivery.organization_id,
+                variant: &delivery.variant,
+                error,
+                context: delivery.context.as_deref(),
+            });
+        }
+    }
+}
+
+/// Kafka producer context that logs producer errors.
+pub struct CaptureErrorContext {
+    hooks: Arc<DeliveryHooks>,
+}
+
+impl CaptureErrorContext {
+    pub fn new(hooks: Arc<DeliveryHooks>) -> Self {
+        Self { hooks }
+    }
+}
 
 impl ClientContext for CaptureErrorContext {}
 
 impl ProducerContext for CaptureErrorContext {
-    type DeliveryOpaque = ();
+    type DeliveryOpaque = Box<Delivery>;
 
     /// This method is called after attempting to send a message to Kafka.
     /// It's called asynchronously for every message, so we want to handle errors explicitly here.
-    fn delivery(&self, result: &DeliveryResult, _delivery_opaque: Self::DeliveryOpaque) {
-        if let Err((error, message)) = result {
-            relay_log::error!(
-                error = error as &dyn Error,
-                payload_len = message.payload_len(),
-                tags.topic = message.topic(),
-                "failed to produce message to Kafka (delivery callback)",
-            );
-
-            metric!(counter(KafkaCounters::ProcessingProduceError) += 1);
+    fn delivery(&self, result: &DeliveryResult, delivery: Self::DeliveryOpaque) {
+        match result {
+            Ok(_) => self.hooks.unhealthy.store(false, Ordering::Relaxed),
+            Err((error, message)) => {
+                relay_log::error!(
+                    error = error as &dyn Error,
+                    payload_len = message.payload_len(),
+                    tags.topic = message.topic(),
+                    "failed to produce message to Kafka (delivery callback)",
+                );
+
+                metric!(counter(KafkaCounters::ProcessingProduceError) += 1);
+
+                self.hooks.unhealthy.store(true, Ordering::Relaxed);
+                self.hooks
+                    .handle_failure(error, *delivery, message.payload().unwrap_or_default());
+            }
         }
     }
 }
@@ -0,0 +1,738 @@
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+/// Magic byte of the schema registry wire format.
+const MAGIC_BYTE: u8 = 0;
+
+/// Namespace of generated Avro schemas.
+const AVRO_NAMESPACE: &str = "io.sentry.relay";
+
+/// Package of generated Protobuf schemas.
+const PROTO_PACKAGE: &str = "sentry.relay";
+
+/// Errors encoding a message with a [`MessageSchema`].
+#[derive(Error, Debug)]
+pub enum EncodingError {
+    /// A value does not match the type declared in the schema.
+    #[error("field `{0}` does not match its schema")]
+    InvalidField(String),
+
+    /// A required field is missing.
+    #[error("missing required field `{0}`")]
+    MissingField(String),
+
+    /// A field is not declared in the schema.
+    #[error("field `{0}` is not declared in the schema")]
+    UnknownField(String),
+}
+
+/// The type of a field in a [`MessageSchema`].
+#[derive(Clone, Copy, Debug)]
+pub enum FieldType {
+    /// A boolean.
+    Bool,
+    /// A signed 64-bit integer.
+    Long,
+    /// A 64-bit floating point number.
+    Double,
+    /// A UTF-8 string.
+    String,
+    /// Raw bytes, given either as string or as array of numbers.
+    Bytes,
+    /// A value that may be `null` or missing.
+    Optional(&'static FieldType),
+    /// A list of values.
+    Array(&'static FieldType),
+    /// A map from strings to values.
+    Map(&'static FieldType),
+    /// A nested message.
+    Record(&'static MessageSchema),
+}
+
+/// A field in a [`MessageSchema`].
+#[derive(Clone, Copy, Debug)]
+pub struct SchemaField {
+    /// The name of the field in the serialized message.
+    pub name: &'static str,
+    /// The Protobuf field number.
+    pub number: u32,
+    /// The type of the field.
+    pub ty: FieldType,
+}
+
+impl SchemaField {
+    /// Creates a new field for use in static schemas.
+    pub const fn new(name: &'static str, number: u32, ty: FieldType) -> Self {
+        Self { name, number, ty }
+    }
+}
+
+/// Describes the structure of a Kafka message for schema-based encodings.
+///
+/// Messages are encoded from their JSON representation and fields are written in the declared
+/// order. Fields of the JSON representation that are not declared in the schema fail encoding with
+/// [`EncodingError::UnknownField`] instead of being dropped.
+///
+/// Schemas are declared by hand next to the message types they describe. Validate serialized
+/// messages in tests to keep both in sync.
+#[derive(Debug)]
+pub struct MessageSchema {
+    /// The name of the message type.
+    pub name: &'static str,
+    /// The fields of the message.
+    pub fields: &'static [SchemaField],
+}
+
+impl MessageSchema {
+    /// Returns the Avro schema of this message.
+    pub fn avro_schema(&self) -> Value {
+        self.avro_record(&mut BTreeSet::new())
+    }
+
+    fn avro_record(&self, defined: &mut BTreeSet<&'static str>) -> Value {
+        if !defined.insert(self.name) {
+            return Value::String(self.name.to_owned());
+        }
+
+        let fields: Vec<_> = self
+            .fields
+            .iter()
+            .map(|field| {
+                let mut value = json!({
+                    "name": field.name,
+                    "type": avro_type(&field.ty, defined),
+                });
+                if let FieldType::Optional(_) = field.ty {
+                    value["default"] = Value::Null;
+                }
+                value
+            })
+            .collect();
+
+        json!({
+            "type": "record",
+            "name": self.name,
+            "namespace": AVRO_NAMESPACE,
+            "fields": fields,
+        })
+    }
+
+    /// Returns the Protobuf schema of this message, including all nested messages.
+    pub fn proto_schema(&self) -> String {
+        let mut messages = Vec::new();
+        collect_records(self, &mut messages);
+
+        let mut proto = format!("syntax = \"proto3\";\n\npackage {PROTO_PACKAGE};\n");
+        for message in messages {
+            proto.push('\n');
+            writeln!(proto, "message {} {{", message.name).ok();
+            for field in message.fields {
+                writeln!(
+                    proto,
+                    "  {} {} = {};",
+                    proto_type(&field.ty),
+                    field.name,
+                    field.number
+                )
+                .ok();
+            }
+            proto.push_str("}\n");
+        }
+
+        proto
+    }
+
+    /// Checks that the value has exactly the fields declared in this schema with matching types.
+    ///
+    /// This performs the same checks as encoding without writing the message. Use this in tests to
+    /// ensure that schemas match the Rust types they describe.
+    pub fn validate(&self, value: &Value) -> Result<(), EncodingError> {
+        validate_record(self, value, "")
+    }
+}
+
+fn avro_type(ty: &FieldType, defined: &mut BTreeSet<&'static str>) -> Value {
+    match ty {
+        FieldType::Bool => json!("boolean"),
+        FieldType::Long => json!("long"),
+        FieldType::Double => json!("double"),
+        FieldType::String => json!("string"),
+        FieldType::Bytes => json!("bytes"),
+        FieldType::Optional(inner) => json!(["null", avro_type(inner, defined)]),
+        FieldType::Array(inner) => json!({"type": "array", "items": avro_type(inner, defined)}),
+        FieldType::Map(inner) => json!({"type": "map", "values": avro_type(inner, defined)}),
+        FieldType::Record(schema) => schema.avro_record(defined),
+    }
+}
+
+fn proto_type(ty: &FieldType) -> String {
+    match ty {
+        FieldType::Bool => "bool".to_owned(),
+        FieldType::Long => "int64".to_owned(),
+        FieldType::Double => "double".to_owned(),
+        FieldType::String => "string".to_owned(),
+        FieldType::Bytes => "bytes".to_owned(),
+        // Only scalars can be marked optional, absent lists are empty and messages have presence.
+        FieldType::Optional(
+            inner @ (FieldType::Array(_) | FieldType::Map(_) | FieldType::Record(_)),
+        ) => proto_type(inner),
+        FieldType::Optional(inner) => format!("optional {}", proto_type(inner)),
+        FieldType::Array(inner) => format!("repeated {}", proto_type(inner)),
+        FieldType::Map(inner) => format!("map<string, {}>", proto_type(inner)),
+        FieldType::Record(schema) => schema.name.to_owned(),
+    }
+}
+
+fn collect_records<'a>(schema: &'a MessageSchema, messages: &mut Vec<&'a MessageSchema>) {
+    if messages.iter().any(|m| m.name == schema.name) {
+        return;
+    }
+
+    messages.push(schema);
+    for field in schema.fields {
+        let mut ty = &field.ty;
+        while let FieldType::Optional(inner) | FieldType::Array(inner) | FieldType::Map(inner) = ty
+        {
+            ty = inner;
+        }
+        if let FieldType::Record(nested) = ty {
+            collect_records(nested, messages);
+        }
+    }
+}
+
+fn field_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+fn record_fields<'a>(
+    value: &'a Value,
+    path: &str,
+) -> Result<&'a Map<String, Value>, EncodingError> {
+    value
+        .as_object()
+        .ok_or_else(|| EncodingError::InvalidField(path.to_owned()))
+}
+
+/// Returns the fields of a record, failing if it contains fields not declared in the schema.
+fn schema_fields<'a>(
+    schema: &MessageSchema,
+    value: &'a Value,
+    path: &str,
+) -> Result<&'a Map<String, Value>, EncodingError> {
+    let object = record_fields(value, path)?;
+
+    for key in object.keys() {
+        if !schema.fields.iter().any(|field| field.name == key) {
+            return Err(EncodingError::UnknownField(field_path(path, key)));
+        }
+    }
+
+    Ok(object)
+}
+
+fn validate_record(schema: &MessageSchema, value: &Value, path: &str) -> Result<(), EncodingError> {
+    let object = schema_fields(schema, value, path)?;
+
+    for field in schema.fields {
+        let path = field_path(path, field.name);
+        validate_value(&field.ty, object.get(field.name), &path)?;
+    }
+
+    Ok(())
+}
+
+fn validate_value(ty: &FieldType, value: Option<&Value>, path: &str) -> Result<(), EncodingError> {
+    let value = match (ty, value) {
+        (FieldType::Optional(_), None | Some(Value::Null)) => return Ok(()),
+        (_, None | Some(Value::Null)) => return Err(EncodingError::MissingField(path.to_owned())),
+        (_, Some(value)) => value,
+    };
+
+    match ty {
+        FieldType::Optional(inner) => validate_value(inner, Some(value), path),
+        FieldType::Array(inner) => {
+            let items = value
+                .as_array()
+                .ok_or_else(|| EncodingError::InvalidField(path.to_owned()))?;
+            items
+                .iter()
+                .try_for_each(|item| validate_value(inner, Some(item), path))
+        }
+        FieldType::Map(inner) => {
+            let object = record_fields(value, path)?;
+            object
+                .values()
+                .try_for_each(|item| validate_value(inner, Some(item), path))
+        }
+        FieldType::Record(schema) => validate_record(schema, value, path),
+        scalar => {
+            let mut buf = Vec::new();
+            write_avro_scalar(&mut buf, scalar, value, path)
+        }
+    }
+}
+
+/// Binary format of schema-encoded messages.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaFormat {
+    /// Apache Avro binary encoding.
+    Avro,
+    /// Protocol Buffers binary encoding.
+    Protobuf,
+}
+
+/// Schema-based encoding of the messages in a topic.
+///
+/// Encoded messages use the wire format of the schema registry: a zero magic byte followed by
+/// the big-endian schema ID. Protobuf messages additionally contain the index of the message type,
+/// which is always the first message in the generated schema.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TopicEncoding {
+    /// The binary format.
+    pub format: SchemaFormat,
+    /// The ID of the schema in the schema registry.
+    pub schema_id: u32,
+}
+
+impl TopicEncoding {
+    /// Encodes the JSON representation of a message with the given schema.
+    pub fn encode(&self, schema: &MessageSchema, value: &Value) -> Result<Vec<u8>, EncodingError> {
+        let mut buf = vec![MAGIC_BYTE];
+        buf.extend_from_slice(&self.schema_id.to_be_bytes());
+
+        match self.format {
+            SchemaFormat::Avro => write_avro_record(&mut buf, schema, value, "")?,
+            SchemaFormat::Protobuf => {
+                // Message indexes: a single zero refers to the first message in the schema.
+                buf.push(0);
+                write_proto_record(&mut buf, schema, value, "")?;
+            }
+        }
+
+        Ok(buf)
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_avro_long(buf: &mut Vec<u8>, value: i64) {
+    write_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_avro_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_avro_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+fn as_long(value: &Value, path: &str) -> Result<i64, EncodingError> {
+    value
+        .as_i64()
+        .or_else(|| value.as_u64().map(|v| v as i64))
+        .ok_or_else(|| EncodingError::InvalidField(path.to_owned()))
+}
+
+fn as_bytes(value: &Value, path: &str) -> Result<Vec<u8>, EncodingError> {
+    match value {
+        Value::String(string) => Ok(string.as_bytes().to_vec()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_u64().and_then(|b| u8::try_from(b).ok()))
+            .collect::<Option<_>>()
+            .ok_or_else(|| EncodingError::InvalidField(path.to_owned())),
+        _ => Err(EncodingError::InvalidField(path.to_owned())),
+    }
+}
+
+fn write_avro_scalar(
+    buf: &mut Vec<u8>,
+    ty: &FieldType,
+    value: &Value,
+    path: &str,
+) -> Result<(), EncodingError> {
+    let invalid = || EncodingError::InvalidField(path.to_owned());
+
+    match ty {
+        FieldType::Bool => buf.push(value.as_bool().ok_or_else(invalid)? as u8),
+        FieldType::Long => write_avro_long(buf, as_long(value, path)?),
+        FieldType::Double => {
+            let double = value.as_f64().ok_or_else(invalid)?;
+            buf.extend_from_slice(&double.to_le_bytes());
+        }
+        FieldType::String => write_avro_bytes(buf, value.as_str().ok_or_else(invalid)?.as_bytes()),
+        FieldType::Bytes => write_avro_bytes(buf, &as_bytes(value, path)?),
+        _ => return Err(invalid()),
+    }
+
+    Ok(())
+}
+
+fn write_avro_record(
+    buf: &mut Vec<u8>,
+    schema: &MessageSchema,
+    value: &Value,
+    path: &str,
+) -> Result<(), EncodingError> {
+    let object = schema_fields(schema, value, path)?;
+    for field in schema.fields {
+        let path = field_path(path, field.name);
+        write_avro_value(buf, &field.ty, object.get(field.name), &path)?;
+    }
+    Ok(())
+}
+
+fn write_avro_value(
+    buf: &mut Vec<u8>,
+    ty: &FieldType,
+    value: Option<&Value>,
+    path: &str,
+) -> Result<(), EncodingError> {
+    let value = match (ty, value) {
+        (FieldType::Optional(_), None | Some(Value::Null)) => {
+            // Index of the `null` branch in the union.
+            write_avro_long(buf, 0);
+            return Ok(());
+        }
+        (_, None | Some(Value::Null)) => return Err(EncodingError::MissingField(path.to_owned())),
+        (_, Some(value)) => value,
+    };
+
+    match ty {
+        FieldType::Optional(inner) => {
+            write_avro_long(buf, 1);
+            write_avro_value(buf, inner, Some(value), path)
+        }
+        FieldType::Array(inner) => {
+            let items = value
+                .as_array()
+                .ok_or_else(|| EncodingError::InvalidField(path.to_owned()))?;
+            if !items.is_empty() {
+                write_avro_long(buf, items.len() as i64);
+                for item in items {
+                    write_avro_value(buf, inner, Some(item), path)?;
+                }
+            }
+            write_avro_long(buf, 0);
+            Ok(())
+        }
+        FieldType::Map(inner) => {
+            let object = record_fields(value, path)?;
+            if !object.is_empty() {
+                write_avro_long(buf, object.len() as i64);
+                for (key, item) in object {
+                    write_avro_bytes(buf, key.as_bytes());
+                    write_avro_value(buf, inner, Some(item), path)?;
+                }
+            }
+            write_avro_long(buf, 0);
+            Ok(())
+        }
+        FieldType::Record(schema) => write_avro_record(buf, schema, value, path),
+        scalar => write_avro_scalar(buf, scalar, value, path),
+    }
+}
+
+/// Protobuf wire type for varints.
+const WIRE_VARINT: u64 = 0;
+/// Protobuf wire type for 64-bit values.
+const WIRE_FIXED64: u64 = 1;
+/// Protobuf wire type for length-delimited values.
+const WIRE_LEN: u64 = 2;
+
+fn write_proto_key(buf: &mut Vec<u8>, number: u32, wire_type: u64) {
+    write_varint(buf, (u64::from(number) << 3) | wire_type);
+}
+
+fn write_proto_len(buf: &mut Vec<u8>, number: u32, bytes: &[u8]) {
+    write_proto_key(buf, number, WIRE_LEN);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Writes a scalar without key. Returns `false` if the type is not a packable scalar.
+fn write_proto_packed(
+    buf: &mut Vec<u8>,
+    ty: &FieldType,
+    value: &Value,
+    path: &str,
+) -> Result<bool, EncodingError> {
+    let invalid = || EncodingError::InvalidField(path.to_owned());
+
+    match ty {
+        FieldType::Bool => write_varint(buf, value.as_bool().ok_or_else(invalid)? as u64),
+        FieldType::Long => write_varint(buf, as_long(value, path)? as u64),
+        FieldType::Double => {
+            let double = value.as_f64().ok_or_else(invalid)?;
+            buf.extend_from_slice(&double.to_le_bytes());
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+fn write_proto_record(
+    buf: &mut Vec<u8>,
+    schema: &MessageSchema,
+    value: &Value,
+    path: &str,
+) -> Result<(), EncodingError> {
+    let object = schema_fields(schema, value, path)?;
+    for field in schema.fields {
+        let path = field_path(path, field.name);
+        write_proto_field(buf, field.number, &field.ty, object.get(field.name), &path)?;
+    }
+    Ok(())
+}
+
+fn write_proto_field(
+    buf: &mut Vec<u8>,
+    number: u32,
+    ty: &FieldType,
+    value: Option<&Value>,
+    path: &str,
+) -> Result<(), EncodingError> {
+    let value = match (ty, value) {
+        (FieldType::Optional(_), None | Some(Value::Null)) => return Ok(()),
+        (_, None | Some(Value::Null)) => return Err(EncodingError::MissingField(path.to_owned())),
+        (_, Some(value)) => value,
+    };
+
+    let invalid = || EncodingError::InvalidField(path.to_owned());
+
+    match ty {
+        FieldType::Optional(inner) => write_proto_field(buf, number, inner, Some(value), path)?,
+        FieldType::Bool | FieldType::Long => {
+            write_proto_key(buf, number, WIRE_VARINT);
+            write_proto_packed(buf, ty, value, path)?;
+        }
+        FieldType::Double => {
+            write_proto_key(buf, number, WIRE_FIXED64);
+            write_proto_packed(buf, ty, value, path)?;
+        }
+        FieldType::String => {
+            write_proto_len(buf, number, value.as_str().ok_or_else(invalid)?.as_bytes())
+        }
+        FieldType::Bytes => write_proto_len(buf, number, &as_bytes(value, path)?),
+        FieldType::Array(inner) => {
+            let items = value.as_array().ok_or_else(invalid)?;
+            let mut packed = Vec::new();
+            for item in items {
+                if !write_proto_packed(&mut packed, inner, item, path)? {
+                    write_proto_field(buf, number, inner, Some(item), path)?;
+                }
+            }
+            if !packed.is_empty() {
+                write_proto_len(buf, number, &packed);
+            }
+        }
+        FieldType::Map(inner) => {
+            for (key, item) in record_fields(value, path)? {
+                let mut entry = Vec::new();
+                write_proto_len(&mut entry, 1, key.as_bytes());
+                write_proto_field(&mut entry, 2, inner, Some(item), path)?;
+                write_proto_len(buf, number, &entry);
+            }
+        }
+        FieldType::Record(schema) => {
+            let mut nested = Vec::new();
+            write_proto_record(&mut nested, schema, value, path)?;
+            write_proto_len(buf, number, &nested);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static POINT: MessageSchema = MessageSchema {
+        name: "Point",
+        fields: &[
+            SchemaField::new("x", 1, FieldType::Long),
+            SchemaField::new("label", 2, FieldType::Optional(&FieldType::String)),
+        ],
+    };
+
+    static SHAPE: MessageSchema = MessageSchema {
+        name: "Shape",
+        fields: &[
+            SchemaField::new("id", 1, FieldType::Long),
+            SchemaField::new("weights", 2, FieldType::Array(&FieldType::Double)),
+            SchemaField::new("tags", 3, FieldType::Map(&FieldType::String)),
+            SchemaField::new("points", 4, FieldType::Array(&FieldType::Record(&POINT))),
+            SchemaField::new("origin", 5, FieldType::Optional(&FieldType::Record(&POINT))),
+            SchemaField::new("payload", 6, FieldType::Bytes),
+        ],
+    };
+
+    fn shape() -> Value {
+        json!({
+            "id": 1,
+            "weights": [0.5],
+            "tags": {"a": "b"},
+            "points": [{"x": -1, "label": "p"}],
+            "origin": null,
+            "payload": [1, 2],
+        })
+    }
+
+    #[test]
+    fn test_avro_schema() {
+        let expected = json!({
+            "type": "record",
+            "name": "Shape",
+            "namespace": "io.sentry.relay",
+            "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "weights", "type": {"type": "array", "items": "double"}},
+                {"name": "tags", "type": {"type": "map", "values": "string"}},
+                {"name": "points", "type": {"type": "array", "items": {
+                    "type": "record",
+                    "name": "Point",
+                    "namespace": "io.sentry.relay",
+                    "fields": [
+                        {"name": "x", "type": "long"},
+                        {"name": "label", "type": ["null", "string"], "default": null},
+                    ],
+                }}},
+                {"name": "origin", "type": ["null", "Point"], "default": null},
+                {"name": "payload", "type": "bytes"},
+            ],
+        });
+
+        assert_eq!(SHAPE.avro_schema(), expected);
+    }
+
+    #[test]
+    fn test_proto_schema() {
+        let expected = r#"syntax = "proto3";
+
+package sentry.relay;
+
+message Shape {
+  int64 id = 1;
+  repeated double weights = 2;
+  map<string, string> tags = 3;
+  repeated Point points = 4;
+  Point origin = 5;
+  bytes payload = 6;
+}
+
+message Point {
+  int64 x = 1;
+  optional string label = 2;
+}
+"#;
+
+        assert_eq!(SHAPE.proto_schema(), expected);
+    }
+
+    #[test]
+    fn test_encode_avro() {
+        let encoding = TopicEncoding {
+            format: SchemaFormat::Avro,
+            schema_id: 7,
+        };
+
+        let encoded = encoding.encode(&SHAPE, &shape()).unwrap();
+        let expected: &[u8] = &[
+            0, 0, 0, 0, 7, // magic byte and schema id
+            2, // id = 1
+            2, 0, 0, 0, 0, 0, 0, 0xe0, 0x3f, 0, // weights = [0.5]
+            2, 2, b'a', 2, b'b', 0, // tags = {"a": "b"}
+            2, 1, 2, 2, b'p', 0, // points = [{"x": -1, "label": "p"}]
+            0, // origin = null
+            4, 1, 2, // payload = [1, 2]
+        ];
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_encode_protobuf() {
+        let encoding = TopicEncoding {
+            format: SchemaFormat::Protobuf,
+            schema_id: 7,
+        };
+
+        let encoded = encoding.encode(&SHAPE, &shape()).unwrap();
+        let mut expected = vec![
+            0, 0, 0, 0, 7, // magic byte and schema id
+            0, // message index
+            0x08, 1, // id = 1
+            0x12, 8, 0, 0, 0, 0, 0, 0, 0xe0, 0x3f, // weights = [0.5], packed
+            0x1a, 6, 0x0a, 1, b'a', 0x12, 1, b'b', // tags = {"a": "b"}
+            0x22, 14, 0x08, // points = [{"x": -1, ...
+        ];
+        expected.extend_from_slice(&[0xff; 9]);
+        expected.extend_from_slice(&[0x01, 0x12, 1, b'p']); // ... "label": "p"}]
+        expected.extend_from_slice(&[0x32, 2, 1, 2]); // payload = [1, 2]
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_encode_missing_field() {
+        let encoding = TopicEncoding {
+            format: SchemaFormat::Avro,
+            schema_id: 7,
+        };
+
+        let mut value = shape();
+        value["points"][0]["x"] = Value::Null;
+        let result = encoding.encode(&SHAPE, &value);
+        assert!(matches!(result, Err(EncodingError::MissingField(path)) if path == "points.x"));
+    }
+
+    #[test]
+    fn test_encode_unknown_field() {
+        for format in [SchemaFormat::Avro, SchemaFormat::Protobuf] {
+            let encoding = TopicEncoding {
+                format,
+                schema_id: 7,
+            };
+
+            let mut value = shape();
+            value["extra"] = json!(true);
+            let result = encoding.encode(&SHAPE, &value);
+            assert!(matches!(result, Err(EncodingError::UnknownField(path)) if path == "extra"));
+
+            let mut value = shape();
+            value["points"][0]["y"] = Value::Null;
+            let result = encoding.encode(&SHAPE, &value);
+            assert!(matches!(result, Err(EncodingError::UnknownField(path)) if path == "points.y"));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(SHAPE.validate(&shape()).is_ok());
+
+        let mut value = shape();
+        value["extra"] = json!(true);
+        let result = SHAPE.validate(&value);
+        assert!(matches!(result, Err(EncodingError::UnknownField(path)) if path == "extra"));
+
+        let mut value = shape();
+        value["id"] = json!("1");
+        let result = SHAPE.validate(&value);
+        assert!(matches!(result, Err(EncodingError::InvalidField(path)) if path == "id"));
+    }
+}
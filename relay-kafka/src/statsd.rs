@@ -8,12 +8,21 @@ pub enum KafkaCounters {
     /// accept the requests over a certain size, which is usually due to invalid or inconsistent
     /// broker/producer configurations.
     ProcessingProduceError,
+
+    /// Number of messages written to the fallback queue on disk because they could not be
+    /// delivered to Kafka.
+    FallbackStored,
+
+    /// Number of messages from the fallback queue that were sent to Kafka again.
+    FallbackDrained,
 }
 
 impl CounterMetric for KafkaCounters {
     fn name(&self) -> &'static str {
         match self {
             Self::ProcessingProduceError => "processing.produce.error",
+            Self::FallbackStored => "processing.produce.fallback_stored",
+            Self::FallbackDrained => "processing.produce.fallback_drained",
         }
     }
 }
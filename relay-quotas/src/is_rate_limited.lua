@@ -5,11 +5,24 @@
 --  * [string] Key of the counter.
 --  * [string] Key of the refund counter.
 --
--- ``ARGV`` (4 per quota):
+-- ``ARGV`` (7 per quota):
 --  * [number]  Quota limit. Can be ``-1`` for unlimited quotas.
 --  * [number]  Absolute Expiration time as Unix timestamp (secs since 1.1.1970 ) for the key.
 --  * [number]  Quantity to increment the quota by, or ``0`` to check without incrementing.
 --  * [boolean] If set to `true` - reject only if the previous update already reached the limit.
+--  * [number]  Burst size of a token bucket, or ``-1`` for fixed window quotas.
+--  * [number]  Refill rate of the token bucket in tokens per second.
+--  * [number]  The current time as Unix timestamp.
+--
+-- Fixed window quotas count the consumed quantity in the key. Token bucket quotas
+-- store the remaining ``tokens`` and the ``ts`` of the last update in a hash at the
+-- key instead, and ignore the refund key. The bucket starts full and refills up to
+-- the burst size.
+--
+-- Counter keys have the layout ``quota:<id>{<org>}<scope id>:<slot>``. The scope id
+-- is omitted for organization quotas. Release and environment quotas use a hash of
+-- the release or environment name as scope id, and user quotas use a hash of the
+-- user identifier.
 --
 -- For example, to check the following two quotas each with a timeout of 10 minutes from now:
 --  * Key ``foo``, refund key ``foo_refund``, limit ``10``; quantity ``5``
@@ -18,7 +31,7 @@
 -- Send these values:
 --
 --     KEYS = {"foo", "foo_refund", "bar", "bar_refund"}
---     ARGV = {10, 600 + now(), 5, false, 20, 600 + now(), 1, true}
+--     ARGV = {10, 600 + now(), 5, false, -1, 0, now(), 20, 600 + now(), 1, true, -1, 0, now()}
 --
 -- The script applies the following logic:
 --  * If all checks pass, the item is accepted and the counters for all quotas
@@ -27,25 +40,59 @@
 --    unchanged.
 --
 -- The result is a Lua table/array (Redis multi bulk reply) that specifies
--- whether or not the item was *rejected* based on the provided limit.
+-- whether or not the item was *rejected* based on the provided limit. Each
+-- entry is ``0`` if the item was accepted. Otherwise, it is ``1`` for fixed
+-- window quotas, and the number of seconds until enough tokens have been
+-- refilled for token bucket quotas.
 assert(#KEYS % 2 == 0, "there must be 2 keys per quota")
-assert(#ARGV % 4 == 0, "there must be 4 args per quota")
-assert(#KEYS / 2 == #ARGV / 4, "incorrect number of keys and arguments provided")
+assert(#ARGV % 7 == 0, "there must be 7 args per quota")
+assert(#KEYS / 2 == #ARGV / 7, "incorrect number of keys and arguments provided")
 
 
 local results = {}
+local tokens = {}
 local failed = false
 local num_quotas = #KEYS / 2
 for i=0, num_quotas - 1 do
     local k = i * 2 + 1
-    local v = i * 4 + 1
+    local v = i * 7 + 1
 
     local limit = tonumber(ARGV[v])
     local quantity = tonumber(ARGV[v+2])
     local over_accept_once = ARGV[v+3]
+    local burst = tonumber(ARGV[v+4])
     local rejected = false
+    if burst >= 0 then
+        local rate = tonumber(ARGV[v+5])
+        local now = tonumber(ARGV[v+6])
+        local state = redis.call('HMGET', KEYS[k], 'tokens', 'ts')
+        local available = tonumber(state[1]) or burst
+        local elapsed = math.max(0, now - (tonumber(state[2]) or now))
+        available = math.min(burst, available + elapsed * rate)
+        tokens[i + 1] = available
+
+        -- Analogous to fixed windows, over_accept_once and checks with quantity 0 only
+        -- reject once the bucket is empty. Otherwise, the bucket must hold enough tokens.
+        local required = quantity
+        if quantity == 0 or over_accept_once == '1' then
+            rejected = available <= 0
+            required = 0
+        else
+            rejected = available < quantity
+        end
+
+        -- Report the seconds until the bucket holds enough tokens again.
+        if rejected then
+            local seconds = (required - available) / rate
+            if required == 0 then
+                seconds = math.floor(seconds) + 1
+            else
+                seconds = math.ceil(seconds)
+            end
+            rejected = math.max(1, seconds)
+        end
     -- limit=-1 means "no limit"
-    if limit >= 0 then
+    elseif limit >= 0 then
         local consumed = (redis.call('GET', KEYS[k]) or 0) - (redis.call('GET', KEYS[k + 1]) or 0)
         -- Without over_accept_once, we never increment past the limit. if quantity is 0, check instead if we reached limit.
         -- With over_accept_once, we only reject if the previous update already reached the limit. 
@@ -60,8 +107,13 @@ for i=0, num_quotas - 1 do
         end
     end
 
+    if rejected == true then
+        rejected = 1
+    end
     if rejected then
         failed = true
+    else
+        rejected = 0
     end
     results[i + 1] = rejected
 end
@@ -69,10 +121,15 @@ end
 if not failed then
     for i=0, num_quotas - 1 do
         local k = i * 2 + 1
-        local v = i * 4 + 1
+        local v = i * 7 + 1
 
         if tonumber(ARGV[v + 2]) > 0 then
-            redis.call('INCRBY', KEYS[k], ARGV[v + 2])
+            if tokens[i + 1] then
+                local remaining = tokens[i + 1] - tonumber(ARGV[v + 2])
+                redis.call('HSET', KEYS[k], 'tokens', remaining, 'ts', ARGV[v + 6])
+            else
+                redis.call('INCRBY', KEYS[k], ARGV[v + 2])
+            end
             redis.call('EXPIREAT', KEYS[k], ARGV[v + 1])
         end
     end
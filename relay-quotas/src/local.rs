@@ -0,0 +1,835 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use relay_common::UnixTimestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::quota::{ItemScoping, Quantity, Quota, QuotaScope};
+use crate::rate_limit::{RateLimit, RateLimits, RetryAfter};
+use crate::REJECT_ALL_SECS;
+
+/// Identifies a tracked quota counter.
+///
+/// This mirrors the key of the Redis rate limiter without the slot, which is stored in the
+/// counter instead.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+struct CounterKey {
+    id: String,
+    scope: QuotaScope,
+    organization_id: u64,
+    scope_id: Option<u64>,
+    window: u64,
+}
+
+impl CounterKey {
+    /// The offset of window starts in seconds, which spreads slots across organizations.
+    fn shift(&self) -> u64 {
+        self.organization_id % self.window
+    }
+
+    /// Returns the slot for the given timestamp.
+    fn slot(&self, timestamp: UnixTimestamp) -> u64 {
+        timestamp.as_secs().saturating_sub(self.shift()) / self.window
+    }
+
+    /// Returns the start of the given slot.
+    fn slot_start(&self, slot: u64) -> UnixTimestamp {
+        UnixTimestamp::from_secs(slot * self.window + self.shift())
+    }
+}
+
+/// Consumed quantities of a quota in the current and previous window.
+#[derive(Debug, Default)]
+struct Counter {
+    /// The slot of the current window.
+    slot: u64,
+    /// Quantity consumed in the current window.
+    current: u64,
+    /// Quantity consumed in the previous window.
+    previous: u64,
+}
+
+impl Counter {
+    /// Moves the counter to the given slot.
+    fn advance(&mut self, slot: u64) {
+        if slot <= self.slot {
+            return;
+        }
+
+        self.previous = if slot == self.slot + 1 {
+            self.current
+        } else {
+            0
+        };
+        self.current = 0;
+        self.slot = slot;
+    }
+
+    /// Returns the consumed quantity in the sliding window ending at `timestamp`.
+    ///
+    /// The previous window is weighted by the share it overlaps with the sliding window.
+    fn consumed(&self, key: &CounterKey, timestamp: UnixTimestamp) -> u64 {
+        let start = key.slot_start(self.slot).as_secs();
+        let elapsed = timestamp.as_secs().saturating_sub(start).min(key.window);
+        let overlap = self.previous as u128 * (key.window - elapsed) as u128 / key.window as u128;
+        self.current.saturating_add(overlap as u64)
+    }
+
+    /// Returns the seconds until the consumed quantity in the sliding window drops to `target`.
+    ///
+    /// This assumes that no quantity is consumed in the meanwhile. If `target` is `None`, the
+    /// quantity can never be accepted and this returns the time until both windows have passed.
+    fn retry_after(&self, key: &CounterKey, target: Option<u64>, timestamp: UnixTimestamp) -> u64 {
+        let window = key.window;
+        let start = key.slot_start(self.slot).as_secs();
+        let end = start + window;
+
+        // The seconds into a window at which the overlap of `consumed` drops to `allowed`. This
+        // inverts the rounding of `consumed`.
+        let decay = |consumed: u64, allowed: u64| {
+            let remaining = ((allowed as u128 + 1) * window as u128 - 1) / consumed.max(1) as u128;
+            window.saturating_sub(remaining.min(window as u128) as u64)
+        };
+
+        let retry_at = match target {
+            Some(target) if self.current <= target => {
+                start + decay(self.previous, target - self.current)
+            }
+            Some(target) => end + decay(self.current, target),
+            None => end + window,
+        };
+
+        retry_at.saturating_sub(timestamp.as_secs())
+    }
+}
+
+/// Remaining tokens of a quota with a burst allowance.
+#[derive(Debug)]
+struct Bucket {
+    /// The maximum number of tokens.
+    burst: f64,
+    /// Tokens refilled per second.
+    rate: f64,
+    /// Remaining tokens at the time of the last update, which may be negative.
+    tokens: f64,
+    /// The time of the last update.
+    updated: UnixTimestamp,
+}
+
+impl Bucket {
+    /// Returns the tokens available at `timestamp`.
+    fn available(&self, timestamp: UnixTimestamp) -> f64 {
+        let elapsed = timestamp.as_secs().saturating_sub(self.updated.as_secs());
+        self.burst.min(self.tokens + elapsed as f64 * self.rate)
+    }
+}
+
+/// Quota consumed by a single Relay within one window.
+///
+/// Returned by [`LocalRateLimiter::flush_usage`] to report usage to the upstream.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct QuotaUsage {
+    /// The identifier of the quota.
+    pub id: String,
+    /// The scope of the quota.
+    pub scope: QuotaScope,
+    /// The organization that consumed the quota.
+    pub organization_id: u64,
+    /// The identifier of the quota's scope, if it is not organization-wide.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope_id: Option<u64>,
+    /// The size of the quota window in seconds.
+    pub window: u64,
+    /// The slot of the window, which starts at `slot * window + organization_id % window`.
+    pub slot: u64,
+    /// The consumed quantity.
+    pub quantity: u64,
+}
+
+#[derive(Debug, Default)]
+struct LocalState {
+    counters: BTreeMap<CounterKey, Counter>,
+    buckets: BTreeMap<CounterKey, Bucket>,
+    /// Quantities consumed since the last flush per counter and slot.
+    usage: BTreeMap<(CounterKey, u64), u64>,
+}
+
+/// A rate limiter that tracks quotas in memory of this process.
+///
+/// This is the counterpart of `RedisRateLimiter` for Relays that do not have access to the shared
+/// Redis instance. It tracks consumption in sliding windows, which approximates the fixed windows
+/// of Redis but avoids a burst of accepted data at the start of every window. Since counters are
+/// not shared, quotas are enforced per Relay instance. Quotas with a `burst` are enforced with a
+/// token bucket instead. Counters of elapsed windows must be removed periodically with
+/// [`prune`](Self::prune). To account for the per-instance counters, consumed quota can be tracked
+/// with [`track_usage`](Self::track_usage) and reported to the upstream.
+///
+/// Clones of this rate limiter share the same counters.
+#[derive(Clone, Debug, Default)]
+pub struct LocalRateLimiter {
+    state: Arc<Mutex<LocalState>>,
+    max_limit: Option<u64>,
+    track_usage: bool,
+}
+
+impl LocalRateLimiter {
+    /// Creates a new `LocalRateLimiter` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum rate limit in seconds.
+    ///
+    /// By default, this rate limiter will return rate limits based on the quotas' `window` fields.
+    /// If a maximum rate limit is set, this limit is bounded.
+    pub fn max_limit(mut self, max_limit: Option<u64>) -> Self {
+        self.max_limit = max_limit;
+        self
+    }
+
+    /// Tracks consumed quota until it is taken with [`flush_usage`](Self::flush_usage).
+    ///
+    /// By default, consumption is only counted to enforce quotas.
+    pub fn track_usage(mut self, track_usage: bool) -> Self {
+        self.track_usage = track_usage;
+        self
+    }
+
+    /// Checks whether any of the quotas in effect for the given project and project key has been
+    /// exceeded and records consumption of the quota.
+    ///
+    /// This has the same semantics as `RedisRateLimiter::is_rate_limited`: The quantity is only
+    /// counted if none of the quotas is exceeded, `over_accept_once` accepts the item that
+    /// exceeds a quota for the first time, and a `quantity` of `0` checks whether a quota limit
+    /// has been reached without incrementing it. Each quota counts the quantity in its own unit.
+    pub fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: Quantity,
+        over_accept_once: bool,
+    ) -> RateLimits {
+        self.is_rate_limited_at(
+            quotas,
+            item_scoping,
+            quantity,
+            over_accept_once,
+            UnixTimestamp::now(),
+        )
+    }
+
+    fn is_rate_limited_at(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: Quantity,
+        over_accept_once: bool,
+        timestamp: UnixTimestamp,
+    ) -> RateLimits {
+        let mut rate_limits = RateLimits::new();
+        let mut tracked_quotas = Vec::new();
+
+        for quota in quotas {
+            if !quota.matches(item_scoping) {
+                // Silently skip all quotas that do not apply to this item.
+            } else if quota.limit == Some(0) {
+                // A zero-sized quota is strongest. Do not increment any counters, as one quota has
+                // reached capacity (this is how regular quotas behave as well).
+                let retry_after = self.retry_after(REJECT_ALL_SECS);
+                rate_limits.add(RateLimit::from_quota(quota, &item_scoping, retry_after));
+            } else if let (Some(id), Some(window @ 1..)) = (quota.id.as_deref(), quota.window) {
+                let key = CounterKey {
+                    id: id.to_owned(),
+                    scope: quota.scope,
+                    organization_id: item_scoping.organization_id,
+                    scope_id: match quota.scope {
+                        QuotaScope::Organization => None,
+                        scope => item_scoping.scope_id(scope),
+                    },
+                    window,
+                };
+                let quantity = quota.quantity(item_scoping.category, quantity) as u64;
+                tracked_quotas.push((quota, key, quantity));
+            }
+            // Quotas without id or window cannot be tracked. They are skipped for
+            // forward-compatibility.
+        }
+
+        if tracked_quotas.is_empty() || rate_limits.is_limited() {
+            return rate_limits;
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let LocalState {
+            counters,
+            buckets,
+            usage,
+        } = &mut *state;
+
+        for &(quota, ref key, quantity) in &tracked_quotas {
+            let slot = key.slot(timestamp);
+            let counter = counters.entry(key.clone()).or_insert_with(|| Counter {
+                slot,
+                ..Default::default()
+            });
+            counter.advance(slot);
+
+            if let (Some(burst), Some(rate)) = (quota.burst, quota.refill_rate()) {
+                let bucket = buckets.entry(key.clone()).or_insert_with(|| Bucket {
+                    burst: burst as f64,
+                    rate,
+                    tokens: burst as f64,
+                    updated: timestamp,
+                });
+
+                // Quota changes apply to existing buckets.
+                bucket.burst = burst as f64;
+                bucket.rate = rate;
+
+                if let Some(seconds) = bucket_retry(bucket, quantity, over_accept_once, timestamp) {
+                    let retry_after = self.retry_after(seconds);
+                    rate_limits.add(RateLimit::from_quota(quota, &item_scoping, retry_after));
+                }
+
+                continue;
+            }
+
+            let Some(limit) = quota.limit else {
+                continue;
+            };
+
+            // The maximum consumed quantity at which the item is accepted.
+            let target = if quantity == 0 || over_accept_once {
+                limit.checked_sub(1)
+            } else {
+                limit.checked_sub(quantity)
+            };
+
+            if target.map_or(true, |target| counter.consumed(key, timestamp) > target) {
+                let seconds = counter.retry_after(key, target, timestamp).max(1);
+                let retry_after = self.retry_after(seconds);
+                rate_limits.add(RateLimit::from_quota(quota, &item_scoping, retry_after));
+            }
+        }
+
+        // Only count the quantity if no quota has been exceeded.
+        if !rate_limits.is_limited() {
+            for &(_, ref key, quantity) in &tracked_quotas {
+                if quantity == 0 {
+                    continue;
+                }
+
+                if let Some(counter) = counters.get_mut(key) {
+                    counter.current = counter.current.saturating_add(quantity);
+
+                    if self.track_usage {
+                        let consumed = usage.entry((key.clone(), counter.slot)).or_default();
+                        *consumed = consumed.saturating_add(quantity);
+                    }
+                }
+
+                if let Some(bucket) = buckets.get_mut(key) {
+                    bucket.tokens = bucket.available(timestamp) - quantity as f64;
+                    bucket.updated = timestamp;
+                }
+            }
+        }
+
+        rate_limits
+    }
+
+    /// Removes counters of elapsed windows and token buckets that have been refilled completely.
+    pub fn prune(&self) {
+        self.prune_at(UnixTimestamp::now())
+    }
+
+    fn prune_at(&self, timestamp: UnixTimestamp) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let LocalState {
+            counters, buckets, ..
+        } = &mut *state;
+
+        buckets.retain(|_, bucket| bucket.available(timestamp) < bucket.burst);
+
+        // The counter is still needed as long as it covers the current or previous window.
+        counters.retain(|key, counter| counter.slot + 1 >= key.slot(timestamp));
+    }
+
+    /// Returns quota consumed since the last flush.
+    ///
+    /// Usage is only tracked if enabled with [`track_usage`](Self::track_usage). It is reported
+    /// once per quota, scope and window slot.
+    pub fn flush_usage(&self) -> Vec<QuotaUsage> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::take(&mut state.usage)
+            .into_iter()
+            .map(|((key, slot), quantity)| QuotaUsage {
+                id: key.id,
+                scope: key.scope,
+                organization_id: key.organization_id,
+                scope_id: key.scope_id,
+                window: key.window,
+                slot,
+                quantity,
+            })
+            .collect()
+    }
+
+    /// Creates a rate limit bounded by `max_limit`.
+    fn retry_after(&self, mut seconds: u64) -> RetryAfter {
+        if let Some(max_limit) = self.max_limit {
+            seconds = std::cmp::min(seconds, max_limit);
+        }
+
+        RetryAfter::from_secs(seconds)
+    }
+}
+
+/// Returns the seconds until the bucket can accept `quantity`, or `None` if it can be accepted.
+///
+/// This has the same semantics as the fixed window check: With `over_accept_once` or a
+/// `quantity` of `0`, the bucket only rejects once it is empty.
+fn bucket_retry(
+    bucket: &Bucket,
+    quantity: u64,
+    over_accept_once: bool,
+    timestamp: UnixTimestamp,
+) -> Option<u64> {
+    let available = bucket.available(timestamp);
+
+    if quantity == 0 || over_accept_once {
+        (available <= 0.0).then(|| (-available / bucket.rate).floor() as u64 + 1)
+    } else {
+        let missing = quantity as f64 - available;
+        (missing > 0.0).then(|| ((missing / bucket.rate).ceil() as u64).max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_common::{ProjectId, ProjectKey};
+
+    use super::*;
+    use crate::quota::{DataCategories, DataCategory, QuotaUnit, ReasonCode, Scoping};
+    use crate::rate_limit::RateLimitScope;
+
+    fn scoping() -> Scoping {
+        Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(43),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(44),
+        }
+    }
+
+    fn quota(scope: QuotaScope, limit: Option<u64>) -> Quota {
+        Quota {
+            id: Some("foo".to_owned()),
+            categories: DataCategories::new(),
+            scope,
+            scope_id: None,
+            limit,
+            window: Some(60),
+            burst: None,
+            unit: None,
+            reason_code: Some(ReasonCode::new("get_lost")),
+        }
+    }
+
+    /// Returns a timestamp at the given offset into the window of the test organization.
+    fn at(slot: u64, offset: u64) -> UnixTimestamp {
+        UnixTimestamp::from_secs(slot * 60 + 42 + offset)
+    }
+
+    #[test]
+    fn test_zero_size_quotas() {
+        let quotas = &[Quota {
+            id: None,
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(0),
+            window: None,
+            burst: None,
+            unit: None,
+            reason_code: Some(ReasonCode::new("get_lost")),
+        }];
+
+        let scoping = scoping();
+        let rate_limits = LocalRateLimiter::new().max_limit(Some(30)).is_rate_limited(
+            quotas,
+            scoping.item(DataCategory::Error),
+            Quantity::new(1, 0),
+            false,
+        );
+
+        let limits: Vec<_> = rate_limits.iter().cloned().collect();
+        assert_eq!(
+            limits,
+            vec![RateLimit {
+                categories: DataCategories::new(),
+                scope: RateLimitScope::Organization(42),
+                reason_code: Some(ReasonCode::new("get_lost")),
+                retry_after: limits[0].retry_after,
+            }]
+        );
+        assert!(limits[0].retry_after.remaining_seconds() <= 30);
+    }
+
+    #[test]
+    fn test_simple_quota() {
+        let quotas = &[quota(QuotaScope::Project, Some(5))];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+
+        for i in 0..10 {
+            let rate_limits = rate_limiter.is_rate_limited_at(
+                quotas,
+                scoping.item(DataCategory::Error),
+                Quantity::new(1, 0),
+                false,
+                at(100, 10),
+            );
+
+            if i >= 5 {
+                let limits: Vec<_> = rate_limits.iter().cloned().collect();
+                assert_eq!(limits.len(), 1);
+                assert_eq!(limits[0].scope, RateLimitScope::Project(ProjectId::new(43)));
+                assert_eq!(limits[0].reason_code, Some(ReasonCode::new("get_lost")));
+            } else {
+                assert!(!rate_limits.is_limited(), "rate limited at {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_over_accept_once() {
+        let quotas = &[quota(QuotaScope::Organization, Some(2))];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        let check = |quantity, over_accept_once| {
+            rate_limiter
+                .is_rate_limited_at(
+                    quotas,
+                    item,
+                    Quantity::new(quantity, 0),
+                    over_accept_once,
+                    at(100, 0),
+                )
+                .is_limited()
+        };
+
+        assert!(!check(1, false));
+        assert!(check(2, false));
+        assert!(!check(2, true));
+        assert!(check(0, false));
+        assert!(check(1, true));
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let quotas = &[quota(QuotaScope::Key, Some(10))];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        let limits =
+            rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(10, 0), false, at(100, 59));
+        assert!(!limits.is_limited());
+
+        // Halfway into the next window, half of the previous window still counts.
+        let limits =
+            rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(6, 0), false, at(101, 30));
+        assert!(limits.is_limited());
+        let limits =
+            rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(5, 0), false, at(101, 30));
+        assert!(!limits.is_limited());
+
+        // After skipping a window, the quota is fully available again.
+        let limits =
+            rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(10, 0), false, at(103, 0));
+        assert!(!limits.is_limited());
+    }
+
+    #[test]
+    fn test_units() {
+        let quotas = &[
+            Quota {
+                id: Some("items".to_owned()),
+                unit: Some(QuotaUnit::Items),
+                ..quota(QuotaScope::Key, Some(2))
+            },
+            Quota {
+                id: Some("bytes".to_owned()),
+                unit: Some(QuotaUnit::Bytes),
+                ..quota(QuotaScope::Key, Some(100))
+            },
+        ];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Replay);
+
+        // Exceeding the bytes quota does not consume the items quota.
+        let limits =
+            rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(1, 200), false, at(100, 0));
+        assert!(limits.is_limited());
+
+        let limits =
+            rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(1, 50), false, at(100, 0));
+        assert!(!limits.is_limited());
+        let limits =
+            rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(1, 50), false, at(100, 0));
+        assert!(!limits.is_limited());
+
+        // Both quotas are exhausted now.
+        let items = Quantity::new(1, 0);
+        let limits = rate_limiter.is_rate_limited_at(&quotas[..1], item, items, false, at(100, 0));
+        assert!(limits.is_limited());
+        let bytes = Quantity::new(0, 1);
+        let limits = rate_limiter.is_rate_limited_at(&quotas[1..], item, bytes, false, at(100, 0));
+        assert!(limits.is_limited());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let quotas = &[quota(QuotaScope::Organization, Some(1))];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        let retry = |quantity, timestamp| {
+            let limits = rate_limiter.is_rate_limited_at(
+                quotas,
+                item,
+                Quantity::new(quantity, 0),
+                false,
+                timestamp,
+            );
+            limits
+                .iter()
+                .next()
+                .map(|limit| limit.retry_after.remaining_seconds())
+        };
+
+        assert_eq!(retry(1, at(100, 20)), None);
+
+        // The consumed quantity leaves the sliding window one second into the next window.
+        let remaining = retry(1, at(100, 20)).unwrap();
+        assert!((40..=41).contains(&remaining), "{remaining}");
+        assert!(retry(1, at(101, 0)).is_some());
+        assert_eq!(retry(1, at(101, 1)), None);
+    }
+
+    #[test]
+    fn test_retry_after_sliding_window() {
+        let quotas = &[quota(QuotaScope::Organization, Some(10))];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        let retry = |quantity, timestamp| {
+            let limits = rate_limiter.is_rate_limited_at(
+                quotas,
+                item,
+                Quantity::new(quantity, 0),
+                false,
+                timestamp,
+            );
+            limits
+                .iter()
+                .next()
+                .map(|limit| limit.retry_after.remaining_seconds())
+        };
+
+        assert_eq!(retry(10, at(100, 59)), None);
+
+        // Halfway into the next window, the previous window decays far enough after a second.
+        let remaining = retry(6, at(101, 30)).unwrap();
+        assert!((1..=2).contains(&remaining), "{remaining}");
+        assert_eq!(retry(6, at(101, 31)), None);
+
+        // Quantities beyond the limit can never be accepted.
+        let remaining = retry(11, at(101, 31)).unwrap();
+        assert!(remaining > 60, "{remaining}");
+    }
+
+    #[test]
+    fn test_all_or_nothing() {
+        let quotas = &[
+            quota(QuotaScope::Organization, Some(10)),
+            Quota {
+                id: Some("bar".to_owned()),
+                ..quota(QuotaScope::Project, Some(1))
+            },
+        ];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        assert!(!rate_limiter
+            .is_rate_limited_at(quotas, item, Quantity::new(1, 0), false, at(100, 0))
+            .is_limited());
+        assert!(rate_limiter
+            .is_rate_limited_at(quotas, item, Quantity::new(1, 0), false, at(100, 0))
+            .is_limited());
+
+        let state = rate_limiter.state.lock().unwrap();
+        let quantities: Vec<_> = state
+            .counters
+            .iter()
+            .map(|(key, counter)| (key.id.as_str(), counter.current))
+            .collect();
+        assert_eq!(quantities, vec![("bar", 1), ("foo", 1)]);
+    }
+
+    #[test]
+    fn test_prune() {
+        let quotas = &[quota(QuotaScope::Key, None)];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(3, 0), false, at(100, 0));
+
+        // Counters are kept while they cover the previous window.
+        rate_limiter.prune_at(at(101, 0));
+        assert_eq!(rate_limiter.state.lock().unwrap().counters.len(), 1);
+
+        // Counters of elapsed windows are removed.
+        rate_limiter.prune_at(at(102, 0));
+        assert!(rate_limiter.state.lock().unwrap().counters.is_empty());
+    }
+
+    #[test]
+    fn test_flush_usage() {
+        let quotas = &[quota(QuotaScope::Key, Some(4))];
+        let scoping = scoping();
+        let item = scoping.item(DataCategory::Error);
+
+        // Usage is not tracked by default.
+        let rate_limiter = LocalRateLimiter::new();
+        rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(1, 0), false, at(100, 0));
+        assert!(rate_limiter.flush_usage().is_empty());
+
+        let rate_limiter = LocalRateLimiter::new().track_usage(true);
+        rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(3, 0), false, at(100, 0));
+        rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(2, 0), false, at(100, 1));
+        rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(2, 0), false, at(101, 30));
+
+        // Rejected quantities are not reported.
+        assert_eq!(
+            rate_limiter.flush_usage(),
+            vec![
+                QuotaUsage {
+                    id: "foo".to_owned(),
+                    scope: QuotaScope::Key,
+                    organization_id: 42,
+                    scope_id: Some(44),
+                    window: 60,
+                    slot: 100,
+                    quantity: 3,
+                },
+                QuotaUsage {
+                    id: "foo".to_owned(),
+                    scope: QuotaScope::Key,
+                    organization_id: 42,
+                    scope_id: Some(44),
+                    window: 60,
+                    slot: 101,
+                    quantity: 2,
+                },
+            ]
+        );
+
+        // Usage is only reported once.
+        assert!(rate_limiter.flush_usage().is_empty());
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let quotas = &[Quota {
+            burst: Some(3),
+            ..quota(QuotaScope::Organization, Some(30))
+        }];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        let retry = |quantity, timestamp| {
+            let limits = rate_limiter.is_rate_limited_at(
+                quotas,
+                item,
+                Quantity::new(quantity, 0),
+                false,
+                timestamp,
+            );
+            limits
+                .iter()
+                .next()
+                .map(|limit| limit.retry_after.remaining_seconds())
+        };
+
+        // The bucket starts full and accepts the entire burst at once.
+        assert_eq!(retry(3, at(100, 0)), None);
+
+        // The bucket is empty, the next token is refilled after 2 seconds.
+        let remaining = retry(1, at(100, 0)).unwrap();
+        assert!(remaining > 0 && remaining <= 2, "{remaining}");
+        assert_eq!(retry(1, at(100, 2)), None);
+
+        // The bucket never refills beyond its burst size, even across windows.
+        assert_eq!(retry(3, at(102, 0)), None);
+        assert!(retry(1, at(102, 0)).is_some());
+    }
+
+    #[test]
+    fn test_token_bucket_over_accept_once() {
+        let quotas = &[Quota {
+            burst: Some(2),
+            ..quota(QuotaScope::Organization, Some(60))
+        }];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        let check = |quantity, over_accept_once| {
+            rate_limiter
+                .is_rate_limited_at(
+                    quotas,
+                    item,
+                    Quantity::new(quantity, 0),
+                    over_accept_once,
+                    at(100, 0),
+                )
+                .is_limited()
+        };
+
+        assert!(!check(1, false));
+        assert!(check(2, false));
+        assert!(!check(2, true));
+        assert!(check(0, false));
+        assert!(check(1, true));
+    }
+
+    #[test]
+    fn test_prune_token_buckets() {
+        let quotas = &[Quota {
+            burst: Some(10),
+            ..quota(QuotaScope::Key, Some(60))
+        }];
+        let scoping = scoping();
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+
+        rate_limiter.is_rate_limited_at(quotas, item, Quantity::new(5, 0), false, at(100, 0));
+
+        rate_limiter.prune_at(at(100, 1));
+        assert_eq!(rate_limiter.state.lock().unwrap().buckets.len(), 1);
+
+        // Buckets are removed once they are full again.
+        rate_limiter.prune_at(at(100, 5));
+        assert!(rate_limiter.state.lock().unwrap().buckets.is_empty());
+    }
+}
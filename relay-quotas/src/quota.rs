@@ -1,6 +1,8 @@
 use std::fmt;
+use std::hash::Hasher;
 use std::str::FromStr;
 
+use fnv::FnvHasher;
 use relay_common::{ProjectId, ProjectKey};
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
@@ -32,10 +34,50 @@ impl Scoping {
         ItemScoping {
             category,
             scoping: self,
+            values: ScopeValues::default(),
         }
     }
 }
 
+/// Returns a hash of the given value that is stable across Relay instances and versions.
+///
+/// This is an unkeyed FNV-1a hash, so that all Relays and Redis agree on the hash without sharing a
+/// secret. It is not a cryptographic hash and does not protect the hashed values.
+fn stable_hash(value: &str) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(value.as_bytes());
+    hasher.finish()
+}
+
+/// Values of an item that identify the release, environment and user scopes.
+///
+/// Quotas with these scopes only apply to items that carry the respective value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ScopeValues<'a> {
+    /// The release of the item.
+    pub release: Option<&'a str>,
+
+    /// The environment of the item.
+    pub environment: Option<&'a str>,
+
+    /// A hash of the user identifier, see [`ScopeValues::hash_user`].
+    pub user: Option<u64>,
+}
+
+impl ScopeValues<'_> {
+    /// Hashes a user identifier for the user scope.
+    ///
+    /// User identifiers are never stored in rate limits or quota counters. Instead, they are
+    /// identified by this hash.
+    ///
+    /// The hash is not salted and is not a pseudonymization: Identifiers with few possible values,
+    /// such as email addresses or numeric IDs, can be recovered from it by brute force. Hashes are
+    /// therefore only reported to downstream Relays and never to clients.
+    pub fn hash_user(identifier: &str) -> u64 {
+        stable_hash(identifier)
+    }
+}
+
 /// Data categorization and scoping information.
 ///
 /// `ItemScoping` is always attached to a `Scope` and references it internally. It is a cheap,
@@ -48,6 +90,9 @@ pub struct ItemScoping<'a> {
 
     /// Scoping of the data.
     pub scoping: &'a Scoping,
+
+    /// Values for release, environment and user scopes.
+    pub values: ScopeValues<'a>,
 }
 
 impl AsRef<Scoping> for ItemScoping<'_> {
@@ -64,17 +109,39 @@ impl std::ops::Deref for ItemScoping<'_> {
     }
 }
 
-impl ItemScoping<'_> {
+impl<'a> ItemScoping<'a> {
+    /// Returns a copy of this item scoping with the given scope values.
+    pub fn with_values(self, values: ScopeValues<'a>) -> Self {
+        Self { values, ..self }
+    }
+
     /// Returns the identifier of the given scope.
+    ///
+    /// Releases and environments are identified by a hash of their name.
     pub fn scope_id(&self, scope: QuotaScope) -> Option<u64> {
         match scope {
             QuotaScope::Organization => Some(self.organization_id),
             QuotaScope::Project => Some(self.project_id.value()),
             QuotaScope::Key => self.key_id,
+            QuotaScope::Release => self.values.release.map(stable_hash),
+            QuotaScope::Environment => self.values.environment.map(stable_hash),
+            QuotaScope::User => self.values.user,
             QuotaScope::Unknown => None,
         }
     }
 
+    /// Returns `true` if the item carries a value for the given scope.
+    ///
+    /// This is always `true` for organizations, projects and keys.
+    pub(crate) fn has_scope(&self, scope: QuotaScope) -> bool {
+        match scope {
+            QuotaScope::Release => self.values.release.is_some(),
+            QuotaScope::Environment => self.values.environment.is_some(),
+            QuotaScope::User => self.values.user.is_some(),
+            _ => true,
+        }
+    }
+
     /// Checks whether the category matches any of the quota's categories.
     pub(crate) fn matches_categories(&self, categories: &DataCategories) -> bool {
         // An empty list of categories means that this quota matches all categories. Note that we
@@ -116,6 +183,85 @@ impl CategoryUnit {
     }
 }
 
+/// The unit in which a quota counts the quantity of items.
+///
+/// Quotas without an explicit unit count in the native unit of each data category, which is bytes
+/// for attachments and items for all other categories.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaUnit {
+    /// The number of items.
+    Items,
+
+    /// The size of items in bytes.
+    Bytes,
+
+    /// Any other unit that is not known by this Relay.
+    #[serde(other)]
+    Unknown,
+}
+
+impl QuotaUnit {
+    /// Returns the native unit of the given data category.
+    pub fn for_category(category: DataCategory) -> Self {
+        match CategoryUnit::from(&category) {
+            Some(CategoryUnit::Bytes) => Self::Bytes,
+            _ => Self::Items,
+        }
+    }
+
+    /// Returns the canonical name of this unit.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Items => "items",
+            Self::Bytes => "bytes",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for QuotaUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The quantity of an item in each [`QuotaUnit`].
+///
+/// Rate limiters consume the quantity in the unit of each quota. All quotas of an item are checked
+/// at once, so that the item is either accepted or rejected by quotas of all units.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Quantity {
+    /// The number of items.
+    pub items: usize,
+
+    /// The size of items in bytes.
+    pub bytes: usize,
+}
+
+impl Quantity {
+    /// Creates a new quantity of the given number of items and bytes.
+    pub fn new(items: usize, bytes: usize) -> Self {
+        Self { items, bytes }
+    }
+
+    /// Returns the quantity in the given unit.
+    ///
+    /// Quotas with an `Unknown` unit are not valid, so their quantity is always `0`.
+    pub fn get(&self, unit: QuotaUnit) -> usize {
+        match unit {
+            QuotaUnit::Items => self.items,
+            QuotaUnit::Bytes => self.bytes,
+            QuotaUnit::Unknown => 0,
+        }
+    }
+
+    /// Returns `true` if the quantity is `0` in all units.
+    pub fn is_empty(&self) -> bool {
+        self.items == 0 && self.bytes == 0
+    }
+}
+
 /// An efficient container for data categories that avoids allocations.
 ///
 /// `DataCategories` is to be treated like a set.
@@ -143,6 +289,21 @@ pub enum QuotaScope {
     /// This is a sub-scope of `Project`.
     Key,
 
+    /// A release within the organization.
+    ///
+    /// Only applies to items that have a release.
+    Release,
+
+    /// An environment within the organization.
+    ///
+    /// Only applies to items that have an environment.
+    Environment,
+
+    /// A user within the organization, identified by a hash of the user identifier.
+    ///
+    /// Only applies to items that have a user.
+    User,
+
     /// Any other scope that is not known by this Relay.
     #[serde(other)]
     Unknown,
@@ -155,6 +316,9 @@ impl QuotaScope {
             "organization" => Self::Organization,
             "project" => Self::Project,
             "key" => Self::Key,
+            "release" => Self::Release,
+            "environment" => Self::Environment,
+            "user" => Self::User,
             _ => Self::Unknown,
         }
     }
@@ -165,6 +329,9 @@ impl QuotaScope {
             Self::Key => "key",
             Self::Project => "project",
             Self::Organization => "organization",
+            Self::Release => "release",
+            Self::Environment => "environment",
+            Self::User => "user",
             Self::Unknown => "unknown",
         }
     }
@@ -245,15 +412,31 @@ pub struct Quota {
     /// unlimited counted quota, or a positive number for enforcement. Requires `window` if the
     /// limit is not `0`.
     ///
-    /// For attachments, this limit expresses the number of allowed bytes.
+    /// The limit is expressed in the quota's `unit`. By default, this is the number of allowed
+    /// bytes for attachments and the number of items for all other categories.
     #[serde(default)]
     pub limit: Option<u64>,
 
+    /// The unit in which this quota counts items.
+    ///
+    /// If missing, items are counted in the native unit of their data category. If set, this quota
+    /// applies to all of its categories in the same unit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<QuotaUnit>,
+
     /// The time window in seconds to enforce this quota in. Required in all cases except `limit=0`,
     /// since those quotas are not measured.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub window: Option<u64>,
 
+    /// The maximum number of items that can be accepted in a burst.
+    ///
+    /// If set, this quota is enforced with a token bucket instead of a fixed window. The bucket
+    /// holds up to `burst` tokens and refills at a rate of `limit` per `window`. Items consume
+    /// tokens in the quota's `unit`. Requires a positive `limit` and `window`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u64>,
+
     /// A machine readable reason returned when this quota is exceeded. Required in all cases except
     /// `limit=None`, since unlimited quotas can never be exceeded.
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -265,7 +448,9 @@ impl Quota {
     ///
     /// There are a few conditions at which quotas are invalid:
     ///  - The quota only applies to `Unknown` data categories.
-    ///  - The quota is counted (not limit `0`) but specifies categories with different units.
+    ///  - The quota is counted (not limit `0`) but specifies an `Unknown` unit.
+    ///  - The quota is counted, has no explicit unit, and specifies categories with different
+    ///    units.
     pub fn is_valid(&self) -> bool {
         let mut units = self.categories.iter().filter_map(CategoryUnit::from);
 
@@ -274,6 +459,8 @@ impl Quota {
             None if !self.categories.is_empty() => false,
             // This is a reject all quota, which is always valid
             _ if self.limit == Some(0) => true,
+            // An explicit unit applies to all categories, unless it is unknown
+            _ if self.unit.is_some() => self.unit != Some(QuotaUnit::Unknown),
             // Applies to all categories, which implies multiple units
             None => false,
             // There are multiple categories, which must all have the same units
@@ -281,13 +468,32 @@ impl Quota {
         }
     }
 
+    /// Returns the refill rate of the token bucket in tokens per second.
+    ///
+    /// Returns `None` if this quota does not specify a `burst`, or if the bucket would never
+    /// refill.
+    pub(crate) fn refill_rate(&self) -> Option<f64> {
+        self.burst?;
+        match (self.limit, self.window) {
+            (Some(limit @ 1..), Some(window @ 1..)) => Some(limit as f64 / window as f64),
+            _ => None,
+        }
+    }
+
     /// Checks whether this quota's scope matches the given item scoping.
     ///
     /// This quota matches, if:
     ///  - there is no `scope_id` constraint
     ///  - the `scope_id` constraint is not numeric
     ///  - the scope identifier matches the one from ascoping and the scope is known
-    fn matches_scope(&self, scoping: ItemScoping<'_>) -> bool {
+    ///
+    /// Release, environment and user quotas only match items that carry the respective value. The
+    /// `scope_id` of release and environment quotas is the name of the release or environment.
+    pub(crate) fn matches_scope(&self, scoping: ItemScoping<'_>) -> bool {
+        if !scoping.has_scope(self.scope) {
+            return false;
+        }
+
         // Check for a scope identifier constraint. If there is no constraint, this means that the
         // quota matches any scope. In case the scope is unknown, it will be coerced to the most
         // specific scope later.
@@ -296,6 +502,15 @@ impl Quota {
             None => return true,
         };
 
+        // Releases and environments are constrained by name rather than a numeric identifier.
+        match self.scope {
+            QuotaScope::Release => return scoping.values.release == Some(scope_id.as_str()),
+            QuotaScope::Environment => {
+                return scoping.values.environment == Some(scope_id.as_str())
+            }
+            _ => (),
+        }
+
         // Check if the scope identifier in the quota is parseable. If not, this means we cannot
         // fulfill the constraint, so the quota does not match.
         let parsed = match scope_id.parse::<u64>() {
@@ -307,6 +522,17 @@ impl Quota {
         scoping.scope_id(self.scope) == Some(parsed)
     }
 
+    /// Returns the unit in which this quota counts items of the given category.
+    pub fn unit_for(&self, category: DataCategory) -> QuotaUnit {
+        self.unit
+            .unwrap_or_else(|| QuotaUnit::for_category(category))
+    }
+
+    /// Returns the quantity that this quota consumes for an item of the given category.
+    pub fn quantity(&self, category: DataCategory, quantity: Quantity) -> usize {
+        quantity.get(self.unit_for(category))
+    }
+
     /// Checks whether the quota's constraints match the current item.
     pub fn matches(&self, scoping: ItemScoping<'_>) -> bool {
         self.matches_scope(scoping) && scoping.matches_categories(&self.categories)
@@ -512,6 +738,32 @@ mod tests {
         "###);
     }
 
+    #[test]
+    fn test_parse_quota_burst() {
+        let json = r#"{
+            "id": "b",
+            "limit": 60,
+            "window": 60,
+            "burst": 100,
+            "reasonCode": "not_so_fast"
+        }"#;
+
+        let quota = serde_json::from_str::<Quota>(json).expect("parse quota");
+        assert_eq!(quota.refill_rate(), Some(1.0));
+
+        insta::assert_ron_snapshot!(quota, @r###"
+        Quota(
+          id: Some("b"),
+          categories: [],
+          scope: organization,
+          limit: Some(60),
+          window: Some(60),
+          burst: Some(100),
+          reasonCode: Some(ReasonCode("not_so_fast")),
+        )
+        "###);
+    }
+
     #[test]
     fn test_quota_valid_reject_all() {
         let quota = Quota {
@@ -521,6 +773,8 @@ mod tests {
             scope_id: None,
             limit: Some(0),
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -536,6 +790,8 @@ mod tests {
             scope_id: None,
             limit: Some(0),
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -551,6 +807,8 @@ mod tests {
             scope_id: None,
             limit: Some(0),
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -566,6 +824,8 @@ mod tests {
             scope_id: None,
             limit: Some(1000),
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -582,6 +842,8 @@ mod tests {
             scope_id: None,
             limit: None,
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -589,6 +851,64 @@ mod tests {
         assert!(!quota.is_valid());
     }
 
+    #[test]
+    fn test_quota_valid_unit_mixed() {
+        let quota = Quota {
+            id: None,
+            categories: smallvec![DataCategory::Replay, DataCategory::Attachment],
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(1000),
+            window: None,
+            burst: None,
+            unit: Some(QuotaUnit::Bytes),
+            reason_code: None,
+        };
+
+        // The explicit unit applies to all categories.
+        assert!(quota.is_valid());
+    }
+
+    #[test]
+    fn test_quota_invalid_unknown_unit() {
+        let quota = serde_json::from_str::<Quota>(
+            r#"{"categories": ["replay"], "limit": 1000, "unit": "megabytes"}"#,
+        )
+        .expect("parse quota");
+
+        assert_eq!(quota.unit, Some(QuotaUnit::Unknown));
+        assert!(!quota.is_valid());
+    }
+
+    #[test]
+    fn test_quota_quantity_unit() {
+        let quota = Quota {
+            id: None,
+            categories: smallvec![DataCategory::Replay, DataCategory::Attachment],
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(1000),
+            window: None,
+            burst: None,
+            unit: Some(QuotaUnit::Bytes),
+            reason_code: None,
+        };
+
+        let quantity = Quantity::new(2, 20);
+
+        // The explicit unit applies to all categories.
+        assert_eq!(quota.quantity(DataCategory::Replay, quantity), 20);
+        assert_eq!(quota.quantity(DataCategory::Attachment, quantity), 20);
+
+        // Without an explicit unit, attachments are counted in bytes.
+        let quota = Quota {
+            unit: None,
+            ..quota
+        };
+        assert_eq!(quota.quantity(DataCategory::Replay, quantity), 2);
+        assert_eq!(quota.quantity(DataCategory::Attachment, quantity), 20);
+    }
+
     #[test]
     fn test_quota_matches_no_categories() {
         let quota = Quota {
@@ -598,6 +918,8 @@ mod tests {
             scope_id: None,
             limit: None,
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -608,7 +930,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -621,6 +944,8 @@ mod tests {
             scope_id: None,
             limit: None,
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -631,7 +956,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -644,6 +970,8 @@ mod tests {
             scope_id: None,
             limit: None,
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -654,7 +982,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -664,7 +993,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -677,6 +1007,8 @@ mod tests {
             scope_id: Some("not_a_number".to_owned()),
             limit: None,
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -687,7 +1019,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -700,6 +1033,8 @@ mod tests {
             scope_id: Some("42".to_owned()),
             limit: None,
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -710,7 +1045,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -720,7 +1056,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -733,6 +1070,8 @@ mod tests {
             scope_id: Some("21".to_owned()),
             limit: None,
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -743,7 +1082,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -753,7 +1093,8 @@ mod tests {
                 project_id: ProjectId::new(0),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -766,6 +1107,8 @@ mod tests {
             scope_id: Some("17".to_owned()),
             limit: None,
             window: None,
+            burst: None,
+            unit: None,
             reason_code: None,
         };
 
@@ -776,7 +1119,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -786,7 +1130,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(0),
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -796,7 +1141,143 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
+
+    #[test]
+    fn test_parse_quota_release() {
+        let json = r#"{
+            "id": "r",
+            "scope": "release",
+            "scopeId": "backend@1.0.0",
+            "limit": 4711,
+            "window": 42,
+            "reasonCode": "noisy_release"
+        }"#;
+
+        let quota = serde_json::from_str::<Quota>(json).expect("parse quota");
+
+        insta::assert_ron_snapshot!(quota, @r###"
+        Quota(
+          id: Some("r"),
+          categories: [],
+          scope: release,
+          scopeId: Some("backend@1.0.0"),
+          limit: Some(4711),
+          window: Some(42),
+          reasonCode: Some(ReasonCode("noisy_release")),
+        )
+        "###);
+    }
+
+    #[test]
+    fn test_quota_matches_release_scope() {
+        let quota = Quota {
+            id: None,
+            categories: DataCategories::new(),
+            scope: QuotaScope::Release,
+            scope_id: Some("backend@1.0.0".to_owned()),
+            limit: None,
+            window: None,
+            burst: None,
+            unit: None,
+            reason_code: None,
+        };
+
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        };
+
+        let item_scoping = |release| {
+            scoping.item(DataCategory::Error).with_values(ScopeValues {
+                release,
+                ..Default::default()
+            })
+        };
+
+        assert!(quota.matches(item_scoping(Some("backend@1.0.0"))));
+        assert!(!quota.matches(item_scoping(Some("backend@2.0.0"))));
+        assert!(!quota.matches(item_scoping(None)));
+    }
+
+    #[test]
+    fn test_quota_matches_environment_scope() {
+        let quota = Quota {
+            id: None,
+            categories: DataCategories::new(),
+            scope: QuotaScope::Environment,
+            scope_id: None,
+            limit: None,
+            window: None,
+            burst: None,
+            unit: None,
+            reason_code: None,
+        };
+
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        };
+
+        let item_scoping = |environment| {
+            scoping.item(DataCategory::Error).with_values(ScopeValues {
+                environment,
+                ..Default::default()
+            })
+        };
+
+        // Without a constraint, the quota applies to every environment separately.
+        let staging = item_scoping(Some("staging"));
+        let production = item_scoping(Some("production"));
+        assert!(quota.matches(staging));
+        assert!(quota.matches(production));
+        assert_ne!(
+            staging.scope_id(QuotaScope::Environment),
+            production.scope_id(QuotaScope::Environment)
+        );
+
+        // Items without an environment are not counted towards environment quotas.
+        assert!(!quota.matches(item_scoping(None)));
+    }
+
+    #[test]
+    fn test_quota_matches_user_scope() {
+        let user = ScopeValues::hash_user("user@example.com");
+        let quota = Quota {
+            id: None,
+            categories: DataCategories::new(),
+            scope: QuotaScope::User,
+            scope_id: Some(user.to_string()),
+            limit: None,
+            window: None,
+            burst: None,
+            unit: None,
+            reason_code: None,
+        };
+
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        };
+
+        let item_scoping = |user| {
+            scoping.item(DataCategory::Error).with_values(ScopeValues {
+                user,
+                ..Default::default()
+            })
+        };
+
+        assert!(quota.matches(item_scoping(Some(user))));
+        assert!(!quota.matches(item_scoping(Some(ScopeValues::hash_user("other")))));
+        assert!(!quota.matches(item_scoping(None)));
+    }
 }
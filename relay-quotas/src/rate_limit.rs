@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
@@ -110,17 +111,77 @@ pub enum RateLimitScope {
     Project(ProjectId),
     /// A DSN public key.
     Key(ProjectKey),
+    /// A release with name.
+    Release(String),
+    /// An environment with name.
+    Environment(String),
+    /// A user with the hash of its identifier.
+    User(u64),
 }
 
 impl RateLimitScope {
-    /// Extracts a rate limiting scope from the given item scoping for a specific quota.
-    pub fn for_quota(scoping: &Scoping, scope: QuotaScope) -> Self {
+    /// Extracts a rate limiting scope from the given scoping for a specific quota.
+    ///
+    /// Since the scoping does not carry values for release, environment and user scopes, this
+    /// returns `None` for them rather than widening them to a broader scope. Use
+    /// [`Self::for_item`] or [`Self::for_value`] to retain them. Unknown scopes are coerced to the
+    /// most specific scope.
+    pub fn for_quota(scoping: &Scoping, scope: QuotaScope) -> Option<Self> {
         match scope {
-            QuotaScope::Organization => RateLimitScope::Organization(scoping.organization_id),
-            QuotaScope::Project => RateLimitScope::Project(scoping.project_id),
-            QuotaScope::Key => RateLimitScope::Key(scoping.project_key),
+            QuotaScope::Organization => Some(RateLimitScope::Organization(scoping.organization_id)),
+            QuotaScope::Project => Some(RateLimitScope::Project(scoping.project_id)),
+            QuotaScope::Key => Some(RateLimitScope::Key(scoping.project_key)),
+            QuotaScope::Release | QuotaScope::Environment | QuotaScope::User => None,
             // For unknown scopes, assume the most specific scope:
-            QuotaScope::Unknown => RateLimitScope::Key(scoping.project_key),
+            QuotaScope::Unknown => Some(RateLimitScope::Key(scoping.project_key)),
+        }
+    }
+
+    /// Extracts a rate limiting scope for a specific quota with an explicit scope value.
+    ///
+    /// The value is the release name, environment name or user hash for the respective scopes and
+    /// is ignored for all other scopes. Returns `None` if a required value is missing or invalid.
+    pub fn for_value(scoping: &Scoping, scope: QuotaScope, value: Option<&str>) -> Option<Self> {
+        match scope {
+            QuotaScope::Release => value.map(|release| RateLimitScope::Release(release.to_owned())),
+            QuotaScope::Environment => {
+                value.map(|environment| RateLimitScope::Environment(environment.to_owned()))
+            }
+            QuotaScope::User => value
+                .and_then(|user| user.parse().ok())
+                .map(RateLimitScope::User),
+            _ => Self::for_quota(scoping, scope),
+        }
+    }
+
+    /// Extracts a rate limiting scope from the given item scoping for a specific quota.
+    pub fn for_item(scoping: &ItemScoping<'_>, scope: QuotaScope) -> Self {
+        let values = scoping.values;
+        let scope = match scope {
+            QuotaScope::Release => values
+                .release
+                .map(|r| RateLimitScope::Release(r.to_owned())),
+            QuotaScope::Environment => values
+                .environment
+                .map(|e| RateLimitScope::Environment(e.to_owned())),
+            QuotaScope::User => values.user.map(RateLimitScope::User),
+            _ => Self::for_quota(scoping, scope),
+        };
+
+        // Quotas for release, environment and user scopes only match items that carry a value for
+        // the scope, so the fallback to the key is never hit for matching quotas.
+        scope.unwrap_or(RateLimitScope::Key(scoping.project_key))
+    }
+
+    /// Returns the value identifying release, environment and user scopes.
+    ///
+    /// All other scopes are fully identified by the [`Scoping`] and return `None`.
+    pub fn value(&self) -> Option<Cow<'_, str>> {
+        match *self {
+            Self::Release(ref release) => Some(Cow::Borrowed(release)),
+            Self::Environment(ref environment) => Some(Cow::Borrowed(environment)),
+            Self::User(user) => Some(Cow::Owned(user.to_string())),
+            Self::Organization(_) | Self::Project(_) | Self::Key(_) => None,
         }
     }
 
@@ -130,6 +191,9 @@ impl RateLimitScope {
             Self::Key(_) => QuotaScope::Key.name(),
             Self::Project(_) => QuotaScope::Project.name(),
             Self::Organization(_) => QuotaScope::Organization.name(),
+            Self::Release(_) => QuotaScope::Release.name(),
+            Self::Environment(_) => QuotaScope::Environment.name(),
+            Self::User(_) => QuotaScope::User.name(),
         }
     }
 }
@@ -154,10 +218,10 @@ pub struct RateLimit {
 
 impl RateLimit {
     /// Creates a new rate limit for the given `Quota`.
-    pub fn from_quota(quota: &Quota, scoping: &Scoping, retry_after: RetryAfter) -> Self {
+    pub fn from_quota(quota: &Quota, scoping: &ItemScoping<'_>, retry_after: RetryAfter) -> Self {
         Self {
             categories: quota.categories.clone(),
-            scope: RateLimitScope::for_quota(scoping, quota.scope),
+            scope: RateLimitScope::for_item(scoping, quota.scope),
             reason_code: quota.reason_code.clone(),
             retry_after,
         }
@@ -174,6 +238,13 @@ impl RateLimit {
             RateLimitScope::Organization(org_id) => scoping.organization_id == org_id,
             RateLimitScope::Project(project_id) => scoping.project_id == project_id,
             RateLimitScope::Key(ref key) => scoping.project_key == *key,
+            RateLimitScope::Release(ref release) => {
+                scoping.values.release == Some(release.as_str())
+            }
+            RateLimitScope::Environment(ref environment) => {
+                scoping.values.environment == Some(environment.as_str())
+            }
+            RateLimitScope::User(user) => scoping.values.user == Some(user),
         }
     }
 }
@@ -240,6 +311,18 @@ impl RateLimits {
         self.limits.retain(|limit| !limit.retry_after.expired());
     }
 
+    /// Retains only the rate limits for which the predicate returns `true`.
+    ///
+    /// Returns the number of rate limits that have been removed.
+    pub fn retain<F>(&mut self, f: F) -> usize
+    where
+        F: FnMut(&RateLimit) -> bool,
+    {
+        let len = self.limits.len();
+        self.limits.retain(f);
+        len - self.limits.len()
+    }
+
     /// Checks whether any rate limits apply to the given scoping.
     ///
     /// If no limits match, then the returned `RateLimits` instance evalutes `is_ok`. Otherwise, it
@@ -345,7 +428,7 @@ mod tests {
     use smallvec::smallvec;
 
     use super::*;
-    use crate::quota::DataCategory;
+    use crate::quota::{DataCategory, ScopeValues};
 
     #[test]
     fn test_parse_retry_after() {
@@ -408,7 +491,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!rate_limit.matches(ItemScoping {
@@ -418,7 +502,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -438,7 +523,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!rate_limit.matches(ItemScoping {
@@ -448,7 +534,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -468,7 +555,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!rate_limit.matches(ItemScoping {
@@ -478,7 +566,8 @@ mod tests {
                 project_id: ProjectId::new(0),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
@@ -500,7 +589,8 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
 
         assert!(!rate_limit.matches(ItemScoping {
@@ -510,10 +600,54 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("deadbeefdeadbeefdeadbeefdeadbeef").unwrap(),
                 key_id: None,
-            }
+            },
+            values: ScopeValues::default(),
         }));
     }
 
+    #[test]
+    fn test_rate_limit_matches_release() {
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: None,
+        };
+
+        let quota = Quota {
+            id: Some("r".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Release,
+            scope_id: None,
+            limit: Some(0),
+            window: None,
+            burst: None,
+            unit: None,
+            reason_code: None,
+        };
+
+        let item_scoping = |release| {
+            scoping.item(DataCategory::Error).with_values(ScopeValues {
+                release,
+                ..Default::default()
+            })
+        };
+
+        let rate_limit = RateLimit::from_quota(
+            &quota,
+            &item_scoping(Some("backend@1.0.0")),
+            RetryAfter::from_secs(1),
+        );
+        assert_eq!(
+            rate_limit.scope,
+            RateLimitScope::Release("backend@1.0.0".to_owned())
+        );
+
+        assert!(rate_limit.matches(item_scoping(Some("backend@1.0.0"))));
+        assert!(!rate_limit.matches(item_scoping(Some("backend@2.0.0"))));
+        assert!(!rate_limit.matches(item_scoping(None)));
+    }
+
     #[test]
     fn test_rate_limits_add_replacement() {
         let mut rate_limits = RateLimits::new();
@@ -719,6 +853,43 @@ mod tests {
         "###);
     }
 
+    #[test]
+    fn test_rate_limits_retain() {
+        let mut rate_limits = RateLimits::new();
+
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Error],
+            scope: RateLimitScope::Organization(42),
+            reason_code: Some(ReasonCode::new("my_limit")),
+            retry_after: RetryAfter::from_secs(1),
+        });
+
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Transaction],
+            scope: RateLimitScope::Organization(42),
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(1),
+        });
+
+        let removed = rate_limits.retain(|limit| limit.reason_code.is_none());
+        assert_eq!(removed, 1);
+
+        insta::assert_ron_snapshot!(rate_limits, @r###"
+        RateLimits(
+          limits: [
+            RateLimit(
+              categories: [
+                transaction,
+              ],
+              scope: Organization(42),
+              reason_code: None,
+              retry_after: RetryAfter(1),
+            ),
+          ],
+        )
+        "###);
+    }
+
     #[test]
     fn test_rate_limits_check() {
         let mut rate_limits = RateLimits::new();
@@ -747,6 +918,7 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
             },
+            values: ScopeValues::default(),
         });
 
         // Check that the error limit is applied
@@ -794,6 +966,7 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
             },
+            values: ScopeValues::default(),
         };
 
         let quotas = &[Quota {
@@ -803,6 +976,8 @@ mod tests {
             scope_id: Some("42".to_owned()),
             limit: Some(0),
             window: None,
+            burst: None,
+            unit: None,
             reason_code: Some(ReasonCode::new("zero")),
         }];
 
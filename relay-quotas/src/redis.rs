@@ -5,9 +5,12 @@ use relay_common::UnixTimestamp;
 use relay_log::protocol::value;
 use relay_redis::redis::Script;
 use relay_redis::{RedisError, RedisPool};
+use serde::Serialize;
 use thiserror::Error;
 
-use crate::quota::{ItemScoping, Quota, QuotaScope};
+use crate::quota::{
+    DataCategories, DataCategory, ItemScoping, Quantity, Quota, QuotaScope, ScopeValues, Scoping,
+};
 use crate::rate_limit::{RateLimit, RateLimits, RetryAfter};
 use crate::REJECT_ALL_SECS;
 
@@ -99,6 +102,29 @@ impl<'a> RedisQuota<'a> {
         UnixTimestamp::from_secs(next_start)
     }
 
+    /// Returns the burst size for Redis (`-1` for fixed window quotas).
+    fn burst(&self) -> i64 {
+        match self.refill_rate() {
+            Some(_) => self.quota.burst.unwrap_or(0).try_into().unwrap_or(i64::MAX),
+            None => -1,
+        }
+    }
+
+    /// Returns the absolute expiration time of the key in Redis.
+    ///
+    /// Token buckets expire once they would have been refilled completely, fixed windows expire
+    /// at the end of the window. Both are extended by a grace period.
+    fn key_expiry(&self) -> u64 {
+        let expiry = match (self.quota.burst, self.refill_rate()) {
+            (Some(burst), Some(rate)) => {
+                self.timestamp.as_secs() + (burst as f64 / rate).ceil() as u64
+            }
+            _ => self.expiry().as_secs(),
+        };
+
+        expiry + GRACE
+    }
+
     fn key(&self) -> String {
         // The subscope id is only formatted into the key if the quota is not organization-scoped.
         // The organization id is always included.
@@ -107,6 +133,16 @@ impl<'a> RedisQuota<'a> {
             scope => self.scoping.scope_id(scope),
         };
 
+        // Token buckets span across windows, so their key does not contain the slot.
+        if self.refill_rate().is_some() {
+            return format!(
+                "quota:{id}{{{org}}}{subscope}:bucket",
+                id = self.prefix,
+                org = self.scoping.organization_id,
+                subscope = OptionalDisplay(subscope),
+            );
+        }
+
         format!(
             "quota:{id}{{{org}}}{subscope}:{slot}",
             id = self.prefix,
@@ -125,6 +161,24 @@ impl std::ops::Deref for RedisQuota<'_> {
     }
 }
 
+/// The current consumption of a fixed-window quota tracked in Redis.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaConsumption {
+    /// The unique identifier of the quota.
+    pub id: String,
+    /// The scope of the quota.
+    pub scope: QuotaScope,
+    /// The data categories counted by the quota.
+    pub categories: DataCategories,
+    /// The maximum number of units allowed within the window, if any.
+    pub limit: Option<u64>,
+    /// The time window in seconds.
+    pub window: u64,
+    /// The units consumed in the current window, net of refunds.
+    pub consumed: u64,
+}
+
 /// A service that executes quotas and checks for rate limits in a shared cache.
 ///
 /// Quotas handle tracking a project's usage and respond whether or not a project has been
@@ -175,6 +229,9 @@ impl RedisRateLimiter {
     /// it over the limit, which normaly would return the _rejection_, setting `over_accept_once`
     /// to `true` will allow accept the incoming data even if the limit is exceeded once.
     ///
+    /// Each quota consumes the `quantity` in its own unit. Quotas of all units are checked in the
+    /// same invocation, so the quantity is counted against all of them or none.
+    ///
     /// The passed `quantity` may be `0`. In this case, the rate limiter will check if the quota
     /// limit has been reached or exceeded without incrementing it in the success case. This can be
     /// useful to check for required quotas in a different data category.
@@ -182,7 +239,7 @@ impl RedisRateLimiter {
         &self,
         quotas: &[Quota],
         item_scoping: ItemScoping<'_>,
-        quantity: usize,
+        quantity: Quantity,
         over_accept_once: bool,
     ) -> Result<RateLimits, RateLimitingError> {
         let timestamp = UnixTimestamp::now();
@@ -208,9 +265,12 @@ impl RedisRateLimiter {
                 invocation.key(refund_key);
 
                 invocation.arg(quota.limit());
-                invocation.arg(quota.expiry().as_secs() + GRACE);
-                invocation.arg(quantity);
+                invocation.arg(quota.key_expiry());
+                invocation.arg(quota.quantity(item_scoping.category, quantity));
                 invocation.arg(over_accept_once);
+                invocation.arg(quota.burst());
+                invocation.arg(quota.refill_rate().unwrap_or(0.0));
+                invocation.arg(timestamp.as_secs());
 
                 tracked_quotas.push(quota);
             } else {
@@ -230,21 +290,96 @@ impl RedisRateLimiter {
         }
 
         let mut client = self.pool.client().map_err(RateLimitingError::Redis)?;
-        let rejections: Vec<bool> = invocation
+        let rejections: Vec<u64> = invocation
             .invoke(&mut client.connection())
             .map_err(RedisError::Redis)
             .map_err(RateLimitingError::Redis)?;
 
-        for (quota, is_rejected) in tracked_quotas.iter().zip(rejections) {
-            if is_rejected {
-                let retry_after = self.retry_after((quota.expiry() - timestamp).as_secs());
-                rate_limits.add(RateLimit::from_quota(quota, &item_scoping, retry_after));
+        for (quota, rejection) in tracked_quotas.iter().zip(rejections) {
+            if rejection == 0 {
+                continue;
             }
+
+            // Token buckets report the time until enough tokens have been refilled.
+            let seconds = match quota.refill_rate() {
+                Some(_) => rejection,
+                None => (quota.expiry() - timestamp).as_secs(),
+            };
+
+            let retry_after = self.retry_after(seconds);
+            rate_limits.add(RateLimit::from_quota(quota, &item_scoping, retry_after));
         }
 
         Ok(rate_limits)
     }
 
+    /// Returns the current consumption of organization, project and key quotas.
+    ///
+    /// This reads the counters of the current window without modifying them. Quotas that cannot be
+    /// tracked in Redis, static reject-all quotas and token buckets are skipped.
+    pub fn consumption(
+        &self,
+        quotas: &[Quota],
+        scoping: &Scoping,
+    ) -> Result<Vec<QuotaConsumption>, RateLimitingError> {
+        let timestamp = UnixTimestamp::now();
+        let item_scoping = ItemScoping {
+            category: DataCategory::Default,
+            scoping,
+            values: ScopeValues::default(),
+        };
+
+        let tracked_quotas: Vec<_> = quotas
+            .iter()
+            .filter(|quota| {
+                let scope = matches!(
+                    quota.scope,
+                    QuotaScope::Organization | QuotaScope::Project | QuotaScope::Key
+                );
+                scope && quota.limit != Some(0) && quota.refill_rate().is_none()
+            })
+            .filter(|quota| quota.matches_scope(item_scoping))
+            .filter_map(|quota| RedisQuota::new(quota, item_scoping, timestamp))
+            .collect();
+
+        if tracked_quotas.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut pipeline = relay_redis::redis::pipe();
+        for quota in &tracked_quotas {
+            let key = quota.key();
+            let refund_key = get_refunded_quota_key(&key);
+            pipeline.get(key).get(refund_key);
+        }
+
+        let mut client = self.pool.client().map_err(RateLimitingError::Redis)?;
+        let counters: Vec<Option<u64>> = pipeline
+            .query(&mut client.connection())
+            .map_err(RedisError::Redis)
+            .map_err(RateLimitingError::Redis)?;
+
+        let consumption = tracked_quotas
+            .iter()
+            .zip(counters.chunks(2))
+            .map(|(quota, counters)| {
+                let consumed = counters[0].unwrap_or(0);
+                let refunded = counters.get(1).copied().flatten().unwrap_or(0);
+
+                QuotaConsumption {
+                    id: quota.prefix.to_owned(),
+                    scope: quota.scope,
+                    categories: quota.categories.clone(),
+                    limit: quota.limit,
+                    window: quota.window,
+                    consumed: consumed.saturating_sub(refunded),
+                }
+            })
+            .collect();
+
+        Ok(consumption)
+    }
+
     /// Creates a rate limit bounded by `max_limit`.
     fn retry_after(&self, mut seconds: u64) -> RetryAfter {
         if let Some(max_limit) = self.max_limit {
@@ -265,7 +400,7 @@ mod tests {
     use relay_redis::RedisConfigOptions;
 
     use super::*;
-    use crate::quota::{DataCategories, DataCategory, ReasonCode, Scoping};
+    use crate::quota::{DataCategories, DataCategory, ReasonCode, ScopeValues, Scoping};
     use crate::rate_limit::RateLimitScope;
 
     fn build_rate_limiter() -> RedisRateLimiter {
@@ -289,6 +424,8 @@ mod tests {
                 scope_id: None,
                 limit: Some(0),
                 window: None,
+                burst: None,
+                unit: None,
                 reason_code: Some(ReasonCode::new("get_lost")),
             },
             Quota {
@@ -298,6 +435,8 @@ mod tests {
                 scope_id: None,
                 limit: None,
                 window: Some(42),
+                burst: None,
+                unit: None,
                 reason_code: Some(ReasonCode::new("unlimited")),
             },
         ];
@@ -310,10 +449,11 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            values: ScopeValues::default(),
         };
 
         let rate_limits: Vec<RateLimit> = build_rate_limiter()
-            .is_rate_limited(quotas, scoping, 1, false)
+            .is_rate_limited(quotas, scoping, Quantity::new(1, 0), false)
             .expect("rate limiting failed")
             .into_iter()
             .collect();
@@ -338,6 +478,8 @@ mod tests {
             scope_id: None,
             limit: Some(5),
             window: Some(60),
+            burst: None,
+            unit: None,
             reason_code: Some(ReasonCode::new("get_lost")),
         }];
 
@@ -349,13 +491,14 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            values: ScopeValues::default(),
         };
 
         let rate_limiter = build_rate_limiter();
 
         for i in 0..10 {
             let rate_limits: Vec<RateLimit> = rate_limiter
-                .is_rate_limited(quotas, scoping, 1, false)
+                .is_rate_limited(quotas, scoping, Quantity::new(1, 0), false)
                 .expect("rate limiting failed")
                 .into_iter()
                 .collect();
@@ -376,6 +519,66 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_quota_consumption() {
+        let quotas = &[
+            Quota {
+                id: Some(format!("test_quota_consumption_{:?}", SystemTime::now())),
+                categories: DataCategories::new(),
+                scope: QuotaScope::Project,
+                scope_id: None,
+                limit: Some(5),
+                window: Some(60),
+                burst: None,
+                unit: None,
+                reason_code: None,
+            },
+            Quota {
+                id: Some("other_project".to_owned()),
+                categories: DataCategories::new(),
+                scope: QuotaScope::Project,
+                scope_id: Some("4711".to_owned()),
+                limit: Some(5),
+                window: Some(60),
+                burst: None,
+                unit: None,
+                reason_code: None,
+            },
+        ];
+
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(43),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(44),
+        };
+
+        let rate_limiter = build_rate_limiter();
+        let consumption = rate_limiter.consumption(quotas, &scoping).unwrap();
+        assert_eq!(consumption.len(), 1);
+        assert_eq!(consumption[0].consumed, 0);
+
+        let item_scoping = scoping.item(DataCategory::Error);
+        for _ in 0..3 {
+            rate_limiter
+                .is_rate_limited(quotas, item_scoping, Quantity::new(1, 0), false)
+                .unwrap();
+        }
+
+        let consumption = rate_limiter.consumption(quotas, &scoping).unwrap();
+        assert_eq!(
+            consumption,
+            vec![QuotaConsumption {
+                id: quotas[0].id.clone().unwrap(),
+                scope: QuotaScope::Project,
+                categories: DataCategories::new(),
+                limit: Some(5),
+                window: 60,
+                consumed: 3,
+            }]
+        );
+    }
+
     #[test]
     fn test_quantity_0() {
         let quotas = &[Quota {
@@ -385,6 +588,8 @@ mod tests {
             scope_id: None,
             limit: Some(1),
             window: Some(60),
+            burst: None,
+            unit: None,
             reason_code: Some(ReasonCode::new("get_lost")),
         }];
 
@@ -396,31 +601,32 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            values: ScopeValues::default(),
         };
 
         let rate_limiter = build_rate_limiter();
 
         // limit is 1, so first call not rate limited
         assert!(!rate_limiter
-            .is_rate_limited(quotas, scoping, 1, false)
+            .is_rate_limited(quotas, scoping, Quantity::new(1, 0), false)
             .unwrap()
             .is_limited());
 
         // quota is now exhausted
         assert!(rate_limiter
-            .is_rate_limited(quotas, scoping, 1, false)
+            .is_rate_limited(quotas, scoping, Quantity::new(1, 0), false)
             .unwrap()
             .is_limited());
 
         // quota is exhausted, regardless of the quantity
         assert!(rate_limiter
-            .is_rate_limited(quotas, scoping, 0, false)
+            .is_rate_limited(quotas, scoping, Quantity::new(0, 0), false)
             .unwrap()
             .is_limited());
 
         // quota is exhausted, regardless of the quantity
         assert!(rate_limiter
-            .is_rate_limited(quotas, scoping, 1, false)
+            .is_rate_limited(quotas, scoping, Quantity::new(1, 0), false)
             .unwrap()
             .is_limited());
     }
@@ -434,6 +640,8 @@ mod tests {
             scope_id: None,
             limit: Some(2),
             window: Some(60),
+            burst: None,
+            unit: None,
             reason_code: Some(ReasonCode::new("get_lost")),
         }];
 
@@ -445,34 +653,35 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            values: ScopeValues::default(),
         };
 
         let rate_limiter = build_rate_limiter();
 
         // limit is 2, so first call not rate limited
         let is_limited = rate_limiter
-            .is_rate_limited(quotas, scoping, 1, true)
+            .is_rate_limited(quotas, scoping, Quantity::new(1, 0), true)
             .unwrap()
             .is_limited();
         assert!(!is_limited);
 
         // go over limit, but first call is over-accepted
         let is_limited = rate_limiter
-            .is_rate_limited(quotas, scoping, 2, true)
+            .is_rate_limited(quotas, scoping, Quantity::new(2, 0), true)
             .unwrap()
             .is_limited();
         assert!(!is_limited);
 
         // quota is exhausted, regardless of the quantity
         let is_limited = rate_limiter
-            .is_rate_limited(quotas, scoping, 0, true)
+            .is_rate_limited(quotas, scoping, Quantity::new(0, 0), true)
             .unwrap()
             .is_limited();
         assert!(is_limited);
 
         // quota is exhausted, regardless of the quantity
         let is_limited = rate_limiter
-            .is_rate_limited(quotas, scoping, 1, true)
+            .is_rate_limited(quotas, scoping, Quantity::new(1, 0), true)
             .unwrap()
             .is_limited();
         assert!(is_limited);
@@ -488,10 +697,11 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            values: ScopeValues::default(),
         };
 
         let rate_limits: Vec<RateLimit> = build_rate_limiter()
-            .is_rate_limited(&[], scoping, 1, false)
+            .is_rate_limited(&[], scoping, Quantity::new(1, 0), false)
             .expect("rate limiting failed")
             .into_iter()
             .collect();
@@ -509,6 +719,8 @@ mod tests {
                 scope_id: None,
                 limit: None,
                 window: Some(1),
+                burst: None,
+                unit: None,
                 reason_code: Some(ReasonCode::new("project_quota0")),
             },
             Quota {
@@ -518,6 +730,8 @@ mod tests {
                 scope_id: None,
                 limit: Some(1),
                 window: Some(1),
+                burst: None,
+                unit: None,
                 reason_code: Some(ReasonCode::new("project_quota1")),
             },
         ];
@@ -530,13 +744,14 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            values: ScopeValues::default(),
         };
 
         let rate_limiter = build_rate_limiter();
 
         for i in 0..1 {
             let rate_limits: Vec<RateLimit> = rate_limiter
-                .is_rate_limited(quotas, scoping, 1, false)
+                .is_rate_limited(quotas, scoping, Quantity::new(1, 0), false)
                 .expect("rate limiting failed")
                 .into_iter()
                 .collect();
@@ -566,6 +781,8 @@ mod tests {
             scope_id: None,
             limit: Some(500),
             window: Some(60),
+            burst: None,
+            unit: None,
             reason_code: Some(ReasonCode::new("get_lost")),
         }];
 
@@ -577,13 +794,14 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            values: ScopeValues::default(),
         };
 
         let rate_limiter = build_rate_limiter();
 
         for i in 0..10 {
             let rate_limits: Vec<RateLimit> = rate_limiter
-                .is_rate_limited(quotas, scoping, 100, false)
+                .is_rate_limited(quotas, scoping, Quantity::new(100, 0), false)
                 .expect("rate limiting failed")
                 .into_iter()
                 .collect();
@@ -612,6 +830,8 @@ mod tests {
             scope: QuotaScope::Project,
             scope_id: Some("42".to_owned()),
             window: Some(2),
+            burst: None,
+            unit: None,
             limit: Some(0),
             reason_code: None,
         };
@@ -624,6 +844,7 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(4711),
             },
+            values: ScopeValues::default(),
         };
 
         let timestamp = UnixTimestamp::from_secs(123_123_123);
@@ -639,6 +860,8 @@ mod tests {
             scope: QuotaScope::Organization,
             scope_id: None,
             window: Some(10),
+            burst: None,
+            unit: None,
             limit: Some(0),
             reason_code: None,
         };
@@ -651,6 +874,7 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(4711),
             },
+            values: ScopeValues::default(),
         };
 
         let timestamp = UnixTimestamp::from_secs(234_531);
@@ -658,6 +882,38 @@ mod tests {
         assert_eq!(redis_quota.key(), "quota:foo{69420}:23453");
     }
 
+    #[test]
+    fn test_get_redis_key_bucket() {
+        let quota = Quota {
+            id: Some("foo".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Project,
+            scope_id: None,
+            window: Some(10),
+            burst: Some(20),
+            unit: None,
+            limit: Some(5),
+            reason_code: None,
+        };
+
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            scoping: &Scoping {
+                organization_id: 69420,
+                project_id: ProjectId::new(42),
+                project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+                key_id: Some(4711),
+            },
+            values: ScopeValues::default(),
+        };
+
+        let timestamp = UnixTimestamp::from_secs(234_531);
+        let redis_quota = RedisQuota::new(&quota, scoping, timestamp).unwrap();
+        assert_eq!(redis_quota.key(), "quota:foo{69420}42:bucket");
+        assert_eq!(redis_quota.burst(), 20);
+        assert_eq!(redis_quota.key_expiry(), 234_531 + 40 + GRACE);
+    }
+
     #[test]
     fn test_large_redis_limit_large() {
         let quota = Quota {
@@ -666,6 +922,8 @@ mod tests {
             scope: QuotaScope::Organization,
             scope_id: None,
             window: Some(10),
+            burst: None,
+            unit: None,
             limit: Some(9223372036854775808), // i64::MAX + 1
             reason_code: None,
         };
@@ -678,6 +936,7 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(4711),
             },
+            values: ScopeValues::default(),
         };
 
         let timestamp = UnixTimestamp::from_secs(234_531);
@@ -718,10 +977,16 @@ mod tests {
             .arg(now + 60) // expiry
             .arg(1) // quantity
             .arg(false) // over accept once
+            .arg(-1) // burst
+            .arg(0) // refill rate
+            .arg(now) // timestamp
             .arg(2) // limit
             .arg(now + 120) // expiry
             .arg(1) // quantity
-            .arg(false); // over accept once
+            .arg(false) // over accept once
+            .arg(-1) // burst
+            .arg(0) // refill rate
+            .arg(now); // timestamp
 
         // The item should not be rate limited by either key.
         assert_eq!(
@@ -768,7 +1033,10 @@ mod tests {
             .arg(1) // limit
             .arg(now + 60) // expiry
             .arg(1) // quantity
-            .arg(false);
+            .arg(false) // over accept once
+            .arg(-1) // burst
+            .arg(0) // refill rate
+            .arg(now); // timestamp
 
         // increment
         assert_eq!(
@@ -789,7 +1057,10 @@ mod tests {
             .arg(1) // limit
             .arg(now + 60) // expiry
             .arg(1) // quantity
-            .arg(false);
+            .arg(false) // over accept once
+            .arg(-1) // burst
+            .arg(0) // refill rate
+            .arg(now); // timestamp
 
         // test that refund key is used
         assert_eq!(
@@ -797,4 +1068,51 @@ mod tests {
             vec![false]
         );
     }
+
+    #[test]
+    fn test_is_rate_limited_script_bucket() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap();
+
+        let rate_limiter = build_rate_limiter();
+        let mut client = rate_limiter.pool.client().expect("get client");
+        let mut conn = client.connection();
+
+        let bucket = format!("bucket___{now}");
+        let r_bucket = format!("r:bucket___{now}");
+
+        let script = load_lua_script();
+
+        let mut invoke = |quantity: u64, now: u64| {
+            script
+                .prepare_invoke()
+                .key(&bucket) // key
+                .key(&r_bucket) // refund key
+                .arg(10) // limit
+                .arg(now + 120) // expiry
+                .arg(quantity) // quantity
+                .arg(false) // over accept once
+                .arg(3) // burst
+                .arg(0.5) // refill rate
+                .arg(now) // timestamp
+                .invoke::<Vec<u64>>(&mut conn)
+                .unwrap()
+        };
+
+        // The bucket starts full and accepts the entire burst at once.
+        assert_eq!(invoke(3, now), vec![0]);
+
+        // The bucket is empty, the next token is refilled after 2 seconds.
+        assert_eq!(invoke(1, now), vec![2]);
+        assert_eq!(invoke(1, now + 2), vec![0]);
+
+        // The bucket never refills beyond its burst size.
+        assert_eq!(invoke(3, now + 100), vec![0]);
+        assert_eq!(invoke(1, now + 100), vec![2]);
+
+        // The refund key is never written for token buckets.
+        let () = conn.get(r_bucket).unwrap();
+    }
 }
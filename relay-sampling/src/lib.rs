@@ -654,6 +654,12 @@ impl FieldValueProvider for Event {
                     }
                 })
             }),
+            "user.geo.country_code" => self
+                .user
+                .value()
+                .and_then(|user| user.geo.value())
+                .and_then(|geo| geo.country_code.as_str())
+                .map_or(Value::Null, Value::from),
 
             // Partial implementation of contexts.
             "contexts.device.name" => self
@@ -1378,7 +1384,7 @@ mod tests {
     use similar_asserts::assert_eq;
 
     use relay_general::protocol::{
-        Contexts, Csp, DeviceContext, EventId, Exception, Headers, IpAddr, JsonLenientString,
+        Contexts, Csp, DeviceContext, EventId, Exception, Geo, Headers, IpAddr, JsonLenientString,
         LenientString, LogEntry, OsContext, PairList, Request, TagEntry, Tags, User, Values,
     };
     use relay_general::types::Annotated;
@@ -1723,6 +1729,10 @@ mod tests {
                 ip_address: Annotated::new(IpAddr("127.0.0.1".to_owned())),
                 id: Annotated::new(LenientString("user-id".into())),
                 segment: Annotated::new("user-seg".into()),
+                geo: Annotated::new(Geo {
+                    country_code: Annotated::new("AT".to_owned()),
+                    ..Default::default()
+                }),
                 ..Default::default()
             }),
             exceptions: Annotated::new(Values {
@@ -1775,6 +1785,10 @@ mod tests {
             Some("user-seg"),
             event.get_value("event.user.segment").as_str()
         );
+        assert_eq!(
+            Some("AT"),
+            event.get_value("event.user.geo.country_code").as_str()
+        );
         assert_eq!(Value::Bool(true), event.get_value("event.is_local_ip"),);
         assert_eq!(
             Value::Bool(true),
@@ -1834,6 +1848,7 @@ mod tests {
 
         assert_eq!(Value::Null, event.get_value("event.user.id"));
         assert_eq!(Value::Null, event.get_value("event.user.segment"));
+        assert_eq!(Value::Null, event.get_value("event.user.geo.country_code"));
         assert_eq!(Value::Null, event.get_value("event.transaction"));
     }
 
@@ -19,9 +19,9 @@ use crate::actors::processor::{EncodeEnvelope, EnvelopeProcessor};
 use crate::actors::project_cache::{ProjectCache, UpdateRateLimits};
 #[cfg(feature = "processing")]
 use crate::actors::store::{Store, StoreEnvelope, StoreError};
-use crate::actors::test_store::{Capture, TestStore};
+use crate::actors::test_store::{Capture, LifecycleStage, TestStore};
 use crate::actors::upstream::{
-    Method, SendRequest, UpstreamRelay, UpstreamRequest, UpstreamRequestError,
+    self, Method, SendRequest, UpstreamRelay, UpstreamRequest, UpstreamRequestError,
 };
 use crate::envelope::{self, ContentType, Envelope, EnvelopeError, Item, ItemType};
 use crate::extractors::{PartialDsn, RequestMeta};
@@ -80,11 +80,23 @@ impl UpstreamRequest for SendEnvelope {
         "envelope"
     }
 
-    fn build(&mut self, _: &Config, builder: RequestBuilder) -> Result<Request, HttpError> {
+    fn build(&mut self, config: &Config, builder: RequestBuilder) -> Result<Request, HttpError> {
         let envelope_body = &self.envelope_body;
         metric!(histogram(RelayHistograms::UpstreamEnvelopeBodySize) = envelope_body.len() as u64);
 
         let meta = &self.envelope_meta;
+
+        // Authenticate this Relay by signing the DSN key, so that the upstream can respond with
+        // rate limits of all scopes.
+        let (signature, next_signature) = match config.credentials() {
+            Some(credentials) => {
+                let (signature, next_signature) =
+                    upstream::sign_request(credentials, meta.public_key().as_str().as_bytes());
+                (Some(signature), next_signature)
+            }
+            None => (None, None),
+        };
+
         builder
             .content_encoding(self.http_encoding)
             .header_opt("Origin", meta.origin().map(|url| url.as_str()))
@@ -93,6 +105,8 @@ impl UpstreamRequest for SendEnvelope {
             .header("X-Forwarded-For", meta.forwarded_for())
             .header("Content-Type", envelope::CONTENT_TYPE)
             .header_opt("X-Sentry-Relay-Shard", self.partition_key.as_ref())
+            .header_opt("X-Sentry-Relay-Signature", signature)
+            .header_opt("X-Sentry-Relay-Next-Signature", next_signature)
             .body(envelope_body)
     }
 
@@ -296,6 +310,18 @@ impl EnvelopeManagerService {
         }
     }
 
+    /// Returns the lifecycle stage of envelopes that have been submitted successfully.
+    fn submitted_stage(&self) -> LifecycleStage {
+        #[cfg(feature = "processing")]
+        {
+            if self.store_forwarder.is_some() {
+                return LifecycleStage::Stored;
+            }
+        }
+
+        LifecycleStage::SentUpstream
+    }
+
     async fn handle_submit(&self, message: SubmitEnvelope) {
         let SubmitEnvelope { mut envelope } = message;
 
@@ -304,9 +330,11 @@ impl EnvelopeManagerService {
         let inner_envelope = envelope.take_envelope();
         match self.submit_envelope(inner_envelope, scoping, None).await {
             Ok(_) => {
+                envelope.record(self.submitted_stage());
                 envelope.accept();
             }
             Err(SendEnvelopeError::UpstreamRequestFailed(e)) if e.is_received() => {
+                envelope.record(self.submitted_stage());
                 envelope.accept();
             }
             Err(error) => {
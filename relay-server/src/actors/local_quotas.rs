@@ -0,0 +1,125 @@
+//! This module contains the service that maintains locally tracked quotas.
+//!
+//! Non-processing Relays can enforce quotas in memory with a [`LocalRateLimiter`]. This service
+//! periodically removes counters of elapsed windows and, if configured, reports the consumed
+//! quota to the upstream.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use relay_config::Config;
+use relay_quotas::{LocalRateLimiter, QuotaUsage};
+use relay_system::{Addr, Service};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::actors::upstream::{Method, SendQuery, UpstreamQuery, UpstreamRelay};
+
+/// The interval at which counters of elapsed windows are removed.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Defines the structure of the HTTP quota usage requests.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SendQuotaUsage {
+    #[serde(default)]
+    pub usage: Vec<QuotaUsage>,
+}
+
+impl UpstreamQuery for SendQuotaUsage {
+    type Response = SendQuotaUsageResponse;
+
+    fn method(&self) -> Method {
+        Method::POST
+    }
+
+    fn path(&self) -> Cow<'static, str> {
+        Cow::Borrowed("/api/0/relays/quotas/usage/")
+    }
+
+    fn retry() -> bool {
+        false
+    }
+
+    fn route(&self) -> &'static str {
+        "quota_usage"
+    }
+}
+
+/// Defines the structure of the HTTP quota usage responses for successful requests.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SendQuotaUsageResponse {
+    // nothing yet, future features will go here
+}
+
+/// Service that prunes the counters of a [`LocalRateLimiter`] and reports its usage.
+#[derive(Debug)]
+pub struct LocalQuotasService {
+    rate_limiter: LocalRateLimiter,
+    upstream_relay: Addr<UpstreamRelay>,
+    sync_interval: Option<Duration>,
+}
+
+impl LocalQuotasService {
+    /// Creates a new service for the given rate limiter.
+    pub fn new(
+        config: Arc<Config>,
+        rate_limiter: LocalRateLimiter,
+        upstream_relay: Addr<UpstreamRelay>,
+    ) -> Self {
+        Self {
+            rate_limiter,
+            upstream_relay,
+            sync_interval: config.local_quotas_sync_interval(),
+        }
+    }
+
+    async fn sync_usage(&self) {
+        let usage = self.rate_limiter.flush_usage();
+        if usage.is_empty() {
+            return;
+        }
+
+        relay_log::trace!("sending quota usage of size {}", usage.len());
+        match self
+            .upstream_relay
+            .send(SendQuery(SendQuotaUsage { usage }))
+            .await
+        {
+            Ok(Ok(_)) => relay_log::trace!("quota usage sent"),
+            Ok(Err(error)) => {
+                relay_log::error!(error = &error as &dyn Error, "quota usage sending failed")
+            }
+            Err(error) => {
+                relay_log::error!(error = &error as &dyn Error, "quota usage sending failed")
+            }
+        }
+    }
+}
+
+impl Service for LocalQuotasService {
+    type Interface = ();
+
+    fn spawn_handler(self, _rx: relay_system::Receiver<Self::Interface>) {
+        tokio::spawn(async move {
+            let mut prune_ticker =
+                tokio::time::interval_at(Instant::now() + PRUNE_INTERVAL, PRUNE_INTERVAL);
+
+            let sync_interval = self.sync_interval.unwrap_or(PRUNE_INTERVAL);
+            let mut sync_ticker =
+                tokio::time::interval_at(Instant::now() + sync_interval, sync_interval);
+
+            loop {
+                tokio::select! {
+                    biased;
+
+                    _ = prune_ticker.tick() => self.rate_limiter.prune(),
+                    _ = sync_ticker.tick(), if self.sync_interval.is_some() => {
+                        self.sync_usage().await
+                    }
+                }
+            }
+        });
+    }
+}
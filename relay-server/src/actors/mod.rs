@@ -30,6 +30,7 @@
 //! ```
 pub mod envelopes;
 pub mod health_check;
+pub mod local_quotas;
 pub mod outcome;
 pub mod outcome_aggregator;
 pub mod processor;
@@ -39,6 +40,8 @@ pub mod project_local;
 pub mod project_upstream;
 pub mod relays;
 pub mod server;
+#[cfg_attr(not(feature = "processing"), allow(dead_code))]
+pub mod sink;
 pub mod spooler;
 pub mod test_store;
 pub mod upstream;
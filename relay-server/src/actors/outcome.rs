@@ -8,32 +8,38 @@ use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::error::Error;
+use std::io::{self, Write};
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
-use std::{fmt, mem};
+use std::{fmt, fs, mem};
 
-#[cfg(feature = "processing")]
 use anyhow::Context;
+use bytes::Bytes;
 use chrono::{DateTime, SecondsFormat, Utc};
-use relay_common::{DataCategory, ProjectId, UnixTimestamp};
-use relay_config::{Config, EmitOutcomes};
+use relay_common::{DataCategory, ProjectId, UnixTimestamp, Uuid};
+use relay_config::{ByteSize, Config, EmitOutcomes, OutcomeExportConfig};
 use relay_filter::FilterStatKey;
 use relay_general::protocol::{ClientReport, DiscardedEvent, EventId};
 #[cfg(feature = "processing")]
-use relay_kafka::{ClientError, KafkaClient, KafkaTopic};
+use relay_kafka::{
+    ClientError, FieldType, KafkaClient, KafkaTopic, Message, MessageAttributes, MessageSchema,
+    SchemaField,
+};
 use relay_quotas::{ReasonCode, Scoping};
 use relay_sampling::MatchedRuleIds;
 use relay_statsd::metric;
-use relay_system::{Addr, FromMessage, Interface, NoResponse, Service};
+use relay_system::{Addr, Controller, FromMessage, Interface, NoResponse, Service, Shutdown};
 use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
 
 use crate::actors::envelopes::{EnvelopeManager, SendClientReports};
 use crate::actors::upstream::{Method, SendQuery, UpstreamQuery, UpstreamRelay};
 #[cfg(feature = "processing")]
 use crate::service::ServiceError;
 use crate::statsd::RelayCounters;
-use crate::utils::SleepHandle;
+use crate::utils::{RetryBackoff, SleepHandle};
 
 /// Defines the structure of the HTTP outcomes requests
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -122,6 +128,11 @@ pub struct TrackOutcome {
     pub category: DataCategory,
     /// The number of events or total attachment size in bytes.
     pub quantity: u32,
+    /// The total size of the items in bytes, or `0` if the size is not known.
+    ///
+    /// This is only reported for categories whose storage is accounted in bytes, see
+    /// [`TrackRawOutcome::bytes`].
+    pub bytes: u32,
 }
 
 impl TrackOutcomeLike for TrackOutcome {
@@ -349,6 +360,18 @@ pub enum DiscardReason {
     /// indicates bugs in Relay, rather than an expected failure.
     Internal,
 
+    /// (Relay) The envelope was evicted from the buffer to make room for an envelope with higher
+    /// priority.
+    Evicted,
+
+    /// (Relay) The envelope buffer was full and the envelope could not evict any envelope with
+    /// lower priority.
+    BufferFull,
+
+    /// (Relay) The ingestion token was missing but required by the project, invalid, expired, or
+    /// did not permit the envelope.
+    IngestionToken,
+
     /// (Relay) Symbolic failed to extract an Unreal Crash report from a request sent to the
     /// Unreal endpoint
     ProcessUnreal,
@@ -363,6 +386,10 @@ pub enum DiscardReason {
     InvalidReplayEventPii,
     InvalidReplayRecordingEvent,
 
+    /// (Relay) A client report contained quantities that were clamped or a timestamp outside of
+    /// the supported time range, so its contents could not be trusted.
+    ImplausibleClientReport,
+
     /// (Relay) Profiling related discard reasons
     Profiling(&'static str),
 }
@@ -402,10 +429,14 @@ impl DiscardReason {
             DiscardReason::Internal => "internal",
             DiscardReason::TransactionSampled => "transaction_sampled",
             DiscardReason::EmptyEnvelope => "empty_envelope",
+            DiscardReason::Evicted => "evicted",
+            DiscardReason::BufferFull => "buffer_full",
+            DiscardReason::IngestionToken => "ingestion_token",
             DiscardReason::InvalidReplayEvent => "invalid_replay",
             DiscardReason::InvalidReplayEventNoPayload => "invalid_replay_no_payload",
             DiscardReason::InvalidReplayEventPii => "invalid_replay_pii_scrubber_failed",
             DiscardReason::InvalidReplayRecordingEvent => "invalid_replay_recording",
+            DiscardReason::ImplausibleClientReport => "implausible_client_report",
             DiscardReason::Profiling(reason) => reason,
         }
     }
@@ -453,6 +484,22 @@ pub struct TrackRawOutcome {
     /// The number of events or total attachment size in bytes.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub quantity: Option<u32>,
+    /// The total size of the items in bytes, if known.
+    ///
+    /// This is reported for attachments, profiles and replays.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u32>,
+}
+
+/// Returns `true` if outcomes of the given category report the size of items in bytes.
+fn tracks_bytes(category: DataCategory) -> bool {
+    matches!(
+        category,
+        DataCategory::Attachment
+            | DataCategory::Profile
+            | DataCategory::ProfileIndexed
+            | DataCategory::Replay
+    )
 }
 
 impl TrackRawOutcome {
@@ -484,6 +531,38 @@ impl TrackRawOutcome {
             source,
             category: msg.category.value(),
             quantity: Some(msg.quantity),
+            bytes: (tracks_bytes(msg.category) && msg.bytes > 0).then_some(msg.bytes),
+        }
+    }
+
+    /// Creates an internal error outcome for data that was lost after it left the store.
+    ///
+    /// Unlike [`TrackOutcome`], this does not require a full [`Scoping`], which is no longer
+    /// available for messages that fail delivery to Kafka.
+    #[cfg(feature = "processing")]
+    pub fn internal_error(
+        organization_id: u64,
+        project_id: ProjectId,
+        event_id: Option<EventId>,
+        category: DataCategory,
+        quantity: u32,
+        config: &Config,
+    ) -> Self {
+        let outcome = Outcome::Invalid(DiscardReason::Internal);
+
+        TrackRawOutcome {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            org_id: Some(organization_id).filter(|id| *id != 0),
+            project_id,
+            key_id: None,
+            outcome: outcome.to_outcome_id(),
+            reason: outcome.to_reason().map(|reason| reason.to_string()),
+            event_id,
+            remote_addr: None,
+            source: config.outcome_source().map(str::to_owned),
+            category: category.value(),
+            quantity: Some(quantity),
+            bytes: None,
         }
     }
 
@@ -503,6 +582,62 @@ impl TrackOutcomeLike for TrackRawOutcome {
     }
 }
 
+/// Schema of [`TrackRawOutcome`] for Kafka topics with a schema-based encoding.
+#[cfg(feature = "processing")]
+static OUTCOME_SCHEMA: MessageSchema = MessageSchema {
+    name: "Outcome",
+    fields: &[
+        SchemaField::new("timestamp", 1, FieldType::String),
+        SchemaField::new("org_id", 2, FieldType::Optional(&FieldType::Long)),
+        SchemaField::new("project_id", 3, FieldType::Long),
+        SchemaField::new("key_id", 4, FieldType::Optional(&FieldType::Long)),
+        SchemaField::new("outcome", 5, FieldType::Long),
+        SchemaField::new("reason", 6, FieldType::Optional(&FieldType::String)),
+        SchemaField::new("event_id", 7, FieldType::Optional(&FieldType::String)),
+        SchemaField::new("remote_addr", 8, FieldType::Optional(&FieldType::String)),
+        SchemaField::new("source", 9, FieldType::Optional(&FieldType::String)),
+        SchemaField::new("category", 10, FieldType::Optional(&FieldType::Long)),
+        SchemaField::new("quantity", 11, FieldType::Optional(&FieldType::Long)),
+        SchemaField::new("bytes", 12, FieldType::Optional(&FieldType::Long)),
+    ],
+};
+
+#[cfg(feature = "processing")]
+impl Message for TrackRawOutcome {
+    /// Returns the event ID of the outcome, or a random key to spread outcomes without event.
+    fn key(&self) -> [u8; 16] {
+        // At the moment, we support outcomes with optional EventId.
+        // Here we create a fake EventId, when we don't have the real one, so that we can
+        // create a kafka message key that spreads the events nicely over all the
+        // kafka consumer groups.
+        *self.event_id.unwrap_or_else(EventId::new).0.as_bytes()
+    }
+
+    fn variant(&self) -> &'static str {
+        "outcome"
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, ClientError> {
+        serde_json::to_vec(self).map_err(ClientError::InvalidJson)
+    }
+
+    fn schema(&self) -> Option<&'static MessageSchema> {
+        Some(&OUTCOME_SCHEMA)
+    }
+
+    fn to_value(&self) -> Result<serde_json::Value, ClientError> {
+        serde_json::to_value(self).map_err(ClientError::InvalidJson)
+    }
+
+    fn attributes(&self) -> MessageAttributes {
+        MessageAttributes {
+            project_id: Some(self.project_id.value()),
+            item_type: Some("outcome"),
+            ..Default::default()
+        }
+    }
+}
+
 impl Interface for TrackRawOutcome {}
 
 impl FromMessage<Self> for TrackRawOutcome {
@@ -519,9 +654,6 @@ pub enum OutcomeError {
     #[error("failed to send kafka message")]
     #[cfg(feature = "processing")]
     SendFailed(ClientError),
-    #[error("json serialization error")]
-    #[cfg(feature = "processing")]
-    SerializationError(serde_json::Error),
 }
 
 /// Outcome producer backend via HTTP as [`TrackRawOutcome`].
@@ -696,6 +828,345 @@ impl Service for ClientReportOutcomeProducer {
     }
 }
 
+/// File extension of outcome files that are still being written.
+const EXPORT_TEMP_EXTENSION: &str = "tmp";
+
+/// File extension of rotated outcome files.
+const EXPORT_EXTENSION: &str = "ndjson";
+
+/// An outcome file that is being written by [`FileOutcomeProducer`].
+#[derive(Debug)]
+struct OutcomeFile {
+    path: PathBuf,
+    file: fs::File,
+    size: u64,
+}
+
+impl OutcomeFile {
+    /// Creates a new file with a unique name, which sorts by the time of its creation.
+    fn create(directory: &Path) -> io::Result<Self> {
+        let name = format!(
+            "outcomes-{}-{}",
+            Utc::now().format("%Y%m%dT%H%M%SZ"),
+            Uuid::new_v4().simple()
+        );
+        let path = directory.join(name).with_extension(EXPORT_TEMP_EXTENSION);
+        let file = fs::OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(&path)?;
+
+        Ok(Self {
+            path,
+            file,
+            size: 0,
+        })
+    }
+
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Completes the file and renames it so that it can be picked up.
+    fn finish(self) -> io::Result<()> {
+        let Self { path, file, .. } = self;
+        file.sync_data()?;
+        drop(file);
+        fs::rename(&path, path.with_extension(EXPORT_EXTENSION))
+    }
+}
+
+/// Outcome producer backend writing [`TrackRawOutcome`]s into rotated NDJSON files.
+///
+/// Outcomes are appended to a file with the `.tmp` extension, one JSON object per line. Once the
+/// file exceeds its maximum size or age, it is renamed to the `.ndjson` extension and a new file
+/// is started with the next outcome.
+#[derive(Debug)]
+struct FileOutcomeProducer {
+    directory: PathBuf,
+    max_file_size: u64,
+    max_file_age: Duration,
+    current: Option<OutcomeFile>,
+    rotate_handle: SleepHandle,
+}
+
+impl FileOutcomeProducer {
+    fn create(directory: &Path, max_file_size: ByteSize, max_file_age: u64) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+
+        // Files of a previous run have not been rotated. Complete them so they are not lost.
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(false, |ext| ext == EXPORT_TEMP_EXTENSION)
+            {
+                fs::rename(&path, path.with_extension(EXPORT_EXTENSION))?;
+            }
+        }
+
+        Ok(Self {
+            directory: directory.to_owned(),
+            max_file_size: max_file_size.as_bytes() as u64,
+            max_file_age: Duration::from_secs(max_file_age),
+            current: None,
+            rotate_handle: SleepHandle::idle(),
+        })
+    }
+
+    fn write(&mut self, message: &TrackRawOutcome) -> io::Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+
+        let file = match self.current {
+            Some(ref mut file) => file,
+            None => {
+                self.rotate_handle.set(self.max_file_age);
+                self.current.insert(OutcomeFile::create(&self.directory)?)
+            }
+        };
+
+        file.write(&line)?;
+        if file.size >= self.max_file_size {
+            self.rotate();
+        }
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) {
+        self.rotate_handle.reset();
+
+        if let Some(file) = self.current.take() {
+            relay_log::trace!("rotating outcome file");
+            if let Err(error) = file.finish() {
+                relay_log::error!(
+                    error = &error as &dyn Error,
+                    "failed to rotate outcome file"
+                );
+            }
+        }
+    }
+
+    fn handle_message(&mut self, message: TrackRawOutcome) {
+        if let Err(error) = self.write(&message) {
+            relay_log::error!(error = &error as &dyn Error, "failed to write outcome file");
+        }
+    }
+}
+
+impl Service for FileOutcomeProducer {
+    type Interface = TrackRawOutcome;
+
+    fn spawn_handler(mut self, mut rx: relay_system::Receiver<Self::Interface>) {
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    // Prioritize rotation over receiving messages to prevent starving.
+                    biased;
+
+                    () = &mut self.rotate_handle => self.rotate(),
+                    Some(message) = rx.recv() => self.handle_message(message),
+                    else => break,
+                }
+            }
+
+            self.rotate();
+        });
+    }
+}
+
+/// Outcome producer backend sending batches of [`TrackRawOutcome`]s to a webhook.
+///
+/// Batches are sent with the same body as [`SendOutcomes`]. Failed requests are retried with
+/// exponential backoff until the maximum number of retries is reached, after which the outcomes
+/// of the batch are dropped.
+///
+/// At most `max_concurrent_requests` batches are sent or retried at the same time. Once this limit
+/// is reached, the producer waits for a request to complete before it sends the next batch. On
+/// shutdown, the pending batch is sent and in-flight requests complete within the timeout.
+#[derive(Debug)]
+struct WebhookOutcomeProducer {
+    config: Arc<Config>,
+    client: reqwest::Client,
+    url: reqwest::Url,
+    max_retries: u32,
+    max_concurrent_requests: u32,
+    requests: Arc<Semaphore>,
+    unsent_outcomes: Vec<TrackRawOutcome>,
+    flush_handle: SleepHandle,
+}
+
+impl WebhookOutcomeProducer {
+    fn create(
+        config: Arc<Config>,
+        url: &str,
+        headers: &BTreeMap<String, String>,
+        max_retries: u32,
+        max_concurrent_requests: usize,
+    ) -> anyhow::Result<Self> {
+        let url = url.parse().context("invalid outcome webhook url")?;
+
+        let mut header_map = reqwest::header::HeaderMap::new();
+        for (name, value) in headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .context("invalid outcome webhook header")?;
+            let value = reqwest::header::HeaderValue::from_str(value)
+                .context("invalid outcome webhook header")?;
+            header_map.insert(name, value);
+        }
+
+        let client = reqwest::Client::builder()
+            .connect_timeout(config.http_connection_timeout())
+            .timeout(config.http_timeout())
+            .default_headers(header_map)
+            .build()?;
+
+        let max_concurrent_requests = max_concurrent_requests.clamp(1, u32::MAX as usize) as u32;
+
+        Ok(Self {
+            config,
+            client,
+            url,
+            max_retries,
+            max_concurrent_requests,
+            requests: Arc::new(Semaphore::new(max_concurrent_requests as usize)),
+            unsent_outcomes: Vec::new(),
+            flush_handle: SleepHandle::idle(),
+        })
+    }
+
+    async fn send_batch(&mut self) {
+        self.flush_handle.reset();
+
+        if self.unsent_outcomes.is_empty() {
+            return;
+        }
+
+        // The semaphore is never closed, so acquiring a permit cannot fail.
+        let permit = match Arc::clone(&self.requests).acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => return,
+        };
+
+        let batch = SendOutcomes {
+            outcomes: mem::take(&mut self.unsent_outcomes),
+        };
+        let count = batch.outcomes.len();
+
+        let body = match serde_json::to_vec(&batch) {
+            Ok(body) => Bytes::from(body),
+            Err(error) => {
+                relay_log::error!(error = &error as &dyn Error, "failed to serialize outcomes");
+                return;
+            }
+        };
+
+        let client = self.client.clone();
+        let url = self.url.clone();
+        let max_retries = self.max_retries as usize;
+        let mut backoff = RetryBackoff::new(self.config.http_max_retry_interval());
+
+        tokio::spawn(async move {
+            // Release the permit once the batch has been sent or dropped.
+            let _permit = permit;
+
+            loop {
+                tokio::time::sleep(backoff.next_backoff()).await;
+
+                let result = client
+                    .post(url.clone())
+                    .header("content-type", "application/json")
+                    .body(body.clone())
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+
+                match result {
+                    Ok(_) => {
+                        relay_log::trace!("outcome batch of size {count} sent to webhook");
+                        return;
+                    }
+                    Err(error) if backoff.attempt() <= max_retries => {
+                        relay_log::debug!(
+                            error = &error as &dyn Error,
+                            "outcome webhook request failed, retrying"
+                        );
+                    }
+                    Err(error) => {
+                        relay_log::error!(
+                            error = &error as &dyn Error,
+                            dropped = count,
+                            "outcome webhook request failed"
+                        );
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    async fn handle_message(&mut self, message: TrackRawOutcome) {
+        self.unsent_outcomes.push(message);
+
+        if self.unsent_outcomes.len() >= self.config.outcome_batch_size() {
+            self.send_batch().await;
+        } else if self.flush_handle.is_idle() {
+            self.flush_handle.set(self.config.outcome_batch_interval());
+        }
+    }
+
+    /// Sends the pending batch and waits for all requests to complete.
+    async fn flush(&mut self) {
+        self.send_batch().await;
+
+        // Acquiring all permits waits for in-flight requests to complete.
+        let _permits = self
+            .requests
+            .acquire_many(self.max_concurrent_requests)
+            .await;
+    }
+
+    async fn handle_shutdown(&mut self, message: Shutdown) {
+        let timeout = match message.timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+
+        if tokio::time::timeout(timeout, self.flush()).await.is_err() {
+            relay_log::error!("outcome webhook requests did not complete before shutdown");
+        }
+    }
+}
+
+impl Service for WebhookOutcomeProducer {
+    type Interface = TrackRawOutcome;
+
+    fn spawn_handler(mut self, mut rx: relay_system::Receiver<Self::Interface>) {
+        tokio::spawn(async move {
+            let mut shutdown = Controller::shutdown_handle();
+
+            loop {
+                tokio::select! {
+                    // Prioritize flush over receiving messages to prevent starving. Shutdown can be
+                    // last since it is not vital if there are still messages in the channel.
+                    biased;
+
+                    () = &mut self.flush_handle => self.send_batch().await,
+                    Some(message) = rx.recv() => self.handle_message(message).await,
+                    shutdown = shutdown.notified() => self.handle_shutdown(shutdown).await,
+                    else => break,
+                }
+            }
+
+            // Outcomes received since the last flush would be lost otherwise.
+            self.flush().await;
+        });
+    }
+}
+
 /// Outcomes producer backend for Kafka.
 ///
 /// Internally, this type creates at least one Kafka producer for the cluster of the `outcomes`
@@ -744,7 +1215,8 @@ impl KafkaOutcomesProducer {
 ///  1. Kafka in processing mode
 ///  2. Upstream Relay via batch HTTP request in point-of-presence configuration
 ///  3. Upstream Relay via client reports in external configuration
-///  4. (default) Disabled
+///  4. Files or a webhook if `outcomes.export` is configured
+///  5. (default) Disabled
 #[derive(Debug)]
 pub enum OutcomeProducer {
     TrackOutcome(TrackOutcome),
@@ -782,6 +1254,7 @@ fn send_outcome_metric(message: &impl TrackOutcomeLike, to: &'static str) {
 enum OutcomeBroker {
     ClientReport(Addr<TrackOutcome>),
     Http(Addr<TrackRawOutcome>),
+    Export(Addr<TrackRawOutcome>),
     #[cfg(feature = "processing")]
     Kafka(KafkaOutcomesProducer),
     Disabled,
@@ -806,14 +1279,6 @@ impl OutcomeBroker {
 
         send_outcome_metric(&message, "kafka");
 
-        let payload = serde_json::to_string(&message).map_err(OutcomeError::SerializationError)?;
-
-        // At the moment, we support outcomes with optional EventId.
-        // Here we create a fake EventId, when we don't have the real one, so that we can
-        // create a kafka message key that spreads the events nicely over all the
-        // kafka consumer groups.
-        let key = message.event_id.unwrap_or_else(EventId::new).0;
-
         // Dispatch to the correct topic and cluster based on the kind of outcome.
         let topic = if message.is_billing() {
             KafkaTopic::OutcomesBilling
@@ -821,13 +1286,9 @@ impl OutcomeBroker {
             KafkaTopic::Outcomes
         };
 
-        let result = producer.client.send(
-            topic,
-            organization_id,
-            key.as_bytes(),
-            "outcome",
-            payload.as_bytes(),
-        );
+        let result = producer
+            .client
+            .send_message(topic, organization_id, &message);
 
         match result {
             Ok(_) => Ok(()),
@@ -868,6 +1329,10 @@ impl OutcomeBroker {
                 send_outcome_metric(&message, "http");
                 producer.send(TrackRawOutcome::from_outcome(message, config));
             }
+            Self::Export(producer) => {
+                send_outcome_metric(&message, "export");
+                producer.send(TrackRawOutcome::from_outcome(message, config));
+            }
             Self::Disabled => (),
         }
     }
@@ -885,6 +1350,10 @@ impl OutcomeBroker {
                 send_outcome_metric(&message, "http");
                 producer.send(message);
             }
+            Self::Export(producer) => {
+                send_outcome_metric(&message, "export");
+                producer.send(message);
+            }
             Self::ClientReport(_) => (),
             Self::Disabled => (),
         }
@@ -945,6 +1414,8 @@ enum ProducerInner {
     Kafka(KafkaOutcomesProducer),
     Http(HttpOutcomeProducer),
     ClientReport(ClientReportOutcomeProducer),
+    File(FileOutcomeProducer),
+    Webhook(WebhookOutcomeProducer),
     Disabled,
 }
 
@@ -955,6 +1426,8 @@ impl ProducerInner {
             ProducerInner::Kafka(inner) => OutcomeBroker::Kafka(inner),
             ProducerInner::Http(inner) => OutcomeBroker::Http(inner.start()),
             ProducerInner::ClientReport(inner) => OutcomeBroker::ClientReport(inner.start()),
+            ProducerInner::File(inner) => OutcomeBroker::Export(inner.start()),
+            ProducerInner::Webhook(inner) => OutcomeBroker::Export(inner.start()),
             ProducerInner::Disabled => OutcomeBroker::Disabled,
         }
     }
@@ -974,6 +1447,38 @@ impl OutcomeProducerService {
         envelope_manager: Addr<EnvelopeManager>,
     ) -> anyhow::Result<Self> {
         let inner = match config.emit_outcomes() {
+            EmitOutcomes::AsOutcomes if config.outcome_export().is_some() => {
+                // We export outcomes, and accept raw outcomes emitted by downstream Relays
+                match config.outcome_export() {
+                    Some(OutcomeExportConfig::File {
+                        path,
+                        max_file_size,
+                        max_file_age,
+                    }) => {
+                        relay_log::info!("Configured to export outcomes to files");
+                        ProducerInner::File(
+                            FileOutcomeProducer::create(path, *max_file_size, *max_file_age)
+                                .context("failed to create outcome export directory")?,
+                        )
+                    }
+                    Some(OutcomeExportConfig::Webhook {
+                        url,
+                        headers,
+                        max_retries,
+                        max_concurrent_requests,
+                    }) => {
+                        relay_log::info!("Configured to export outcomes to a webhook");
+                        ProducerInner::Webhook(WebhookOutcomeProducer::create(
+                            Arc::clone(&config),
+                            url,
+                            headers,
+                            *max_retries,
+                            *max_concurrent_requests,
+                        )?)
+                    }
+                    None => ProducerInner::Disabled,
+                }
+            }
             #[cfg(feature = "processing")]
             EmitOutcomes::AsOutcomes if config.processing_enabled() => {
                 // We emit raw outcomes, and accept raw outcomes emitted by downstream Relays
@@ -1022,3 +1527,207 @@ impl Service for OutcomeProducerService {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use relay_common::ProjectKey;
+    use relay_quotas::ReasonCode;
+    use relay_sampling::RuleId;
+
+    use super::*;
+
+    fn track_outcome(outcome: Outcome) -> TrackOutcome {
+        TrackOutcome {
+            timestamp: Utc::now(),
+            scoping: Scoping {
+                organization_id: 1,
+                project_id: ProjectId::new(42),
+                project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+                key_id: Some(3),
+            },
+            outcome,
+            event_id: None,
+            remote_addr: None,
+            category: DataCategory::Error,
+            quantity: 1,
+            bytes: 0,
+        }
+    }
+
+    fn count_files(directory: &Path, extension: &str) -> usize {
+        fs::read_dir(directory)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension().unwrap() == extension)
+            .count()
+    }
+
+    fn read_exported(directory: &Path) -> Vec<serde_json::Value> {
+        let mut paths = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut values = Vec::new();
+        for path in paths {
+            assert_eq!(path.extension().unwrap(), EXPORT_EXTENSION);
+            for line in fs::read_to_string(path).unwrap().lines() {
+                values.push(serde_json::from_str(line).unwrap());
+            }
+        }
+        values
+    }
+
+    #[tokio::test]
+    async fn test_export_file_outcomes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::default();
+
+        let outcomes = [
+            Outcome::Filtered(FilterStatKey::Localhost),
+            Outcome::FilteredSampling(MatchedRuleIds(vec![RuleId(1), RuleId(2)])),
+            Outcome::RateLimited(Some(ReasonCode::new("project_quota"))),
+            Outcome::RateLimited(None),
+            Outcome::Invalid(DiscardReason::Payload),
+            Outcome::Abuse,
+            Outcome::ClientDiscard("queue_overflow".to_owned()),
+        ];
+
+        let mut producer =
+            FileOutcomeProducer::create(dir.path(), ByteSize::mebibytes(1), 3600).unwrap();
+        for outcome in &outcomes {
+            let message = TrackRawOutcome::from_outcome(track_outcome(outcome.clone()), &config);
+            producer.handle_message(message);
+        }
+
+        // The current file is only completed after rotation.
+        assert_eq!(count_files(dir.path(), EXPORT_EXTENSION), 0);
+        producer.rotate();
+
+        let exported = read_exported(dir.path());
+        let exported = exported
+            .iter()
+            .map(|value| (value["outcome"].as_u64().unwrap(), value["reason"].as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            exported,
+            [
+                (1, Some("localhost")),
+                (1, Some("Sampled:1,2")),
+                (2, Some("project_quota")),
+                (2, None),
+                (3, Some("payload")),
+                (4, None),
+                (5, Some("queue_overflow")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_file_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::default();
+        let message = TrackRawOutcome::from_outcome(track_outcome(Outcome::Abuse), &config);
+        let size = serde_json::to_vec(&message).unwrap().len() as u32 + 1;
+
+        let mut producer =
+            FileOutcomeProducer::create(dir.path(), ByteSize::bytes(size * 2), 3600).unwrap();
+        for _ in 0..5 {
+            producer.handle_message(message.clone());
+        }
+
+        // Two full files have been rotated, and one outcome remains in the current file.
+        assert_eq!(count_files(dir.path(), EXPORT_EXTENSION), 2);
+        assert_eq!(count_files(dir.path(), EXPORT_TEMP_EXTENSION), 1);
+
+        // Reopening the directory completes files of the previous run.
+        drop(producer);
+        FileOutcomeProducer::create(dir.path(), ByteSize::mebibytes(1), 3600).unwrap();
+        assert_eq!(read_exported(dir.path()).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_export_webhook_flush() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let router = axum::Router::new().route(
+            "/outcomes",
+            axum::routing::post(move |body: Bytes| async move {
+                sender.send(body).ok();
+            }),
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/outcomes", listener.local_addr().unwrap());
+        let server = axum::Server::from_tcp(listener).unwrap();
+        tokio::spawn(server.serve(router.into_make_service()));
+
+        // Batches are neither full nor due, so outcomes are only sent when the producer stops.
+        let config = Config::from_json_value(serde_json::json!({
+            "outcomes": {"batch_size": 100, "batch_interval": 3600}
+        }))
+        .unwrap();
+        let config = Arc::new(config);
+
+        let producer =
+            WebhookOutcomeProducer::create(config.clone(), &url, &BTreeMap::new(), 0, 1).unwrap();
+        let addr = producer.start();
+        for _ in 0..2 {
+            addr.send(TrackRawOutcome::from_outcome(
+                track_outcome(Outcome::Abuse),
+                &config,
+            ));
+        }
+        drop(addr);
+
+        let body = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let batch: SendOutcomes = serde_json::from_slice(&body).unwrap();
+        assert_eq!(batch.outcomes.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "processing")]
+    fn test_outcome_schema() {
+        let outcome = TrackRawOutcome {
+            timestamp: "2023-05-23T10:00:00.000000Z".to_owned(),
+            org_id: Some(1),
+            project_id: ProjectId::new(42),
+            key_id: Some(3),
+            outcome: OutcomeId::RATE_LIMITED,
+            reason: Some("project_quota".to_owned()),
+            event_id: Some(EventId::new()),
+            remote_addr: Some("127.0.0.1".to_owned()),
+            source: Some("relay".to_owned()),
+            category: Some(DataCategory::Attachment as u8),
+            quantity: Some(1),
+            bytes: Some(1024),
+        };
+
+        let value = serde_json::to_value(&outcome).unwrap();
+        OUTCOME_SCHEMA.validate(&value).unwrap();
+
+        insta::assert_snapshot!(OUTCOME_SCHEMA.proto_schema(), @r###"
+        syntax = "proto3";
+
+        package sentry.relay;
+
+        message Outcome {
+          string timestamp = 1;
+          optional int64 org_id = 2;
+          int64 project_id = 3;
+          optional int64 key_id = 4;
+          int64 outcome = 5;
+          optional string reason = 6;
+          optional string event_id = 7;
+          optional string remote_addr = 8;
+          optional string source = 9;
+          optional int64 category = 10;
+          optional int64 quantity = 11;
+          optional int64 bytes = 12;
+        }
+        "###);
+    }
+}
@@ -16,7 +16,7 @@ use crate::actors::outcome::{DiscardReason, Outcome, OutcomeProducer, TrackOutco
 use crate::statsd::RelayTimers;
 use crate::utils::SleepHandle;
 
-/// Contains everything to construct a `TrackOutcome`, except quantity
+/// Contains everything to construct a `TrackOutcome`, except quantity and bytes
 #[derive(Debug, PartialEq, Eq, Hash)]
 struct BucketKey {
     /// The time slot for which outcomes are aggregated. timestamp = offset * bucket_interval
@@ -50,8 +50,8 @@ pub struct OutcomeAggregator {
     bucket_interval: u64,
     /// The number of seconds between flushes of all buckets
     flush_interval: u64,
-    /// Mapping from bucket key to quantity.
-    buckets: HashMap<BucketKey, u32>,
+    /// Mapping from bucket key to quantity and bytes.
+    buckets: HashMap<BucketKey, (u32, u32)>,
     /// The recipient of the aggregated outcomes
     outcome_producer: Addr<OutcomeProducer>,
     /// An optional timeout to the next scheduled flush.
@@ -113,8 +113,9 @@ impl OutcomeAggregator {
             category: msg.category,
         };
 
-        let counter = self.buckets.entry(bucket_key).or_insert(0);
-        *counter += msg.quantity;
+        let (quantity, bytes) = self.buckets.entry(bucket_key).or_insert((0, 0));
+        *quantity += msg.quantity;
+        *bytes += msg.bytes;
 
         if self.flush_interval == 0 {
             // Flush immediately. This is useful for integration tests.
@@ -131,7 +132,7 @@ impl OutcomeAggregator {
         let bucket_interval = self.bucket_interval;
         let outcome_producer = self.outcome_producer.clone();
 
-        for (bucket_key, quantity) in self.buckets.drain() {
+        for (bucket_key, (quantity, bytes)) in self.buckets.drain() {
             let BucketKey {
                 offset,
                 scoping,
@@ -153,6 +154,7 @@ impl OutcomeAggregator {
                 remote_addr,
                 category,
                 quantity,
+                bytes,
             };
 
             relay_log::trace!("Flushing outcome for timestamp {timestamp}");
@@ -21,7 +21,7 @@ use relay_common::{ProjectId, ProjectKey, UnixTimestamp};
 use relay_config::{Config, HttpEncoding};
 use relay_dynamic_config::{ErrorBoundary, Feature, ProjectConfig, SessionMetricsConfig};
 use relay_filter::FilterStatKey;
-use relay_general::pii::{PiiAttachmentsProcessor, PiiConfigError, PiiProcessor};
+use relay_general::pii::{PiiAttachmentsProcessor, PiiConfig, PiiConfigError, PiiProcessor};
 use relay_general::processor::{process_value, ProcessingState};
 use relay_general::protocol::Context::Trace;
 use relay_general::protocol::Contexts;
@@ -36,7 +36,9 @@ use relay_general::store::{
 use relay_general::types::{Annotated, Array, Empty, FromValue, Object, ProcessingAction, Value};
 use relay_general::user_agent::RawUserAgentInfo;
 use relay_metrics::{Bucket, InsertMetrics, MergeBuckets, Metric};
-use relay_quotas::{DataCategory, ReasonCode};
+use relay_quotas::{
+    DataCategory, ItemScoping, LocalRateLimiter, Quantity, Quota, RateLimits, ReasonCode,
+};
 use relay_redis::RedisPool;
 use relay_replays::recording::RecordingScrubber;
 use relay_sampling::{DynamicSamplingContext, MatchedRuleIds};
@@ -45,9 +47,8 @@ use relay_system::{Addr, FromMessage, NoResponse, Service};
 #[cfg(feature = "processing")]
 use {
     crate::actors::envelopes::SendMetrics,
-    crate::actors::project_cache::UpdateRateLimits,
     crate::service::ServiceError,
-    crate::utils::{EnvelopeLimiter, MetricsLimiter},
+    crate::utils::MetricsLimiter,
     anyhow::Context,
     relay_general::protocol::{Context as SentryContext, ProfileContext},
     relay_general::store::{GeoIpLookup, StoreConfig, StoreProcessor},
@@ -58,7 +59,8 @@ use {
 use crate::actors::envelopes::{EnvelopeManager, SendEnvelope, SendEnvelopeError, SubmitEnvelope};
 use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
 use crate::actors::project::ProjectState;
-use crate::actors::project_cache::ProjectCache;
+use crate::actors::project_cache::{ProjectCache, UpdateRateLimits};
+use crate::actors::test_store::LifecycleStage;
 use crate::actors::upstream::{SendRequest, UpstreamRelay};
 use crate::envelope::{AttachmentType, ContentType, Envelope, Item, ItemType};
 use crate::extractors::RequestMeta;
@@ -67,8 +69,9 @@ use crate::metrics_extraction::transactions::extract_transaction_metrics;
 use crate::metrics_extraction::transactions::types::ExtractMetricsError;
 use crate::statsd::{RelayCounters, RelayHistograms, RelayTimers};
 use crate::utils::{
-    self, get_sampling_key, log_transaction_name_metrics, ChunkedFormDataAggregator, FormDataIter,
-    ItemAction, ManagedEnvelope, SamplingResult,
+    self, get_sampling_key, log_transaction_name_metrics, ChunkedFormDataAggregator,
+    EnvelopeLimiter, EventDeduplicator, FormDataIter, ItemAction, ManagedEnvelope, SamplingResult,
+    SourceMapCache, SourceMapResolver,
 };
 
 /// The minimum clock drift for correction to apply.
@@ -129,6 +132,9 @@ pub enum ProcessingError {
 
     #[error("invalid pii config")]
     PiiConfigError(PiiConfigError),
+
+    #[error("duplicate event")]
+    DuplicateEvent,
 }
 
 impl ProcessingError {
@@ -144,6 +150,7 @@ impl ProcessingError {
             Self::InvalidTimestamp => Some(Outcome::Invalid(DiscardReason::Timestamp)),
             Self::DuplicateItem(_) => Some(Outcome::Invalid(DiscardReason::DuplicateItem)),
             Self::NoEventPayload => Some(Outcome::Invalid(DiscardReason::NoEventPayload)),
+            Self::DuplicateEvent => Some(Outcome::Invalid(DiscardReason::Duplicate)),
 
             // Processing-only outcomes (Sentry-internal Relays)
             #[cfg(feature = "processing")]
@@ -220,6 +227,29 @@ fn has_unprintable_fields(event: &Annotated<Event>) -> bool {
     }
 }
 
+/// Returns the conditional PII configs of the project that match the event.
+///
+/// Conditions are evaluated against the unscrubbed event, so that scrubbing cannot change whether
+/// a config applies. The matching configs apply to the event as well as to its attachments. Without
+/// an event, for instance in attachment-only envelopes, no condition matches.
+fn matching_conditional_pii_configs<'a>(
+    project_state: &'a ProjectState,
+    event: &Annotated<Event>,
+) -> Vec<&'a PiiConfig> {
+    let event = match event.value() {
+        Some(event) => event,
+        None => return Vec::new(),
+    };
+
+    project_state
+        .config
+        .conditional_pii_configs
+        .iter()
+        .filter(|conditional| conditional.matches(event))
+        .map(|conditional| &conditional.pii_config)
+        .collect()
+}
+
 #[derive(Debug, Default)]
 struct ExtractedMetrics {
     /// Metrics associated with the project that the transaction belongs to.
@@ -320,6 +350,12 @@ impl ProcessEnvelopeState {
         self.managed_envelope.envelope_mut()
     }
 
+    /// Records a completed processing step in the lifecycle of the envelope.
+    fn record_step(&mut self, step: &'static str) {
+        self.managed_envelope
+            .record(LifecycleStage::Processed { step });
+    }
+
     /// Returns whether any item in the envelope creates an event in any relay.
     ///
     /// This is used to branch into the processing pipeline. If this function returns false, only
@@ -356,7 +392,6 @@ impl ProcessEnvelopeState {
     }
 
     /// Removes the event payload from this processing state.
-    #[cfg(feature = "processing")]
     fn remove_event(&mut self) {
         self.event = Annotated::empty();
     }
@@ -378,6 +413,37 @@ enum ClientReportField {
     ClientDiscard,
 }
 
+impl ClientReportField {
+    /// Returns the name of the field used to tag metrics.
+    fn name(self) -> &'static str {
+        match self {
+            ClientReportField::Filtered => "filtered",
+            ClientReportField::FilteredSampling => "filtered_sampling",
+            ClientReportField::RateLimited => "rate_limited",
+            ClientReportField::ClientDiscard => "client_discard",
+        }
+    }
+}
+
+/// Reasons that SDKs are allowed to report in the `discarded_events` of a client report.
+///
+/// See the [SDK documentation](https://develop.sentry.dev/sdk/client-reports/) for the meaning of
+/// each reason. Discarded events with any other reason are ignored.
+const CLIENT_DISCARD_REASONS: &[&str] = &[
+    "queue_overflow",
+    "cache_overflow",
+    "buffer_overflow",
+    "ratelimit_backoff",
+    "network_error",
+    "sample_rate",
+    "before_send",
+    "event_processor",
+    "send_error",
+    "internal_sdk_error",
+    "insufficient_data",
+    "backpressure",
+];
+
 /// Parse an outcome from an outcome ID and a reason string.
 ///
 /// Currently only used to reconstruct outcomes encoded in client reports.
@@ -531,6 +597,9 @@ pub struct EnvelopeProcessorService {
     project_cache: Addr<ProjectCache>,
     outcome_aggregator: Addr<TrackOutcome>,
     upstream_relay: Addr<UpstreamRelay>,
+    local_rate_limiter: Option<LocalRateLimiter>,
+    deduplicator: Option<EventDeduplicator>,
+    source_maps: SourceMapCache,
     #[cfg(feature = "processing")]
     rate_limiter: Option<RedisRateLimiter>,
     #[cfg(feature = "processing")]
@@ -542,6 +611,7 @@ impl EnvelopeProcessorService {
     pub fn new(
         config: Arc<Config>,
         _redis: Option<RedisPool>,
+        local_rate_limiter: Option<LocalRateLimiter>,
         envelope_manager: Addr<EnvelopeManager>,
         outcome_aggregator: Addr<TrackOutcome>,
         project_cache: Addr<ProjectCache>,
@@ -554,6 +624,13 @@ impl EnvelopeProcessorService {
                 None => None,
             };
 
+            let deduplicator = config.dedupe_enabled().then(|| match _redis {
+                Some(ref pool) => EventDeduplicator::redis(pool.clone(), config.dedupe_window()),
+                None => {
+                    EventDeduplicator::local(config.dedupe_window(), config.dedupe_max_entries())
+                }
+            });
+
             let rate_limiter =
                 _redis.map(|pool| RedisRateLimiter::new(pool).max_limit(config.max_rate_limit()));
 
@@ -565,16 +642,24 @@ impl EnvelopeProcessorService {
                 outcome_aggregator,
                 project_cache,
                 upstream_relay,
+                local_rate_limiter,
+                deduplicator,
+                source_maps: SourceMapCache::new(),
             })
         }
 
         #[cfg(not(feature = "processing"))]
         Ok(Self {
+            deduplicator: config.dedupe_enabled().then(|| {
+                EventDeduplicator::local(config.dedupe_window(), config.dedupe_max_entries())
+            }),
             config,
             envelope_manager,
             outcome_aggregator,
             project_cache,
             upstream_relay,
+            local_rate_limiter,
+            source_maps: SourceMapCache::new(),
         })
     }
 
@@ -936,7 +1021,9 @@ impl EnvelopeProcessorService {
 
         let mut timestamp = None;
         let mut output_events = BTreeMap::new();
+        let mut implausible = false;
         let received = state.managed_envelope.received_at();
+        let max_quantity = self.config.max_client_report_quantity();
 
         let clock_drift_processor = ClockDriftProcessor::new(state.envelope().sent_at(), received)
             .at_least(MINIMUM_CLOCK_DRIFT);
@@ -976,13 +1063,28 @@ impl EnvelopeProcessorService {
                             relay_log::trace!("ignored client outcome with an overlong reason");
                             continue;
                         }
-                        *output_events
+                        if outcome_type == ClientReportField::ClientDiscard
+                            && !CLIENT_DISCARD_REASONS.contains(&discarded_event.reason.as_str())
+                        {
+                            relay_log::trace!(
+                                reason = discarded_event.reason,
+                                "ignored client outcome with an unknown reason"
+                            );
+                            continue;
+                        }
+                        if discarded_event.quantity > max_quantity {
+                            relay_log::trace!("clamping client outcome with an excessive quantity");
+                            implausible = true;
+                        }
+                        let quantity = output_events
                             .entry((
                                 outcome_type,
                                 discarded_event.reason,
                                 discarded_event.category,
                             ))
-                            .or_insert(0) += discarded_event.quantity;
+                            .or_insert(0u32);
+                        *quantity =
+                            quantity.saturating_add(discarded_event.quantity.min(max_quantity));
                     }
                     if let Some(ts) = report_timestamp {
                         timestamp.get_or_insert(ts);
@@ -999,6 +1101,20 @@ impl EnvelopeProcessorService {
             return;
         }
 
+        let scoping = state.managed_envelope.scoping();
+        let track_implausible = || {
+            self.outcome_aggregator.send(TrackOutcome {
+                timestamp: received,
+                scoping,
+                outcome: Outcome::Invalid(DiscardReason::ImplausibleClientReport),
+                event_id: None,
+                remote_addr: None,
+                category: DataCategory::Default,
+                quantity: 1,
+                bytes: 0,
+            });
+        };
+
         let timestamp =
             timestamp.get_or_insert_with(|| UnixTimestamp::from_secs(received.timestamp() as u64));
 
@@ -1018,6 +1134,7 @@ impl EnvelopeProcessorService {
                 "skipping client outcomes older than {} days",
                 max_age.num_days()
             );
+            track_implausible();
             return;
         }
 
@@ -1032,9 +1149,18 @@ impl EnvelopeProcessorService {
                 "skipping client outcomes more than {}s in the future",
                 max_future.num_seconds()
             );
+            track_implausible();
             return;
         }
 
+        if implausible {
+            track_implausible();
+        }
+
+        let meta = state.envelope().meta();
+        let sdk_name = utils::sdk_name_tag(meta.client_name());
+        let sdk_version = utils::sdk_version_tag(meta.client_version());
+
         for ((outcome_type, reason, category), quantity) in output_events.into_iter() {
             let outcome = match outcome_from_parts(outcome_type, &reason) {
                 Ok(outcome) => outcome,
@@ -1044,17 +1170,26 @@ impl EnvelopeProcessorService {
                 }
             };
 
+            metric!(
+                counter(RelayCounters::ClientReportQuantity) += quantity as i64,
+                sdk = sdk_name,
+                sdk_version = &sdk_version,
+                outcome = outcome_type.name(),
+                category = category.name(),
+            );
+
             self.outcome_aggregator.send(TrackOutcome {
                 // If we get to this point, the unwrap should not be used anymore, since we know by
                 // now that the timestamp can be parsed, but just incase we fallback to UTC current
                 // `DateTime`.
                 timestamp: timestamp.as_datetime().unwrap_or_else(Utc::now),
-                scoping: state.managed_envelope.scoping(),
+                scoping,
                 outcome,
                 event_id: None,
                 remote_addr: None, // omitting the client address allows for better aggregation
                 category,
                 quantity,
+                bytes: 0,
             });
         }
     }
@@ -1122,16 +1257,15 @@ impl EnvelopeProcessorService {
     /// transactions.
     #[cfg(feature = "processing")]
     fn count_processed_profiles(&self, state: &mut ProcessEnvelopeState) {
-        let profile_count: usize = state
+        let (profile_count, profile_bytes) = state
             .managed_envelope
             .envelope_mut()
             .items_mut()
             .filter(|item| item.ty() == &ItemType::Profile)
-            .map(|item| {
+            .fold((0, 0), |(count, bytes), item| {
                 item.set_profile_counted_as_processed();
-                item.quantity()
-            })
-            .sum();
+                (count + item.quantity(), bytes + item.len())
+            });
 
         if profile_count == 0 {
             return;
@@ -1145,6 +1279,7 @@ impl EnvelopeProcessorService {
             remote_addr: None,
             category: DataCategory::Profile,
             quantity: profile_count as u32, // truncates to `u32::MAX`
+            bytes: profile_bytes as u32,
         });
 
         // TODO: At this point, we should also ensure that the envelope summary gets recomputed.
@@ -2032,6 +2167,33 @@ impl EnvelopeProcessorService {
         })
     }
 
+    /// Drops the envelope if an event with the same ID has recently been processed for the project.
+    ///
+    /// See [`EventDeduplicator`] for more information.
+    fn deduplicate_event(&self, state: &mut ProcessEnvelopeState) -> Result<(), ProcessingError> {
+        let deduplicator = match self.deduplicator.as_ref() {
+            Some(deduplicator) => deduplicator,
+            None => return Ok(()),
+        };
+
+        let event_id = match state.envelope().event_id() {
+            Some(event_id) => event_id,
+            None => return Ok(()),
+        };
+
+        if deduplicator.is_duplicate(state.project_id, event_id) {
+            relay_log::trace!("dropping duplicate event {}", event_id);
+            return Err(ProcessingError::DuplicateEvent);
+        }
+
+        // Retries must not be dropped if this envelope or its event is not accepted in the end.
+        state
+            .managed_envelope
+            .set_deduplicated(deduplicator.clone(), state.project_id);
+
+        Ok(())
+    }
+
     #[cfg(feature = "processing")]
     fn enforce_quotas(&self, state: &mut ProcessEnvelopeState) -> Result<(), ProcessingError> {
         let rate_limiter = match self.rate_limiter.as_ref() {
@@ -2039,10 +2201,56 @@ impl EnvelopeProcessorService {
             None => return Ok(()),
         };
 
+        let event_limited = self.enforce_quotas_with(state, |quotas, item_scope, quantity| {
+            rate_limiter.is_rate_limited(quotas, item_scope, quantity, false)
+        })?;
+
+        if event_limited {
+            debug_assert!(state.envelope().is_empty());
+        }
+
+        Ok(())
+    }
+
+    /// Enforces quotas in memory of a non-processing Relay.
+    ///
+    /// See [`LocalRateLimiter`] for more information.
+    fn enforce_local_quotas(
+        &self,
+        state: &mut ProcessEnvelopeState,
+    ) -> Result<(), ProcessingError> {
+        let rate_limiter = match self.local_rate_limiter.as_ref() {
+            Some(rate_limiter) => rate_limiter,
+            None => return Ok(()),
+        };
+
+        // Non-processing Relays forward items that do not belong to the event, such as sessions.
+        // These remain in the envelope if the event is rate limited.
+        self.enforce_quotas_with(state, |quotas, item_scope, quantity| {
+            Ok::<_, ProcessingError>(
+                rate_limiter.is_rate_limited(quotas, item_scope, quantity, false),
+            )
+        })?;
+
+        Ok(())
+    }
+
+    /// Enforces the project's quotas with the given rate limiting function.
+    ///
+    /// Items exceeding a quota are removed from the envelope and reported as rate limited. Returns
+    /// `true` if the event has been rate limited and removed from the processing state.
+    fn enforce_quotas_with<E>(
+        &self,
+        state: &mut ProcessEnvelopeState,
+        check: impl Fn(&[Quota], ItemScoping<'_>, Quantity) -> Result<RateLimits, E>,
+    ) -> Result<bool, ProcessingError>
+    where
+        ProcessingError: From<E>,
+    {
         let project_state = &state.project_state;
         let quotas = project_state.config.quotas.as_slice();
         if quotas.is_empty() {
-            return Ok(());
+            return Ok(false);
         }
 
         let event_category = state.event_category();
@@ -2051,7 +2259,7 @@ impl EnvelopeProcessorService {
         // remove it from the processing state eventually.
         let mut envelope_limiter =
             EnvelopeLimiter::new(Some(&project_state.config), |item_scope, quantity| {
-                rate_limiter.is_rate_limited(quotas, item_scope, quantity, false)
+                check(quotas, item_scope, quantity)
             });
 
         // Tell the envelope limiter about the event, since it has been removed from the Envelope at
@@ -2060,19 +2268,26 @@ impl EnvelopeProcessorService {
             envelope_limiter.assume_event(category, state.transaction_metrics_extracted);
         }
 
+        // Release, environment and user quotas apply to the values of the event, if there is one.
+        if let Some(event) = state.event.value() {
+            envelope_limiter.scope_values(utils::event_scope_values(event));
+        }
+
         let scoping = state.managed_envelope.scoping();
         let (enforcement, limits) = metric!(timer(RelayTimers::EventProcessingRateLimiting), {
             envelope_limiter.enforce(state.managed_envelope.envelope_mut(), &scoping)?
         });
 
         if limits.is_limited() {
+            state.managed_envelope.record(LifecycleStage::RateLimited);
             self.project_cache
                 .send(UpdateRateLimits::new(scoping.project_key, limits));
         }
 
-        if enforcement.event_active() {
+        let event_active = enforcement.event_active();
+        if event_active {
             state.remove_event();
-            debug_assert!(state.envelope().is_empty());
+            state.managed_envelope.forget_deduplicated();
         }
 
         enforcement.track_outcomes(
@@ -2081,7 +2296,7 @@ impl EnvelopeProcessorService {
             self.outcome_aggregator.clone(),
         );
 
-        Ok(())
+        Ok(event_active)
     }
 
     /// Extract metrics for transaction events with breakdowns and measurements.
@@ -2146,8 +2361,14 @@ impl EnvelopeProcessorService {
 
     /// Apply data privacy rules to the event payload.
     ///
-    /// This uses both the general `datascrubbing_settings`, as well as the the PII rules.
-    fn scrub_event(&self, state: &mut ProcessEnvelopeState) -> Result<(), ProcessingError> {
+    /// This uses both the general `datascrubbing_settings`, as well as the the PII rules. PII
+    /// configs with a matching condition are applied in addition, see
+    /// [`matching_conditional_pii_configs`].
+    fn scrub_event(
+        &self,
+        state: &mut ProcessEnvelopeState,
+        conditional_configs: &[&PiiConfig],
+    ) -> Result<(), ProcessingError> {
         let event = &mut state.event;
         let config = &state.project_state.config;
 
@@ -2156,6 +2377,10 @@ impl EnvelopeProcessorService {
                 let mut processor = PiiProcessor::new(config.compiled());
                 process_value(event, &mut processor, ProcessingState::root())?;
             }
+            for config in conditional_configs {
+                let mut processor = PiiProcessor::new(config.compiled());
+                process_value(event, &mut processor, ProcessingState::root())?;
+            }
             let pii_config = config
                 .datascrubbing_settings
                 .pii_config()
@@ -2173,17 +2398,33 @@ impl EnvelopeProcessorService {
     ///
     /// This only applies the new PII rules that explicitly select `ValueType::Binary` or one of the
     /// attachment types. When special attachments are detected, these are scrubbed with custom
-    /// logic; otherwise the entire attachment is treated as a single binary blob.
-    fn scrub_attachments(&self, state: &mut ProcessEnvelopeState) {
+    /// logic; otherwise the entire attachment is treated as a single binary blob. PII configs with
+    /// a matching condition are applied after the project's PII config.
+    fn scrub_attachments(
+        &self,
+        state: &mut ProcessEnvelopeState,
+        conditional_configs: &[&PiiConfig],
+    ) {
+        let configs: Vec<&PiiConfig> = state
+            .project_state
+            .config
+            .pii_config
+            .iter()
+            .chain(conditional_configs.iter().copied())
+            .collect();
+        if configs.is_empty() {
+            return;
+        }
+
         let envelope = state.managed_envelope.envelope_mut();
-        if let Some(ref config) = state.project_state.config.pii_config {
-            let minidump = envelope
-                .get_item_by_mut(|item| item.attachment_type() == Some(&AttachmentType::Minidump));
+        let minidump = envelope
+            .get_item_by_mut(|item| item.attachment_type() == Some(&AttachmentType::Minidump));
 
-            if let Some(item) = minidump {
-                let filename = item.filename().unwrap_or_default();
-                let mut payload = item.payload().to_vec();
+        if let Some(item) = minidump {
+            let filename = item.filename().unwrap_or_default();
+            let mut payload = item.payload().to_vec();
 
+            for config in configs {
                 let processor = PiiAttachmentsProcessor::new(config.compiled());
 
                 // Minidump scrubbing can fail if the minidump cannot be parsed. In this case, we
@@ -2211,14 +2452,14 @@ impl EnvelopeProcessorService {
                         })
                     }
                 }
+            }
 
-                let content_type = item
-                    .content_type()
-                    .unwrap_or(&ContentType::Minidump)
-                    .clone();
+            let content_type = item
+                .content_type()
+                .unwrap_or(&ContentType::Minidump)
+                .clone();
 
-                item.set_payload(content_type, payload);
-            }
+            item.set_payload(content_type, payload);
         }
     }
 
@@ -2237,6 +2478,18 @@ impl EnvelopeProcessorService {
         // If transaction metrics were extracted, set the corresponding item header
         event_item.set_metrics_extracted(state.transaction_metrics_extracted);
 
+        // Remember the sampling decision of the trace for the headers of Kafka messages.
+        let sampled = state
+            .event
+            .value()
+            .and_then(|event| event.contexts.value())
+            .and_then(|contexts| contexts.get_context(TraceContext::default_key()))
+            .and_then(|context| match context {
+                Trace(context) => context.sampled.value().copied(),
+                _ => None,
+            });
+        event_item.set_sampled(sampled);
+
         // If there are sample rates, write them back to the envelope. In processing mode, sample
         // rates have been removed from the state and burnt into the event via `finalize_event`.
         if let Some(sample_rates) = state.sample_rates.take() {
@@ -2360,6 +2613,38 @@ impl EnvelopeProcessorService {
         }
     }
 
+    /// Resolves minified JavaScript stack traces with source maps from the local artifact directory.
+    ///
+    /// See [`SourceMapResolver`] for more information.
+    fn resolve_source_maps(&self, state: &mut ProcessEnvelopeState) {
+        let path = match self.config.source_maps_path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        let event = match state.event.value_mut() {
+            Some(event) => event,
+            None => return,
+        };
+
+        if !matches!(event.platform.as_str(), Some("javascript" | "node")) {
+            return;
+        }
+
+        let release = match event.release.as_str() {
+            Some(release) => release,
+            None => return,
+        };
+
+        let resolver =
+            SourceMapResolver::new(&self.source_maps, path, release, event.dist.as_str());
+        if let Some(mut resolver) = resolver {
+            metric!(timer(RelayTimers::EventProcessingSourceMaps), {
+                resolver.resolve_event(event);
+            });
+        }
+    }
+
     fn light_normalize_event(
         &self,
         state: &mut ProcessEnvelopeState,
@@ -2403,6 +2688,7 @@ impl EnvelopeProcessorService {
                 is_renormalize: false,
                 light_normalize_spans,
                 span_description_rules: state.project_state.config.span_description_rules.as_ref(),
+                grouping_config: state.project_state.config.grouping_config.as_ref(),
             };
 
             metric!(timer(RelayTimers::EventProcessingLightNormalization), {
@@ -2437,11 +2723,14 @@ impl EnvelopeProcessorService {
             // This makes it possible to get in this code block while not really having an event in
             // the envelope.
 
+            self.deduplicate_event(state)?;
+
             if_processing!({
                 self.expand_unreal(state)?;
             });
 
             self.extract_event(state)?;
+            state.record_step("extract_event");
 
             if_processing!({
                 self.process_unreal(state)?;
@@ -2449,20 +2738,26 @@ impl EnvelopeProcessorService {
             });
 
             self.finalize_event(state)?;
+            self.resolve_source_maps(state);
             self.light_normalize_event(state)?;
             self.normalize_dsc(state);
+            state.record_step("normalize_event");
             self.filter_event(state)?;
+            state.record_step("filter_event");
             self.run_dynamic_sampling(state);
             self.extract_transaction_metrics(state)?;
             self.sample_envelope(state)?;
+            state.record_step("dynamic_sampling");
 
             if_processing!({
                 self.store_process_event(state)?;
+                state.record_step("store_process_event");
             });
         }
 
         if_processing!({
             self.enforce_quotas(state)?;
+            state.record_step("enforce_quotas");
             // Any profile that reaches this point counts as "processed", regardless of whether
             // they survive the actual `process_profiles` step. This is to be consistent with
             // profiles that are dropped by dynamic sampling, which also count as "processed"
@@ -2473,12 +2768,22 @@ impl EnvelopeProcessorService {
             self.process_check_ins(state);
         });
 
+        if !self.config.processing_enabled() {
+            self.enforce_local_quotas(state)?;
+            state.record_step("enforce_quotas");
+        }
+
+        let project_state = state.project_state.clone();
+        let conditional_pii_configs =
+            matching_conditional_pii_configs(&project_state, &state.event);
+
         if state.has_event() {
-            self.scrub_event(state)?;
+            self.scrub_event(state, &conditional_pii_configs)?;
             self.serialize_event(state)?;
+            state.record_step("scrub_event");
         }
 
-        self.scrub_attachments(state);
+        self.scrub_attachments(state, &conditional_pii_configs);
 
         Ok(())
     }
@@ -2639,8 +2944,6 @@ impl EnvelopeProcessorService {
     /// Check and apply rate limits to metrics buckets.
     #[cfg(feature = "processing")]
     fn handle_rate_limit_flush_buckets(&self, message: RateLimitFlushBuckets) {
-        use relay_quotas::ItemScoping;
-
         let RateLimitFlushBuckets {
             mut bucket_limiter,
             partition_key,
@@ -2649,10 +2952,7 @@ impl EnvelopeProcessorService {
         let scoping = *bucket_limiter.scoping();
 
         if let Some(rate_limiter) = self.rate_limiter.as_ref() {
-            let item_scoping = ItemScoping {
-                category: DataCategory::Transaction,
-                scoping: &scoping,
-            };
+            let item_scoping = scoping.item(DataCategory::Transaction);
 
             // We set over_accept_once such that the limit is actually reached, which allows subsequent
             // calls with quantity=0 to be rate limited.
@@ -2660,7 +2960,7 @@ impl EnvelopeProcessorService {
             let rate_limits = rate_limiter.is_rate_limited(
                 bucket_limiter.quotas(),
                 item_scoping,
-                bucket_limiter.transaction_count(),
+                Quantity::new(bucket_limiter.transaction_count(), 0),
                 over_accept_once,
             );
 
@@ -3133,6 +3433,9 @@ mod tests {
             outcome_aggregator,
             project_cache,
             upstream_relay,
+            local_rate_limiter: None,
+            deduplicator: None,
+            source_maps: SourceMapCache::new(),
             #[cfg(feature = "processing")]
             rate_limiter: None,
             #[cfg(feature = "processing")]
@@ -3449,6 +3752,236 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_conditional_pii_config() {
+        let processor = create_test_processor(Default::default());
+
+        let project_config: ProjectConfig = serde_json::from_value(serde_json::json!({
+            "conditionalPiiConfigs": [{
+                "condition": {
+                    "op": "eq",
+                    "name": "event.user.geo.country_code",
+                    "value": ["AT", "DE"]
+                },
+                "piiConfig": {
+                    "applications": {
+                        "$user.email": ["@anything:remove"],
+                        "$minidump.code_file": ["@anything:mask"]
+                    }
+                }
+            }]
+        }))
+        .unwrap();
+
+        let mut project_state = ProjectState::allowed();
+        project_state.config = project_config;
+        let project_state = Arc::new(project_state);
+
+        let minidump = &include_bytes!("../../../tests/fixtures/windows.dmp")[..];
+        let scrub = |country_code: &str| {
+            let (outcome_aggregator, test_store) = services();
+            let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+                .parse()
+                .unwrap();
+            let mut envelope = Envelope::from_request(None, RequestMeta::new(dsn));
+            envelope.add_item({
+                let mut item = Item::new(ItemType::Event);
+                item.set_payload(
+                    ContentType::Json,
+                    serde_json::json!({
+                        "user": {
+                            "email": "foo@example.com",
+                            "geo": {"country_code": country_code}
+                        }
+                    })
+                    .to_string(),
+                );
+                item
+            });
+            envelope.add_item({
+                let mut item = Item::new(ItemType::Attachment);
+                item.set_payload(ContentType::Minidump, minidump);
+                item.set_attachment_type(AttachmentType::Minidump);
+                item.set_filename("windows.dmp");
+                item
+            });
+
+            let message = ProcessEnvelope {
+                envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+                project_state: project_state.clone(),
+                sampling_project_state: None,
+            };
+
+            let envelope_response = processor.process(message).unwrap();
+            let new_envelope = envelope_response.envelope.unwrap();
+            let new_envelope = new_envelope.envelope();
+
+            let event_item = new_envelope.get_item_by(|item| item.ty() == &ItemType::Event);
+            let event =
+                Annotated::<Event>::from_json_bytes(&event_item.unwrap().payload()).unwrap();
+            let email = event
+                .value()
+                .and_then(|event| event.user.value())
+                .and_then(|user| user.email.as_str())
+                .map(str::to_owned);
+
+            let minidump_item = new_envelope.get_item_by(|item| item.ty() == &ItemType::Attachment);
+            let minidump_scrubbed = minidump_item.unwrap().payload() != minidump;
+
+            (email, minidump_scrubbed)
+        };
+
+        assert_eq!(scrub("AT"), (None, true));
+        assert_eq!(scrub("US"), (Some("foo@example.com".to_owned()), false));
+    }
+
+    #[tokio::test]
+    async fn test_local_quotas() {
+        let mut processor = create_test_processor(Default::default());
+        processor.local_rate_limiter = Some(LocalRateLimiter::new());
+
+        let project_config: ProjectConfig = serde_json::from_value(serde_json::json!({
+            "quotas": [{
+                "id": "foo",
+                "categories": ["error"],
+                "scope": "organization",
+                "limit": 1,
+                "window": 60,
+                "reasonCode": "local_limit"
+            }]
+        }))
+        .unwrap();
+
+        let mut project_state = ProjectState::allowed();
+        project_state.config = project_config;
+        let project_state = Arc::new(project_state);
+
+        let process = || {
+            let (outcome_aggregator, test_store) = services();
+            let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+                .parse()
+                .unwrap();
+            let mut envelope = Envelope::from_request(None, RequestMeta::new(dsn));
+            envelope.add_item({
+                let mut item = Item::new(ItemType::Event);
+                item.set_payload(ContentType::Json, r#"{"message": "hello"}"#);
+                item
+            });
+
+            let message = ProcessEnvelope {
+                envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+                project_state: project_state.clone(),
+                sampling_project_state: None,
+            };
+
+            let envelope_response = processor.process(message).unwrap();
+            envelope_response
+                .envelope
+                .map_or(0, |envelope| envelope.envelope().len())
+        };
+
+        assert_eq!(process(), 1);
+        assert_eq!(process(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_deduplicate_events() {
+        let mut processor = create_test_processor(Default::default());
+        processor.deduplicator = Some(EventDeduplicator::local(Duration::from_secs(60), 100));
+
+        let project_state = Arc::new(ProjectState::allowed());
+
+        let process = |event_id: EventId| {
+            let (outcome_aggregator, test_store) = services();
+            let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+                .parse()
+                .unwrap();
+            let mut envelope = Envelope::from_request(Some(event_id), RequestMeta::new(dsn));
+            envelope.add_item({
+                let mut item = Item::new(ItemType::Event);
+                item.set_payload(ContentType::Json, r#"{"message": "hello"}"#);
+                item
+            });
+
+            let message = ProcessEnvelope {
+                envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+                project_state: project_state.clone(),
+                sampling_project_state: None,
+            };
+
+            processor.process(message).map(|response| {
+                response.envelope.unwrap().accept();
+            })
+        };
+
+        let event_id = EventId::new();
+        assert!(process(event_id).is_ok());
+        assert!(matches!(
+            process(event_id),
+            Err(ProcessingError::DuplicateEvent)
+        ));
+        assert!(process(EventId::new()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_deduplicate_rate_limited_retry() {
+        let mut processor = create_test_processor(Default::default());
+        processor.local_rate_limiter = Some(LocalRateLimiter::new());
+        processor.deduplicator = Some(EventDeduplicator::local(Duration::from_secs(60), 100));
+
+        let mut limited_state = ProjectState::allowed();
+        limited_state.config = serde_json::from_value(serde_json::json!({
+            "quotas": [{
+                "id": "foo",
+                "categories": ["error"],
+                "scope": "organization",
+                "limit": 0,
+                "reasonCode": "local_limit"
+            }]
+        }))
+        .unwrap();
+
+        let process = |project_state: ProjectState, event_id: EventId| {
+            let (outcome_aggregator, test_store) = services();
+            let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+                .parse()
+                .unwrap();
+            let mut envelope = Envelope::from_request(Some(event_id), RequestMeta::new(dsn));
+            envelope.add_item({
+                let mut item = Item::new(ItemType::Event);
+                item.set_payload(ContentType::Json, r#"{"message": "hello"}"#);
+                item
+            });
+
+            let message = ProcessEnvelope {
+                envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+                project_state: Arc::new(project_state),
+                sampling_project_state: None,
+            };
+
+            processor
+                .process(message)
+                .map(|response| match response.envelope {
+                    Some(envelope) => {
+                        envelope.accept();
+                        true
+                    }
+                    None => false,
+                })
+        };
+
+        // The first attempt is rate limited, which must not drop the retry as duplicate.
+        let event_id = EventId::new();
+        assert!(!process(limited_state, event_id).unwrap());
+        assert!(process(ProjectState::allowed(), event_id).unwrap());
+
+        // Once the event has been accepted, further submissions are duplicates.
+        assert!(matches!(
+            process(ProjectState::allowed(), event_id),
+            Err(ProcessingError::DuplicateEvent)
+        ));
+    }
+
     #[tokio::test]
     async fn test_client_report_removal() {
         relay_test::setup();
@@ -3548,6 +4081,115 @@ mod tests {
         ctx.accept(); // do not try to capture or emit outcomes
     }
 
+    /// Processes an envelope with a single client report and returns the tracked outcomes.
+    async fn process_client_report(report: serde_json::Value) -> Vec<(Outcome, DataCategory, u32)> {
+        let (outcome_aggregator, test_store) = services();
+
+        let config = Config::from_json_value(serde_json::json!({
+            "outcomes": {
+                "emit_outcomes": true,
+                "emit_client_outcomes": true,
+                "max_client_report_quantity": 100
+            }
+        }))
+        .unwrap();
+
+        let mut processor = create_test_processor(config);
+        let (tracked, handle) = mock_service("outcome_aggregator", vec![], |outcomes, msg| {
+            let TrackOutcome {
+                outcome,
+                category,
+                quantity,
+                ..
+            } = msg;
+            outcomes.push((outcome, category, quantity));
+        });
+        processor.outcome_aggregator = tracked;
+
+        let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+            .parse()
+            .unwrap();
+
+        let request_meta = RequestMeta::new(dsn);
+        let mut envelope = Envelope::from_request(None, request_meta);
+
+        envelope.add_item({
+            let mut item = Item::new(ItemType::ClientReport);
+            item.set_payload(ContentType::Json, report.to_string());
+            item
+        });
+
+        let message = ProcessEnvelope {
+            envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+            project_state: Arc::new(ProjectState::allowed()),
+            sampling_project_state: None,
+        };
+
+        let envelope_response = processor.process(message).unwrap();
+        assert!(envelope_response.envelope.is_none());
+
+        drop(processor);
+        handle.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_report_validation() {
+        relay_test::setup();
+
+        let outcomes = process_client_report(serde_json::json!({
+            "discarded_events": [
+                ["queue_overflow", "error", 42],
+                ["queue_overflow", "error", 1],
+                ["sample_rate", "transaction", 1000],
+                ["made_up_reason", "error", 5]
+            ]
+        }))
+        .await;
+
+        assert_eq!(
+            outcomes,
+            vec![
+                (
+                    Outcome::Invalid(DiscardReason::ImplausibleClientReport),
+                    DataCategory::Default,
+                    1
+                ),
+                (
+                    Outcome::ClientDiscard("queue_overflow".to_owned()),
+                    DataCategory::Error,
+                    43
+                ),
+                (
+                    Outcome::ClientDiscard("sample_rate".to_owned()),
+                    DataCategory::Transaction,
+                    100
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_report_implausible_timestamp() {
+        relay_test::setup();
+
+        let outcomes = process_client_report(serde_json::json!({
+            "timestamp": "2015-01-01T00:00:00Z",
+            "discarded_events": [
+                ["queue_overflow", "error", 42]
+            ]
+        }))
+        .await;
+
+        assert_eq!(
+            outcomes,
+            vec![(
+                Outcome::Invalid(DiscardReason::ImplausibleClientReport),
+                DataCategory::Default,
+                1
+            )]
+        );
+    }
+
     #[tokio::test]
     #[cfg(feature = "processing")]
     async fn test_client_report_removal_in_processing() {
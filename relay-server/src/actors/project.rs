@@ -2,12 +2,12 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use chrono::{DateTime, Utc};
-use relay_common::{ProjectId, ProjectKey};
+use relay_common::{ProjectId, ProjectKey, UnixTimestamp};
 use relay_config::Config;
 use relay_dynamic_config::{Feature, LimitedProjectConfig, ProjectConfig};
 use relay_filter::matches_any_origin;
 use relay_metrics::{Aggregator, Bucket, InsertMetrics, MergeBuckets, Metric, MetricsContainer};
-use relay_quotas::{Quota, RateLimits, Scoping};
+use relay_quotas::{DataCategories, Quota, RateLimits, ReasonCode, Scoping};
 use relay_statsd::metric;
 use relay_system::{Addr, BroadcastChannel};
 use serde::{Deserialize, Serialize};
@@ -20,6 +20,7 @@ use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
 #[cfg(feature = "processing")]
 use crate::actors::processor::RateLimitFlushBuckets;
 use crate::actors::project_cache::{CheckedEnvelope, ProjectCache, RequestUpdate, Services};
+use crate::actors::test_store::LifecycleStage;
 
 use crate::extractors::RequestMeta;
 use crate::statsd::RelayCounters;
@@ -178,6 +179,15 @@ impl ProjectState {
         &self.config
     }
 
+    /// Returns the retention of lifecycle records if lifecycle debugging is active.
+    pub fn lifecycle_debug_ttl(&self) -> Option<Duration> {
+        self.config
+            .lifecycle_debug
+            .as_ref()
+            .filter(|debug| debug.is_active(UnixTimestamp::now()))
+            .map(|debug| Duration::from_secs(debug.ttl))
+    }
+
     /// Returns `true` if the given project ID matches this project.
     ///
     /// If the project state has not been loaded, this check is skipped because the project
@@ -329,6 +339,32 @@ impl ProjectState {
         Ok(())
     }
 
+    /// Checks that the request carries an ingestion token if the project requires one.
+    ///
+    /// Tokens and their claims are verified once when the envelope is received, since they may
+    /// expire while the envelope is buffered. Processing Relays without a key to verify tokens
+    /// reject envelopes of projects that require tokens, otherwise verification is left to the
+    /// upstream.
+    pub fn check_ingestion_token(
+        &self,
+        meta: &RequestMeta,
+        config: &Config,
+    ) -> Result<(), DiscardReason> {
+        if !self.config.require_ingestion_token {
+            return Ok(());
+        }
+
+        let verified = match config.ingestion_token_key() {
+            Some(_) => meta.ingestion_token().is_some(),
+            None => !config.processing_enabled(),
+        };
+
+        match verified {
+            true => Ok(()),
+            false => Err(DiscardReason::IngestionToken),
+        }
+    }
+
     /// Validates data in this project state and removes values that are partially invalid.
     pub fn sanitize(mut self) -> Self {
         self.config.quotas.retain(Quota::is_valid);
@@ -424,6 +460,25 @@ impl Project {
         self.rate_limits.merge(rate_limits);
     }
 
+    /// Removes cached rate limits and returns the number of removed limits.
+    ///
+    /// If `categories` is empty, rate limits of all categories are removed. Otherwise, only rate
+    /// limits sharing at least one of the given categories are removed. If a `reason_code` is
+    /// given, only rate limits with this reason code are removed.
+    pub fn clear_rate_limits(
+        &mut self,
+        categories: &DataCategories,
+        reason_code: Option<&ReasonCode>,
+    ) -> usize {
+        self.rate_limits.retain(|limit| {
+            let category_matches =
+                categories.is_empty() || limit.categories.iter().any(|c| categories.contains(c));
+            let reason_matches =
+                reason_code.map_or(true, |r| limit.reason_code.as_ref() == Some(r));
+            !(category_matches && reason_matches)
+        })
+    }
+
     /// Returns the current [`ExpiryState`] for this project.
     /// If the project state's [`Expiry`] is `Expired`, do not return it.
     pub fn expiry_state(&self) -> ExpiryState {
@@ -765,8 +820,15 @@ impl Project {
         if let Some(ref state) = state {
             scoping = state.scope_request(envelope.envelope().meta());
             envelope.scope(scoping);
+            envelope.set_lifecycle_debug(state.lifecycle_debug_ttl());
 
-            if let Err(reason) = state.check_request(envelope.envelope().meta(), &self.config) {
+            let result = state
+                .check_request(envelope.envelope().meta(), &self.config)
+                .and_then(|()| {
+                    state.check_ingestion_token(envelope.envelope().meta(), &self.config)
+                });
+
+            if let Err(reason) = result {
                 envelope.reject(Outcome::Invalid(reason));
                 return Err(reason);
             }
@@ -785,6 +847,10 @@ impl Project {
         enforcement.track_outcomes(envelope.envelope(), &scoping, outcome_aggregator);
         envelope.update();
 
+        if rate_limits.is_limited() {
+            envelope.record(LifecycleStage::RateLimited);
+        }
+
         let envelope = if envelope.envelope().is_empty() {
             // Individual rate limits have already been issued above
             envelope.reject(Outcome::RateLimited(None));
@@ -876,12 +942,23 @@ impl Project {
 mod tests {
     use std::sync::Arc;
 
-    use relay_common::{ProjectId, ProjectKey, UnixTimestamp};
+    use relay_common::{DataCategory, ProjectId, ProjectKey, UnixTimestamp};
     use relay_metrics::{Bucket, BucketValue, Metric, MetricValue};
+    use relay_quotas::{RateLimit, RateLimitScope, RetryAfter};
     use relay_test::mock_service;
     use serde_json::json;
+    use smallvec::smallvec;
 
-    use super::{Config, Project, ProjectState, StateChannel};
+    use relay_dynamic_config::LifecycleDebugConfig;
+
+    use crate::actors::test_store::{LifecycleStage, TestStore};
+    use crate::envelope::Envelope;
+    use crate::testutils::new_envelope;
+    use crate::utils::ManagedEnvelope;
+
+    use super::{
+        Config, DataCategories, Project, ProjectState, RateLimits, ReasonCode, StateChannel,
+    };
 
     #[test]
     fn get_state_expired() {
@@ -969,6 +1046,126 @@ mod tests {
         project.fetch_state(addr, false);
     }
 
+    #[test]
+    fn test_check_ingestion_token() {
+        let (_, public_key) = relay_auth::generate_key_pair();
+        let config = Config::from_json_value(json!({
+            "auth": {"ingestion_token_key": public_key.to_string()}
+        }))
+        .unwrap();
+
+        let mut state = ProjectState::allowed();
+        state.config.require_ingestion_token = true;
+
+        let envelope = |token: Option<&str>| {
+            let header = match token {
+                Some(token) => json!({
+                    "dsn": "https://a94ae32be2584e0bbd7a4cbb95971fee:@sentry.io/42",
+                    "ingestion_token": token,
+                }),
+                None => json!({"dsn": "https://a94ae32be2584e0bbd7a4cbb95971fee:@sentry.io/42"}),
+            };
+            let bytes = format!("{header}\n{{\"type\":\"event\"}}\n{{}}\n");
+            Envelope::parse_bytes(bytes.into()).unwrap()
+        };
+
+        // Tokens are verified at ingestion, so only their presence is checked here.
+        let valid = envelope(Some("abc.def.ghi"));
+        assert!(state.check_ingestion_token(valid.meta(), &config).is_ok());
+
+        let missing = envelope(None);
+        assert!(state
+            .check_ingestion_token(missing.meta(), &config)
+            .is_err());
+
+        // Without a key, verification is left to the upstream.
+        assert!(state
+            .check_ingestion_token(missing.meta(), &Config::default())
+            .is_ok());
+
+        state.config.require_ingestion_token = false;
+        assert!(state.check_ingestion_token(missing.meta(), &config).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_envelope_lifecycle() {
+        let (outcome_aggregator, _) = mock_service("outcome_aggregator", (), |&mut (), _| {});
+        let (test_store, handle) = mock_service("test_store", vec![], |stages, message| {
+            if let TestStore::RecordLifecycle(message) = message {
+                stages.extend(message.records.into_iter().map(|record| record.stage));
+            }
+        });
+
+        let project_key = ProjectKey::parse("e12d836b15bb49d7bbf99e64295d995b").unwrap();
+        let mut project_state = ProjectState::allowed();
+        project_state.config.lifecycle_debug = Some(LifecycleDebugConfig {
+            until: UnixTimestamp::from_secs(UnixTimestamp::now().as_secs() + 60),
+            ttl: 60,
+        });
+        let mut project = Project::new(project_key, Arc::new(Config::default()));
+        project.state = Some(Arc::new(project_state));
+
+        let envelope = ManagedEnvelope::untracked(
+            new_envelope(false, "foo"),
+            outcome_aggregator.clone(),
+            test_store,
+        );
+        let mut envelope = project
+            .check_envelope(envelope, outcome_aggregator)
+            .unwrap()
+            .envelope
+            .unwrap();
+        envelope.record(LifecycleStage::SentUpstream);
+        envelope.accept();
+
+        let stages = handle.await.unwrap();
+        assert_eq!(
+            stages,
+            [LifecycleStage::Received, LifecycleStage::SentUpstream]
+        );
+    }
+
+    #[test]
+    fn test_clear_rate_limits() {
+        let mut project = create_project(None);
+        let scope = RateLimitScope::Project(ProjectId::new(42));
+
+        let mut rate_limits = RateLimits::new();
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Error],
+            scope: scope.clone(),
+            reason_code: Some(ReasonCode::new("errors")),
+            retry_after: RetryAfter::from_secs(60),
+        });
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Transaction],
+            scope: scope.clone(),
+            reason_code: Some(ReasonCode::new("transactions")),
+            retry_after: RetryAfter::from_secs(60),
+        });
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Attachment],
+            scope,
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(60),
+        });
+        project.merge_rate_limits(rate_limits);
+
+        // Reason code does not match the category.
+        let categories = smallvec![DataCategory::Error];
+        let reason_code = ReasonCode::new("transactions");
+        assert_eq!(
+            project.clear_rate_limits(&categories, Some(&reason_code)),
+            0
+        );
+
+        assert_eq!(project.clear_rate_limits(&categories, None), 1);
+        assert_eq!(project.rate_limits().iter().count(), 2);
+
+        assert_eq!(project.clear_rate_limits(&DataCategories::new(), None), 2);
+        assert!(project.rate_limits().is_ok());
+    }
+
     fn create_project(config: Option<serde_json::Value>) -> Project {
         let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
         let mut project = Project::new(project_key, Arc::new(Config::default()));
@@ -3,9 +3,9 @@ use std::error::Error;
 use std::sync::Arc;
 
 use relay_common::ProjectKey;
-use relay_config::{Config, RelayMode};
+use relay_config::{Config, Priority, RelayMode};
 use relay_metrics::{self, Aggregator, FlushBuckets, InsertMetrics, MergeBuckets};
-use relay_quotas::RateLimits;
+use relay_quotas::{DataCategories, RateLimits, ReasonCode, Scoping};
 use relay_redis::RedisPool;
 use relay_statsd::metric;
 use relay_system::{Addr, FromMessage, Interface, Sender, Service};
@@ -23,11 +23,11 @@ use crate::actors::project_upstream::{UpstreamProjectSource, UpstreamProjectSour
 use crate::actors::spooler::{
     self, Buffer, BufferService, DequeueMany, Enqueue, QueueKey, RemoveMany,
 };
-use crate::actors::test_store::TestStore;
+use crate::actors::test_store::{LifecycleStage, TestStore};
 use crate::actors::upstream::UpstreamRelay;
 
 use crate::statsd::{RelayCounters, RelayGauges, RelayHistograms, RelayTimers};
-use crate::utils::{self, BufferGuard, GarbageDisposal, ManagedEnvelope};
+use crate::utils::{self, BufferGuard, GarbageDisposal, ManagedEnvelope, SemaphorePermit};
 
 /// Requests a refresh of a project state from one of the available sources.
 ///
@@ -165,6 +165,60 @@ impl UpdateRateLimits {
     }
 }
 
+/// Returns the rate limits currently cached for a list of projects.
+///
+/// If the list of project keys is empty, all cached projects with active rate limits are returned.
+/// Expired rate limits are never included in the response. This message does not fetch project
+/// states for projects that are not in the cache.
+#[derive(Debug)]
+pub struct GetRateLimits {
+    project_keys: Vec<ProjectKey>,
+}
+
+impl GetRateLimits {
+    pub fn new(project_keys: Vec<ProjectKey>) -> Self {
+        Self { project_keys }
+    }
+}
+
+/// Rate limits of a single project, returned by [`GetRateLimits`].
+#[derive(Debug)]
+pub struct ProjectRateLimits {
+    /// The public key of the project.
+    pub project_key: ProjectKey,
+    /// Scoping of the project, if its state has been fetched.
+    pub scoping: Option<Scoping>,
+    /// The cached project state, if it is still valid.
+    pub state: Option<Arc<ProjectState>>,
+    /// Active rate limits of the project.
+    pub rate_limits: RateLimits,
+}
+
+/// Removes cached rate limits of a project.
+///
+/// See [`Project::clear_rate_limits`] for which rate limits are removed. Responds with the number
+/// of removed rate limits.
+#[derive(Debug)]
+pub struct ClearRateLimits {
+    project_key: ProjectKey,
+    categories: DataCategories,
+    reason_code: Option<ReasonCode>,
+}
+
+impl ClearRateLimits {
+    pub fn new(
+        project_key: ProjectKey,
+        categories: DataCategories,
+        reason_code: Option<ReasonCode>,
+    ) -> Self {
+        Self {
+            project_key,
+            categories,
+            reason_code,
+        }
+    }
+}
+
 /// Updates the buffer index for [`ProjectKey`] with the [`QueueKey`] keys.
 ///
 /// This message is sent from the project buffer in case of the error while fetching the data from
@@ -185,6 +239,21 @@ impl UpdateBufferIndex {
 #[derive(Debug)]
 pub struct SpoolHealth;
 
+/// Evicts a queued envelope with lower priority to make room for a new envelope.
+///
+/// See [`spooler::Evict`] for which envelope is evicted. Responds with the permit of the evicted
+/// envelope, or `None` if no envelope has been evicted.
+#[derive(Debug)]
+pub struct SpoolEvict {
+    priority: Priority,
+}
+
+impl SpoolEvict {
+    pub fn new(priority: Priority) -> Self {
+        Self { priority }
+    }
+}
+
 /// A cache for [`ProjectState`]s.
 ///
 /// The project maintains information about organizations, projects, and project keys along with
@@ -210,11 +279,14 @@ pub enum ProjectCache {
     ),
     ValidateEnvelope(ValidateEnvelope),
     UpdateRateLimits(UpdateRateLimits),
+    GetRateLimits(GetRateLimits, Sender<Vec<ProjectRateLimits>>),
+    ClearRateLimits(ClearRateLimits, Sender<usize>),
     InsertMetrics(InsertMetrics),
     MergeBuckets(MergeBuckets),
     FlushBuckets(FlushBuckets),
     UpdateBufferIndex(UpdateBufferIndex),
     SpoolHealth(Sender<bool>),
+    SpoolEvict(SpoolEvict, Sender<Option<SemaphorePermit>>),
 }
 
 impl Interface for ProjectCache {}
@@ -281,6 +353,22 @@ impl FromMessage<UpdateRateLimits> for ProjectCache {
     }
 }
 
+impl FromMessage<GetRateLimits> for ProjectCache {
+    type Response = relay_system::AsyncResponse<Vec<ProjectRateLimits>>;
+
+    fn from_message(message: GetRateLimits, sender: Sender<Vec<ProjectRateLimits>>) -> Self {
+        Self::GetRateLimits(message, sender)
+    }
+}
+
+impl FromMessage<ClearRateLimits> for ProjectCache {
+    type Response = relay_system::AsyncResponse<usize>;
+
+    fn from_message(message: ClearRateLimits, sender: Sender<usize>) -> Self {
+        Self::ClearRateLimits(message, sender)
+    }
+}
+
 impl FromMessage<InsertMetrics> for ProjectCache {
     type Response = relay_system::NoResponse;
 
@@ -313,6 +401,14 @@ impl FromMessage<SpoolHealth> for ProjectCache {
     }
 }
 
+impl FromMessage<SpoolEvict> for ProjectCache {
+    type Response = relay_system::AsyncResponse<Option<SemaphorePermit>>;
+
+    fn from_message(message: SpoolEvict, sender: Sender<Option<SemaphorePermit>>) -> Self {
+        Self::SpoolEvict(message, sender)
+    }
+}
+
 /// Helper type that contains all configured sources for project cache fetching.
 ///
 /// See [`RequestUpdate`] for a description on how project states are fetched.
@@ -723,7 +819,9 @@ impl ProjectCacheBroker {
     ///
     /// The flushing of the buffered envelopes happens in `update_state`.
     fn handle_validate_envelope(&mut self, message: ValidateEnvelope) {
-        let ValidateEnvelope { envelope: context } = message;
+        let ValidateEnvelope {
+            envelope: mut context,
+        } = message;
         let project_cache = self.services.project_cache.clone();
         let envelope = context.envelope();
 
@@ -752,6 +850,7 @@ impl ProjectCacheBroker {
         }
 
         let key = QueueKey::new(own_key, sampling_key.unwrap_or(own_key));
+        context.record(LifecycleStage::Spooled);
         self.enqueue(key, context);
     }
 
@@ -760,6 +859,46 @@ impl ProjectCacheBroker {
             .merge_rate_limits(message.rate_limits);
     }
 
+    fn handle_get_rate_limits(&mut self, message: GetRateLimits) -> Vec<ProjectRateLimits> {
+        let project_rate_limits = |project_key: ProjectKey, project: Option<&Project>| {
+            let mut rate_limits = project
+                .map(|p| p.rate_limits().clone())
+                .unwrap_or_default();
+            rate_limits.clean_expired();
+
+            ProjectRateLimits {
+                project_key,
+                scoping: project.and_then(Project::scoping),
+                state: project.and_then(Project::valid_state),
+                rate_limits,
+            }
+        };
+
+        if message.project_keys.is_empty() {
+            return self
+                .projects
+                .iter()
+                .map(|(key, project)| project_rate_limits(*key, Some(project)))
+                .filter(|limits| limits.rate_limits.is_limited())
+                .collect();
+        }
+
+        message
+            .project_keys
+            .into_iter()
+            .map(|key| project_rate_limits(key, self.projects.get(&key)))
+            .collect()
+    }
+
+    fn handle_clear_rate_limits(&mut self, message: ClearRateLimits) -> usize {
+        match self.projects.get_mut(&message.project_key) {
+            Some(project) => {
+                project.clear_rate_limits(&message.categories, message.reason_code.as_ref())
+            }
+            None => 0,
+        }
+    }
+
     fn handle_insert_metrics(&mut self, message: InsertMetrics) {
         let aggregator = self.services.aggregator.clone();
         let outcome_aggregator = self.services.outcome_aggregator.clone();
@@ -790,6 +929,15 @@ impl ProjectCacheBroker {
         self.buffer.send(spooler::Health(sender))
     }
 
+    fn handle_spool_evict(
+        &mut self,
+        message: SpoolEvict,
+        sender: Sender<Option<SemaphorePermit>>,
+    ) {
+        self.buffer
+            .send(spooler::Evict::new(message.priority, sender))
+    }
+
     fn handle_message(&mut self, message: ProjectCache) {
         match message {
             ProjectCache::RequestUpdate(message) => self.handle_request_update(message),
@@ -802,11 +950,18 @@ impl ProjectCacheBroker {
             }
             ProjectCache::ValidateEnvelope(message) => self.handle_validate_envelope(message),
             ProjectCache::UpdateRateLimits(message) => self.handle_rate_limits(message),
+            ProjectCache::GetRateLimits(message, sender) => {
+                sender.send(self.handle_get_rate_limits(message))
+            }
+            ProjectCache::ClearRateLimits(message, sender) => {
+                sender.send(self.handle_clear_rate_limits(message))
+            }
             ProjectCache::InsertMetrics(message) => self.handle_insert_metrics(message),
             ProjectCache::MergeBuckets(message) => self.handle_merge_buckets(message),
             ProjectCache::FlushBuckets(message) => self.handle_flush_buckets(message),
             ProjectCache::UpdateBufferIndex(message) => self.handle_buffer_index(message),
             ProjectCache::SpoolHealth(sender) => self.handle_spool_health(sender),
+            ProjectCache::SpoolEvict(message, sender) => self.handle_spool_evict(message, sender),
         }
     }
 }
@@ -6,7 +6,8 @@ use std::time::{Duration, Instant};
 use relay_auth::{PublicKey, RelayId};
 use relay_config::{Config, RelayInfo};
 use relay_system::{
-    Addr, BroadcastChannel, BroadcastResponse, BroadcastSender, FromMessage, Interface, Service,
+    Addr, BroadcastChannel, BroadcastResponse, BroadcastSender, FromMessage, Interface, NoResponse,
+    Service,
 };
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
@@ -31,9 +32,30 @@ pub struct GetRelay {
 /// This is `Some` if the Relay is known by the upstream or `None` the Relay is unknown.
 pub type GetRelayResult = Option<RelayInfo>;
 
+/// Accepts the next public key of a Relay during a key rotation.
+///
+/// The next key must be announced in the signature header of a request that has been verified with
+/// the Relay's current key. Until the upstream reports the new key, signatures of either key are
+/// accepted for this Relay. If the upstream replaces or revokes the current key, the next key is
+/// dropped.
+///
+/// Next keys of statically configured Relays are only accepted from `static_relays`.
+#[derive(Debug)]
+pub struct AcceptNextKey {
+    /// The unique identifier of the Relay deployment.
+    pub relay_id: RelayId,
+    /// The current public key that verified the announcement.
+    pub current_key: PublicKey,
+    /// The next public key announced by the Relay.
+    pub next_key: PublicKey,
+}
+
 /// Manages authentication information for downstream Relays.
 #[derive(Debug)]
-pub struct RelayCache(GetRelay, BroadcastSender<GetRelayResult>);
+pub enum RelayCache {
+    GetRelay(GetRelay, BroadcastSender<GetRelayResult>),
+    AcceptNextKey(AcceptNextKey),
+}
 
 impl Interface for RelayCache {}
 
@@ -41,7 +63,15 @@ impl FromMessage<GetRelay> for RelayCache {
     type Response = BroadcastResponse<GetRelayResult>;
 
     fn from_message(message: GetRelay, sender: BroadcastSender<GetRelayResult>) -> Self {
-        Self(message, sender)
+        Self::GetRelay(message, sender)
+    }
+}
+
+impl FromMessage<AcceptNextKey> for RelayCache {
+    type Response = NoResponse;
+
+    fn from_message(message: AcceptNextKey, _: ()) -> Self {
+        Self::AcceptNextKey(message)
     }
 }
 
@@ -156,6 +186,14 @@ impl RelayState {
         }
     }
 
+    /// Returns a mutable reference to the existing entry, if any.
+    fn as_option_mut(&mut self) -> Option<&mut RelayInfo> {
+        match *self {
+            RelayState::Exists { ref mut relay, .. } => Some(relay),
+            _ => None,
+        }
+    }
+
     /// Constructs a cache entry from an upstream response.
     fn from_option(option: Option<RelayInfo>) -> Self {
         match option {
@@ -170,6 +208,13 @@ impl RelayState {
     }
 }
 
+/// A next key announced by a Relay, along with the current key it was accepted against.
+#[derive(Debug)]
+struct AcceptedKey {
+    current_key: PublicKey,
+    next_key: PublicKey,
+}
+
 /// Result type of the background fetch task.
 ///
 ///  - `Ok`: The task succeeded and information from the response should be inserted into the cache.
@@ -181,6 +226,7 @@ type FetchResult = Result<GetRelaysResponse, HashMap<RelayId, BroadcastChannel<G
 pub struct RelayCacheService {
     static_relays: HashMap<RelayId, RelayInfo>,
     relays: HashMap<RelayId, RelayState>,
+    accepted_keys: HashMap<RelayId, AcceptedKey>,
     channels: HashMap<RelayId, BroadcastChannel<GetRelayResult>>,
     fetch_channel: (mpsc::Sender<FetchResult>, mpsc::Receiver<FetchResult>),
     backoff: RetryBackoff,
@@ -195,6 +241,7 @@ impl RelayCacheService {
         Self {
             static_relays: config.static_relays().clone(),
             relays: HashMap::new(),
+            accepted_keys: HashMap::new(),
             channels: HashMap::new(),
             fetch_channel: mpsc::channel(1),
             backoff: RetryBackoff::new(config.http_max_retry_interval()),
@@ -280,7 +327,22 @@ impl RelayCacheService {
             Ok(response) => {
                 self.backoff.reset();
 
-                for (id, info) in response.relays {
+                for (id, mut info) in response.relays {
+                    // Retain an accepted next key until the upstream knows about the rotation. It
+                    // is dropped if the upstream no longer reports the key it was accepted against.
+                    if let Some(accepted) = self.accepted_keys.remove(&id) {
+                        match info.as_mut() {
+                            Some(info)
+                                if info.public_key == accepted.current_key
+                                    && info.next_public_key.is_none() =>
+                            {
+                                info.next_public_key = Some(accepted.next_key.clone());
+                                self.accepted_keys.insert(id, accepted);
+                            }
+                            _ => relay_log::debug!("relay {id} dropped accepted next public key"),
+                        }
+                    }
+
                     self.relays.insert(id, RelayState::from_option(info));
                 }
             }
@@ -332,6 +394,52 @@ impl RelayCacheService {
             self.schedule_fetch();
         }
     }
+
+    /// Accepts the next public key announced by a Relay.
+    ///
+    /// The key is only accepted for Relays known from the upstream, and only if the key that
+    /// verified the announcement is still the Relay's current key. Statically configured Relays
+    /// are never changed at runtime.
+    fn accept_next_key(&mut self, message: AcceptNextKey) {
+        let AcceptNextKey {
+            relay_id,
+            current_key,
+            next_key,
+        } = message;
+
+        let relay = match self
+            .relays
+            .get_mut(&relay_id)
+            .and_then(RelayState::as_option_mut)
+        {
+            Some(relay) => relay,
+            None => return,
+        };
+
+        if relay.public_key != current_key || relay.public_key == next_key {
+            return;
+        }
+
+        if relay.next_public_key.as_ref() != Some(&next_key) {
+            relay_log::debug!("relay {relay_id} announced next public key");
+            relay.next_public_key = Some(next_key.clone());
+        }
+
+        self.accepted_keys.insert(
+            relay_id,
+            AcceptedKey {
+                current_key,
+                next_key,
+            },
+        );
+    }
+
+    fn handle_message(&mut self, message: RelayCache) {
+        match message {
+            RelayCache::GetRelay(message, sender) => self.get_or_fetch(message, sender),
+            RelayCache::AcceptNextKey(message) => self.accept_next_key(message),
+        }
+    }
 }
 
 impl Service for RelayCacheService {
@@ -348,7 +456,7 @@ impl Service for RelayCacheService {
 
                     Some(result) = self.fetch_channel.1.recv() => self.handle_fetch_result(result),
                     () = &mut self.delay => self.fetch_relays(),
-                    Some(message) = rx.recv() => self.get_or_fetch(message.0, message.1),
+                    Some(message) = rx.recv() => self.handle_message(message),
                     else => break,
                 }
             }
@@ -357,3 +465,96 @@ impl Service for RelayCacheService {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use relay_auth::generate_key_pair;
+    use relay_test::mock_service;
+
+    use super::*;
+
+    fn service() -> RelayCacheService {
+        let (upstream_relay, _) = mock_service("upstream_relay", (), |&mut (), _| {});
+        RelayCacheService::new(Arc::new(Config::default()), upstream_relay)
+    }
+
+    fn fetch_result(relay_id: RelayId, public_key: &PublicKey) -> FetchResult {
+        let info = RelayInfo::new(public_key.clone());
+        Ok(GetRelaysResponse {
+            relays: HashMap::from([(relay_id, Some(info))]),
+        })
+    }
+
+    fn cached_next_key(service: &RelayCacheService, relay_id: RelayId) -> Option<PublicKey> {
+        let relay = service.relays.get(&relay_id)?.as_option()?;
+        relay.next_public_key.clone()
+    }
+
+    #[tokio::test]
+    async fn test_accepted_key_retained() {
+        let mut service = service();
+        let relay_id = RelayId::new_v4();
+        let (_, current_key) = generate_key_pair();
+        let (_, next_key) = generate_key_pair();
+
+        service.handle_fetch_result(fetch_result(relay_id, &current_key));
+        service.accept_next_key(AcceptNextKey {
+            relay_id,
+            current_key: current_key.clone(),
+            next_key: next_key.clone(),
+        });
+        assert_eq!(cached_next_key(&service, relay_id), Some(next_key.clone()));
+
+        // The upstream does not know about the rotation yet.
+        service.handle_fetch_result(fetch_result(relay_id, &current_key));
+        assert_eq!(cached_next_key(&service, relay_id), Some(next_key.clone()));
+
+        // The upstream completed the rotation.
+        service.handle_fetch_result(fetch_result(relay_id, &next_key));
+        assert_eq!(cached_next_key(&service, relay_id), None);
+    }
+
+    #[tokio::test]
+    async fn test_accepted_key_dropped_on_replaced_key() {
+        let mut service = service();
+        let relay_id = RelayId::new_v4();
+        let (_, current_key) = generate_key_pair();
+        let (_, next_key) = generate_key_pair();
+        let (_, replaced_key) = generate_key_pair();
+
+        service.handle_fetch_result(fetch_result(relay_id, &current_key));
+        service.accept_next_key(AcceptNextKey {
+            relay_id,
+            current_key: current_key.clone(),
+            next_key,
+        });
+
+        service.handle_fetch_result(fetch_result(relay_id, &replaced_key));
+        assert_eq!(cached_next_key(&service, relay_id), None);
+
+        // The accepted key does not come back if the upstream restores the old key.
+        service.handle_fetch_result(fetch_result(relay_id, &current_key));
+        assert_eq!(cached_next_key(&service, relay_id), None);
+    }
+
+    #[tokio::test]
+    async fn test_accepted_key_static_relay() {
+        let relay_id = RelayId::new_v4();
+        let (_, current_key) = generate_key_pair();
+        let (_, next_key) = generate_key_pair();
+
+        let mut service = service();
+        service
+            .static_relays
+            .insert(relay_id, RelayInfo::new(current_key.clone()));
+
+        service.accept_next_key(AcceptNextKey {
+            relay_id,
+            current_key,
+            next_key,
+        });
+
+        assert_eq!(service.static_relays[&relay_id].next_public_key, None);
+        assert!(service.accepted_keys.is_empty());
+    }
+}
@@ -6,7 +6,7 @@ use std::sync::Arc;
 
 use futures::stream::{self, StreamExt};
 use relay_common::ProjectKey;
-use relay_config::Config;
+use relay_config::{Config, Priority};
 use relay_system::{Addr, Controller, FromMessage, Interface, Sender, Service};
 use sqlx::migrate::MigrateError;
 use sqlx::sqlite::{
@@ -16,13 +16,13 @@ use sqlx::sqlite::{
 use sqlx::{Pool, Row, Sqlite};
 use tokio::sync::mpsc;
 
-use crate::actors::outcome::TrackOutcome;
+use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
 use crate::actors::project_cache::{ProjectCache, UpdateBufferIndex};
 use crate::actors::test_store::TestStore;
 use crate::envelope::{Envelope, EnvelopeError};
 use crate::extractors::StartTime;
 use crate::statsd::{RelayCounters, RelayGauges, RelayHistograms};
-use crate::utils::{BufferGuard, ManagedEnvelope};
+use crate::utils::{BufferGuard, ManagedEnvelope, SemaphorePermit};
 
 mod sql;
 
@@ -135,6 +135,26 @@ impl RemoveMany {
 #[derive(Debug)]
 pub struct Health(pub Sender<bool>);
 
+/// Evicts a queued envelope with a lower priority than the given one from the in-memory buffer.
+///
+/// The oldest envelope with the lowest priority is evicted and rejected with an outcome. Responds
+/// with the evicted envelope's permit of the envelope buffer, so that the capacity is reserved for
+/// the envelope with higher priority. Responds with `None` if no envelope has been evicted.
+///
+/// Envelopes that have been spooled to disk do not occupy capacity, and envelopes that have left
+/// the buffer for processing can no longer be evicted.
+#[derive(Debug)]
+pub struct Evict {
+    priority: Priority,
+    sender: Sender<Option<SemaphorePermit>>,
+}
+
+impl Evict {
+    pub fn new(priority: Priority, sender: Sender<Option<SemaphorePermit>>) -> Self {
+        Self { priority, sender }
+    }
+}
+
 /// The interface for [`BufferService`].
 ///
 /// Buffer maintaince internal storage (internal buffer) of the envelopes, which keep accumulating
@@ -156,6 +176,7 @@ pub enum Buffer {
     DequeueMany(DequeueMany),
     RemoveMany(RemoveMany),
     Health(Health),
+    Evict(Evict),
 }
 
 impl Interface for Buffer {}
@@ -192,6 +213,14 @@ impl FromMessage<Health> for Buffer {
     }
 }
 
+impl FromMessage<Evict> for Buffer {
+    type Response = relay_system::NoResponse;
+
+    fn from_message(message: Evict, _: ()) -> Self {
+        Self::Evict(message)
+    }
+}
+
 /// The configuration which describes the in-memory [`BufferState`].
 #[derive(Debug)]
 struct InMemory {
@@ -268,6 +297,42 @@ impl InMemory {
         );
     }
 
+    /// Removes the oldest envelope with the lowest priority below `priority` from the buffer.
+    ///
+    /// Returns `None` if all envelopes in the buffer have the same or a higher priority.
+    fn evict(&mut self, priority: Priority, config: &Config) -> Option<ManagedEnvelope> {
+        let (key, index) = self
+            .buffer
+            .iter()
+            .flat_map(|(key, envelopes)| {
+                envelopes.iter().enumerate().map(move |(index, envelope)| {
+                    let priority = envelope.envelope().priority(config);
+                    (priority, envelope.start_time(), *key, index)
+                })
+            })
+            .filter(|(envelope_priority, ..)| *envelope_priority < priority)
+            .min_by_key(|(priority, start_time, ..)| (*priority, *start_time))
+            .map(|(_, _, key, index)| (key, index))?;
+
+        let envelopes = self.buffer.get_mut(&key)?;
+        let envelope = envelopes.remove(index);
+        if envelopes.is_empty() {
+            self.buffer.remove(&key);
+        }
+
+        self.used_memory -= envelope.estimated_size();
+        self.envelope_count = self.envelope_count.saturating_sub(1);
+        relay_statsd::metric!(
+            histogram(RelayHistograms::BufferEnvelopesMemoryBytes) = self.used_memory as f64
+        );
+        relay_statsd::metric!(
+            gauge(RelayGauges::BufferEnvelopesMemoryCount) = self.envelope_count as u64
+        );
+        relay_statsd::metric!(counter(RelayCounters::BufferEnvelopesEvicted) += 1);
+
+        Some(envelope)
+    }
+
     /// Returns `true` if the in-memory buffer is full, `false` otherwise.
     fn is_full(&self) -> bool {
         self.used_memory >= self.max_memory_size
@@ -730,17 +795,31 @@ impl BufferService {
     async fn handle_enqueue(&mut self, message: Enqueue) -> Result<(), BufferError> {
         let Enqueue {
             key,
-            value: managed_envelope,
+            value: mut managed_envelope,
         } = message;
 
         match self.state {
-            BufferState::Memory(ref mut ram)
-            | BufferState::MemoryFileStandby { ref mut ram, .. } => {
+            BufferState::Memory(ref mut ram) => {
+                // Without a disk spool, make room by evicting envelopes with lower priority. If
+                // there are none, the envelope is still accepted.
+                let priority = managed_envelope.envelope().priority(&self.config);
+                while ram.is_full() {
+                    match ram.evict(priority, &self.config) {
+                        Some(mut evicted) => {
+                            evicted.reject(Outcome::Invalid(DiscardReason::Evicted))
+                        }
+                        None => break,
+                    }
+                }
+                ram.enqueue(key, managed_envelope);
+            }
+            BufferState::MemoryFileStandby { ref mut ram, .. } => {
                 ram.enqueue(key, managed_envelope);
             }
             BufferState::Disk(ref mut disk) => {
                 // The disk is full, drop the incoming envelopes.
                 if disk.is_full().await? {
+                    managed_envelope.reject(Outcome::Invalid(DiscardReason::BufferFull));
                     return Err(BufferError::SpoolIsFull);
                 }
                 disk.enqueue(key, managed_envelope).await?;
@@ -822,6 +901,30 @@ impl BufferService {
         Ok(())
     }
 
+    /// Handles the eviction of queued envelopes in favor of an envelope with higher priority.
+    ///
+    /// Envelopes are only evicted from memory, since spooled envelopes do not occupy capacity in
+    /// the envelope buffer.
+    fn handle_evict(&mut self, message: Evict) {
+        let Evict { priority, sender } = message;
+
+        let evicted = match self.state {
+            BufferState::Memory(ref mut ram)
+            | BufferState::MemoryFileStandby { ref mut ram, .. } => {
+                ram.evict(priority, &self.config)
+            }
+            BufferState::Disk(_) => None,
+        };
+
+        let slot = evicted.and_then(|mut envelope| {
+            let slot = envelope.take_slot();
+            envelope.reject(Outcome::Invalid(DiscardReason::Evicted));
+            slot
+        });
+
+        sender.send(slot);
+    }
+
     /// Handles all the incoming messages from the [`Buffer`] interface.
     async fn handle_message(&mut self, message: Buffer) -> Result<(), BufferError> {
         match message {
@@ -829,6 +932,10 @@ impl BufferService {
             Buffer::DequeueMany(message) => self.handle_dequeue(message).await,
             Buffer::RemoveMany(message) => self.handle_remove(message).await,
             Buffer::Health(message) => self.handle_health(message).await,
+            Buffer::Evict(message) => {
+                self.handle_evict(message);
+                Ok(())
+            }
         }
     }
 
@@ -910,8 +1017,10 @@ mod tests {
 
     use insta::assert_debug_snapshot;
     use relay_common::Uuid;
+    use relay_system::{AsyncResponse, MessageResponse};
     use relay_test::mock_service;
 
+    use crate::envelope::{Item, ItemType};
     use crate::testutils::empty_envelope;
 
     use super::*;
@@ -938,6 +1047,120 @@ mod tests {
         ManagedEnvelope::untracked(envelope, outcome_aggregator, test_store)
     }
 
+    fn managed_envelope_with_item(ty: ItemType) -> ManagedEnvelope {
+        let mut envelope = empty_envelope();
+        envelope.add_item(Item::new(ty));
+        let Services {
+            outcome_aggregator,
+            test_store,
+            ..
+        } = services();
+        ManagedEnvelope::untracked(envelope, outcome_aggregator, test_store)
+    }
+
+    #[tokio::test]
+    async fn evict_lower_priority_envelopes() {
+        let buffer_guard: Arc<_> = BufferGuard::new(10).into();
+        let config: Arc<_> = Config::default().into();
+        let mut service = BufferService::create(buffer_guard.clone(), services(), config)
+            .await
+            .unwrap();
+
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let key = QueueKey::new(project_key, project_key);
+
+        for ty in [ItemType::ReplayRecording, ItemType::Transaction] {
+            let mut envelope = empty_envelope();
+            envelope.add_item(Item::new(ty));
+            let Services {
+                outcome_aggregator,
+                test_store,
+                ..
+            } = services();
+            let value = buffer_guard
+                .enter(envelope, outcome_aggregator, test_store)
+                .unwrap();
+            service
+                .handle_enqueue(Enqueue { key, value })
+                .await
+                .unwrap();
+        }
+
+        // The replay recording is evicted in favor of an event, which takes over its permit.
+        let (sender, request) = AsyncResponse::channel();
+        service.handle_evict(Evict::new(Priority::High, sender));
+        let slot = request.await.unwrap();
+        assert!(slot.is_some());
+        assert_eq!(buffer_guard.used(), 2);
+        drop(slot);
+        assert_eq!(buffer_guard.used(), 1);
+
+        // There is no envelope with lower priority than a transaction anymore.
+        let (sender, request) = AsyncResponse::channel();
+        service.handle_evict(Evict::new(Priority::Normal, sender));
+        assert!(request.await.unwrap().is_none());
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        service
+            .handle_dequeue(DequeueMany::new(project_key, vec![key], tx))
+            .await
+            .unwrap();
+
+        let mut types = Vec::new();
+        while let Some(envelope) = rx.recv().await {
+            let item = envelope.envelope().items().next().unwrap();
+            types.push(item.ty().clone());
+        }
+        assert_eq!(types, [ItemType::Transaction]);
+    }
+
+    #[tokio::test]
+    async fn evict_when_memory_is_full() {
+        let buffer_guard: Arc<_> = BufferGuard::new(10).into();
+        let config: Arc<_> = Config::from_json_value(serde_json::json!({
+            "spool": {
+                "envelopes": {
+                    "max_memory_size": 1, // Full with a single envelope.
+                }
+            }
+        }))
+        .unwrap()
+        .into();
+        let mut service = BufferService::create(buffer_guard, services(), config)
+            .await
+            .unwrap();
+
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let key = QueueKey::new(project_key, project_key);
+
+        // The replay recording is evicted in favor of the event. The transaction is accepted even
+        // though there is no envelope with lower priority left.
+        for ty in [
+            ItemType::ReplayRecording,
+            ItemType::Event,
+            ItemType::Transaction,
+        ] {
+            let value = managed_envelope_with_item(ty);
+            service
+                .handle_enqueue(Enqueue { key, value })
+                .await
+                .unwrap();
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        service
+            .handle_dequeue(DequeueMany::new(project_key, vec![key], tx))
+            .await
+            .unwrap();
+
+        let mut types = Vec::new();
+        while let Some(envelope) = rx.recv().await {
+            let item = envelope.envelope().items().next().unwrap();
+            types.push(item.ty().clone());
+        }
+        assert_eq!(types, [ItemType::Event, ItemType::Transaction]);
+    }
+
     #[tokio::test]
     async fn ensure_start_time_restore() {
         let buffer_guard: Arc<_> = BufferGuard::new(10).into();
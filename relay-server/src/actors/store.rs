@@ -1,23 +1,32 @@
 //! This module contains the service that forwards events and attachments to the Sentry store.
-//! The service uses kafka topics to forward data to Sentry
+//! The service uses kafka topics to forward data to Sentry. Topics can additionally be routed to
+//! batching sinks that write files locally or into object storage.
 
 use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+use anyhow::Context;
 use bytes::Bytes;
+use chrono::Utc;
+use futures::future::{self, BoxFuture, FutureExt};
 use once_cell::sync::OnceCell;
-use relay_common::{ProjectId, UnixTimestamp, Uuid};
-use relay_config::Config;
+use relay_common::{DataCategory, ProjectId, UnixTimestamp, Uuid};
+use relay_config::{Config, KAFKA_SINK};
 use relay_general::protocol::{self, EventId, SessionAggregates, SessionStatus, SessionUpdate};
-use relay_kafka::{ClientError, KafkaClient, KafkaTopic, Message};
+use relay_kafka::{
+    ClientError, DeliveryContext, DeliveryFailure, FallbackQueue, FieldType, KafkaClient,
+    KafkaTopic, Message, MessageAttributes, MessageSchema, SchemaField,
+};
 use relay_metrics::{Bucket, BucketValue, MetricNamespace, MetricResourceIdentifier};
 use relay_quotas::Scoping;
 use relay_statsd::metric;
-use relay_system::{AsyncResponse, FromMessage, Interface, Sender, Service};
+use relay_system::{Addr, AsyncResponse, Controller, FromMessage, Interface, Sender, Service};
 use serde::ser::Error;
 use serde::Serialize;
 
+use crate::actors::outcome::{OutcomeProducer, TrackRawOutcome};
+use crate::actors::sink::{BatchSink, SinkError, SinkPartition};
 use crate::envelope::{AttachmentType, Envelope, Item, ItemType};
 use crate::service::ServiceError;
 use crate::statsd::RelayCounters;
@@ -28,10 +37,15 @@ const MAX_EXPLODED_SESSIONS: usize = 100;
 /// Fallback name used for attachment items without a `filename` header.
 const UNNAMED_ATTACHMENT: &str = "Unnamed Attachment";
 
+/// Interval in which batching sinks are checked for batches that are due.
+const SINK_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug, thiserror::Error)]
 pub enum StoreError {
     #[error("failed to send the message to kafka")]
     SendFailed(#[from] ClientError),
+    #[error("failed to write the message to a sink")]
+    SinkFailed(#[from] SinkError),
     #[error("failed to store event because event id was missing")]
     NoEventId,
 }
@@ -45,16 +59,125 @@ fn make_distinct_id(s: &str) -> Uuid {
         .unwrap_or_else(|_| Uuid::new_v5(namespace, s.as_bytes()))
 }
 
-struct Producer {
+/// A destination for messages produced by the [`StoreService`].
+trait StoreSink: Send {
+    /// Writes a message for the given topic to this sink.
+    fn send(
+        &self,
+        topic: KafkaTopic,
+        organization_id: u64,
+        message: &KafkaMessage,
+        attributes: &MessageAttributes,
+    ) -> Result<(), StoreError>;
+
+    /// Writes out buffered messages.
+    ///
+    /// If `force` is `false`, only messages that are due are written.
+    fn flush(&self, _force: bool) {}
+
+    /// Writes out all buffered messages and waits for pending writes to complete.
+    fn shutdown(&self) -> BoxFuture<'static, ()> {
+        future::ready(()).boxed()
+    }
+}
+
+/// Attributes of an envelope that are shared by all Kafka messages created from it.
+#[derive(Clone, Copy, Debug, Default)]
+struct EnvelopeAttributes {
+    /// The trace ID from the dynamic sampling context.
+    trace_id: Option<Uuid>,
+    /// Whether the trace of the event was sampled.
+    sampled: Option<bool>,
+    /// Whether the event of the envelope is a transaction.
+    transaction: bool,
+}
+
+/// A [`KafkaMessage`] with the attributes for its partitioning key and headers.
+struct AttributedMessage<'a> {
+    message: &'a KafkaMessage,
+    attributes: &'a MessageAttributes,
+}
+
+impl Message for AttributedMessage<'_> {
+    fn key(&self) -> [u8; 16] {
+        self.message.key()
+    }
+
+    fn variant(&self) -> &'static str {
+        self.message.variant()
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, ClientError> {
+        Message::serialize(self.message)
+    }
+
+    fn schema(&self) -> Option<&'static MessageSchema> {
+        self.message.schema()
+    }
+
+    fn to_value(&self) -> Result<serde_json::Value, ClientError> {
+        self.message.to_value()
+    }
+
+    fn attributes(&self) -> MessageAttributes {
+        self.attributes.clone()
+    }
+}
+
+/// Information to report an outcome for a message that could not be delivered to Kafka.
+#[derive(Debug)]
+struct LostMessage {
+    project_id: ProjectId,
+    event_id: Option<EventId>,
+    category: DataCategory,
+    quantity: u32,
+}
+
+/// The built-in sink producing messages to Kafka.
+///
+/// Messages that cannot be delivered are stored in the fallback queue if configured, and
+/// reported as internal errors in outcomes otherwise.
+struct KafkaSink {
     client: KafkaClient,
+    drain_batch_size: usize,
 }
 
-impl Producer {
-    pub fn create(config: &Arc<Config>) -> anyhow::Result<Self> {
+impl KafkaSink {
+    pub fn create(
+        config: &Arc<Config>,
+        outcome_producer: Addr<OutcomeProducer>,
+    ) -> anyhow::Result<Self> {
         let mut client_builder = KafkaClient::builder();
+        let mut drain_batch_size = 0;
+
+        if let Some(fallback) = config.kafka_fallback() {
+            let queue = FallbackQueue::open(&fallback.path, fallback.max_size.as_bytes() as u64)
+                .context(ServiceError::Kafka)?;
+            client_builder = client_builder.fallback(queue);
+            drain_batch_size = fallback.drain_batch_size;
+        }
+
+        let outcome_config = config.clone();
+        client_builder =
+            client_builder.on_delivery_failure(Arc::new(move |failure: DeliveryFailure<'_>| {
+                let lost = failure
+                    .context
+                    .and_then(|c| c.downcast_ref::<LostMessage>());
+                if let Some(lost) = lost {
+                    outcome_producer.send(TrackRawOutcome::internal_error(
+                        failure.organization_id,
+                        lost.project_id,
+                        lost.event_id,
+                        lost.category,
+                        lost.quantity,
+                        &outcome_config,
+                    ));
+                }
+            }));
 
         for topic in KafkaTopic::iter()
             .filter(|t| **t != KafkaTopic::Outcomes || **t != KafkaTopic::OutcomesBilling)
+            .filter(|t| config.sink_route(**t).contains(&KAFKA_SINK))
         {
             let kafka_config = &config
                 .kafka_config(*topic)
@@ -66,10 +189,74 @@ impl Producer {
 
         Ok(Self {
             client: client_builder.build(),
+            drain_batch_size,
         })
     }
 }
 
+impl StoreSink for KafkaSink {
+    fn send(
+        &self,
+        topic: KafkaTopic,
+        organization_id: u64,
+        message: &KafkaMessage,
+        attributes: &MessageAttributes,
+    ) -> Result<(), StoreError> {
+        let context = message
+            .lost_message(topic)
+            .map(|lost| Box::new(lost) as DeliveryContext);
+
+        let message = AttributedMessage {
+            message,
+            attributes,
+        };
+        self.client
+            .send_message_with_context(topic, organization_id, &message, context)?;
+        Ok(())
+    }
+
+    fn flush(&self, force: bool) {
+        // Stored messages remain in the fallback queue during shutdown.
+        if force || self.drain_batch_size == 0 {
+            return;
+        }
+
+        if let Err(error) = self.client.drain_fallback(self.drain_batch_size) {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to drain kafka fallback queue"
+            );
+        }
+    }
+}
+
+impl StoreSink for BatchSink {
+    fn send(
+        &self,
+        topic: KafkaTopic,
+        _organization_id: u64,
+        message: &KafkaMessage,
+        _attributes: &MessageAttributes,
+    ) -> Result<(), StoreError> {
+        let partition = SinkPartition::new(
+            message.project_id(),
+            message.data_category(topic),
+            Utc::now(),
+        );
+        let record = serde_json::to_vec(message).map_err(SinkError::from)?;
+        self.append(partition, &record)?;
+        Ok(())
+    }
+
+    fn flush(&self, force: bool) {
+        BatchSink::flush(self, force)
+    }
+
+    fn shutdown(&self) -> BoxFuture<'static, ()> {
+        BatchSink::shutdown(self)
+    }
+}
+
 /// Publishes an [`Envelope`] to the Sentry core application through Kafka topics.
 #[derive(Clone, Debug)]
 pub struct StoreEnvelope {
@@ -95,13 +282,68 @@ impl FromMessage<StoreEnvelope> for Store {
 /// Service implementing the [`Store`] interface.
 pub struct StoreService {
     config: Arc<Config>,
-    producer: Producer,
+    sinks: Vec<Box<dyn StoreSink>>,
+    routes: BTreeMap<KafkaTopic, Vec<usize>>,
 }
 
 impl StoreService {
-    pub fn create(config: Arc<Config>) -> anyhow::Result<Self> {
-        let producer = Producer::create(&config)?;
-        Ok(Self { config, producer })
+    pub fn create(
+        config: Arc<Config>,
+        outcome_producer: Addr<OutcomeProducer>,
+    ) -> anyhow::Result<Self> {
+        let mut names = Vec::new();
+        let mut sinks: Vec<Box<dyn StoreSink>> = Vec::new();
+
+        // Kafka is only required if at least one topic produced by the store is routed to it.
+        let uses_kafka = KafkaTopic::iter()
+            .filter(|t| !matches!(t, KafkaTopic::Outcomes | KafkaTopic::OutcomesBilling))
+            .any(|t| config.sink_route(*t).contains(&KAFKA_SINK));
+
+        if uses_kafka {
+            names.push(KAFKA_SINK);
+            sinks.push(Box::new(KafkaSink::create(&config, outcome_producer)?));
+        }
+
+        for (name, sink_config) in config.store_sinks() {
+            if name == KAFKA_SINK {
+                anyhow::bail!("store sink name `{KAFKA_SINK}` is reserved");
+            }
+
+            let sink = BatchSink::create(sink_config)
+                .with_context(|| format!("failed to create store sink `{name}`"))?;
+            names.push(name.as_str());
+            sinks.push(Box::new(sink));
+        }
+
+        let mut routes = BTreeMap::new();
+        for topic in KafkaTopic::iter() {
+            let mut route = Vec::new();
+            for name in config.sink_route(*topic) {
+                let index = names
+                    .iter()
+                    .position(|n| *n == name)
+                    .with_context(|| format!("unknown store sink `{name}`"))?;
+                route.push(index);
+            }
+            routes.insert(*topic, route);
+        }
+
+        Ok(Self {
+            config,
+            sinks,
+            routes,
+        })
+    }
+
+    fn flush_sinks(&self, force: bool) {
+        for sink in &self.sinks {
+            sink.flush(force);
+        }
+    }
+
+    /// Writes out all sinks and waits for their pending writes.
+    async fn handle_shutdown(&self) {
+        future::join_all(self.sinks.iter().map(|sink| sink.shutdown())).await;
     }
 
     fn handle_message(&self, message: Store) {
@@ -125,6 +367,12 @@ impl StoreService {
             )
         });
 
+        let attributes = EnvelopeAttributes {
+            trace_id: envelope.dsc().map(|dsc| dsc.trace_id),
+            sampled: event_item.and_then(|item| item.sampled()),
+            transaction: event_item.map(|item| item.ty()) == Some(&ItemType::Transaction),
+        };
+
         let topic = if envelope.get_item_by(is_slow_item).is_some() {
             KafkaTopic::Attachments
         } else if event_item.map(|x| x.ty()) == Some(&ItemType::Transaction) {
@@ -144,6 +392,7 @@ impl StoreService {
                         scoping.organization_id,
                         scoping.project_id,
                         item,
+                        &attributes,
                     )?;
                     attachments.push(attachment);
                 }
@@ -155,6 +404,7 @@ impl StoreService {
                         scoping.project_id,
                         start_time,
                         item,
+                        &attributes,
                     )?;
                     metric!(
                         counter(RelayCounters::ProcessingMessageProduced) += 1,
@@ -182,10 +432,16 @@ impl StoreService {
                     scoping.key_id,
                     start_time,
                     item,
+                    &attributes,
+                )?,
+                ItemType::ReplayRecording => self.produce_replay_recording(
+                    event_id,
+                    scoping,
+                    item,
+                    start_time,
+                    retention,
+                    &attributes,
                 )?,
-                ItemType::ReplayRecording => {
-                    self.produce_replay_recording(event_id, scoping, item, start_time, retention)?
-                }
                 ItemType::ReplayEvent => self.produce_replay_event(
                     event_id.ok_or(StoreError::NoEventId)?,
                     scoping.organization_id,
@@ -193,6 +449,7 @@ impl StoreService {
                     start_time,
                     retention,
                     item,
+                    &attributes,
                 )?,
                 ItemType::CheckIn => self.produce_check_in(
                     scoping.organization_id,
@@ -201,6 +458,7 @@ impl StoreService {
                     client,
                     retention,
                     item,
+                    &attributes,
                 )?,
                 _ => {}
             }
@@ -225,7 +483,7 @@ impl StoreService {
         for message in kafka_messages {
             let is_attachment = matches!(&message, KafkaMessage::Attachment(_));
 
-            self.produce(topic, scoping.organization_id, message)?;
+            self.produce(topic, scoping.organization_id, message, &attributes)?;
 
             if is_attachment {
                 metric!(
@@ -296,12 +554,14 @@ impl StoreService {
         organization_id: u64,
         // Takes message by value to ensure it is not being produced twice.
         message: KafkaMessage,
+        envelope: &EnvelopeAttributes,
     ) -> Result<(), StoreError> {
         relay_log::trace!("Sending kafka message of type {}", message.variant());
 
-        self.producer
-            .client
-            .send_message(topic, organization_id, &message)?;
+        let attributes = message.attributes(envelope);
+        for index in self.routes.get(&topic).into_iter().flatten() {
+            self.sinks[*index].send(topic, organization_id, &message, &attributes)?;
+        }
 
         Ok(())
     }
@@ -312,6 +572,7 @@ impl StoreService {
         organization_id: u64,
         project_id: ProjectId,
         item: &Item,
+        attributes: &EnvelopeAttributes,
     ) -> Result<ChunkedAttachment, StoreError> {
         let id = Uuid::new_v4().to_string();
 
@@ -332,7 +593,12 @@ impl StoreService {
                 id: id.clone(),
                 chunk_index,
             });
-            self.produce(KafkaTopic::Attachments, organization_id, attachment_message)?;
+            self.produce(
+                KafkaTopic::Attachments,
+                organization_id,
+                attachment_message,
+                attributes,
+            )?;
             offset += chunk_size;
             chunk_index += 1;
         }
@@ -363,6 +629,7 @@ impl StoreService {
         project_id: ProjectId,
         start_time: Instant,
         item: &Item,
+        attributes: &EnvelopeAttributes,
     ) -> Result<(), StoreError> {
         let message = KafkaMessage::UserReport(UserReportKafkaMessage {
             project_id,
@@ -371,7 +638,12 @@ impl StoreService {
             start_time: UnixTimestamp::from_instant(start_time).as_secs(),
         });
 
-        self.produce(KafkaTopic::Attachments, organization_id, message)
+        self.produce(
+            KafkaTopic::Attachments,
+            organization_id,
+            message,
+            attributes,
+        )
     }
 
     fn produce_sessions(
@@ -549,7 +821,12 @@ impl StoreService {
             }
         };
 
-        self.produce(topic, organization_id, KafkaMessage::Metric(message))?;
+        self.produce(
+            topic,
+            organization_id,
+            KafkaMessage::Metric(message),
+            &EnvelopeAttributes::default(),
+        )?;
         metric!(
             counter(RelayCounters::ProcessingMessageProduced) += 1,
             event_type = "metric"
@@ -593,6 +870,7 @@ impl StoreService {
             KafkaTopic::Sessions,
             organization_id,
             KafkaMessage::Session(message),
+            &EnvelopeAttributes::default(),
         )?;
         metric!(
             counter(RelayCounters::ProcessingMessageProduced) += 1,
@@ -608,6 +886,7 @@ impl StoreService {
         key_id: Option<u64>,
         start_time: Instant,
         item: &Item,
+        attributes: &EnvelopeAttributes,
     ) -> Result<(), StoreError> {
         let message = ProfileKafkaMessage {
             organization_id,
@@ -620,6 +899,7 @@ impl StoreService {
             KafkaTopic::Profiles,
             organization_id,
             KafkaMessage::Profile(message),
+            attributes,
         )?;
         metric!(
             counter(RelayCounters::ProcessingMessageProduced) += 1,
@@ -636,6 +916,7 @@ impl StoreService {
         start_time: Instant,
         retention_days: u16,
         item: &Item,
+        attributes: &EnvelopeAttributes,
     ) -> Result<(), StoreError> {
         let message = ReplayEventKafkaMessage {
             replay_id,
@@ -648,6 +929,7 @@ impl StoreService {
             KafkaTopic::ReplayEvents,
             organization_id,
             KafkaMessage::ReplayEvent(message),
+            attributes,
         )?;
         metric!(
             counter(RelayCounters::ProcessingMessageProduced) += 1,
@@ -663,6 +945,7 @@ impl StoreService {
         item: &Item,
         start_time: Instant,
         retention: u16,
+        attributes: &EnvelopeAttributes,
     ) -> Result<(), StoreError> {
         // Payloads must be chunked if they exceed a certain threshold. We do not chunk every
         // message because we can achieve better parallelism when dealing with a single
@@ -690,6 +973,7 @@ impl StoreService {
                 KafkaTopic::ReplayRecordings,
                 scoping.organization_id,
                 message,
+                attributes,
             )?;
 
             metric!(
@@ -703,6 +987,7 @@ impl StoreService {
                 scoping.organization_id,
                 scoping.project_id,
                 item,
+                attributes,
             )?;
 
             let message = KafkaMessage::ReplayRecording(ReplayRecordingKafkaMessage {
@@ -719,6 +1004,7 @@ impl StoreService {
                 KafkaTopic::ReplayRecordings,
                 scoping.organization_id,
                 message,
+                attributes,
             )?;
 
             metric!(
@@ -736,6 +1022,7 @@ impl StoreService {
         organization_id: u64,
         project_id: ProjectId,
         item: &Item,
+        attributes: &EnvelopeAttributes,
     ) -> Result<ReplayRecordingChunkMeta, StoreError> {
         let id = Uuid::new_v4().to_string();
 
@@ -765,6 +1052,7 @@ impl StoreService {
                 KafkaTopic::ReplayRecordings,
                 organization_id,
                 replay_recording_chunk_message,
+                attributes,
             )?;
 
             offset += chunk_size;
@@ -789,6 +1077,7 @@ impl StoreService {
         client: Option<&str>,
         retention_days: u16,
         item: &Item,
+        attributes: &EnvelopeAttributes,
     ) -> Result<(), StoreError> {
         let message = KafkaMessage::CheckIn(CheckInKafkaMessage {
             project_id,
@@ -798,7 +1087,7 @@ impl StoreService {
             payload: item.payload(),
         });
 
-        self.produce(KafkaTopic::Monitors, organization_id, message)?;
+        self.produce(KafkaTopic::Monitors, organization_id, message, attributes)?;
 
         metric!(
             counter(RelayCounters::ProcessingMessageProduced) += 1,
@@ -816,10 +1105,26 @@ impl Service for StoreService {
         tokio::spawn(async move {
             relay_log::info!("store forwarder started");
 
-            while let Some(message) = rx.recv().await {
-                self.handle_message(message);
+            let mut shutdown = Controller::shutdown_handle();
+            let mut shutting_down = false;
+            let mut ticker = tokio::time::interval(SINK_FLUSH_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    biased;
+
+                    Some(message) = rx.recv() => self.handle_message(message),
+                    // After the shutdown signal, write all batches instead of only due ones.
+                    _ = ticker.tick() => self.flush_sinks(shutting_down),
+                    _ = shutdown.notified(), if !shutting_down => {
+                        shutting_down = true;
+                        self.handle_shutdown().await;
+                    }
+                    else => break,
+                }
             }
 
+            self.handle_shutdown().await;
             relay_log::info!("store forwarder stopped");
         });
     }
@@ -860,6 +1165,20 @@ struct ChunkedAttachment {
     rate_limited: Option<bool>,
 }
 
+/// Schema of [`ChunkedAttachment`] for Kafka topics with a schema-based encoding.
+static CHUNKED_ATTACHMENT_SCHEMA: MessageSchema = MessageSchema {
+    name: "ChunkedAttachment",
+    fields: &[
+        SchemaField::new("id", 1, FieldType::String),
+        SchemaField::new("name", 2, FieldType::String),
+        SchemaField::new("content_type", 3, FieldType::Optional(&FieldType::String)),
+        SchemaField::new("attachment_type", 4, FieldType::String),
+        SchemaField::new("chunks", 5, FieldType::Long),
+        SchemaField::new("size", 6, FieldType::Optional(&FieldType::Long)),
+        SchemaField::new("rate_limited", 7, FieldType::Optional(&FieldType::Bool)),
+    ],
+};
+
 /// A hack to make rmp-serde behave more like serde-json when serializing enums.
 ///
 /// Cannot serialize bytes.
@@ -892,6 +1211,23 @@ struct EventKafkaMessage {
     attachments: Vec<ChunkedAttachment>,
 }
 
+/// Schema of [`EventKafkaMessage`] for Kafka topics with a schema-based encoding.
+static EVENT_SCHEMA: MessageSchema = MessageSchema {
+    name: "EventMessage",
+    fields: &[
+        SchemaField::new("payload", 1, FieldType::Bytes),
+        SchemaField::new("start_time", 2, FieldType::Long),
+        SchemaField::new("event_id", 3, FieldType::String),
+        SchemaField::new("project_id", 4, FieldType::Long),
+        SchemaField::new("remote_addr", 5, FieldType::Optional(&FieldType::String)),
+        SchemaField::new(
+            "attachments",
+            6,
+            FieldType::Array(&FieldType::Record(&CHUNKED_ATTACHMENT_SCHEMA)),
+        ),
+    ],
+};
+
 #[derive(Debug, Serialize)]
 struct ReplayEventKafkaMessage {
     /// Raw event payload.
@@ -1030,6 +1366,28 @@ struct SessionKafkaMessage {
     retention_days: u16,
 }
 
+/// Schema of [`SessionKafkaMessage`] for Kafka topics with a schema-based encoding.
+static SESSION_SCHEMA: MessageSchema = MessageSchema {
+    name: "SessionMessage",
+    fields: &[
+        SchemaField::new("org_id", 1, FieldType::Long),
+        SchemaField::new("project_id", 2, FieldType::Long),
+        SchemaField::new("session_id", 3, FieldType::String),
+        SchemaField::new("distinct_id", 4, FieldType::String),
+        SchemaField::new("quantity", 5, FieldType::Long),
+        SchemaField::new("seq", 6, FieldType::Long),
+        SchemaField::new("received", 7, FieldType::Double),
+        SchemaField::new("started", 8, FieldType::Double),
+        SchemaField::new("duration", 9, FieldType::Optional(&FieldType::Double)),
+        SchemaField::new("status", 10, FieldType::String),
+        SchemaField::new("errors", 11, FieldType::Long),
+        SchemaField::new("release", 12, FieldType::String),
+        SchemaField::new("environment", 13, FieldType::Optional(&FieldType::String)),
+        SchemaField::new("sdk", 14, FieldType::Optional(&FieldType::String)),
+        SchemaField::new("retention_days", 15, FieldType::Long),
+    ],
+};
+
 #[derive(Clone, Debug, Serialize)]
 struct MetricKafkaMessage {
     org_id: u64,
@@ -1042,6 +1400,72 @@ struct MetricKafkaMessage {
     retention_days: u16,
 }
 
+impl MetricKafkaMessage {
+    /// Returns the representation of this message that is encoded with [`METRIC_SCHEMA`].
+    ///
+    /// Since schemas cannot describe the polymorphic `value`, it is moved into a separate field
+    /// for every metric type.
+    fn schema_value(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let field = match self.value {
+            BucketValue::Counter(_) => "counter",
+            BucketValue::Distribution(_) => "distribution",
+            BucketValue::Set(_) => "set",
+            BucketValue::Gauge(_) => "gauge",
+        };
+
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            if let Some(inner) = object.remove("value") {
+                object.insert(field.to_owned(), inner);
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+/// Schema of a [`GaugeValue`](relay_metrics::GaugeValue) in [`METRIC_SCHEMA`].
+static GAUGE_SCHEMA: MessageSchema = MessageSchema {
+    name: "GaugeValue",
+    fields: &[
+        SchemaField::new("last", 1, FieldType::Double),
+        SchemaField::new("min", 2, FieldType::Double),
+        SchemaField::new("max", 3, FieldType::Double),
+        SchemaField::new("sum", 4, FieldType::Double),
+        SchemaField::new("count", 5, FieldType::Long),
+    ],
+};
+
+/// Schema of [`MetricKafkaMessage`] for Kafka topics with a schema-based encoding.
+static METRIC_SCHEMA: MessageSchema = MessageSchema {
+    name: "MetricMessage",
+    fields: &[
+        SchemaField::new("org_id", 1, FieldType::Long),
+        SchemaField::new("project_id", 2, FieldType::Long),
+        SchemaField::new("name", 3, FieldType::String),
+        SchemaField::new("type", 4, FieldType::String),
+        SchemaField::new("counter", 5, FieldType::Optional(&FieldType::Double)),
+        SchemaField::new(
+            "distribution",
+            6,
+            FieldType::Optional(&FieldType::Array(&FieldType::Double)),
+        ),
+        SchemaField::new(
+            "set",
+            7,
+            FieldType::Optional(&FieldType::Array(&FieldType::Long)),
+        ),
+        SchemaField::new(
+            "gauge",
+            8,
+            FieldType::Optional(&FieldType::Record(&GAUGE_SCHEMA)),
+        ),
+        SchemaField::new("timestamp", 9, FieldType::Long),
+        SchemaField::new("tags", 10, FieldType::Map(&FieldType::String)),
+        SchemaField::new("retention_days", 11, FieldType::Long),
+    ],
+};
+
 #[derive(Clone, Debug, Serialize)]
 struct ProfileKafkaMessage {
     organization_id: u64,
@@ -1141,6 +1565,121 @@ impl Message for KafkaMessage {
             _ => rmp_serde::to_vec_named(&self).map_err(ClientError::InvalidMsgPack),
         }
     }
+
+    fn schema(&self) -> Option<&'static MessageSchema> {
+        match self {
+            KafkaMessage::Event(_) => Some(&EVENT_SCHEMA),
+            KafkaMessage::Session(_) => Some(&SESSION_SCHEMA),
+            KafkaMessage::Metric(_) => Some(&METRIC_SCHEMA),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Result<serde_json::Value, ClientError> {
+        let value = match self {
+            KafkaMessage::Event(message) => serde_json::to_value(message),
+            KafkaMessage::Session(message) => serde_json::to_value(message),
+            KafkaMessage::Metric(message) => message.schema_value(),
+            _ => return Err(ClientError::MissingSchema),
+        };
+
+        value.map_err(ClientError::InvalidJson)
+    }
+}
+
+impl KafkaMessage {
+    /// Returns the project that this message belongs to.
+    fn project_id(&self) -> ProjectId {
+        match self {
+            Self::Event(message) => message.project_id,
+            Self::Attachment(message) => message.project_id,
+            Self::AttachmentChunk(message) => message.project_id,
+            Self::UserReport(message) => message.project_id,
+            Self::Session(message) => message.project_id,
+            Self::Metric(message) => message.project_id,
+            Self::Profile(message) => message.project_id,
+            Self::ReplayEvent(message) => message.project_id,
+            Self::ReplayRecordingNotChunked(message) => message.project_id,
+            Self::ReplayRecording(message) => message.project_id,
+            Self::ReplayRecordingChunk(message) => message.project_id,
+            Self::CheckIn(message) => message.project_id,
+        }
+    }
+
+    /// Returns the outcome to report if this message is lost, if any.
+    ///
+    /// Attachment and replay recording chunks are not reported, since they are accounted for by
+    /// the message that references them.
+    fn lost_message(&self, topic: KafkaTopic) -> Option<LostMessage> {
+        let (event_id, quantity) = match self {
+            Self::Event(message) => (Some(message.event_id), 1),
+            Self::Attachment(message) => (
+                Some(message.event_id),
+                message.attachment.size?.try_into().ok()?,
+            ),
+            Self::Profile(_) | Self::CheckIn(_) => (None, 1),
+            Self::ReplayRecording(message) => (Some(message.replay_id), 1),
+            Self::ReplayRecordingNotChunked(message) => (Some(message.replay_id), 1),
+            Self::AttachmentChunk(_)
+            | Self::UserReport(_)
+            | Self::Session(_)
+            | Self::Metric(_)
+            | Self::ReplayEvent(_)
+            | Self::ReplayRecordingChunk(_) => return None,
+        };
+
+        Some(LostMessage {
+            project_id: self.project_id(),
+            event_id,
+            category: self.data_category(topic),
+            quantity,
+        })
+    }
+
+    /// Returns the type of the envelope item that this message was created from.
+    fn item_type(&self, envelope: &EnvelopeAttributes) -> &'static str {
+        match self {
+            Self::Event(_) if envelope.transaction => "transaction",
+            Self::Event(_) => "event",
+            Self::Attachment(_) | Self::AttachmentChunk(_) => "attachment",
+            Self::UserReport(_) => "user_report",
+            Self::Session(_) => "session",
+            Self::Metric(_) => "metric_buckets",
+            Self::Profile(_) => "profile",
+            Self::ReplayEvent(_) => "replay_event",
+            Self::ReplayRecordingNotChunked(_)
+            | Self::ReplayRecording(_)
+            | Self::ReplayRecordingChunk(_) => "replay_recording",
+            Self::CheckIn(_) => "check_in",
+        }
+    }
+
+    /// Returns the attributes for the partitioning key and headers of this message.
+    fn attributes(&self, envelope: &EnvelopeAttributes) -> MessageAttributes {
+        MessageAttributes {
+            project_id: Some(self.project_id().value()),
+            item_type: Some(self.item_type(envelope)),
+            trace_id: envelope.trace_id.map(|trace_id| *trace_id.as_bytes()),
+            sampled: envelope.sampled,
+        }
+    }
+
+    /// Returns the data category used to partition this message in batching sinks.
+    fn data_category(&self, topic: KafkaTopic) -> DataCategory {
+        match self {
+            Self::Event(_) if topic == KafkaTopic::Transactions => DataCategory::Transaction,
+            Self::Event(_) => DataCategory::Error,
+            Self::Attachment(_) | Self::AttachmentChunk(_) => DataCategory::Attachment,
+            Self::UserReport(_) | Self::Metric(_) => DataCategory::Default,
+            Self::Session(_) => DataCategory::Session,
+            Self::Profile(_) => DataCategory::Profile,
+            Self::ReplayEvent(_)
+            | Self::ReplayRecordingNotChunked(_)
+            | Self::ReplayRecording(_)
+            | Self::ReplayRecordingChunk(_) => DataCategory::Replay,
+            Self::CheckIn(_) => DataCategory::Monitor,
+        }
+    }
 }
 
 /// Determines if the given item is considered slow.
@@ -1285,4 +1824,163 @@ mod tests {
             panic!("No event found")
         }
     }
+
+    /// Validates messages against their schema and checks that every field of the schema is
+    /// serialized in at least one of the messages.
+    ///
+    /// Together, this ensures that the hand-written schemas match the message types.
+    fn assert_schema(schema: &MessageSchema, values: &[serde_json::Value]) {
+        for value in values {
+            schema.validate(value).unwrap();
+        }
+
+        for field in schema.fields {
+            assert!(
+                values.iter().any(|value| value.get(field.name).is_some()),
+                "field `{}` of `{}` is not serialized",
+                field.name,
+                schema.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_message_schemas() {
+        let (_, event_id, scoping, mut attachments) = arguments_extract_kafka_msgs();
+        attachments[0].content_type = Some("text/plain".to_owned());
+        attachments[0].size = Some(42);
+
+        let event = EventKafkaMessage {
+            payload: Bytes::from_static(b"{}"),
+            start_time: 0,
+            event_id,
+            project_id: scoping.project_id,
+            remote_addr: Some("127.0.0.1".to_owned()),
+            attachments,
+        };
+        let value = serde_json::to_value(&event).unwrap();
+        assert_schema(
+            &CHUNKED_ATTACHMENT_SCHEMA,
+            &[value["attachments"][0].clone()],
+        );
+        assert_schema(&EVENT_SCHEMA, &[value]);
+
+        let session = SessionKafkaMessage {
+            org_id: scoping.organization_id,
+            project_id: scoping.project_id,
+            session_id: Uuid::new_v4(),
+            distinct_id: Uuid::new_v4(),
+            quantity: 1,
+            seq: 0,
+            received: 1.5,
+            started: 1.0,
+            duration: Some(0.5),
+            status: SessionStatus::Ok,
+            errors: 0,
+            release: "1.0".to_owned(),
+            environment: Some("production".to_owned()),
+            sdk: Some("sentry.python".to_owned()),
+            retention_days: 90,
+        };
+        let value = serde_json::to_value(&session).unwrap();
+        assert_schema(&SESSION_SCHEMA, &[value]);
+
+        let values = [
+            BucketValue::Counter(1.0),
+            BucketValue::Distribution(relay_metrics::dist![1.0, 2.0]),
+            BucketValue::Set([1, 2].into()),
+            BucketValue::Gauge(relay_metrics::GaugeValue::single(1.0)),
+        ];
+
+        let mut metrics = Vec::new();
+        for value in values {
+            let metric = MetricKafkaMessage {
+                org_id: scoping.organization_id,
+                project_id: scoping.project_id,
+                name: "c:transactions/count_per_root_project@none".to_owned(),
+                value,
+                timestamp: UnixTimestamp::from_secs(0),
+                tags: BTreeMap::from([("transaction".to_owned(), "/".to_owned())]),
+                retention_days: 90,
+            };
+            metrics.push(metric.schema_value().unwrap());
+        }
+        assert_schema(&METRIC_SCHEMA, &metrics);
+
+        let gauges: Vec<_> = metrics
+            .iter()
+            .filter_map(|m| m.get("gauge"))
+            .cloned()
+            .collect();
+        assert_schema(&GAUGE_SCHEMA, &gauges);
+
+        insta::assert_snapshot!(METRIC_SCHEMA.proto_schema(), @r###"
+        syntax = "proto3";
+
+        package sentry.relay;
+
+        message MetricMessage {
+          int64 org_id = 1;
+          int64 project_id = 2;
+          string name = 3;
+          string type = 4;
+          optional double counter = 5;
+          repeated double distribution = 6;
+          repeated int64 set = 7;
+          GaugeValue gauge = 8;
+          int64 timestamp = 9;
+          map<string, string> tags = 10;
+          int64 retention_days = 11;
+        }
+
+        message GaugeValue {
+          double last = 1;
+          double min = 2;
+          double max = 3;
+          double sum = 4;
+          int64 count = 5;
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_message_attributes() {
+        let (_, event_id, scoping, attachments) = arguments_extract_kafka_msgs();
+        let trace_id = Uuid::new_v4();
+
+        let message = KafkaMessage::Event(EventKafkaMessage {
+            payload: Bytes::from_static(b"{}"),
+            start_time: 0,
+            event_id,
+            project_id: scoping.project_id,
+            remote_addr: None,
+            attachments,
+        });
+
+        let envelope = EnvelopeAttributes {
+            trace_id: Some(trace_id),
+            sampled: Some(true),
+            transaction: true,
+        };
+
+        assert_eq!(
+            message.attributes(&envelope),
+            MessageAttributes {
+                project_id: Some(21),
+                item_type: Some("transaction"),
+                trace_id: Some(*trace_id.as_bytes()),
+                sampled: Some(true),
+            }
+        );
+
+        assert_eq!(
+            message.attributes(&EnvelopeAttributes::default()),
+            MessageAttributes {
+                project_id: Some(21),
+                item_type: Some("event"),
+                trace_id: None,
+                sampled: None,
+            }
+        );
+    }
 }
@@ -1,13 +1,27 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use relay_config::{Config, RelayMode};
 use relay_general::protocol::EventId;
 use relay_system::{AsyncResponse, FromMessage, NoResponse, Sender};
+use serde::Serialize;
 
 use crate::actors::outcome::Outcome;
 use crate::envelope::Envelope;
 
+/// Maximum number of events whose lifecycle is recorded at the same time.
+const MAX_LIFECYCLES: usize = 10_000;
+
+/// Maximum number of records in the lifecycle of a single event.
+///
+/// Envelopes with the same event ID, such as retries, append to the same lifecycle.
+const MAX_LIFECYCLE_RECORDS: usize = 100;
+
+/// Interval in which expired lifecycles are removed.
+const LIFECYCLE_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Either a captured envelope or an error that occured during processing.
 pub type CapturedEnvelope = Result<Box<Envelope>, String>;
 
@@ -53,11 +67,75 @@ pub struct GetCapturedEnvelope {
     pub event_id: EventId,
 }
 
+/// A stage in the lifecycle of an envelope.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum LifecycleStage {
+    /// The envelope was received by this Relay.
+    Received,
+    /// The envelope was buffered until the project config is available.
+    Spooled,
+    /// A step of the envelope processor has completed.
+    Processed { step: &'static str },
+    /// Items of the envelope were rate limited.
+    RateLimited,
+    /// An item was removed from the envelope, for example by a rate limit.
+    ItemDropped { item: String, outcome: String },
+    /// The envelope was dropped, for example by a filter, dynamic sampling or a rate limit.
+    Rejected { outcome: String },
+    /// The envelope was sent to the upstream.
+    SentUpstream,
+    /// The envelope was written to Kafka.
+    Stored,
+}
+
+/// A stage in the lifecycle of an envelope along with the time it was reached.
+#[derive(Clone, Debug, Serialize)]
+pub struct LifecycleRecord {
+    /// The time at which the stage was reached.
+    pub timestamp: DateTime<Utc>,
+    /// The lifecycle stage.
+    #[serde(flatten)]
+    pub stage: LifecycleStage,
+}
+
+impl LifecycleRecord {
+    /// Creates a record for a stage that was reached now.
+    pub fn now(stage: LifecycleStage) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            stage,
+        }
+    }
+}
+
+/// Appends records to the lifecycle log of an event.
+///
+/// Lifecycle logs are retained for the given TTL after the first record of the event. Records
+/// beyond a fixed limit per event are dropped.
+#[derive(Debug)]
+pub struct RecordLifecycle {
+    pub event_id: EventId,
+    pub ttl: Duration,
+    pub records: Vec<LifecycleRecord>,
+}
+
+/// Resolves the lifecycle log of an event by the given `event_id`.
+#[derive(Debug)]
+pub struct GetLifecycle {
+    pub event_id: EventId,
+}
+
 /// Stores and retrieves Envelopes for integration testing.
+///
+/// This also keeps the lifecycle logs of envelopes in projects that have lifecycle debugging
+/// enabled, regardless of the Relay mode.
 #[derive(Debug)]
 pub enum TestStore {
     Capture(Box<Capture>),
     Get(GetCapturedEnvelope, Sender<Option<CapturedEnvelope>>),
+    RecordLifecycle(RecordLifecycle),
+    GetLifecycle(GetLifecycle, Sender<Option<Vec<LifecycleRecord>>>),
 }
 
 impl relay_system::Interface for TestStore {}
@@ -81,10 +159,34 @@ impl FromMessage<GetCapturedEnvelope> for TestStore {
     }
 }
 
+impl FromMessage<RecordLifecycle> for TestStore {
+    type Response = NoResponse;
+
+    fn from_message(message: RecordLifecycle, _: ()) -> Self {
+        Self::RecordLifecycle(message)
+    }
+}
+
+impl FromMessage<GetLifecycle> for TestStore {
+    type Response = AsyncResponse<Option<Vec<LifecycleRecord>>>;
+
+    fn from_message(message: GetLifecycle, sender: Sender<Option<Vec<LifecycleRecord>>>) -> Self {
+        Self::GetLifecycle(message, sender)
+    }
+}
+
+/// The recorded lifecycle of a single event.
+#[derive(Debug)]
+struct Lifecycle {
+    expires: Instant,
+    records: Vec<LifecycleRecord>,
+}
+
 /// Service implementing the [`TestStore`] interface.
 pub struct TestStoreService {
     config: Arc<Config>,
     captures: BTreeMap<EventId, CapturedEnvelope>,
+    lifecycles: BTreeMap<EventId, Lifecycle>,
 }
 
 impl TestStoreService {
@@ -92,6 +194,7 @@ impl TestStoreService {
         Self {
             config,
             captures: BTreeMap::new(),
+            lifecycles: BTreeMap::new(),
         }
     }
 
@@ -118,10 +221,55 @@ impl TestStoreService {
         self.captures.get(&message.event_id).cloned()
     }
 
+    fn record_lifecycle(&mut self, message: RecordLifecycle) {
+        let now = Instant::now();
+
+        if !self.lifecycles.contains_key(&message.event_id) {
+            self.prune_lifecycles(now);
+
+            if self.lifecycles.len() >= MAX_LIFECYCLES {
+                relay_log::debug!(event_id = %message.event_id, "dropping lifecycle records");
+                return;
+            }
+        }
+
+        let lifecycle = self
+            .lifecycles
+            .entry(message.event_id)
+            .or_insert_with(|| Lifecycle {
+                expires: now + message.ttl,
+                records: Vec::new(),
+            });
+
+        let remaining = MAX_LIFECYCLE_RECORDS.saturating_sub(lifecycle.records.len());
+        if message.records.len() > remaining {
+            relay_log::debug!(event_id = %message.event_id, "dropping lifecycle records");
+        }
+
+        lifecycle
+            .records
+            .extend(message.records.into_iter().take(remaining));
+    }
+
+    /// Removes all lifecycles that have expired.
+    fn prune_lifecycles(&mut self, now: Instant) {
+        self.lifecycles
+            .retain(|_, lifecycle| lifecycle.expires > now);
+    }
+
+    fn get_lifecycle(&self, message: GetLifecycle) -> Option<Vec<LifecycleRecord>> {
+        self.lifecycles
+            .get(&message.event_id)
+            .filter(|lifecycle| lifecycle.expires > Instant::now())
+            .map(|lifecycle| lifecycle.records.clone())
+    }
+
     fn handle_message(&mut self, message: TestStore) {
         match message {
             TestStore::Capture(message) => self.capture(*message),
             TestStore::Get(message, sender) => sender.send(self.get(message)),
+            TestStore::RecordLifecycle(message) => self.record_lifecycle(message),
+            TestStore::GetLifecycle(message, sender) => sender.send(self.get_lifecycle(message)),
         }
     }
 }
@@ -131,9 +279,59 @@ impl relay_system::Service for TestStoreService {
 
     fn spawn_handler(mut self, mut rx: relay_system::Receiver<Self::Interface>) {
         tokio::spawn(async move {
-            while let Some(message) = rx.recv().await {
-                self.handle_message(message);
+            let mut ticker = tokio::time::interval(LIFECYCLE_PRUNE_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    biased;
+
+                    Some(message) = rx.recv() => self.handle_message(message),
+                    _ = ticker.tick() => self.prune_lifecycles(Instant::now()),
+                    else => break,
+                }
             }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(service: &mut TestStoreService, event_id: EventId, ttl: Duration, count: usize) {
+        service.record_lifecycle(RecordLifecycle {
+            event_id,
+            ttl,
+            records: (0..count)
+                .map(|_| LifecycleRecord::now(LifecycleStage::Received))
+                .collect(),
+        });
+    }
+
+    #[test]
+    fn test_lifecycle_records_limit() {
+        let mut service = TestStoreService::new(Arc::new(Config::default()));
+        let event_id = EventId::new();
+        let ttl = Duration::from_secs(60);
+
+        record(&mut service, event_id, ttl, MAX_LIFECYCLE_RECORDS - 1);
+        record(&mut service, event_id, ttl, 2);
+
+        let records = service.get_lifecycle(GetLifecycle { event_id }).unwrap();
+        assert_eq!(records.len(), MAX_LIFECYCLE_RECORDS);
+    }
+
+    #[test]
+    fn test_prune_lifecycles() {
+        let mut service = TestStoreService::new(Arc::new(Config::default()));
+        let expired = EventId::new();
+        let active = EventId::new();
+
+        record(&mut service, expired, Duration::ZERO, 1);
+        record(&mut service, active, Duration::from_secs(60), 1);
+        service.prune_lifecycles(Instant::now());
+
+        assert!(!service.lifecycles.contains_key(&expired));
+        assert!(service.lifecycles.contains_key(&active));
+    }
+}
@@ -12,11 +12,12 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use itertools::Itertools;
-use relay_auth::{RegisterChallenge, RegisterRequest, RegisterResponse, Registration};
-use relay_config::{Config, Credentials, RelayMode};
+use relay_auth::{
+    RegisterChallenge, RegisterRequest, RegisterResponse, Registration, SignatureHeader,
+};
+use relay_config::{Config, Credentials, RelayMode, UpstreamDescriptor, UpstreamTarget};
 use relay_quotas::{
-    DataCategories, QuotaScope, RateLimit, RateLimitScope, RateLimits, ReasonCode, RetryAfter,
-    Scoping,
+    DataCategories, RateLimit, RateLimitScope, RateLimits, ReasonCode, RetryAfter, Scoping,
 };
 use relay_system::{
     AsyncResponse, FromMessage, Interface, MessageResponse, NoResponse, Sender, Service,
@@ -85,7 +86,7 @@ impl UpstreamRateLimits {
         if !rate_limits.is_limited() {
             rate_limits.add(RateLimit {
                 categories: DataCategories::new(),
-                scope: RateLimitScope::for_quota(scoping, QuotaScope::Key),
+                scope: RateLimitScope::Key(scoping.project_key),
                 reason_code: Some(ReasonCode::new("generic")),
                 retry_after: self.retry_after,
             });
@@ -121,6 +122,28 @@ pub enum UpstreamRequestError {
     AuthDenied,
 }
 
+/// An error loading the client identity for mutual TLS with the upstream.
+#[derive(Debug, thiserror::Error)]
+pub enum ClientIdentityError {
+    /// The identity file could not be read.
+    #[error("could not read client identity")]
+    Io(#[from] std::io::Error),
+    /// The identity is not a valid PKCS12 archive.
+    #[error("invalid client identity")]
+    Invalid(#[from] reqwest::Error),
+}
+
+/// Loads the identity that is presented as client certificate to the upstream, if configured.
+fn load_client_identity(config: &Config) -> Result<Option<reqwest::Identity>, ClientIdentityError> {
+    let Some(path) = config.http_client_identity_path() else {
+        return Ok(None);
+    };
+
+    let der = std::fs::read(path)?;
+    let password = config.http_client_identity_password().unwrap_or_default();
+    Ok(Some(reqwest::Identity::from_pkcs12_der(&der, password)?))
+}
+
 impl UpstreamRequestError {
     /// Returns the status code of the HTTP request sent to the upstream.
     ///
@@ -383,6 +406,64 @@ pub trait UpstreamQuery: Serialize + Send + Sync + fmt::Debug {
 /// Transmitting end of the return channel for [`UpstreamQuery`].
 type QuerySender<T> = Sender<Result<<T as UpstreamQuery>::Response, UpstreamRequestError>>;
 
+/// Serialized body of an [`UpstreamQuery`] along with its signatures.
+#[derive(Debug)]
+struct SignedQuery {
+    body: Vec<u8>,
+    signature: String,
+    /// Signature with the next key while the credentials are being rotated.
+    next_signature: Option<String>,
+}
+
+impl SignedQuery {
+    /// Serializes and signs the query.
+    ///
+    /// During a key rotation, the query is signed with both keys. The signature of the current key
+    /// additionally announces the next public key in its header.
+    fn new<T: Serialize>(credentials: &Credentials, query: &T) -> Self {
+        let next_keys = credentials
+            .next_secret_key
+            .as_ref()
+            .zip(credentials.next_public_key.as_ref());
+
+        let Some((next_secret_key, next_public_key)) = next_keys else {
+            let (body, signature) = credentials.secret_key.pack(query);
+            return Self {
+                body,
+                signature,
+                next_signature: None,
+            };
+        };
+
+        let header = SignatureHeader {
+            next_key: Some(next_public_key.clone()),
+            ..Default::default()
+        };
+        let (body, signature) = credentials.secret_key.pack_with_header(query, &header);
+        let next_signature = next_secret_key.sign(&body);
+
+        Self {
+            body,
+            signature,
+            next_signature: Some(next_signature),
+        }
+    }
+}
+
+/// Signs data that authenticates a request other than an [`UpstreamQuery`].
+///
+/// Returns the signature of the current key and, while the credentials are being rotated, the
+/// signature of the next key. Signatures carry a timestamp, so that they expire.
+pub fn sign_request(credentials: &Credentials, data: &[u8]) -> (String, Option<String>) {
+    let signature = credentials.secret_key.sign(data);
+    let next_signature = credentials
+        .next_secret_key
+        .as_ref()
+        .map(|secret_key| secret_key.sign(data));
+
+    (signature, next_signature)
+}
+
 /// Memoized implementation of [`UpstreamRequest`] for an [`UpstreamQuery`].
 ///
 /// This can be used to send queries as requests to the upstream. The request wraps an internal
@@ -390,7 +471,7 @@ type QuerySender<T> = Sender<Result<<T as UpstreamQuery>::Response, UpstreamRequ
 #[derive(Debug)]
 struct UpstreamQueryRequest<T: UpstreamQuery> {
     query: T,
-    compiled: Option<(Vec<u8>, String)>,
+    compiled: Option<SignedQuery>,
     max_response_size: usize,
     sender: QuerySender<T>,
 }
@@ -445,22 +526,26 @@ where
     fn build(&mut self, config: &Config, builder: RequestBuilder) -> Result<Request, HttpError> {
         // Memoize the serialized body and signature for retries.
         let credentials = config.credentials().ok_or(HttpError::NoCredentials)?;
-        let (body, signature) = self
+        let signed = self
             .compiled
-            .get_or_insert_with(|| credentials.secret_key.pack(&self.query));
+            .get_or_insert_with(|| SignedQuery::new(credentials, &self.query));
 
         // This config attribute is needed during `respond`, which does not have access to the
         // config. For this reason, we need to store it on the request struct.
         self.max_response_size = config.max_api_payload_size();
 
         relay_statsd::metric!(
-            histogram(RelayHistograms::UpstreamQueryBodySize) = body.len() as u64
+            histogram(RelayHistograms::UpstreamQueryBodySize) = signed.body.len() as u64
         );
 
         builder
-            .header("X-Sentry-Relay-Signature", signature.as_bytes())
+            .header("X-Sentry-Relay-Signature", signed.signature.as_bytes())
+            .header_opt(
+                "X-Sentry-Relay-Next-Signature",
+                signed.next_signature.as_ref().map(String::as_bytes),
+            )
             .header(header::CONTENT_TYPE, b"application/json")
-            .body(&body)
+            .body(&signed.body)
     }
 
     fn respond(
@@ -701,23 +786,44 @@ impl UpstreamQuery for RegisterResponse {
 #[derive(Debug, Clone)]
 struct SharedClient {
     config: Arc<Config>,
+    upstream: UpstreamDescriptor<'static>,
     reqwest: reqwest::Client,
 }
 
 impl SharedClient {
-    /// Creates a new `SharedClient` instance.
-    pub fn build(config: Arc<Config>) -> Self {
-        let reqwest = reqwest::ClientBuilder::new()
+    /// Creates a new `SharedClient` instance for the preferred upstream.
+    ///
+    /// If an `identity` is given, it is presented as client certificate to the upstream.
+    pub fn build(config: Arc<Config>, identity: Option<reqwest::Identity>) -> Self {
+        let mut builder = reqwest::ClientBuilder::new()
             .connect_timeout(config.http_connection_timeout())
             .timeout(config.http_timeout())
             // In the forward endpoint, this means that content negotiation is done twice, and the
             // response body is first decompressed by the client, then re-compressed by the server.
             .gzip(true)
-            .trust_dns(true)
-            .build()
-            .unwrap();
+            .trust_dns(true);
+
+        if let Some(identity) = identity {
+            builder = builder.identity(identity);
+        }
+
+        let reqwest = builder.build().unwrap();
+        let upstream = config.upstream_descriptor().clone().into_owned();
+
+        Self {
+            config,
+            upstream,
+            reqwest,
+        }
+    }
 
-        Self { config, reqwest }
+    /// Returns a client for the given upstream that shares the connection pool with this client.
+    pub fn for_upstream(&self, upstream: UpstreamDescriptor<'static>) -> Self {
+        Self {
+            config: self.config.clone(),
+            upstream,
+            reqwest: self.reqwest.clone(),
+        }
     }
 
     /// Builds the request in a non-blocking fashion.
@@ -730,15 +836,12 @@ impl SharedClient {
         request: &mut dyn UpstreamRequest,
     ) -> Result<reqwest::Request, UpstreamRequestError> {
         tokio::task::block_in_place(|| {
-            let url = self
-                .config
-                .upstream_descriptor()
-                .get_url(request.path().as_ref());
+            let url = self.upstream.get_url(request.path().as_ref());
 
             let host_header = self
                 .config
                 .http_host_header()
-                .unwrap_or_else(|| self.config.upstream_descriptor().host());
+                .unwrap_or_else(|| self.upstream.host());
 
             let mut builder = RequestBuilder::reqwest(self.reqwest.request(request.method(), url))
                 .header("Host", host_header.as_bytes());
@@ -1007,19 +1110,20 @@ enum Action {
     ///
     /// The entry is placed on the front of the [`UpstreamQueue`].
     Retry(Entry),
-    /// Notifies completion of a request with a given outcome.
+    /// Notifies completion of a request to the upstream at the given index with a given outcome.
     ///
     /// Dropped request that need retries will additionally invoke the [`Retry`](Self::Retry)
     /// action.
-    Complete(RequestOutcome),
-    /// Previously lost connection has been regained.
+    Complete(usize, RequestOutcome),
+    /// Previously lost connection to the upstream at the given index has been regained.
     ///
-    /// This message is delivered to the [`ConnectionMonitor`] instance.
-    Connected,
-    /// The auth monitor indicates a change in the authentication state.
+    /// This message is delivered to the [`ConnectionMonitor`] instance of the upstream.
+    Connected(usize),
+    /// The auth monitor of the upstream at the given index indicates a change in the
+    /// authentication state.
     ///
     /// The new auth state is mirrored in an internal field for immediate access.
-    UpdateAuth(AuthState),
+    UpdateAuth(usize, AuthState),
 }
 
 type ActionTx = mpsc::UnboundedSender<Action>;
@@ -1036,6 +1140,7 @@ type ActionTx = mpsc::UnboundedSender<Action>;
 struct AuthMonitor {
     config: Arc<Config>,
     client: SharedClient,
+    index: usize,
     state: AuthState,
     tx: ActionTx,
 }
@@ -1059,7 +1164,7 @@ impl AuthMonitor {
     fn send_state(&mut self, state: AuthState) -> Result<(), UpstreamRequestError> {
         self.state = state;
         self.tx
-            .send(Action::UpdateAuth(state))
+            .send(Action::UpdateAuth(self.index, state))
             .map_err(|_| UpstreamRequestError::ChannelClosed)
     }
 
@@ -1076,7 +1181,7 @@ impl AuthMonitor {
         credentials: &Credentials,
     ) -> Result<(), UpstreamRequestError> {
         relay_log::info!(
-            descriptor = %self.config.upstream_descriptor(),
+            descriptor = %self.client.upstream,
             "registering with upstream"
         );
 
@@ -1199,14 +1304,16 @@ enum ConnectionState {
 struct ConnectionMonitor {
     state: ConnectionState,
     client: SharedClient,
+    index: usize,
 }
 
 impl ConnectionMonitor {
-    /// Creates a new `ConnectionMonitor` in connected state.
-    pub fn new(client: SharedClient) -> Self {
+    /// Creates a new `ConnectionMonitor` in connected state for the upstream at the given index.
+    pub fn new(client: SharedClient, index: usize) -> Self {
         Self {
             state: ConnectionState::Connected,
             client,
+            index,
         }
     }
 
@@ -1236,12 +1343,15 @@ impl ConnectionMonitor {
     }
 
     /// Performs connection attempts with exponential backoff until successful.
-    async fn connect(client: SharedClient, tx: ActionTx) {
+    async fn connect(client: SharedClient, index: usize, tx: ActionTx) {
         let mut backoff = RetryBackoff::new(client.config.http_max_retry_interval());
 
         loop {
             let next_backoff = backoff.next_backoff();
-            relay_log::warn!("network outage, scheduling another check in {next_backoff:?}");
+            relay_log::warn!(
+                descriptor = %client.upstream,
+                "network outage, scheduling another check in {next_backoff:?}"
+            );
 
             tokio::time::sleep(next_backoff).await;
             match client.send(&mut GetHealthCheck).await {
@@ -1251,7 +1361,7 @@ impl ConnectionMonitor {
             }
         }
 
-        tx.send(Action::Connected).ok();
+        tx.send(Action::Connected(index)).ok();
     }
 
     /// Notifies the monitor of a request that resulted in a network error.
@@ -1275,7 +1385,7 @@ impl ConnectionMonitor {
         // Only take action if we exceeded the grace period.
         if first_error + self.client.config.http_outage_grace_period() <= now {
             let return_tx = return_tx.clone();
-            let task = tokio::spawn(Self::connect(self.client.clone(), return_tx));
+            let task = tokio::spawn(Self::connect(self.client.clone(), self.index, return_tx));
             self.state = ConnectionState::Reconnecting(task);
         }
     }
@@ -1292,47 +1402,142 @@ impl ConnectionMonitor {
     }
 }
 
+/// Connection and authentication state of one of the upstreams.
+#[derive(Debug)]
+struct Upstream {
+    client: SharedClient,
+    priority: u32,
+    weight: u32,
+    /// Running weight for smooth weighted round-robin among upstreams of the same priority.
+    current_weight: i64,
+    auth_state: AuthState,
+    conn: ConnectionMonitor,
+}
+
+impl Upstream {
+    /// Creates the state of the upstream at the given index.
+    fn new(config: &Config, client: SharedClient, index: usize, target: &UpstreamTarget) -> Self {
+        Self {
+            client: client.clone(),
+            priority: target.priority,
+            weight: target.weight,
+            current_weight: 0,
+            auth_state: AuthState::init(config),
+            conn: ConnectionMonitor::new(client, index),
+        }
+    }
+
+    /// Returns `true` if requests can be sent to this upstream.
+    fn is_available(&mut self) -> bool {
+        self.auth_state.is_authenticated() && self.conn.is_stable()
+    }
+}
+
+/// Selects the upstream for the next request.
+///
+/// Among the available upstreams with the lowest priority value, requests are distributed with
+/// smooth weighted round-robin. This fails over to upstreams with higher priority values while
+/// preferred upstreams are unavailable, and fails back once they recover.
+fn select_upstream(upstreams: &mut [Upstream]) -> Option<usize> {
+    let available: Vec<_> = (0..upstreams.len())
+        .filter(|&index| upstreams[index].is_available())
+        .collect();
+
+    let priority = available
+        .iter()
+        .map(|&index| upstreams[index].priority)
+        .min()?;
+
+    let candidates: Vec<_> = available
+        .into_iter()
+        .filter(|&index| upstreams[index].priority == priority)
+        .collect();
+
+    let mut total_weight = 0;
+    let mut selected = None;
+    for index in candidates {
+        let upstream = &mut upstreams[index];
+        upstream.current_weight += i64::from(upstream.weight);
+        total_weight += i64::from(upstream.weight);
+
+        let current_weight = upstream.current_weight;
+        if selected.map_or(true, |(_, max)| current_weight > max) {
+            selected = Some((index, current_weight));
+        }
+    }
+
+    let (index, _) = selected?;
+    upstreams[index].current_weight -= total_weight;
+    Some(index)
+}
+
 /// Main broker of the [`UpstreamRelayService`].
 ///
 /// This handles incoming public messages, internal actions, and maintains the upstream queue.
 #[derive(Debug)]
 struct UpstreamBroker {
-    client: SharedClient,
+    upstreams: Vec<Upstream>,
+    /// The upstream that received the last request, used to log failovers.
+    active: Option<usize>,
     queue: UpstreamQueue,
-    auth_state: AuthState,
-    conn: ConnectionMonitor,
     permits: usize,
     action_tx: ActionTx,
 }
 
 impl UpstreamBroker {
-    /// Returns the next entry from the queue if the upstream is in a healthy state.
+    /// Returns `true` if any upstream is authenticated.
+    fn is_authenticated(&self) -> bool {
+        self.upstreams
+            .iter()
+            .any(|u| u.auth_state.is_authenticated())
+    }
+
+    /// Returns `true` if all upstreams are in outage state.
+    fn is_outage(&mut self) -> bool {
+        self.upstreams.iter_mut().all(|u| u.conn.is_outage())
+    }
+
+    /// Returns the next entry from the queue and the upstream to send it to.
     ///
     /// This returns `None` in any of the following conditions:
     ///  - Maximum request concurrency has been reached. A slot will be reclaimed through
     ///    [`Action::Complete`].
-    ///  - The connection is in outage state and all outgoing requests are suspended. Outage state
-    ///    will be reset through [`Action::Connected`].
-    ///  - Relay is not authenticated, including failed renewals. Auth state will be updated through
-    ///    [`Action::UpdateAuth`].
+    ///  - No upstream is available. Either connections are in outage state, which will be reset
+    ///    through [`Action::Connected`], or Relay is not authenticated, including failed renewals.
+    ///    Auth state will be updated through [`Action::UpdateAuth`].
     ///  - The request queue is empty. New requests will be added through [`SendRequest`] or
     ///    [`SendQuery`] in the main message loop.
-    async fn next_request(&mut self) -> Option<Entry> {
-        if self.permits == 0 || self.conn.is_outage() || !self.auth_state.is_authenticated() {
+    async fn next_request(&mut self) -> Option<(usize, Entry)> {
+        if self.permits == 0 || self.queue.len() == 0 {
             return None;
         }
 
+        let index = select_upstream(&mut self.upstreams)?;
         let entry = self.queue.dequeue()?;
+
+        if self.active != Some(index) {
+            relay_log::info!(
+                descriptor = %self.upstreams[index].client.upstream,
+                "sending requests to upstream"
+            );
+            self.active = Some(index);
+        }
+
         self.permits -= 1;
-        Some(entry)
+        Some((index, entry))
     }
 
     /// Attempts to place a new request into the queue.
     ///
-    /// If authentication is permanently denied, the request will be failed immediately. In all
-    /// other cases, the request is enqueued and will wait for submission.
+    /// If authentication is permanently denied by all upstreams, the request will be failed
+    /// immediately. In all other cases, the request is enqueued and will wait for submission.
     async fn enqueue(&mut self, request: Box<dyn UpstreamRequest>) {
-        if let AuthState::Denied = self.auth_state {
+        let denied = self
+            .upstreams
+            .iter()
+            .all(|u| matches!(u.auth_state, AuthState::Denied));
+
+        if denied {
             // This respond is near-instant because it should just send the error into the request's
             // response channel. We do not expect that this blocks the broker.
             request.respond(Err(UpstreamRequestError::AuthDenied)).await;
@@ -1344,10 +1549,8 @@ impl UpstreamBroker {
     /// Handler of the main message loop.
     async fn handle_message(&mut self, message: UpstreamRelay) {
         match message {
-            UpstreamRelay::IsAuthenticated(_, sender) => {
-                sender.send(self.auth_state.is_authenticated())
-            }
-            UpstreamRelay::IsNetworkOutage(_, sender) => sender.send(self.conn.is_outage()),
+            UpstreamRelay::IsAuthenticated(_, sender) => sender.send(self.is_authenticated()),
+            UpstreamRelay::IsNetworkOutage(_, sender) => sender.send(self.is_outage()),
             UpstreamRelay::SendRequest(request) => self.enqueue(request).await,
         }
     }
@@ -1356,8 +1559,8 @@ impl UpstreamBroker {
     ///
     /// The request will run concurrently with other spawned requests and notify the action channel
     /// on completion.
-    fn execute(&self, mut entry: Entry) {
-        let client = self.client.clone();
+    fn execute(&self, index: usize, mut entry: Entry) {
+        let client = self.upstreams[index].client.clone();
         let action_tx = self.action_tx.clone();
 
         tokio::spawn(async move {
@@ -1381,17 +1584,18 @@ impl UpstreamBroker {
             // Send an action back to the action channel of the broker, which will invoke
             // `handle_action`. This is to let the broker know in a synchronized fashion that the
             // request has finished and may need to be retried (above).
-            action_tx.send(Action::Complete(status)).ok();
+            action_tx.send(Action::Complete(index, status)).ok();
         });
     }
 
     /// Marks completion of a running request and reclaims its slot.
-    fn complete(&mut self, status: RequestOutcome) {
+    fn complete(&mut self, index: usize, status: RequestOutcome) {
         self.permits += 1;
 
+        let conn = &mut self.upstreams[index].conn;
         match status {
-            RequestOutcome::Dropped => self.conn.notify_error(&self.action_tx),
-            RequestOutcome::Received => self.conn.reset_error(),
+            RequestOutcome::Dropped => conn.notify_error(&self.action_tx),
+            RequestOutcome::Received => conn.reset_error(),
         }
     }
 
@@ -1399,9 +1603,9 @@ impl UpstreamBroker {
     fn handle_action(&mut self, action: Action) {
         match action {
             Action::Retry(request) => self.queue.enqueue_immediate(request),
-            Action::Complete(status) => self.complete(status),
-            Action::Connected => self.conn.reset_error(),
-            Action::UpdateAuth(state) => self.auth_state = state,
+            Action::Complete(index, status) => self.complete(index, status),
+            Action::Connected(index) => self.upstreams[index].conn.reset_error(),
+            Action::UpdateAuth(index, state) => self.upstreams[index].auth_state = state,
         }
     }
 }
@@ -1410,13 +1614,21 @@ impl UpstreamBroker {
 #[derive(Debug)]
 pub struct UpstreamRelayService {
     config: Arc<Config>,
+    client_identity: Option<reqwest::Identity>,
 }
 
 impl UpstreamRelayService {
     /// Creates a new `UpstreamRelay` instance.
-    pub fn new(config: Arc<Config>) -> Self {
+    ///
+    /// Fails if a client identity is configured but cannot be loaded.
+    pub fn new(config: Arc<Config>) -> Result<Self, ClientIdentityError> {
+        let client_identity = load_client_identity(&config)?;
+
         // Broker and other actual components are implemented in the Service's `spawn_handler`.
-        Self { config }
+        Ok(Self {
+            config,
+            client_identity,
+        })
     }
 }
 
@@ -1424,31 +1636,41 @@ impl Service for UpstreamRelayService {
     type Interface = UpstreamRelay;
 
     fn spawn_handler(self, mut rx: relay_system::Receiver<Self::Interface>) {
-        let Self { config } = self;
+        let Self {
+            config,
+            client_identity,
+        } = self;
 
-        let client = SharedClient::build(config.clone());
+        let shared_client = SharedClient::build(config.clone(), client_identity);
 
-        // Channel for serialized communication from the auth monitor, connection monitor, and
+        // Channel for serialized communication from the auth monitors, connection monitors, and
         // concurrent requests back to the broker.
         let (action_tx, mut action_rx) = mpsc::unbounded_channel();
 
-        // Spawn a recurring background check for authentication. It terminates automatically if
-        // authentication is not required or rejected.
-        let auth = AuthMonitor {
-            config: config.clone(),
-            client: client.clone(),
-            state: AuthState::Unknown,
-            tx: action_tx.clone(),
-        };
-        tokio::spawn(auth.run());
+        let mut upstreams = Vec::new();
+        for (index, target) in config.upstream_targets().iter().enumerate() {
+            let client = shared_client.for_upstream(target.url.clone());
+
+            // Spawn a recurring background check for authentication with every upstream. It
+            // terminates automatically if authentication is not required or rejected.
+            let auth = AuthMonitor {
+                config: config.clone(),
+                client: client.clone(),
+                index,
+                state: AuthState::Unknown,
+                tx: action_tx.clone(),
+            };
+            tokio::spawn(auth.run());
+
+            upstreams.push(Upstream::new(&config, client, index, target));
+        }
 
         // Main broker that serializes public and internal messages, as well as maintains connection
         // and authentication state.
         let mut broker = UpstreamBroker {
-            client: client.clone(),
+            upstreams,
+            active: None,
             queue: UpstreamQueue::new(),
-            auth_state: AuthState::init(&config),
-            conn: ConnectionMonitor::new(client),
             permits: config.max_concurrent_requests(),
             action_tx,
         };
@@ -1459,7 +1681,7 @@ impl Service for UpstreamRelayService {
                     biased;
 
                     Some(action) = action_rx.recv() => broker.handle_action(action),
-                    Some(request) = broker.next_request() => broker.execute(request),
+                    Some((index, request)) = broker.next_request() => broker.execute(index, request),
                     Some(message) = rx.recv() => broker.handle_message(message).await,
 
                     else => break,
@@ -1468,3 +1690,73 @@ impl Service for UpstreamRelayService {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstreams(targets: &[(&str, u32, u32)]) -> Vec<Upstream> {
+        let config = Arc::new(Config::default());
+        let client = SharedClient::build(config.clone(), None);
+
+        targets
+            .iter()
+            .enumerate()
+            .map(|(index, &(url, priority, weight))| {
+                let target = UpstreamTarget {
+                    url: url.parse().unwrap(),
+                    priority,
+                    weight,
+                };
+                let client = client.for_upstream(target.url.clone());
+                let mut upstream = Upstream::new(&config, client, index, &target);
+                upstream.auth_state = AuthState::Registered;
+                upstream
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_select_upstream_weights() {
+        let mut upstreams = upstreams(&[
+            ("https://a.example.com/", 0, 1),
+            ("https://b.example.com/", 0, 3),
+            ("https://c.example.com/", 1, 1),
+        ]);
+
+        let selected: Vec<_> = (0..4)
+            .map(|_| select_upstream(&mut upstreams).unwrap())
+            .collect();
+
+        assert_eq!(selected.iter().filter(|&&i| i == 0).count(), 1);
+        assert_eq!(selected.iter().filter(|&&i| i == 1).count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_select_upstream_failover() {
+        let mut upstreams = upstreams(&[
+            ("https://a.example.com/", 0, 1),
+            ("https://b.example.com/", 1, 1),
+        ]);
+
+        assert_eq!(select_upstream(&mut upstreams), Some(0));
+
+        // Fail over while the preferred upstream is not authenticated.
+        upstreams[0].auth_state = AuthState::Registering;
+        assert_eq!(select_upstream(&mut upstreams), Some(1));
+
+        // Fail over while the preferred upstream is in outage state.
+        upstreams[0].auth_state = AuthState::Registered;
+        let task = tokio::spawn(std::future::pending());
+        upstreams[0].conn.state = ConnectionState::Reconnecting(task);
+        assert_eq!(select_upstream(&mut upstreams), Some(1));
+
+        // Fail back once the preferred upstream has recovered.
+        upstreams[0].conn.reset_error();
+        assert_eq!(select_upstream(&mut upstreams), Some(0));
+
+        upstreams[0].auth_state = AuthState::Denied;
+        upstreams[1].auth_state = AuthState::Denied;
+        assert_eq!(select_upstream(&mut upstreams), None);
+    }
+}
@@ -2,6 +2,8 @@
 
 use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
+use relay_auth::IngestionClaims;
+use relay_config::{Config, Priority};
 use relay_general::protocol::{EventId, EventType};
 use relay_quotas::RateLimits;
 use relay_statsd::metric;
@@ -9,7 +11,7 @@ use serde::Deserialize;
 
 use crate::actors::outcome::{DiscardReason, Outcome};
 use crate::actors::processor::ProcessMetrics;
-use crate::actors::project_cache::{CheckEnvelope, ValidateEnvelope};
+use crate::actors::project_cache::{CheckEnvelope, SpoolEvict, ValidateEnvelope};
 use crate::envelope::{AttachmentType, Envelope, EnvelopeError, Item, ItemType, Items};
 use crate::service::ServiceState;
 use crate::statsd::RelayCounters;
@@ -313,6 +315,62 @@ fn queue_envelope(
     Ok(())
 }
 
+/// Verifies the ingestion token of an envelope and the restrictions in its claims.
+///
+/// Tokens are only verified if this Relay is configured with a key to verify them, otherwise
+/// verification is left to the upstream. This runs once when the envelope is received, so that
+/// envelopes accepted with a valid token are not rejected if the token expires while buffered.
+fn check_ingestion_token(config: &Config, envelope: &Envelope) -> Result<(), DiscardReason> {
+    let public_key = match config.ingestion_token_key() {
+        Some(public_key) => public_key,
+        None => return Ok(()),
+    };
+
+    let meta = envelope.meta();
+    let token = match meta.ingestion_token() {
+        Some(token) => token,
+        None => return Ok(()),
+    };
+
+    let claims =
+        IngestionClaims::unpack(token, public_key).map_err(|_| DiscardReason::IngestionToken)?;
+
+    if claims.project_key != meta.public_key() {
+        return Err(DiscardReason::IngestionToken);
+    }
+
+    if let Some(max_size) = claims.max_size {
+        // Compressed requests are decoded before parsing, so this is the size of the actual data
+        // rather than the size on the wire.
+        let size: usize = envelope.items().map(Item::len).sum();
+        if size as u64 > max_size {
+            return Err(DiscardReason::IngestionToken);
+        }
+    }
+
+    let allowed = envelope
+        .items()
+        .all(|item| claims.allows_item_type(item.ty().as_str()));
+    if !allowed {
+        return Err(DiscardReason::IngestionToken);
+    }
+
+    Ok(())
+}
+
+/// Removes rate limits from a response that the client of the request cannot apply.
+///
+/// Rate limits scoped to a release, environment or user only apply to a segment of the client's
+/// data, but SDKs ignore the scope and would stop sending all data. They are only reported to
+/// authenticated downstream Relays.
+fn client_rate_limits(from_relay: bool, mut rate_limits: RateLimits) -> RateLimits {
+    if !from_relay {
+        rate_limits.retain(|limit| limit.scope.value().is_none());
+    }
+
+    rate_limits
+}
+
 /// Handles an envelope store request.
 ///
 /// Sentry envelopes may come either directly from an HTTP request (the envelope endpoint calls this
@@ -326,13 +384,29 @@ pub async fn handle_envelope(
     envelope: Box<Envelope>,
 ) -> Result<Option<EventId>, BadStoreRequest> {
     let buffer_guard = state.buffer_guard();
-    let mut managed_envelope = buffer_guard
-        .enter(
+    let from_relay = envelope.meta().is_from_relay();
+
+    // Under backpressure, envelopes with lower priority are rejected first. Envelopes with higher
+    // priority are validated without occupying capacity, and may take the place of a queued
+    // envelope with lower priority afterwards. Low priority envelopes cannot evict any envelope.
+    let priority = envelope.priority(state.config());
+    let mut managed_envelope = if buffer_guard.has_capacity(priority) {
+        buffer_guard
+            .enter(
+                envelope,
+                state.outcome_aggregator().clone(),
+                state.test_store().clone(),
+            )
+            .map_err(BadStoreRequest::QueueFailed)?
+    } else if priority > Priority::Low {
+        ManagedEnvelope::unbound(
             envelope,
             state.outcome_aggregator().clone(),
             state.test_store().clone(),
         )
-        .map_err(BadStoreRequest::QueueFailed)?;
+    } else {
+        return Err(BadStoreRequest::QueueFailed(BufferError));
+    };
 
     // If configured, remove unknown items at the very beginning. If the envelope is
     // empty, we fail the request with a special control flow error to skip checks and
@@ -345,6 +419,11 @@ pub async fn handle_envelope(
         return Ok(event_id);
     }
 
+    if let Err(reason) = check_ingestion_token(state.config(), managed_envelope.envelope()) {
+        managed_envelope.reject(Outcome::Invalid(reason));
+        return Err(BadStoreRequest::EventRejected(reason));
+    }
+
     let checked = state
         .project_cache()
         .send(CheckEnvelope::new(managed_envelope))
@@ -352,8 +431,13 @@ pub async fn handle_envelope(
         .map_err(|_| BadStoreRequest::ScheduleFailed)?
         .map_err(BadStoreRequest::EventRejected)?;
 
+    let rate_limits = client_rate_limits(from_relay, checked.rate_limits);
     let Some(mut managed_envelope) = checked.envelope else {
-        return Err(BadStoreRequest::RateLimited(checked.rate_limits));
+        return if rate_limits.is_limited() {
+            Err(BadStoreRequest::RateLimited(rate_limits))
+        } else {
+            Ok(event_id)
+        };
     };
 
     if !utils::check_envelope_size_limits(state.config(), managed_envelope.envelope()) {
@@ -361,10 +445,28 @@ pub async fn handle_envelope(
         return Err(BadStoreRequest::Overflow);
     }
 
+    if !managed_envelope.has_slot() {
+        // The permit of the evicted envelope is handed over directly, so that no other request can
+        // take the freed capacity in the meantime.
+        let slot = state
+            .project_cache()
+            .send(SpoolEvict::new(priority))
+            .await
+            .unwrap_or_default();
+
+        match slot {
+            Some(slot) => managed_envelope.set_slot(slot),
+            None => {
+                managed_envelope.reject(Outcome::Invalid(DiscardReason::BufferFull));
+                return Err(BadStoreRequest::QueueFailed(BufferError));
+            }
+        }
+    }
+
     queue_envelope(state, managed_envelope, buffer_guard)?;
 
-    if checked.rate_limits.is_limited() {
-        Err(BadStoreRequest::RateLimited(checked.rate_limits))
+    if rate_limits.is_limited() {
+        Err(BadStoreRequest::RateLimited(rate_limits))
     } else {
         Ok(event_id)
     }
@@ -388,6 +490,11 @@ impl IntoResponse for TextResponse {
 
 #[cfg(test)]
 mod tests {
+    use relay_common::{DataCategory, ProjectId, ProjectKey, UnixTimestamp};
+    use relay_quotas::{RateLimit, RateLimitScope, RetryAfter};
+    use serde_json::json;
+    use smallvec::smallvec;
+
     use super::*;
 
     #[test]
@@ -441,4 +548,169 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_client_rate_limits() {
+        let limit = |scope| RateLimit {
+            categories: smallvec![DataCategory::Error],
+            scope,
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(42),
+        };
+
+        let mut rate_limits = RateLimits::new();
+        rate_limits.add(limit(RateLimitScope::Project(ProjectId::new(42))));
+        rate_limits.add(limit(RateLimitScope::Release("1.0".to_owned())));
+        rate_limits.add(limit(RateLimitScope::User(4711)));
+
+        // Downstream Relays receive all rate limits.
+        let limits = client_rate_limits(true, rate_limits.clone());
+        assert_eq!(limits.iter().count(), 3);
+
+        // Clients only receive rate limits that apply to all of their data.
+        let limits = client_rate_limits(false, rate_limits);
+        let scopes: Vec<_> = limits.iter().map(|limit| limit.scope.name()).collect();
+        assert_eq!(scopes, vec!["project"]);
+    }
+
+    #[test]
+    fn test_check_ingestion_token() {
+        let (secret_key, public_key) = relay_auth::generate_key_pair();
+        let config = Config::from_json_value(json!({
+            "auth": {"ingestion_token_key": public_key.to_string()}
+        }))
+        .unwrap();
+
+        let envelope = |token: Option<String>| {
+            let header = match token {
+                Some(token) => json!({
+                    "dsn": "https://a94ae32be2584e0bbd7a4cbb95971fee:@sentry.io/42",
+                    "ingestion_token": token,
+                }),
+                None => json!({"dsn": "https://a94ae32be2584e0bbd7a4cbb95971fee:@sentry.io/42"}),
+            };
+            let bytes = format!("{header}\n{{\"type\":\"event\"}}\n{{}}\n");
+            Envelope::parse_bytes(bytes.into()).unwrap()
+        };
+
+        let claims = IngestionClaims {
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            item_types: vec![],
+            max_size: Some(1024),
+            exp: UnixTimestamp::from_secs(UnixTimestamp::now().as_secs() + 60),
+        };
+
+        let valid = envelope(Some(claims.pack(&secret_key)));
+        assert!(check_ingestion_token(&config, &valid).is_ok());
+
+        // Requirements of the project are checked later, see `ProjectState`.
+        let missing = envelope(None);
+        assert!(check_ingestion_token(&config, &missing).is_ok());
+
+        let restricted = IngestionClaims {
+            item_types: vec!["transaction".to_owned()],
+            ..claims.clone()
+        };
+        let restricted = envelope(Some(restricted.pack(&secret_key)));
+        assert!(check_ingestion_token(&config, &restricted).is_err());
+
+        let small = IngestionClaims {
+            max_size: Some(1),
+            ..claims.clone()
+        };
+        let small = envelope(Some(small.pack(&secret_key)));
+        assert!(check_ingestion_token(&config, &small).is_err());
+
+        let expired = IngestionClaims {
+            exp: UnixTimestamp::from_secs(UnixTimestamp::now().as_secs() - 60),
+            ..claims.clone()
+        };
+        let expired = envelope(Some(expired.pack(&secret_key)));
+        assert!(check_ingestion_token(&config, &expired).is_err());
+
+        let other_key = IngestionClaims {
+            project_key: ProjectKey::parse("e12d836b15bb49d7bbf99e64295d995b").unwrap(),
+            ..claims
+        };
+        let other_key = envelope(Some(other_key.pack(&secret_key)));
+        assert!(check_ingestion_token(&config, &other_key).is_err());
+
+        // Without a key, verification is left to the upstream.
+        assert!(check_ingestion_token(&Config::default(), &other_key).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_ingestion_token_compressed() {
+        use std::io::Write;
+        use std::sync::Arc;
+
+        use axum::body::{Body, Bytes};
+        use axum::http::Request;
+        use axum::routing::post;
+        use axum::Router;
+        use flate2::write::GzEncoder;
+        use tower::{ServiceBuilder, ServiceExt};
+
+        use crate::middlewares::{
+            decompression_error, HandleErrorLayer, RequestDecompressionLayer,
+        };
+
+        let (secret_key, public_key) = relay_auth::generate_key_pair();
+        let config = Config::from_json_value(json!({
+            "auth": {"ingestion_token_key": public_key.to_string()}
+        }))
+        .unwrap();
+        let config = Arc::new(config);
+
+        let router = Router::new()
+            .route(
+                "/",
+                post(move |body: Bytes| async move {
+                    let envelope = Envelope::parse_bytes(body).unwrap();
+                    match check_ingestion_token(&config, &envelope) {
+                        Ok(()) => StatusCode::OK,
+                        Err(_) => StatusCode::FORBIDDEN,
+                    }
+                }),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(decompression_error))
+                    .layer(RequestDecompressionLayer::new()),
+            );
+
+        let send = |max_size: u64| {
+            let claims = IngestionClaims {
+                project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+                item_types: vec![],
+                max_size: Some(max_size),
+                exp: UnixTimestamp::from_secs(UnixTimestamp::now().as_secs() + 60),
+            };
+            let header = json!({
+                "dsn": "https://a94ae32be2584e0bbd7a4cbb95971fee:@sentry.io/42",
+                "ingestion_token": claims.pack(&secret_key),
+            });
+            let payload = json!({"message": "a".repeat(2000)});
+            let bytes = format!("{header}\n{{\"type\":\"event\"}}\n{payload}\n");
+
+            let mut encoder = GzEncoder::new(Vec::new(), Default::default());
+            encoder.write_all(bytes.as_bytes()).unwrap();
+            let body = encoder.finish().unwrap();
+            assert!(body.len() < 1024);
+
+            let request = Request::post("/")
+                .header(header::CONTENT_ENCODING, "gzip")
+                .header(header::CONTENT_LENGTH, body.len())
+                .body(Body::from(body))
+                .unwrap();
+            router.clone().oneshot(request)
+        };
+
+        // The compressed request is smaller than the limit, but the envelope is not.
+        let response = send(1024).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = send(4096).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
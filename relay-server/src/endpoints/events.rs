@@ -1,13 +1,15 @@
-//! Returns captured events.
+//! Returns captured events and lifecycle logs of events.
 
 use axum::extract::Path;
 use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
 use relay_general::protocol::EventId;
+use serde::Serialize;
 
-use crate::actors::test_store::GetCapturedEnvelope;
+use crate::actors::test_store::{GetCapturedEnvelope, GetLifecycle, LifecycleRecord};
 use crate::endpoints::common::ServiceUnavailable;
 use crate::envelope;
+use crate::extractors::SignedJson;
 use crate::service::ServiceState;
 
 pub async fn handle(
@@ -28,3 +30,32 @@ pub async fn handle(
         None => StatusCode::NOT_FOUND.into_response(),
     })
 }
+
+/// Response body of the lifecycle endpoint.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LifecycleResponse {
+    event_id: EventId,
+    records: Vec<LifecycleRecord>,
+}
+
+/// Returns the recorded lifecycle of an event.
+///
+/// Lifecycles are only recorded for projects with `lifecycleDebug` in their project config. This
+/// endpoint can only be queried by internal Relays.
+pub async fn handle_lifecycle(
+    state: ServiceState,
+    Path(event_id): Path<EventId>,
+    body: SignedJson<serde_json::Value>,
+) -> Result<impl IntoResponse, ServiceUnavailable> {
+    if !body.relay.internal {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let records = state.test_store().send(GetLifecycle { event_id }).await?;
+
+    Ok(match records {
+        Some(records) => axum::Json(LifecycleResponse { event_id, records }).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    })
+}
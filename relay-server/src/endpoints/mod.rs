@@ -13,6 +13,7 @@ mod minidump;
 mod outcomes;
 mod project_configs;
 mod public_keys;
+mod rate_limits;
 mod security_report;
 mod statics;
 mod store;
@@ -37,8 +38,12 @@ where
     let internal_routes = Router::new()
         .route("/api/relay/healthcheck/:kind/", get(health_check::handle))
         .route("/api/relay/events/:event_id/", get(events::handle))
+        .route("/api/relay/events/:event_id/lifecycle/", post(events::handle_lifecycle))
+        .route("/api/relay/ratelimits/", post(rate_limits::handle))
+        .route("/api/relay/ratelimits/clear/", post(rate_limits::handle_clear))
         // Fallback route, but with a name, and just on `/api/relay/*`.
-        .route("/api/relay/*not_found", any(statics::not_found));
+        .route("/api/relay/*not_found", any(statics::not_found))
+        .route_layer(DefaultBodyLimit::max(crate::constants::MAX_JSON_SIZE));
 
     // Sentry Web API routes pointing to /api/0/relays/
     let web_routes = Router::new()
@@ -182,3 +182,58 @@ where
         forward::forward(state, req).await
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use relay_common::DataCategory;
+    use relay_quotas::{LocalRateLimiter, Quantity, Scoping};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_limited_config_quotas() {
+        let state: ProjectState = serde_json::from_value(json!({
+            "projectId": 42,
+            "organizationId": 1,
+            "publicKeys": [
+                {"publicKey": "a94ae32be2584e0bbd7a4cbb95971fee", "numericId": 3}
+            ],
+            "config": {
+                "allowedDomains": ["*"],
+                "quotas": [{
+                    "id": "foo",
+                    "scope": "key",
+                    "categories": ["error"],
+                    "limit": 1,
+                    "window": 60,
+                    "reasonCode": "get_lost"
+                }]
+            }
+        }))
+        .unwrap();
+
+        // External Relays receive the limited config and enforce its quotas locally.
+        let response = serde_json::to_string(&ProjectStateWrapper::new(state, false)).unwrap();
+        let state: ProjectState = serde_json::from_str(&response).unwrap();
+
+        let scoping = Scoping {
+            organization_id: state.organization_id.unwrap(),
+            project_id: state.project_id.unwrap(),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: state.get_public_key_config().unwrap().numeric_id,
+        };
+
+        let rate_limiter = LocalRateLimiter::new();
+        let item = scoping.item(DataCategory::Error);
+        let quotas = state.get_quotas();
+
+        assert!(!rate_limiter
+            .is_rate_limited(quotas, item, Quantity::new(1, 0), false)
+            .is_limited());
+
+        let limits = rate_limiter.is_rate_limited(quotas, item, Quantity::new(1, 0), false);
+        let limit = limits.iter().next().unwrap();
+        assert_eq!(limit.reason_code.as_ref().unwrap().as_str(), "get_lost");
+    }
+}
@@ -0,0 +1,173 @@
+//! Introspection of rate limits cached in the project cache.
+
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use relay_common::ProjectKey;
+use relay_quotas::{DataCategories, RateLimit, RateLimitScope, ReasonCode};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "processing")]
+use crate::actors::project_cache::ProjectRateLimits;
+use crate::actors::project_cache::{ClearRateLimits, GetRateLimits};
+use crate::endpoints::common::ServiceUnavailable;
+use crate::extractors::SignedJson;
+use crate::service::ServiceState;
+
+/// Request body of the rate limits endpoint.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRateLimitsRequest {
+    /// Project keys to return rate limits for. If empty, all rate limited projects are returned.
+    #[serde(default)]
+    project_keys: Vec<ProjectKey>,
+}
+
+/// A rate limit as reported by the rate limits endpoint.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RateLimitInfo<'a> {
+    scope: &'static str,
+    scope_id: String,
+    categories: &'a DataCategories,
+    reason_code: Option<&'a ReasonCode>,
+    retry_after: u64,
+}
+
+impl<'a> From<&'a RateLimit> for RateLimitInfo<'a> {
+    fn from(limit: &'a RateLimit) -> Self {
+        let scope_id = match limit.scope {
+            RateLimitScope::Organization(id) => id.to_string(),
+            RateLimitScope::Project(id) => id.to_string(),
+            RateLimitScope::Key(key) => key.to_string(),
+            RateLimitScope::Release(ref release) => release.clone(),
+            RateLimitScope::Environment(ref environment) => environment.clone(),
+            RateLimitScope::User(user) => user.to_string(),
+        };
+
+        Self {
+            scope: limit.scope.name(),
+            scope_id,
+            categories: &limit.categories,
+            reason_code: limit.reason_code.as_ref(),
+            retry_after: limit.retry_after.remaining_seconds(),
+        }
+    }
+}
+
+/// Rate limits of a single project as reported by the rate limits endpoint.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectRateLimitsInfo<'a> {
+    project_key: ProjectKey,
+    rate_limits: Vec<RateLimitInfo<'a>>,
+    /// Current consumption of quotas, only available with processing and Redis.
+    #[cfg(feature = "processing")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quota_consumption: Option<Vec<relay_quotas::QuotaConsumption>>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetRateLimitsResponse<'a> {
+    projects: Vec<ProjectRateLimitsInfo<'a>>,
+}
+
+/// Queries current consumption of the project's quotas from Redis.
+#[cfg(feature = "processing")]
+async fn quota_consumption(
+    state: &ServiceState,
+    project: &ProjectRateLimits,
+) -> Option<Vec<relay_quotas::QuotaConsumption>> {
+    let rate_limiter = state.rate_limiter()?.clone();
+    let scoping = project.scoping?;
+    let project_state = project.state.clone()?;
+
+    let result = tokio::task::spawn_blocking(move || {
+        rate_limiter.consumption(project_state.get_quotas(), &scoping)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(consumption)) => Some(consumption),
+        Ok(Err(error)) => {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to query quota consumption"
+            );
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+pub async fn handle(
+    state: ServiceState,
+    body: SignedJson<GetRateLimitsRequest>,
+) -> Result<impl IntoResponse, ServiceUnavailable> {
+    if !body.relay.internal {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let projects = state
+        .project_cache()
+        .send(GetRateLimits::new(body.inner.project_keys))
+        .await?;
+
+    let mut infos = Vec::with_capacity(projects.len());
+    for project in &projects {
+        infos.push(ProjectRateLimitsInfo {
+            project_key: project.project_key,
+            rate_limits: project
+                .rate_limits
+                .iter()
+                .map(RateLimitInfo::from)
+                .collect(),
+            #[cfg(feature = "processing")]
+            quota_consumption: quota_consumption(&state, project).await,
+        });
+    }
+
+    let response = GetRateLimitsResponse { projects: infos };
+    Ok(axum::Json(response).into_response())
+}
+
+/// Request body of the endpoint clearing rate limits.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClearRateLimitsRequest {
+    /// The project key to clear rate limits for.
+    project_key: ProjectKey,
+    /// Only clear rate limits of these categories. If empty, all categories are cleared.
+    #[serde(default)]
+    categories: DataCategories,
+    /// Only clear rate limits with this reason code.
+    #[serde(default)]
+    reason_code: Option<ReasonCode>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClearRateLimitsResponse {
+    cleared: usize,
+}
+
+pub async fn handle_clear(
+    state: ServiceState,
+    body: SignedJson<ClearRateLimitsRequest>,
+) -> Result<impl IntoResponse, ServiceUnavailable> {
+    if !body.relay.internal {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let ClearRateLimitsRequest {
+        project_key,
+        categories,
+        reason_code,
+    } = body.inner;
+
+    let cleared = state
+        .project_cache()
+        .send(ClearRateLimits::new(project_key, categories, reason_code))
+        .await?;
+
+    relay_log::info!("cleared {cleared} cached rate limits of project {project_key}");
+    Ok(axum::Json(ClearRateLimitsResponse { cleared }).into_response())
+}
@@ -39,6 +39,7 @@ use std::time::Instant;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use relay_common::{DataCategory, UnixTimestamp};
+use relay_config::{Config, Priority};
 use relay_dynamic_config::ErrorBoundary;
 use relay_general::protocol::{EventId, EventType};
 use relay_general::types::Value;
@@ -131,31 +132,38 @@ impl ItemType {
     }
 }
 
-impl fmt::Display for ItemType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl ItemType {
+    /// Returns the name of this item type as used in the envelope item header.
+    pub fn as_str(&self) -> &str {
         match self {
-            Self::Event => write!(f, "event"),
-            Self::Transaction => write!(f, "transaction"),
-            Self::Security => write!(f, "security"),
-            Self::Attachment => write!(f, "attachment"),
-            Self::FormData => write!(f, "form_data"),
-            Self::RawSecurity => write!(f, "raw_security"),
-            Self::UnrealReport => write!(f, "unreal_report"),
-            Self::UserReport => write!(f, "user_report"),
-            Self::Session => write!(f, "session"),
-            Self::Sessions => write!(f, "sessions"),
-            Self::Metrics => write!(f, "metrics"),
-            Self::MetricBuckets => write!(f, "metric_buckets"),
-            Self::ClientReport => write!(f, "client_report"),
-            Self::Profile => write!(f, "profile"),
-            Self::ReplayEvent => write!(f, "replay_event"),
-            Self::ReplayRecording => write!(f, "replay_recording"),
-            Self::CheckIn => write!(f, "check_in"),
-            Self::Unknown(s) => s.fmt(f),
+            Self::Event => "event",
+            Self::Transaction => "transaction",
+            Self::Security => "security",
+            Self::Attachment => "attachment",
+            Self::FormData => "form_data",
+            Self::RawSecurity => "raw_security",
+            Self::UnrealReport => "unreal_report",
+            Self::UserReport => "user_report",
+            Self::Session => "session",
+            Self::Sessions => "sessions",
+            Self::Metrics => "metrics",
+            Self::MetricBuckets => "metric_buckets",
+            Self::ClientReport => "client_report",
+            Self::Profile => "profile",
+            Self::ReplayEvent => "replay_event",
+            Self::ReplayRecording => "replay_recording",
+            Self::CheckIn => "check_in",
+            Self::Unknown(s) => s,
         }
     }
 }
 
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 impl std::str::FromStr for ItemType {
     type Err = std::convert::Infallible;
 
@@ -488,6 +496,12 @@ pub struct ItemHeaders {
     #[serde(skip)]
     profile_counted_as_processed: bool,
 
+    /// Whether the trace of this event was sampled, as recorded in its trace context.
+    ///
+    /// NOTE: This is internal-only and not exposed into the Envelope.
+    #[serde(default, skip)]
+    sampled: Option<bool>,
+
     /// Other attributes for forward compatibility.
     #[serde(flatten)]
     other: BTreeMap<String, Value>,
@@ -515,6 +529,7 @@ impl Item {
                 other: BTreeMap::new(),
                 metrics_extracted: false,
                 profile_counted_as_processed: false,
+                sampled: None,
             },
             payload: Bytes::new(),
         }
@@ -641,6 +656,16 @@ impl Item {
         self.headers.rate_limited = rate_limited;
     }
 
+    /// Returns whether the trace of this event was sampled, if known.
+    pub fn sampled(&self) -> Option<bool> {
+        self.headers.sampled
+    }
+
+    /// Sets whether the trace of this event was sampled.
+    pub fn set_sampled(&mut self, sampled: Option<bool>) {
+        self.headers.sampled = sampled;
+    }
+
     /// Removes sample rates from the headers, if any.
     pub fn take_sample_rates(&mut self) -> Option<Value> {
         self.headers.sample_rates.take()
@@ -793,6 +818,48 @@ impl Item {
             ItemType::Unknown(_) => false,
         }
     }
+
+    /// Returns the priority of this item when shedding load.
+    ///
+    /// A priority configured for the item type takes precedence over one configured for the item's
+    /// data category. Without configuration, events and crash reports have a high priority, while
+    /// replays and client reports have a low priority.
+    pub fn priority(&self, config: &Config) -> Priority {
+        if let Some(priority) = config.item_priority(self.ty().as_str()) {
+            return priority;
+        }
+
+        let category_priority = self
+            .outcome_category(false)
+            .and_then(|category| config.category_priority(category));
+        if let Some(priority) = category_priority {
+            return priority;
+        }
+
+        match self.ty() {
+            ItemType::Event
+            | ItemType::Security
+            | ItemType::RawSecurity
+            | ItemType::UnrealReport
+            | ItemType::FormData => Priority::High,
+            ItemType::Attachment => match self.attachment_type() {
+                Some(AttachmentType::Minidump | AttachmentType::AppleCrashReport) => Priority::High,
+                _ => Priority::Normal,
+            },
+            ItemType::ReplayEvent | ItemType::ReplayRecording | ItemType::ClientReport => {
+                Priority::Low
+            }
+            ItemType::Transaction
+            | ItemType::UserReport
+            | ItemType::Session
+            | ItemType::Sessions
+            | ItemType::Metrics
+            | ItemType::MetricBuckets
+            | ItemType::Profile
+            | ItemType::CheckIn
+            | ItemType::Unknown(_) => Priority::Normal,
+        }
+    }
 }
 
 pub type Items = SmallVec<[Item; 3]>;
@@ -948,6 +1015,16 @@ impl Envelope {
         self.items.is_empty()
     }
 
+    /// Returns the priority of this envelope when shedding load.
+    ///
+    /// This is the highest priority of all items, or [`Priority::Normal`] for empty envelopes.
+    pub fn priority(&self, config: &Config) -> Priority {
+        self.items()
+            .map(|item| item.priority(config))
+            .max()
+            .unwrap_or(Priority::Normal)
+    }
+
     /// Unique identifier of the event associated to this envelope.
     ///
     /// The envelope may directly contain an event which has this id. Alternatively, it can contain
@@ -1727,4 +1804,42 @@ mod tests {
             assert_eq!(item.ty(), &ItemType::Attachment);
         }
     }
+
+    #[test]
+    fn test_envelope_priority() {
+        let mut envelope = Envelope::from_request(Some(EventId::new()), request_meta());
+        let config = Config::default();
+        assert_eq!(envelope.priority(&config), Priority::Normal);
+
+        envelope.add_item(Item::new(ItemType::ReplayRecording));
+        assert_eq!(envelope.priority(&config), Priority::Low);
+
+        let mut attachment = Item::new(ItemType::Attachment);
+        envelope.add_item(attachment.clone());
+        assert_eq!(envelope.priority(&config), Priority::Normal);
+
+        attachment.set_attachment_type(AttachmentType::Minidump);
+        envelope.add_item(attachment);
+        assert_eq!(envelope.priority(&config), Priority::High);
+    }
+
+    #[test]
+    fn test_item_priority_configured() {
+        let config = Config::from_json_value(serde_json::json!({
+            "load_shedding": {
+                "items": {"replay_recording": "high"},
+                "categories": {"replay": "normal", "error": "low"}
+            }
+        }))
+        .unwrap();
+
+        let item = Item::new(ItemType::ReplayRecording);
+        assert_eq!(item.priority(&config), Priority::High);
+
+        let item = Item::new(ItemType::ReplayEvent);
+        assert_eq!(item.priority(&config), Priority::Normal);
+
+        let item = Item::new(ItemType::Event);
+        assert_eq!(item.priority(&config), Priority::Low);
+    }
 }
@@ -19,7 +19,7 @@ use relay_quotas::Scoping;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::extractors::{ForwardedFor, StartTime};
+use crate::extractors::{authenticate_relay, ForwardedFor, StartTime};
 use crate::service::ServiceState;
 use crate::statsd::RelayCounters;
 use crate::utils::ApiErrorResponse;
@@ -210,6 +210,18 @@ pub struct RequestMeta<D = PartialDsn> {
     #[serde(default = "make_false", skip_serializing_if = "is_false")]
     no_cache: bool,
 
+    /// A signed ingestion token sent along with the DSN key.
+    ///
+    /// The token is verified against the project key during ingestion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ingestion_token: Option<String>,
+
+    /// Whether the request was sent by an authenticated downstream Relay.
+    //
+    // NOTE: This is internal-only and not exposed to Envelope headers.
+    #[serde(skip)]
+    from_relay: bool,
+
     /// The time at which the request started.
     //
     // NOTE: This is internal-only and not exposed to Envelope headers.
@@ -234,6 +246,15 @@ impl<D> RequestMeta<D> {
         Some(name)
     }
 
+    /// Returns the version of the client that sent the event.
+    ///
+    /// If the client is not sent in standard format, this method returns `None`.
+    pub fn client_version(&self) -> Option<&str> {
+        let client = self.client()?;
+        let (_name, version) = client.split_once('/')?;
+        Some(version)
+    }
+
     /// Returns the protocol version of the event payload.
     #[allow(dead_code)] // used in tests and processing mode
     pub fn version(&self) -> u16 {
@@ -282,6 +303,16 @@ impl<D> RequestMeta<D> {
         self.no_cache
     }
 
+    /// Returns the raw ingestion token, if sent.
+    pub fn ingestion_token(&self) -> Option<&str> {
+        self.ingestion_token.as_deref()
+    }
+
+    /// Returns `true` if the request was sent by an authenticated downstream Relay.
+    pub fn is_from_relay(&self) -> bool {
+        self.from_relay
+    }
+
     /// The time at which the request started.
     pub fn start_time(&self) -> Instant {
         self.start_time
@@ -305,6 +336,8 @@ impl RequestMeta {
             forwarded_for: "".to_string(),
             user_agent: Some(crate::constants::SERVER.to_owned()),
             no_cache: false,
+            ingestion_token: None,
+            from_relay: false,
             start_time: Instant::now(),
             client_hints: ClientHints::default(),
         }
@@ -414,6 +447,9 @@ impl PartialMeta {
         if self.no_cache {
             complete.no_cache = true;
         }
+        if self.ingestion_token.is_some() {
+            complete.ingestion_token = self.ingestion_token;
+        }
 
         complete
     }
@@ -447,6 +483,8 @@ where
                 .into_inner(),
             user_agent: ua.user_agent,
             no_cache: false,
+            ingestion_token: ingestion_token_from_parts(parts),
+            from_relay: false,
             start_time: StartTime::from_request_parts(parts, state)
                 .await?
                 .into_inner(),
@@ -481,7 +519,9 @@ fn auth_from_parts(req: &Parts, path_key: Option<String>) -> Result<Auth, BadEve
 
     // try to extract authentication info from URL query_param .../?sentry_...=<key>...
     let query = req.uri.query().unwrap_or_default();
-    if query.contains("sentry_") {
+    if url::form_urlencoded::parse(query.as_bytes())
+        .any(|(key, _)| key.starts_with("sentry_") && key != INGESTION_TOKEN_PARAM)
+    {
         if auth.is_some() {
             return Err(BadEventMeta::MultipleAuth);
         }
@@ -504,6 +544,31 @@ fn auth_from_parts(req: &Parts, path_key: Option<String>) -> Result<Auth, BadEve
     auth.ok_or(BadEventMeta::MissingAuth)
 }
 
+/// Name of the auth parameter containing the ingestion token.
+const INGESTION_TOKEN_PARAM: &str = "sentry_token";
+
+/// Extracts the ingestion token from the auth headers or the query string.
+///
+/// The token is sent as `sentry_token` parameter, next to `sentry_key`.
+fn ingestion_token_from_parts(req: &Parts) -> Option<String> {
+    let from_header = |header: &str| {
+        header[7..].split(',').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key.trim() == INGESTION_TOKEN_PARAM).then(|| value.trim().to_owned())
+        })
+    };
+
+    let token = get_auth_header(req, "x-sentry-auth").and_then(from_header);
+    let token = token.or_else(|| get_auth_header(req, header::AUTHORIZATION).and_then(from_header));
+
+    token.or_else(|| {
+        let query = req.uri.query().unwrap_or_default();
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == INGESTION_TOKEN_PARAM)
+            .map(|(_, value)| value.into_owned())
+    })
+}
+
 fn parse_header_url(req: &Parts, header: impl AsHeaderName) -> Option<Url> {
     req.headers
         .get(header)
@@ -578,6 +643,9 @@ impl FromRequestParts<ServiceState> for RequestMeta {
             version = &version.to_string()
         );
 
+        // Downstream Relays sign the DSN key of their requests.
+        let relay = authenticate_relay(&parts.headers, state, public_key.as_str().as_bytes()).await;
+
         Ok(RequestMeta {
             dsn,
             version,
@@ -587,6 +655,8 @@ impl FromRequestParts<ServiceState> for RequestMeta {
             forwarded_for: partial_meta.forwarded_for,
             user_agent: partial_meta.user_agent,
             no_cache: key_flags.contains(&"no-cache"),
+            ingestion_token: partial_meta.ingestion_token,
+            from_relay: relay.is_some(),
             start_time: partial_meta.start_time,
             client_hints: partial_meta.client_hints,
         })
@@ -609,6 +679,8 @@ mod tests {
                 forwarded_for: String::new(),
                 user_agent: Some("sentry/agent".to_string()),
                 no_cache: false,
+                ingestion_token: None,
+                from_relay: false,
                 start_time: Instant::now(),
                 client_hints: ClientHints::default(),
             }
@@ -645,6 +717,8 @@ mod tests {
             forwarded_for: "8.8.8.8".to_string(),
             user_agent: Some("0x8000".to_string()),
             no_cache: false,
+            ingestion_token: None,
+            from_relay: false,
             start_time: Instant::now(),
             client_hints: ClientHints {
                 sec_ch_ua_platform: Some("macOS".to_owned()),
@@ -659,4 +733,42 @@ mod tests {
         deserialized.start_time = reqmeta.start_time;
         assert_eq!(deserialized, reqmeta);
     }
+
+    #[test]
+    fn test_ingestion_token_from_parts() {
+        let parts = |uri: &str, auth: Option<&str>| {
+            let mut builder = axum::http::Request::builder().uri(uri);
+            if let Some(auth) = auth {
+                builder = builder.header("x-sentry-auth", auth);
+            }
+            builder.body(()).unwrap().into_parts().0
+        };
+
+        let req = parts(
+            "/api/42/envelope/",
+            Some("Sentry sentry_key=a94ae32be2584e0bbd7a4cbb95971fee, sentry_token=abc.def.ghi"),
+        );
+        assert_eq!(
+            ingestion_token_from_parts(&req).as_deref(),
+            Some("abc.def.ghi")
+        );
+        assert!(auth_from_parts(&req, None).is_ok());
+
+        let req = parts(
+            "/api/42/envelope/?sentry_token=abc.def.ghi",
+            Some("Sentry sentry_key=a94ae32be2584e0bbd7a4cbb95971fee"),
+        );
+        assert_eq!(
+            ingestion_token_from_parts(&req).as_deref(),
+            Some("abc.def.ghi")
+        );
+        // The token in the query does not conflict with auth in the header.
+        assert!(auth_from_parts(&req, None).is_ok());
+
+        let req = parts(
+            "/api/42/envelope/?sentry_key=a94ae32be2584e0bbd7a4cbb95971fee",
+            None,
+        );
+        assert_eq!(ingestion_token_from_parts(&req), None);
+    }
 }
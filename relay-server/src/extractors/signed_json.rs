@@ -1,16 +1,22 @@
+use std::error::Error;
+
 use axum::extract::rejection::BytesRejection;
 use axum::extract::FromRequest;
-use axum::http::{Request, StatusCode};
+use axum::http::{HeaderMap, Request, StatusCode};
 use axum::response::{IntoResponse, Response};
 use bytes::Bytes;
-use relay_auth::{RelayId, UnpackError};
-use relay_config::RelayInfo;
+use chrono::Duration;
+use relay_auth::{PublicKey, RelayId, UnpackError};
+use relay_config::{Config, RelayInfo};
 use serde::de::DeserializeOwned;
 
-use crate::actors::relays::GetRelay;
+use crate::actors::relays::{AcceptNextKey, GetRelay};
 use crate::service::ServiceState;
 use crate::utils::ApiErrorResponse;
 
+/// The maximum age in seconds of signatures on requests without a signed body.
+const REQUEST_SIGNATURE_MAX_AGE_SECS: i64 = 5 * 60;
+
 #[derive(Debug)]
 pub struct SignedJson<T> {
     pub inner: T,
@@ -27,6 +33,10 @@ pub enum SignatureError {
     MalformedHeader(&'static str),
     #[error("unknown relay id")]
     UnknownRelay,
+    #[error("client certificate does not match relay")]
+    CertificateMismatch,
+    #[error("client certificate header is not configured")]
+    CertificateNotConfigured,
     #[error(transparent)]
     MalformedBody(#[from] BytesRejection),
     #[error("invalid JSON data")]
@@ -57,12 +67,169 @@ impl From<UnpackError> for SignatureError {
     }
 }
 
-fn get_header<'a, B>(
-    request: &'a Request<B>,
-    name: &'static str,
-) -> Result<&'a str, SignatureError> {
-    let value = request
-        .headers()
+/// Verifies the body against the signatures with all public keys of the relay.
+///
+/// During a key rotation, the relay signs requests with both its current and next key, and either
+/// key may be known. The first signature that verifies with any of the keys is accepted.
+///
+/// Additionally returns the next key announced in the signature header if the signature was
+/// verified with the relay's current key and the next key is not known yet.
+fn unpack<T: DeserializeOwned>(
+    relay: &RelayInfo,
+    body: &[u8],
+    signature: &str,
+    next_signature: Option<&str>,
+) -> Result<(T, Option<PublicKey>), UnpackError> {
+    let mut result = Err(UnpackError::BadSignature);
+
+    for signature in std::iter::once(signature).chain(next_signature) {
+        for public_key in relay.public_keys() {
+            result = public_key.unpack_meta(body, signature);
+            if !matches!(result, Err(UnpackError::BadSignature)) {
+                let (header, inner) = result?;
+                let next_key = header.next_key.filter(|next_key| {
+                    *public_key == relay.public_key
+                        && relay.public_keys().all(|known| known != next_key)
+                });
+                return Ok((inner, next_key));
+            }
+        }
+    }
+
+    result.map(|(_, inner)| (inner, None))
+}
+
+/// Authenticates the request of a relay by its client certificate.
+///
+/// Relay does not terminate TLS. The subject of the verified certificate is forwarded by the
+/// TLS-terminating proxy in the configured header. Returns `true` if the request carries the
+/// certificate of the relay, in which case it does not have to be signed. Returns `false` if the
+/// relay has no certificate subject or the request carries no certificate.
+fn check_certificate(
+    headers: &HeaderMap,
+    config: &Config,
+    relay: &RelayInfo,
+) -> Result<bool, SignatureError> {
+    let expected = match relay.certificate_subject {
+        Some(ref subject) => subject,
+        None => return Ok(false),
+    };
+
+    // Without the header, certificates cannot be verified. Never fall back to trusting a relay
+    // with a certificate subject in this case.
+    let header = match config.client_certificate_header() {
+        Some(header) => header,
+        None => return Err(SignatureError::CertificateNotConfigured),
+    };
+
+    let subject = match headers.get(header) {
+        Some(value) => value
+            .to_str()
+            .map_err(|_| SignatureError::MalformedHeader("client certificate"))?,
+        None => return Ok(false),
+    };
+
+    if subject != expected {
+        return Err(SignatureError::CertificateMismatch);
+    }
+
+    Ok(true)
+}
+
+/// Authenticates the request of a relay by its client certificate or signatures.
+fn verify<T: DeserializeOwned>(
+    headers: &HeaderMap,
+    body: &[u8],
+    config: &Config,
+    relay: &RelayInfo,
+) -> Result<(T, Option<PublicKey>), SignatureError> {
+    if check_certificate(headers, config, relay)? {
+        return Ok((serde_json::from_slice(body)?, None));
+    }
+
+    let signature = get_header(headers, "x-sentry-relay-signature")?;
+    let next_signature = match get_header(headers, "x-sentry-relay-next-signature") {
+        Ok(signature) => Some(signature),
+        Err(SignatureError::MissingHeader(_)) => None,
+        Err(error) => return Err(error),
+    };
+
+    Ok(unpack(relay, body, signature, next_signature)?)
+}
+
+/// Authenticates a relay by its client certificate or signatures of `data` that expire after a
+/// short time.
+fn verify_request(
+    headers: &HeaderMap,
+    data: &[u8],
+    config: &Config,
+    relay: &RelayInfo,
+) -> Result<(), SignatureError> {
+    if check_certificate(headers, config, relay)? {
+        return Ok(());
+    }
+
+    let signature = get_header(headers, "x-sentry-relay-signature")?;
+    let next_signature = match get_header(headers, "x-sentry-relay-next-signature") {
+        Ok(signature) => Some(signature),
+        Err(SignatureError::MissingHeader(_)) => None,
+        Err(error) => return Err(error),
+    };
+
+    let verified = std::iter::once(signature)
+        .chain(next_signature)
+        .any(|signature| {
+            relay.public_keys().any(|public_key| {
+                let max_age = Duration::seconds(REQUEST_SIGNATURE_MAX_AGE_SECS);
+                public_key.verify_timestamp(data, signature, Some(max_age))
+            })
+        });
+
+    if !verified {
+        return Err(SignatureError::BadSignature(UnpackError::BadSignature));
+    }
+
+    Ok(())
+}
+
+/// Authenticates the relay that sent a request without a signed body, such as an envelope.
+///
+/// Instead of the body, relays sign `data` identifying the request. Static relays may present their
+/// client certificate instead. Returns `None` if the request was not sent by a known relay or
+/// cannot be authenticated, in which case it should be treated like a request of any other client.
+pub async fn authenticate_relay(
+    headers: &HeaderMap,
+    state: &ServiceState,
+    data: &[u8],
+) -> Option<RelayInfo> {
+    let relay_id = headers
+        .get("x-sentry-relay-id")?
+        .to_str()
+        .ok()?
+        .parse::<RelayId>()
+        .ok()?;
+
+    let relay = state
+        .relay_cache()
+        .send(GetRelay { relay_id })
+        .await
+        .ok()??;
+
+    match verify_request(headers, data, state.config(), &relay) {
+        Ok(()) => Some(relay),
+        Err(error) => {
+            relay_log::debug!(
+                error = &error as &dyn Error,
+                relay_id = %relay_id,
+                "failed to authenticate relay request"
+            );
+            None
+        }
+    }
+}
+
+fn get_header<'a>(headers: &'a HeaderMap, name: &'static str) -> Result<&'a str, SignatureError> {
+    let value = headers
         .get(name)
         .ok_or(SignatureError::MissingHeader(name))?;
 
@@ -85,23 +252,260 @@ where
         request: Request<B>,
         state: &ServiceState,
     ) -> Result<Self, Self::Rejection> {
-        let relay_id = get_header(&request, "x-sentry-relay-id")?
+        let relay_id = get_header(request.headers(), "x-sentry-relay-id")?
             .parse::<RelayId>()
             .map_err(|_| SignatureError::MalformedHeader("x-sentry-relay-id"))?;
 
         // Track the relay header value even if is not a string.
         relay_log::configure_scope(|s| s.set_tag("relay_id", relay_id.to_string()));
 
-        let signature = get_header(&request, "x-sentry-relay-signature")?.to_owned();
-
-        let relay = state
+        let mut relay = state
             .relay_cache()
             .send(GetRelay { relay_id })
             .await?
             .ok_or(SignatureError::UnknownRelay)?;
 
+        let headers = request.headers().clone();
         let body = Bytes::from_request(request, state).await?;
-        let inner = relay.public_key.unpack(&body, &signature, None)?;
+        let (inner, next_key) = verify(&headers, &body, state.config(), &relay)?;
+
+        // The next key was announced with a signature of the current key, so it can be trusted.
+        if let Some(next_key) = next_key {
+            state.relay_cache().send(AcceptNextKey {
+                relay_id,
+                current_key: relay.public_key.clone(),
+                next_key: next_key.clone(),
+            });
+            relay.next_public_key = Some(next_key);
+        }
+
         Ok(SignedJson { inner, relay })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use relay_auth::{generate_key_pair, SignatureHeader};
+
+    use super::*;
+
+    fn certificate_config() -> Config {
+        Config::from_json_value(serde_json::json!({
+            "auth": {
+                "client_certificate_header": "x-ssl-client-s-dn",
+            }
+        }))
+        .unwrap()
+    }
+
+    fn signed_headers(signature: Option<&str>, subject: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(signature) = signature {
+            headers.insert("x-sentry-relay-signature", signature.parse().unwrap());
+        }
+        if let Some(subject) = subject {
+            headers.insert("x-ssl-client-s-dn", subject.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_certificate_authentication() {
+        let config = certificate_config();
+        let (secret_key, public_key) = generate_key_pair();
+        let relay = RelayInfo {
+            internal: true,
+            certificate_subject: Some("CN=relay-1".to_owned()),
+            ..RelayInfo::new(public_key)
+        };
+
+        // A matching certificate replaces the signature.
+        let headers = signed_headers(None, Some("CN=relay-1"));
+        let (value, next_key) = verify::<u32>(&headers, b"42", &config, &relay).unwrap();
+        assert_eq!(value, 42);
+        assert_eq!(next_key, None);
+        assert!(verify_request(&headers, b"data", &config, &relay).is_ok());
+
+        // Without a certificate, the relay authenticates with its signature.
+        let (body, signature) = secret_key.pack(42);
+        let headers = signed_headers(Some(&signature), None);
+        let (value, _) = verify::<u32>(&headers, &body, &config, &relay).unwrap();
+        assert_eq!(value, 42);
+
+        let headers = signed_headers(None, None);
+        assert!(matches!(
+            verify::<u32>(&headers, b"42", &config, &relay),
+            Err(SignatureError::MissingHeader("x-sentry-relay-signature"))
+        ));
+        assert!(verify_request(&headers, b"data", &config, &relay).is_err());
+    }
+
+    #[test]
+    fn test_certificate_mismatch() {
+        let config = certificate_config();
+        let (secret_key, public_key) = generate_key_pair();
+        let relay = RelayInfo {
+            certificate_subject: Some("CN=relay-1".to_owned()),
+            ..RelayInfo::new(public_key.clone())
+        };
+
+        // The certificate of another relay is rejected even with a valid signature.
+        let (body, signature) = secret_key.pack(42);
+        let headers = signed_headers(Some(&signature), Some("CN=relay-2"));
+        assert!(matches!(
+            verify::<u32>(&headers, &body, &config, &relay),
+            Err(SignatureError::CertificateMismatch)
+        ));
+        assert!(matches!(
+            verify_request(&headers, b"data", &config, &relay),
+            Err(SignatureError::CertificateMismatch)
+        ));
+
+        // Relays without a certificate subject cannot authenticate with a certificate.
+        let relay = RelayInfo::new(public_key);
+        let headers = signed_headers(None, Some("CN=relay-1"));
+        assert!(matches!(
+            verify::<u32>(&headers, b"42", &config, &relay),
+            Err(SignatureError::MissingHeader("x-sentry-relay-signature"))
+        ));
+
+        let headers = signed_headers(Some(&signature), Some("CN=relay-1"));
+        let (value, _) = verify::<u32>(&headers, &body, &config, &relay).unwrap();
+        assert_eq!(value, 42);
+    }
+
+    #[test]
+    fn test_certificate_not_configured() {
+        let config = Config::default();
+        let (secret_key, public_key) = generate_key_pair();
+        let relay = RelayInfo {
+            certificate_subject: Some("CN=relay-1".to_owned()),
+            ..RelayInfo::new(public_key)
+        };
+
+        // Certificates cannot be verified, so the relay is rejected even with a signature.
+        let (body, signature) = secret_key.pack(42);
+        let headers = signed_headers(Some(&signature), Some("CN=relay-1"));
+        assert!(matches!(
+            verify::<u32>(&headers, &body, &config, &relay),
+            Err(SignatureError::CertificateNotConfigured)
+        ));
+        assert!(matches!(
+            verify_request(&headers, b"data", &config, &relay),
+            Err(SignatureError::CertificateNotConfigured)
+        ));
+    }
+
+    #[test]
+    fn test_verify_request() {
+        let config = Config::default();
+        let (secret_key, public_key) = generate_key_pair();
+        let (next_secret_key, next_public_key) = generate_key_pair();
+        let relay = RelayInfo::new(public_key);
+
+        let data = b"a94ae32be2584e0bbd7a4cbb95971fee";
+        let headers = signed_headers(Some(&secret_key.sign(data)), None);
+        assert!(verify_request(&headers, data, &config, &relay).is_ok());
+
+        // The signature only authenticates the signed data.
+        assert!(matches!(
+            verify_request(
+                &headers,
+                b"e12d836b15bb49d7bbf99e64295d995b",
+                &config,
+                &relay
+            ),
+            Err(SignatureError::BadSignature(_))
+        ));
+
+        // Signatures expire.
+        let header = SignatureHeader {
+            timestamp: Some(chrono::Utc::now() - Duration::seconds(10 * 60)),
+            ..Default::default()
+        };
+        let expired = secret_key.sign_with_header(data, &header);
+        let headers = signed_headers(Some(&expired), None);
+        assert!(verify_request(&headers, data, &config, &relay).is_err());
+
+        // During a key rotation, the signature of either key is accepted.
+        let relay = RelayInfo::new(next_public_key);
+        let mut headers = signed_headers(Some(&secret_key.sign(data)), None);
+        headers.insert(
+            "x-sentry-relay-next-signature",
+            next_secret_key.sign(data).parse().unwrap(),
+        );
+        assert!(verify_request(&headers, data, &config, &relay).is_ok());
+    }
+
+    #[test]
+    fn test_unpack_rotated_keys() {
+        let (secret_key, public_key) = generate_key_pair();
+        let (next_secret_key, next_public_key) = generate_key_pair();
+
+        let (body, signature) = secret_key.pack(42);
+        let next_signature = next_secret_key.sign(&body);
+
+        // Only the current key is known.
+        let relay = RelayInfo::new(public_key.clone());
+        let (value, _): (u32, _) =
+            unpack(&relay, &body, &signature, Some(&next_signature)).unwrap();
+        assert_eq!(value, 42);
+
+        // Only the next key is known.
+        let relay = RelayInfo::new(next_public_key.clone());
+        let (value, _): (u32, _) =
+            unpack(&relay, &body, &signature, Some(&next_signature)).unwrap();
+        assert_eq!(value, 42);
+        assert!(matches!(
+            unpack::<u32>(&relay, &body, &signature, None),
+            Err(UnpackError::BadSignature)
+        ));
+
+        // Both keys are known, but only one signature was sent.
+        let relay = RelayInfo {
+            next_public_key: Some(next_public_key),
+            ..RelayInfo::new(public_key)
+        };
+        let (value, _): (u32, _) = unpack(&relay, &body, &next_signature, None).unwrap();
+        assert_eq!(value, 42);
+    }
+
+    #[test]
+    fn test_unpack_announced_next_key() {
+        let (secret_key, public_key) = generate_key_pair();
+        let (next_secret_key, next_public_key) = generate_key_pair();
+
+        let header = SignatureHeader {
+            next_key: Some(next_public_key.clone()),
+            ..Default::default()
+        };
+        let (body, signature) = secret_key.pack_with_header(42, &header);
+
+        // The next key is accepted from a signature of the current key.
+        let relay = RelayInfo::new(public_key.clone());
+        let (_, next_key) = unpack::<u32>(&relay, &body, &signature, None).unwrap();
+        assert_eq!(next_key, Some(next_public_key.clone()));
+
+        // Once known, the next key is not announced again.
+        let relay = RelayInfo {
+            next_public_key: Some(next_public_key.clone()),
+            ..RelayInfo::new(public_key)
+        };
+        let (_, next_key) = unpack::<u32>(&relay, &body, &signature, None).unwrap();
+        assert_eq!(next_key, None);
+
+        // A key announced with a signature of any other key is ignored.
+        let (_, other_public_key) = generate_key_pair();
+        let header = SignatureHeader {
+            next_key: Some(other_public_key),
+            ..Default::default()
+        };
+        let (body, signature) = next_secret_key.pack_with_header(42, &header);
+        let relay = RelayInfo {
+            next_public_key: Some(next_public_key),
+            ..RelayInfo::new(generate_key_pair().1)
+        };
+        let (_, next_key) = unpack::<u32>(&relay, &body, &signature, None).unwrap();
+        assert_eq!(next_key, None);
+    }
+}
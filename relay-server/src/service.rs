@@ -6,14 +6,18 @@ use anyhow::{Context, Result};
 use axum::extract::FromRequestParts;
 use axum::http::request::Parts;
 use relay_aws_extension::AwsExtension;
-use relay_config::Config;
+use relay_config::{Config, Priority};
 use relay_metrics::{Aggregator, AggregatorService};
+use relay_quotas::LocalRateLimiter;
+#[cfg(feature = "processing")]
+use relay_quotas::RedisRateLimiter;
 use relay_redis::RedisPool;
 use relay_system::{channel, Addr, Service};
 use tokio::runtime::Runtime;
 
 use crate::actors::envelopes::{EnvelopeManager, EnvelopeManagerService};
 use crate::actors::health_check::{HealthCheck, HealthCheckService};
+use crate::actors::local_quotas::LocalQuotasService;
 use crate::actors::outcome::{OutcomeProducer, OutcomeProducerService, TrackOutcome};
 use crate::actors::outcome_aggregator::OutcomeAggregator;
 use crate::actors::processor::{EnvelopeProcessor, EnvelopeProcessorService};
@@ -41,6 +45,10 @@ pub enum ServiceError {
     /// Initializing the Redis cluster client failed.
     #[error("could not initialize redis cluster client")]
     Redis,
+
+    /// Loading the client identity for the upstream failed.
+    #[error("could not load the TLS client identity")]
+    ClientIdentity,
 }
 
 #[derive(Clone)]
@@ -89,6 +97,8 @@ struct StateInner {
     _project_runtime: Arc<Runtime>,
     _upstream_runtime: Arc<Runtime>,
     _store_runtime: Option<Arc<Runtime>>,
+    #[cfg(feature = "processing")]
+    rate_limiter: Option<RedisRateLimiter>,
 }
 
 /// Server state.
@@ -106,7 +116,9 @@ impl ServiceState {
         let outcome_runtime = create_runtime("outcome-rt", 1);
         let mut _store_runtime = None;
 
-        let upstream_relay = UpstreamRelayService::new(config.clone()).start_in(&upstream_runtime);
+        let upstream_relay = UpstreamRelayService::new(config.clone())
+            .context(ServiceError::ClientIdentity)?
+            .start_in(&upstream_runtime);
         let test_store = TestStoreService::new(config.clone()).start();
 
         let redis_pool = match config.redis() {
@@ -116,7 +128,12 @@ impl ServiceState {
             _ => None,
         };
 
-        let buffer = Arc::new(BufferGuard::new(config.envelope_buffer_size()));
+        let buffer = Arc::new(
+            BufferGuard::new(config.envelope_buffer_size()).priority_limits(
+                config.priority_limit(Priority::Low),
+                config.priority_limit(Priority::Normal),
+            ),
+        );
 
         // Create an address for the `EnvelopeManagerService`, which can be injected into the
         // other services. This also solves the issue of circular dependencies with `EnvelopeProcessorService`.
@@ -130,10 +147,22 @@ impl ServiceState {
         let outcome_aggregator =
             OutcomeAggregator::new(&config, outcome_producer.clone()).start_in(&outcome_runtime);
 
+        let local_rate_limiter = config.local_quotas_enabled().then(|| {
+            LocalRateLimiter::new()
+                .max_limit(config.max_rate_limit())
+                .track_usage(config.local_quotas_sync_interval().is_some())
+        });
+
+        if let Some(ref rate_limiter) = local_rate_limiter {
+            LocalQuotasService::new(config.clone(), rate_limiter.clone(), upstream_relay.clone())
+                .start();
+        }
+
         let (project_cache, project_cache_rx) = channel(ProjectCacheService::name());
         let processor = EnvelopeProcessorService::new(
             config.clone(),
             redis_pool.clone(),
+            local_rate_limiter,
             envelope_manager.clone(),
             outcome_aggregator.clone(),
             project_cache.clone(),
@@ -160,7 +189,8 @@ impl ServiceState {
         #[cfg(feature = "processing")]
         if config.processing_enabled() {
             let rt = create_runtime("store-rt", 1);
-            let store = StoreService::create(config.clone())?.start_in(&rt);
+            let store =
+                StoreService::create(config.clone(), outcome_producer.clone())?.start_in(&rt);
             envelope_manager_service.set_store_forwarder(store);
             _store_runtime = Some(rt);
         }
@@ -215,6 +245,11 @@ impl ServiceState {
             upstream_relay,
         };
 
+        #[cfg(feature = "processing")]
+        let rate_limiter = redis_pool
+            .clone()
+            .map(|pool| RedisRateLimiter::new(pool).max_limit(config.max_rate_limit()));
+
         let state = StateInner {
             buffer_guard: buffer,
             config,
@@ -224,6 +259,8 @@ impl ServiceState {
             _project_runtime: Arc::new(project_runtime),
             _upstream_runtime: Arc::new(upstream_runtime),
             _store_runtime: _store_runtime.map(Arc::new),
+            #[cfg(feature = "processing")]
+            rate_limiter,
         };
 
         Ok(ServiceState {
@@ -283,6 +320,12 @@ impl ServiceState {
     pub fn outcome_aggregator(&self) -> &Addr<TrackOutcome> {
         &self.inner.registry.outcome_aggregator
     }
+
+    /// Returns the Redis rate limiter if processing and Redis are enabled.
+    #[cfg(feature = "processing")]
+    pub fn rate_limiter(&self) -> Option<&RedisRateLimiter> {
+        self.inner.rate_limiter.as_ref()
+    }
 }
 
 #[axum::async_trait]
@@ -189,6 +189,9 @@ pub enum RelayTimers {
     /// Time in milliseconds spent running light normalization on an event. Light normalization
     /// happens before envelope filtering and metrics extraction.
     EventProcessingLightNormalization,
+    /// Time in milliseconds spent resolving JavaScript stack traces with source maps from the
+    /// local artifact directory.
+    EventProcessingSourceMaps,
     /// Time in milliseconds spent running event processors on an event for normalization. Event
     /// processing happens before filtering.
     #[cfg(feature = "processing")]
@@ -200,7 +203,6 @@ pub enum RelayTimers {
     /// Not all events reach this point. After an event is rate limited for the first time, the rate
     /// limit is cached. Events coming in after this will be discarded earlier in the request queue
     /// and do not reach the processing queue.
-    #[cfg(feature = "processing")]
     EventProcessingRateLimiting,
     /// Time in milliseconds spent in data scrubbing for the current event. Data scrubbing happens
     /// last before serializing the event back to JSON.
@@ -327,10 +329,10 @@ impl TimerMetric for RelayTimers {
             RelayTimers::EventProcessingLightNormalization => {
                 "event_processing.light_normalization"
             }
+            RelayTimers::EventProcessingSourceMaps => "event_processing.source_maps",
             #[cfg(feature = "processing")]
             RelayTimers::EventProcessingProcess => "event_processing.process",
             RelayTimers::EventProcessingFiltering => "event_processing.filtering",
-            #[cfg(feature = "processing")]
             RelayTimers::EventProcessingRateLimiting => "event_processing.rate_limiting",
             RelayTimers::EventProcessingPii => "event_processing.pii",
             RelayTimers::EventProcessingSerialization => "event_processing.serialization",
@@ -389,6 +391,9 @@ pub enum RelayCounters {
     BufferEnvelopesWritten,
     /// Number of _envelopes_ the envelope buffer reads back from disk.
     BufferEnvelopesRead,
+    /// Number of _envelopes_ evicted from the in-memory buffer in favor of envelopes with higher
+    /// priority.
+    BufferEnvelopesEvicted,
     ///
     /// Number of outcomes and reasons for rejected Envelopes.
     ///
@@ -541,6 +546,19 @@ pub enum RelayCounters {
     ///  - `sdk`: The name of the Sentry SDK sending the transaction. This tag is only set for
     ///    Sentry's SDKs and defaults to "proprietary".
     OpenTelemetryEvent,
+    /// Quantities reported by SDKs in client reports.
+    ///
+    /// Only entries that passed validation are counted, after their quantities have been
+    /// clamped to `outcomes.max_client_report_quantity`.
+    ///
+    /// This metric is tagged with:
+    ///  - `sdk`: The name of the SDK sending the client report. This tag is only set for
+    ///    Sentry's SDKs and defaults to "proprietary".
+    ///  - `sdk_version`: The major and minor version of the SDK sending the client report, such
+    ///    as `"7.54"`, or "unknown".
+    ///  - `outcome`: The field of the client report, such as `"client_discard"`.
+    ///  - `category`: The data category of the discarded items.
+    ClientReportQuantity,
 }
 
 impl CounterMetric for RelayCounters {
@@ -554,6 +572,7 @@ impl CounterMetric for RelayCounters {
             RelayCounters::BufferReads => "buffer.reads",
             RelayCounters::BufferEnvelopesWritten => "buffer.envelopes_written",
             RelayCounters::BufferEnvelopesRead => "buffer.envelopes_read",
+            RelayCounters::BufferEnvelopesEvicted => "buffer.envelopes_evicted",
             RelayCounters::Outcomes => "events.outcomes",
             RelayCounters::ProjectStateGet => "project_state.get",
             RelayCounters::ProjectStateRequest => "project_state.request",
@@ -575,6 +594,7 @@ impl CounterMetric for RelayCounters {
             RelayCounters::MetricBucketsParsingFailed => "metrics.buckets.parsing_failed",
             RelayCounters::MetricsTransactionNameExtracted => "metrics.transaction_name",
             RelayCounters::OpenTelemetryEvent => "event.opentelemetry",
+            RelayCounters::ClientReportQuantity => "client_report.quantity",
         }
     }
 }
@@ -1,5 +1,6 @@
 use std::fmt;
 
+use relay_config::Priority;
 use relay_system::Addr;
 
 use crate::actors::outcome::TrackOutcome;
@@ -26,12 +27,17 @@ impl std::error::Error for BufferError {}
 /// The buffer guard is basically a semaphore that ensures the buffer does not outgrow the maximum
 /// number of envelopes configured through `envelope_buffer_size`. To enter a new envelope
 /// into the processing pipeline, use [`BufferGuard::enter`].
+///
+/// Envelopes with low and normal [`Priority`] may only occupy a share of the capacity, which can be
+/// checked with [`BufferGuard::has_capacity`] before entering an envelope.
 #[derive(Debug)]
 pub struct BufferGuard {
     inner: Semaphore,
     capacity: usize,
     high_watermark: f64,
     low_watermark: f64,
+    low_priority_limit: f64,
+    normal_priority_limit: f64,
 }
 
 impl BufferGuard {
@@ -43,9 +49,18 @@ impl BufferGuard {
             capacity,
             high_watermark: 0.8,
             low_watermark: 0.5,
+            low_priority_limit: 1.0,
+            normal_priority_limit: 1.0,
         }
     }
 
+    /// Limits the fraction of the capacity that low and normal priority envelopes may occupy.
+    pub fn priority_limits(mut self, low: f64, normal: f64) -> Self {
+        self.low_priority_limit = low;
+        self.normal_priority_limit = normal;
+        self
+    }
+
     /// Returns the current usage of `BufferGuard` permits.
     #[inline]
     fn usage(&self) -> f64 {
@@ -80,6 +95,20 @@ impl BufferGuard {
         self.capacity.saturating_sub(self.available())
     }
 
+    /// Returns `true` if an envelope with the given priority can enter the pipeline.
+    ///
+    /// High priority envelopes can use the full capacity, while low and normal priority envelopes
+    /// are limited to a fraction of the capacity.
+    pub fn has_capacity(&self, priority: Priority) -> bool {
+        let limit = match priority {
+            Priority::Low => self.low_priority_limit,
+            Priority::Normal => self.normal_priority_limit,
+            Priority::High => 1.0,
+        };
+
+        (self.used() as f64) < self.capacity as f64 * limit
+    }
+
     /// Reserves resources for processing an envelope in Relay.
     ///
     /// Returns `Ok(ManagedEnvelope)` on success, which internally holds a handle to the reserved
@@ -112,3 +141,44 @@ impl BufferGuard {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use relay_test::mock_service;
+
+    use crate::testutils::empty_envelope;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_has_capacity() {
+        let (outcome_aggregator, _) = mock_service("outcome_aggregator", (), |&mut (), _| {});
+        let (test_store, _) = mock_service("test_store", (), |&mut (), _| {});
+        let buffer_guard = BufferGuard::new(4).priority_limits(0.5, 0.75);
+
+        let _first = buffer_guard
+            .enter(
+                empty_envelope(),
+                outcome_aggregator.clone(),
+                test_store.clone(),
+            )
+            .unwrap();
+        assert!(buffer_guard.has_capacity(Priority::Low));
+
+        let _second = buffer_guard
+            .enter(
+                empty_envelope(),
+                outcome_aggregator.clone(),
+                test_store.clone(),
+            )
+            .unwrap();
+        assert!(!buffer_guard.has_capacity(Priority::Low));
+        assert!(buffer_guard.has_capacity(Priority::Normal));
+
+        let _third = buffer_guard
+            .enter(empty_envelope(), outcome_aggregator, test_store)
+            .unwrap();
+        assert!(!buffer_guard.has_capacity(Priority::Normal));
+        assert!(buffer_guard.has_capacity(Priority::High));
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "processing")]
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use relay_common::ProjectId;
+use relay_general::protocol::EventId;
+#[cfg(feature = "processing")]
+use relay_redis::{RedisError, RedisPool};
+
+/// Key of an event in the deduplication window.
+type EventKey = (ProjectId, EventId);
+
+/// Event IDs seen within the window, in the order in which they were first seen.
+///
+/// Forgotten event IDs are only removed from `seen`. Their stale entries in `order` are skipped
+/// when they are evicted.
+#[derive(Debug)]
+struct SeenEvents {
+    seen: HashMap<EventKey, Instant>,
+    order: VecDeque<(EventKey, Instant)>,
+    max_entries: usize,
+}
+
+impl SeenEvents {
+    fn new(max_entries: usize) -> Self {
+        Self {
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            max_entries,
+        }
+    }
+
+    /// Records the event and returns `true` if it has been seen within the window.
+    fn check_and_insert(&mut self, key: EventKey, window: Duration, now: Instant) -> bool {
+        while let Some(&(_, seen_at)) = self.order.front() {
+            if now.saturating_duration_since(seen_at) < window {
+                break;
+            }
+            self.evict_oldest();
+        }
+
+        if self.seen.contains_key(&key) {
+            return true;
+        }
+
+        if self.max_entries > 0 {
+            while self.order.len() >= self.max_entries {
+                self.evict_oldest();
+            }
+            self.seen.insert(key, now);
+            self.order.push_back((key, now));
+        }
+
+        false
+    }
+
+    /// Removes the event so that it is no longer considered a duplicate.
+    fn remove(&mut self, key: EventKey) {
+        self.seen.remove(&key);
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some((key, seen_at)) = self.order.pop_front() {
+            // The event may have been removed and seen again since this entry was added.
+            if self.seen.get(&key) == Some(&seen_at) {
+                self.seen.remove(&key);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum DeduplicatorInner {
+    Local(Mutex<SeenEvents>),
+    #[cfg(feature = "processing")]
+    Redis(RedisPool),
+}
+
+/// Detects events that have been submitted repeatedly for the same project.
+///
+/// Event IDs are remembered for the configured window, either in memory of this Relay or in the
+/// shared Redis instance of processing Relays. The in-memory set is bounded and drops the oldest
+/// event IDs once it is full.
+///
+/// Event IDs are recorded as soon as they are checked, so that concurrent submissions are detected
+/// as well. If the event is not accepted in the end, for example because it is rate limited or
+/// cannot be sent, the event ID must be removed with [`forget`](Self::forget). Otherwise, retries
+/// of the event would be dropped as duplicates.
+///
+/// Clones of this deduplicator share the same set of event IDs.
+#[derive(Clone, Debug)]
+pub struct EventDeduplicator {
+    inner: Arc<DeduplicatorInner>,
+    window: Duration,
+}
+
+impl EventDeduplicator {
+    /// Creates a deduplicator that remembers at most `max_entries` event IDs in memory.
+    pub fn local(window: Duration, max_entries: usize) -> Self {
+        Self {
+            inner: Arc::new(DeduplicatorInner::Local(Mutex::new(SeenEvents::new(
+                max_entries,
+            )))),
+            window,
+        }
+    }
+
+    /// Creates a deduplicator that remembers event IDs in Redis.
+    #[cfg(feature = "processing")]
+    pub fn redis(pool: RedisPool, window: Duration) -> Self {
+        Self {
+            inner: Arc::new(DeduplicatorInner::Redis(pool)),
+            window,
+        }
+    }
+
+    /// Records the event and returns `true` if it has already been seen within the window.
+    ///
+    /// If Redis cannot be reached, the event is not considered a duplicate.
+    pub fn is_duplicate(&self, project_id: ProjectId, event_id: EventId) -> bool {
+        match *self.inner {
+            DeduplicatorInner::Local(ref seen) => {
+                seen.lock()
+                    .check_and_insert((project_id, event_id), self.window, Instant::now())
+            }
+            #[cfg(feature = "processing")]
+            DeduplicatorInner::Redis(ref pool) => {
+                match self.is_duplicate_redis(pool, project_id, event_id) {
+                    Ok(is_duplicate) => is_duplicate,
+                    Err(err) => {
+                        relay_log::error!(
+                            error = &err as &dyn Error,
+                            "failed to deduplicate event in redis"
+                        );
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes a recorded event so that it is no longer considered a duplicate.
+    pub fn forget(&self, project_id: ProjectId, event_id: EventId) {
+        match *self.inner {
+            DeduplicatorInner::Local(ref seen) => seen.lock().remove((project_id, event_id)),
+            #[cfg(feature = "processing")]
+            DeduplicatorInner::Redis(ref pool) => {
+                if let Err(err) = self.forget_redis(pool, project_id, event_id) {
+                    relay_log::error!(
+                        error = &err as &dyn Error,
+                        "failed to remove deduplicated event from redis"
+                    );
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "processing")]
+    fn redis_key(project_id: ProjectId, event_id: EventId) -> String {
+        format!("relay:dedupe:{project_id}:{event_id}")
+    }
+
+    #[cfg(feature = "processing")]
+    fn forget_redis(
+        &self,
+        pool: &RedisPool,
+        project_id: ProjectId,
+        event_id: EventId,
+    ) -> Result<(), RedisError> {
+        relay_redis::redis::cmd("DEL")
+            .arg(Self::redis_key(project_id, event_id))
+            .query(&mut pool.client()?.connection())
+            .map_err(RedisError::Redis)
+    }
+
+    #[cfg(feature = "processing")]
+    fn is_duplicate_redis(
+        &self,
+        pool: &RedisPool,
+        project_id: ProjectId,
+        event_id: EventId,
+    ) -> Result<bool, RedisError> {
+        let mut command = relay_redis::redis::cmd("SET");
+        command
+            .arg(Self::redis_key(project_id, event_id))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.window.as_secs().max(1));
+
+        let inserted: Option<String> = command
+            .query(&mut pool.client()?.connection())
+            .map_err(RedisError::Redis)?;
+
+        Ok(inserted.is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(project_id: u64, event_id: &str) -> EventKey {
+        (ProjectId::new(project_id), event_id.parse().unwrap())
+    }
+
+    #[test]
+    fn test_duplicate_within_window() {
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+        let mut seen = SeenEvents::new(10);
+
+        let event = key(42, "52df9022835246eeb317dbd739ccd059");
+        assert!(!seen.check_and_insert(event, window, now));
+        assert!(seen.check_and_insert(event, window, now + Duration::from_secs(30)));
+
+        // The same event ID in another project is not a duplicate.
+        let other = key(43, "52df9022835246eeb317dbd739ccd059");
+        assert!(!seen.check_and_insert(other, window, now + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_duplicate_after_window() {
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+        let mut seen = SeenEvents::new(10);
+
+        let event = key(42, "52df9022835246eeb317dbd739ccd059");
+        assert!(!seen.check_and_insert(event, window, now));
+        assert!(!seen.check_and_insert(event, window, now + Duration::from_secs(60)));
+        assert!(seen.check_and_insert(event, window, now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn test_max_entries() {
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+        let mut seen = SeenEvents::new(2);
+
+        let first = key(42, "52df9022835246eeb317dbd739ccd059");
+        let second = key(42, "52df9022835246eeb317dbd739ccd05a");
+        let third = key(42, "52df9022835246eeb317dbd739ccd05b");
+
+        assert!(!seen.check_and_insert(first, window, now));
+        assert!(!seen.check_and_insert(second, window, now));
+        assert!(!seen.check_and_insert(third, window, now));
+
+        // The oldest event ID has been evicted to make room.
+        assert_eq!(seen.order.len(), 2);
+        assert!(!seen.check_and_insert(first, window, now));
+        assert!(seen.check_and_insert(third, window, now));
+    }
+
+    #[test]
+    fn test_remove() {
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+        let mut seen = SeenEvents::new(2);
+
+        let first = key(42, "52df9022835246eeb317dbd739ccd059");
+        let second = key(42, "52df9022835246eeb317dbd739ccd05a");
+
+        assert!(!seen.check_and_insert(first, window, now));
+        seen.remove(first);
+        assert!(!seen.check_and_insert(first, window, now + Duration::from_secs(1)));
+
+        // Evicting the stale entry of the removed event keeps the event seen again.
+        assert!(!seen.check_and_insert(second, window, now + Duration::from_secs(2)));
+        assert!(seen.check_and_insert(first, window, now + Duration::from_secs(3)));
+    }
+}
@@ -1,19 +1,22 @@
 //! Envelope context type and helpers to ensure outcomes.
 
 use std::mem::size_of;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
-use relay_common::DataCategory;
+use relay_common::{DataCategory, ProjectId};
+use relay_general::protocol::EventId;
 use relay_quotas::Scoping;
 use relay_system::Addr;
 
 use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
-use crate::actors::test_store::{Capture, TestStore};
+use crate::actors::test_store::{
+    Capture, LifecycleRecord, LifecycleStage, RecordLifecycle, TestStore,
+};
 use crate::envelope::{Envelope, Item};
 use crate::extractors::RequestMeta;
 use crate::statsd::{RelayCounters, RelayTimers};
-use crate::utils::{EnvelopeSummary, SemaphorePermit};
+use crate::utils::{EnvelopeSummary, EventDeduplicator, SemaphorePermit};
 
 /// Denotes the success of handling an envelope.
 #[derive(Clone, Copy, Debug)]
@@ -55,11 +58,24 @@ pub enum ItemAction {
     DropSilently,
 }
 
+/// State of the lifecycle log of a [`ManagedEnvelope`].
+#[derive(Debug)]
+enum LifecycleLog {
+    /// The project config is not known yet, so records are kept until it is.
+    Pending(EventId, Vec<LifecycleRecord>),
+    /// Lifecycle debugging is enabled for the project, records are sent to the [`TestStore`].
+    Enabled(EventId, Duration),
+    /// Lifecycle debugging is disabled, or the envelope has no event ID.
+    Disabled,
+}
+
 #[derive(Debug)]
 struct EnvelopeContext {
     summary: EnvelopeSummary,
     scoping: Scoping,
     slot: Option<SemaphorePermit>,
+    lifecycle: LifecycleLog,
+    deduplicated: Option<(EventDeduplicator, ProjectId, EventId)>,
     done: bool,
 }
 
@@ -97,12 +113,24 @@ impl ManagedEnvelope {
         let meta = &envelope.meta();
         let summary = EnvelopeSummary::compute(envelope.as_ref());
         let scoping = meta.get_partial_scoping();
+        let lifecycle = match envelope.event_id() {
+            Some(event_id) => LifecycleLog::Pending(
+                event_id,
+                vec![LifecycleRecord {
+                    timestamp: relay_common::instant_to_date_time(meta.start_time()),
+                    stage: LifecycleStage::Received,
+                }],
+            ),
+            None => LifecycleLog::Disabled,
+        };
         Self {
             envelope,
             context: EnvelopeContext {
                 summary,
                 scoping,
                 slot,
+                lifecycle,
+                deduplicated: None,
                 done: false,
             },
             outcome_aggregator,
@@ -146,6 +174,36 @@ impl ManagedEnvelope {
         Self::new_internal(envelope, Some(slot), outcome_aggregator, test_store)
     }
 
+    /// Computes a managed envelope that is not bound to the processing queue yet.
+    ///
+    /// The envelope does not occupy capacity in the processing queue. Before it is queued, it has
+    /// to be bound with [`set_slot`](Self::set_slot).
+    pub fn unbound(
+        envelope: Box<Envelope>,
+        outcome_aggregator: Addr<TrackOutcome>,
+        test_store: Addr<TestStore>,
+    ) -> Self {
+        Self::new_internal(envelope, None, outcome_aggregator, test_store)
+    }
+
+    /// Returns `true` if this envelope holds a permit of the processing queue.
+    pub fn has_slot(&self) -> bool {
+        self.context.slot.is_some()
+    }
+
+    /// Binds this envelope to the processing queue with the given permit.
+    pub fn set_slot(&mut self, slot: SemaphorePermit) {
+        self.context.slot = Some(slot);
+    }
+
+    /// Takes the permit of the processing queue from this envelope.
+    ///
+    /// This allows to hand the capacity of an envelope that is about to be rejected to another
+    /// envelope, see [`set_slot`](Self::set_slot).
+    pub fn take_slot(&mut self) -> Option<SemaphorePermit> {
+        self.context.slot.take()
+    }
+
     /// Returns a reference to the contained [`Envelope`].
     pub fn envelope(&self) -> &Envelope {
         self.envelope.as_ref()
@@ -190,20 +248,31 @@ impl ManagedEnvelope {
         F: FnMut(&mut Item) -> ItemAction,
     {
         let mut outcomes = vec![];
+        let mut dropped = vec![];
         let use_indexed = self.use_index_category();
+        let records_lifecycle = self.records_lifecycle();
         self.envelope.retain_items(|item| match f(item) {
             ItemAction::Keep => true,
             ItemAction::Drop(outcome) => {
+                if records_lifecycle {
+                    dropped.push(LifecycleStage::ItemDropped {
+                        item: item.ty().to_string(),
+                        outcome: outcome.to_string(),
+                    });
+                }
                 if let Some(category) = item.outcome_category(use_indexed) {
-                    outcomes.push((outcome, category, item.quantity()));
+                    outcomes.push((outcome, category, item.quantity(), item.len()));
                 }
 
                 false
             }
             ItemAction::DropSilently => false,
         });
-        for (outcome, category, quantity) in outcomes {
-            self.track_outcome(outcome, category, quantity);
+        for stage in dropped {
+            self.record(stage);
+        }
+        for (outcome, category, quantity, bytes) in outcomes {
+            self.track_outcome(outcome, category, quantity, bytes);
         }
         // TODO: once `update` is private, it should be called here.
     }
@@ -224,11 +293,81 @@ impl ManagedEnvelope {
         self
     }
 
+    /// Enables or disables recording the lifecycle of this envelope.
+    ///
+    /// This should be called as soon as the project config is known. Until then, lifecycle records
+    /// are kept in the managed envelope. With `Some(ttl)`, these and all subsequent records are
+    /// sent to the [`TestStore`], otherwise they are discarded. Only the first call has an effect.
+    pub fn set_lifecycle_debug(&mut self, ttl: Option<Duration>) -> &mut Self {
+        let LifecycleLog::Pending(event_id, ref mut records) = self.context.lifecycle else {
+            return self;
+        };
+
+        match ttl {
+            Some(ttl) => {
+                self.test_store.send(RecordLifecycle {
+                    event_id,
+                    ttl,
+                    records: std::mem::take(records),
+                });
+                self.context.lifecycle = LifecycleLog::Enabled(event_id, ttl);
+            }
+            None => self.context.lifecycle = LifecycleLog::Disabled,
+        }
+
+        self
+    }
+
+    /// Remembers that the event of this envelope has been recorded by the deduplicator.
+    ///
+    /// If the envelope is rejected, the event is removed from the deduplicator again, so that
+    /// retries of the event are not dropped as duplicates. Use
+    /// [`forget_deduplicated`](Self::forget_deduplicated) if only the event is dropped.
+    pub fn set_deduplicated(&mut self, deduplicator: EventDeduplicator, project_id: ProjectId) {
+        if let Some(event_id) = self.envelope.event_id() {
+            self.context.deduplicated = Some((deduplicator, project_id, event_id));
+        }
+    }
+
+    /// Removes the event of this envelope from the deduplicator, if it has been recorded.
+    pub fn forget_deduplicated(&mut self) {
+        if let Some((deduplicator, project_id, event_id)) = self.context.deduplicated.take() {
+            deduplicator.forget(project_id, event_id);
+        }
+    }
+
+    /// Returns `true` if the lifecycle of this envelope is or may be recorded.
+    fn records_lifecycle(&self) -> bool {
+        !matches!(self.context.lifecycle, LifecycleLog::Disabled)
+    }
+
+    /// Records that the envelope has reached a stage of its lifecycle.
+    ///
+    /// This has no effect unless lifecycle debugging is enabled for the project, see
+    /// [`set_lifecycle_debug`](Self::set_lifecycle_debug).
+    pub fn record(&mut self, stage: LifecycleStage) {
+        match self.context.lifecycle {
+            LifecycleLog::Pending(_, ref mut records) => records.push(LifecycleRecord::now(stage)),
+            LifecycleLog::Enabled(event_id, ttl) => self.test_store.send(RecordLifecycle {
+                event_id,
+                ttl,
+                records: vec![LifecycleRecord::now(stage)],
+            }),
+            LifecycleLog::Disabled => (),
+        }
+    }
+
     /// Records an outcome scoped to this envelope's context.
     ///
     /// This managed envelope should be updated using [`update`](Self::update) soon after this
     /// operation to ensure that subsequent outcomes are consistent.
-    fn track_outcome(&self, outcome: Outcome, category: DataCategory, quantity: usize) {
+    fn track_outcome(
+        &self,
+        outcome: Outcome,
+        category: DataCategory,
+        quantity: usize,
+        bytes: usize,
+    ) {
         self.outcome_aggregator.send(TrackOutcome {
             timestamp: self.received_at(),
             scoping: self.context.scoping,
@@ -239,6 +378,7 @@ impl ManagedEnvelope {
             // Quantities are usually `usize` which lets us go all the way to 64-bit on our
             // machines, but the protocol and data store can only do 32-bit.
             quantity: quantity as u32,
+            bytes: bytes as u32,
         });
     }
 
@@ -285,6 +425,8 @@ impl ManagedEnvelope {
             return;
         }
 
+        self.forget_deduplicated();
+
         // Errors are only logged for what we consider failed request handling. In other cases, we
         // "expect" errors and log them as debug level.
         let handling = Handling::from_outcome(&outcome);
@@ -307,31 +449,41 @@ impl ManagedEnvelope {
             }
         }
 
+        if self.records_lifecycle() {
+            self.record(LifecycleStage::Rejected {
+                outcome: outcome.to_string(),
+            });
+        }
+
         // TODO: This could be optimized with Capture::should_capture
         self.test_store
             .send(Capture::rejected(self.envelope.event_id(), &outcome));
 
+        let summary = &self.context.summary;
+
         if let Some(category) = self.event_category() {
-            self.track_outcome(outcome.clone(), category, 1);
+            self.track_outcome(outcome.clone(), category, 1, summary.event_bytes);
         }
 
-        if self.context.summary.attachment_quantity > 0 {
+        if summary.attachment_quantity > 0 {
             self.track_outcome(
                 outcome.clone(),
                 DataCategory::Attachment,
-                self.context.summary.attachment_quantity,
+                summary.attachment_quantity,
+                summary.attachment_quantity,
             );
         }
 
-        if self.context.summary.profile_quantity > 0 {
+        if summary.profile_quantity > 0 {
             self.track_outcome(
                 outcome,
-                if self.context.summary.profile_counted_as_processed {
+                if summary.profile_counted_as_processed {
                     DataCategory::ProfileIndexed
                 } else {
                     DataCategory::Profile
                 },
-                self.context.summary.profile_quantity,
+                summary.profile_quantity,
+                summary.profile_bytes,
             );
         }
 
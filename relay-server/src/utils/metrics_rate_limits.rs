@@ -2,7 +2,7 @@
 use chrono::Utc;
 use relay_common::{DataCategory, UnixTimestamp};
 use relay_metrics::{MetricNamespace, MetricResourceIdentifier, MetricsContainer};
-use relay_quotas::{ItemScoping, Quota, RateLimits, Scoping};
+use relay_quotas::{Quota, RateLimits, Scoping};
 use relay_system::Addr;
 
 use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
@@ -120,6 +120,7 @@ impl<M: MetricsContainer, Q: AsRef<Vec<Quota>>> MetricsLimiter<M, Q> {
                 remote_addr: None,
                 category: DataCategory::Transaction,
                 quantity: self.transaction_count as u32,
+                bytes: 0,
             });
         }
     }
@@ -139,10 +140,7 @@ impl<M: MetricsContainer, Q: AsRef<Vec<Quota>>> MetricsLimiter<M, Q> {
         let mut dropped_stuff = false;
         match rate_limits {
             Ok(rate_limits) => {
-                let item_scoping = ItemScoping {
-                    category: DataCategory::Transaction,
-                    scoping: &self.scoping,
-                };
+                let item_scoping = self.scoping.item(DataCategory::Transaction);
                 let active_rate_limits =
                     rate_limits.check_with_quotas(self.quotas.as_ref(), item_scoping);
 
@@ -1,5 +1,6 @@
 mod api;
 mod buffer;
+mod dedupe;
 mod dynamic_sampling;
 mod garbage;
 mod managed_envelope;
@@ -11,6 +12,7 @@ mod retry;
 mod semaphore;
 mod sizes;
 mod sleep_handle;
+mod sourcemaps;
 mod statsd;
 
 #[cfg(feature = "processing")]
@@ -20,6 +22,7 @@ mod unreal;
 
 pub use self::api::*;
 pub use self::buffer::*;
+pub use self::dedupe::*;
 pub use self::dynamic_sampling::*;
 pub use self::garbage::*;
 pub use self::managed_envelope::*;
@@ -33,6 +36,7 @@ pub use self::retry::*;
 pub use self::semaphore::*;
 pub use self::sizes::*;
 pub use self::sleep_handle::*;
+pub use self::sourcemaps::*;
 pub use self::statsd::*;
 #[cfg(feature = "processing")]
 pub use self::unreal::*;
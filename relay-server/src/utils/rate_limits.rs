@@ -2,11 +2,14 @@ use std::fmt::{self, Write};
 
 use relay_common::DataCategory;
 use relay_dynamic_config::{ErrorBoundary, ProjectConfig};
+use relay_general::protocol::Event;
 use relay_quotas::{
-    DataCategories, ItemScoping, QuotaScope, RateLimit, RateLimitScope, RateLimits, ReasonCode,
-    Scoping,
+    DataCategories, ItemScoping, Quantity, QuotaScope, RateLimit, RateLimitScope, RateLimits,
+    ReasonCode, ScopeValues, Scoping,
 };
+use relay_sampling::DynamicSamplingContext;
 use relay_system::Addr;
+use url::form_urlencoded;
 
 use crate::actors::outcome::{Outcome, TrackOutcome};
 use crate::envelope::{Envelope, Item, ItemType};
@@ -34,8 +37,18 @@ pub fn format_rate_limits(rate_limits: &RateLimits) -> String {
 
         write!(header, ":{}", rate_limit.scope.name()).ok();
 
+        let scope_value = rate_limit.scope.value();
         if let Some(ref reason_code) = rate_limit.reason_code {
             write!(header, ":{reason_code}").ok();
+        } else if scope_value.is_some() {
+            header.push(':');
+        }
+
+        // Release, environment and user scopes cannot be restored from the scoping. Append their
+        // value, percent-encoded to escape the separators of this header.
+        if let Some(value) = scope_value {
+            header.push(':');
+            header.extend(form_urlencoded::byte_serialize(value.as_bytes()));
         }
     }
 
@@ -43,6 +56,9 @@ pub fn format_rate_limits(rate_limits: &RateLimits) -> String {
 }
 
 /// Parses the `X-Sentry-Rate-Limits` header.
+///
+/// Rate limits for release, environment and user scopes without a scope value are skipped, since
+/// they cannot be applied without widening them to a broader scope.
 pub fn parse_rate_limits(scoping: &Scoping, string: &str) -> RateLimits {
     let mut rate_limits = RateLimits::new();
 
@@ -67,9 +83,17 @@ pub fn parse_rate_limits(scoping: &Scoping, string: &str) -> RateLimits {
         }
 
         let quota_scope = QuotaScope::from_name(components.next().unwrap_or(""));
-        let scope = RateLimitScope::for_quota(scoping, quota_scope);
 
-        let reason_code = components.next().map(ReasonCode::new);
+        let reason_code = components
+            .next()
+            .filter(|s| !s.is_empty())
+            .map(ReasonCode::new);
+
+        let scope_value = components.next().and_then(decode_scope_value);
+        let scope = match RateLimitScope::for_value(scoping, quota_scope, scope_value.as_deref()) {
+            Some(scope) => scope,
+            None => continue,
+        };
 
         rate_limits.add(RateLimit {
             categories,
@@ -82,6 +106,44 @@ pub fn parse_rate_limits(scoping: &Scoping, string: &str) -> RateLimits {
     rate_limits
 }
 
+/// Decodes a percent-encoded scope value from the `X-Sentry-Rate-Limits` header.
+fn decode_scope_value(encoded: &str) -> Option<String> {
+    // The encoded value contains no `&` or `=`, so it parses as a single key.
+    form_urlencoded::parse(encoded.as_bytes())
+        .next()
+        .map(|(value, _)| value.into_owned())
+}
+
+/// Returns values for release, environment and user quotas from the dynamic sampling context.
+fn dsc_scope_values(dsc: &DynamicSamplingContext) -> ScopeValues<'_> {
+    let user_id = dsc.user.user_id.as_str();
+
+    ScopeValues {
+        release: dsc.release.as_deref(),
+        environment: dsc.environment.as_deref(),
+        user: (!user_id.is_empty()).then(|| ScopeValues::hash_user(user_id)),
+    }
+}
+
+/// Returns values for release, environment and user quotas from the event.
+///
+/// The user is identified by the first available of its id, username, email and IP address.
+pub fn event_scope_values(event: &Event) -> ScopeValues<'_> {
+    let user = event.user.value().and_then(|user| {
+        user.id
+            .as_str()
+            .or_else(|| user.username.as_str())
+            .or_else(|| user.email.as_str())
+            .or_else(|| user.ip_address.as_str())
+    });
+
+    ScopeValues {
+        release: event.release.as_str(),
+        environment: event.environment.as_str(),
+        user: user.map(ScopeValues::hash_user),
+    }
+}
+
 /// Infer the data category from an item.
 ///
 /// Categories depend mostly on the item type, with a few special cases:
@@ -116,27 +178,48 @@ fn infer_event_category(item: &Item) -> Option<DataCategory> {
 ///
 /// Summarizes the contained event, size of attachments, session updates, and whether there are
 /// plain attachments. This is used for efficient rate limiting or outcome handling.
+///
+/// For every data category, the summary counts both the number of items and their size in bytes,
+/// so that quotas can be enforced in either unit.
 #[non_exhaustive]
 #[derive(Clone, Copy, Debug, Default)]
 pub struct EnvelopeSummary {
     /// The data category of the event in the envelope. `None` if there is no event.
     pub event_category: Option<DataCategory>,
 
+    /// The size of the event payload in bytes.
+    pub event_bytes: usize,
+
     /// The quantity of all attachments combined in bytes.
     pub attachment_quantity: usize,
 
+    /// The number of attachments.
+    pub attachment_count: usize,
+
     /// The number of all session updates.
     pub session_quantity: usize,
 
+    /// The size of all session updates in bytes.
+    pub session_bytes: usize,
+
     /// The number of profiles.
     pub profile_quantity: usize,
 
+    /// The size of all profiles in bytes.
+    pub profile_bytes: usize,
+
     /// The number of replays.
     pub replay_quantity: usize,
 
+    /// The size of all replay events and recordings in bytes.
+    pub replay_bytes: usize,
+
     /// The number of monitor check-ins.
     pub checkin_quantity: usize,
 
+    /// The size of all monitor check-ins in bytes.
+    pub checkin_bytes: usize,
+
     /// Indicates that the envelope contains regular attachments that do not create event payloads.
     pub has_plain_attachments: bool,
 
@@ -192,16 +275,36 @@ impl EnvelopeSummary {
     }
 
     fn set_quantity(&mut self, item: &Item) {
-        let target_quantity = match item.ty() {
-            ItemType::Attachment => &mut self.attachment_quantity,
-            ItemType::Session => &mut self.session_quantity,
-            ItemType::Profile => &mut self.profile_quantity,
-            ItemType::ReplayEvent => &mut self.replay_quantity,
-            ItemType::ReplayRecording => &mut self.replay_quantity,
-            ItemType::CheckIn => &mut self.checkin_quantity,
-            _ => return,
-        };
-        *target_quantity += item.quantity();
+        let bytes = item.len();
+
+        match item.ty() {
+            ItemType::Event
+            | ItemType::Transaction
+            | ItemType::Security
+            | ItemType::RawSecurity
+            | ItemType::UnrealReport => self.event_bytes += bytes,
+            ItemType::Attachment => {
+                self.attachment_quantity += item.quantity();
+                self.attachment_count += 1;
+            }
+            ItemType::Session => {
+                self.session_quantity += item.quantity();
+                self.session_bytes += bytes;
+            }
+            ItemType::Profile => {
+                self.profile_quantity += item.quantity();
+                self.profile_bytes += bytes;
+            }
+            ItemType::ReplayEvent | ItemType::ReplayRecording => {
+                self.replay_quantity += item.quantity();
+                self.replay_bytes += bytes;
+            }
+            ItemType::CheckIn => {
+                self.checkin_quantity += item.quantity();
+                self.checkin_bytes += bytes;
+            }
+            _ => (),
+        }
     }
 
     /// Infers the appropriate [`DataCategory`] for the envelope [`Item`].
@@ -226,6 +329,8 @@ struct CategoryLimit {
     ///
     /// This will be `0` if nothing was rate limited.
     quantity: usize,
+    /// The total size of rate limited items in bytes.
+    bytes: usize,
     /// The reason code of the applied rate limit.
     ///
     /// Defaults to `None` if the quota does not declare a reason code.
@@ -236,11 +341,17 @@ impl CategoryLimit {
     /// Creates a new `CategoryLimit`.
     ///
     /// Returns an inactive limit if `quantity` is `0` or `rate_limit` is `None`.
-    fn new(category: DataCategory, quantity: usize, rate_limit: Option<&RateLimit>) -> Self {
+    fn new(
+        category: DataCategory,
+        quantity: usize,
+        bytes: usize,
+        rate_limit: Option<&RateLimit>,
+    ) -> Self {
         match rate_limit {
             Some(limit) => Self {
                 category,
                 quantity,
+                bytes,
                 reason_code: limit.reason_code.clone(),
             },
             None => Self::default(),
@@ -261,6 +372,7 @@ impl Default for CategoryLimit {
         Self {
             category: DataCategory::Default,
             quantity: 0,
+            bytes: 0,
             reason_code: None,
         }
     }
@@ -287,7 +399,6 @@ pub struct Enforcement {
 
 impl Enforcement {
     /// Returns `true` if the event should be rate limited.
-    #[cfg(feature = "processing")]
     pub fn event_active(&self) -> bool {
         self.event.is_active()
     }
@@ -335,6 +446,7 @@ impl Enforcement {
                 // XXX: on the limiter we have quantity of usize, but in the protocol
                 // and data store we're limited to u32.
                 quantity: limit.quantity as u32,
+                bytes: limit.bytes as u32,
             })
     }
 
@@ -367,18 +479,20 @@ impl Enforcement {
 pub struct EnvelopeLimiter<'a, F> {
     check: F,
     event_category: Option<(DataCategory, bool)>,
+    scope_values: Option<ScopeValues<'a>>,
     config: Option<&'a ProjectConfig>,
 }
 
 impl<'a, E, F> EnvelopeLimiter<'a, F>
 where
-    F: FnMut(ItemScoping<'_>, usize) -> Result<RateLimits, E>,
+    F: FnMut(ItemScoping<'_>, Quantity) -> Result<RateLimits, E>,
 {
     /// Create a new `EnvelopeLimiter` with the given `check` function.
     pub fn new(config: Option<&'a ProjectConfig>, check: F) -> Self {
         Self {
             check,
             event_category: None,
+            scope_values: None,
             config,
         }
     }
@@ -388,11 +502,17 @@ where
     /// This ensures that rate limits for the given data category are checked even if there is no
     /// matching item in the envelope. Other items are handled according to the rules as if the
     /// event item were present.
-    #[cfg(feature = "processing")]
     pub fn assume_event(&mut self, category: DataCategory, metrics_extracted: bool) {
         self.event_category = Some((category, metrics_extracted));
     }
 
+    /// Use the given values for release, environment and user quotas.
+    ///
+    /// By default, these values are taken from the dynamic sampling context of the envelope.
+    pub fn scope_values(&mut self, values: ScopeValues<'a>) {
+        self.scope_values = Some(values);
+    }
+
     /// Process rate limits for the envelope, removing offending items and returning applied limits.
     ///
     /// Returns a tuple of `Enforcement` and `RateLimits`:
@@ -447,7 +567,12 @@ where
             summary.event_metrics_extracted = metrics_extracted;
         }
 
-        let (enforcement, rate_limits) = self.execute(&summary, scoping)?;
+        let values = match self.scope_values {
+            Some(values) => values,
+            None => envelope.dsc().map(dsc_scope_values).unwrap_or_default(),
+        };
+
+        let (enforcement, rate_limits) = self.execute(&summary, scoping, values)?;
         envelope.retain_items(|item| self.retain_item(item, &enforcement));
         Ok((enforcement, rate_limits))
     }
@@ -476,7 +601,9 @@ where
         &mut self,
         summary: &EnvelopeSummary,
         scoping: &Scoping,
+        values: ScopeValues<'_>,
     ) -> Result<(Enforcement, RateLimits), E> {
+        let scope_item = |category| scoping.item(category).with_values(values);
         let mut rate_limits = RateLimits::new();
         let mut enforcement = Enforcement::default();
 
@@ -487,33 +614,37 @@ where
             if let Some(index_category) = self.index_category(category) {
                 // Check for rate limits on the main category (e.g. transaction) but do not consume
                 // quota. Quota will be consumed by metrics in the metrics aggregator instead.
-                event_limits = (self.check)(scoping.item(category), 0)?;
+                event_limits = (self.check)(scope_item(category), Quantity::default())?;
                 longest = event_limits.longest();
 
                 // Only enforce and record an outcome if metrics haven't been extracted yet.
                 // Otherwise, the outcome is logged at a different place.
                 if !summary.event_metrics_extracted {
-                    enforcement.event_metrics = CategoryLimit::new(category, 1, longest);
+                    enforcement.event_metrics = CategoryLimit::new(category, 1, 0, longest);
                 }
 
                 // If the main category is rate limited, we drop both the event and metrics. If
                 // there's no rate limit, check for specific indexing quota and drop just the event.
                 if summary.event_metrics_extracted && longest.is_none() {
-                    event_limits = (self.check)(scoping.item(index_category), 1)?;
+                    let quantity = Quantity::new(1, summary.event_bytes);
+                    event_limits = (self.check)(scope_item(index_category), quantity)?;
                     longest = event_limits.longest();
                 }
 
-                enforcement.event = CategoryLimit::new(index_category, 1, longest);
+                enforcement.event =
+                    CategoryLimit::new(index_category, 1, summary.event_bytes, longest);
             } else {
-                event_limits = (self.check)(scoping.item(category), 1)?;
+                let quantity = Quantity::new(1, summary.event_bytes);
+                event_limits = (self.check)(scope_item(category), quantity)?;
                 longest = event_limits.longest();
-                enforcement.event = CategoryLimit::new(category, 1, longest);
+                enforcement.event = CategoryLimit::new(category, 1, summary.event_bytes, longest);
             }
 
             // Record the same reason for attachments, if there are any.
             enforcement.attachments = CategoryLimit::new(
                 DataCategory::Attachment,
                 summary.attachment_quantity,
+                summary.attachment_quantity,
                 longest,
             );
 
@@ -524,18 +655,26 @@ where
             } else {
                 DataCategory::Profile
             };
-            enforcement.profiles =
-                CategoryLimit::new(profile_category, summary.profile_quantity, longest);
+            enforcement.profiles = CategoryLimit::new(
+                profile_category,
+                summary.profile_quantity,
+                summary.profile_bytes,
+                longest,
+            );
 
             rate_limits.merge(event_limits);
         }
 
         if !enforcement.event.is_active() && summary.attachment_quantity > 0 {
-            let item_scoping = scoping.item(DataCategory::Attachment);
-            let attachment_limits = (self.check)(item_scoping, summary.attachment_quantity)?;
+            // Attachments are natively counted in bytes, so the number of items is passed along for
+            // quotas with the `items` unit.
+            let item_scoping = scope_item(DataCategory::Attachment);
+            let quantity = Quantity::new(summary.attachment_count, summary.attachment_quantity);
+            let attachment_limits = (self.check)(item_scoping, quantity)?;
             enforcement.attachments = CategoryLimit::new(
                 DataCategory::Attachment,
                 summary.attachment_quantity,
+                summary.attachment_quantity,
                 attachment_limits.longest(),
             );
 
@@ -548,19 +687,22 @@ where
         }
 
         if summary.session_quantity > 0 {
-            let item_scoping = scoping.item(DataCategory::Session);
-            let session_limits = (self.check)(item_scoping, summary.session_quantity)?;
+            let item_scoping = scope_item(DataCategory::Session);
+            let quantity = Quantity::new(summary.session_quantity, summary.session_bytes);
+            let session_limits = (self.check)(item_scoping, quantity)?;
             enforcement.sessions = CategoryLimit::new(
                 DataCategory::Session,
                 summary.session_quantity,
+                summary.session_bytes,
                 session_limits.longest(),
             );
             rate_limits.merge(session_limits);
         }
 
         if !enforcement.event.is_active() && summary.profile_quantity > 0 {
-            let item_scoping = scoping.item(DataCategory::Profile);
-            let profile_limits = (self.check)(item_scoping, summary.profile_quantity)?;
+            let item_scoping = scope_item(DataCategory::Profile);
+            let quantity = Quantity::new(summary.profile_quantity, summary.profile_bytes);
+            let profile_limits = (self.check)(item_scoping, quantity)?;
             enforcement.profiles = CategoryLimit::new(
                 if summary.profile_counted_as_processed {
                     DataCategory::ProfileIndexed
@@ -568,28 +710,33 @@ where
                     DataCategory::Profile
                 },
                 summary.profile_quantity,
+                summary.profile_bytes,
                 profile_limits.longest(),
             );
             rate_limits.merge(profile_limits);
         }
 
         if summary.replay_quantity > 0 {
-            let item_scoping = scoping.item(DataCategory::Replay);
-            let replay_limits = (self.check)(item_scoping, summary.replay_quantity)?;
+            let item_scoping = scope_item(DataCategory::Replay);
+            let quantity = Quantity::new(summary.replay_quantity, summary.replay_bytes);
+            let replay_limits = (self.check)(item_scoping, quantity)?;
             enforcement.replays = CategoryLimit::new(
                 DataCategory::Replay,
                 summary.replay_quantity,
+                summary.replay_bytes,
                 replay_limits.longest(),
             );
             rate_limits.merge(replay_limits);
         }
 
         if summary.checkin_quantity > 0 {
-            let item_scoping = scoping.item(DataCategory::Monitor);
-            let checkin_limits = (self.check)(item_scoping, summary.checkin_quantity)?;
+            let item_scoping = scope_item(DataCategory::Monitor);
+            let quantity = Quantity::new(summary.checkin_quantity, summary.checkin_bytes);
+            let checkin_limits = (self.check)(item_scoping, quantity)?;
             enforcement.check_ins = CategoryLimit::new(
                 DataCategory::Monitor,
                 summary.checkin_quantity,
+                summary.checkin_bytes,
                 checkin_limits.longest(),
             );
             rate_limits.merge(checkin_limits);
@@ -653,7 +800,8 @@ mod tests {
 
     use relay_common::{ProjectId, ProjectKey};
     use relay_dynamic_config::TransactionMetricsConfig;
-    use relay_quotas::{ItemScoping, RetryAfter};
+    use relay_general::types::Annotated;
+    use relay_quotas::{ItemScoping, Quota, QuotaUnit, RetryAfter};
     use smallvec::smallvec;
 
     use super::*;
@@ -764,6 +912,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_format_rate_limits_scope_values() {
+        let mut rate_limits = RateLimits::new();
+
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Error],
+            scope: RateLimitScope::Release("backend@1.0.0: beta, 2".to_owned()),
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(42),
+        });
+
+        rate_limits.add(RateLimit {
+            categories: DataCategories::new(),
+            scope: RateLimitScope::User(4711),
+            reason_code: Some(ReasonCode::new("my_limit")),
+            retry_after: RetryAfter::from_secs(42),
+        });
+
+        let formatted = format_rate_limits(&rate_limits);
+        let expected = "42:error:release::backend%401.0.0%3A+beta%2C+2, 42::user:my_limit:4711";
+        assert_eq!(formatted, expected);
+    }
+
+    #[test]
+    fn test_parse_rate_limits_scope_values() {
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        };
+
+        // The environment limit has no value and must not be widened to the key.
+        let formatted = "42:error:release::backend%401.0.0%3A+beta%2C+2, 42::user:my_limit:4711, \
+                         42::environment:my_limit, 42::user::invalid";
+        let rate_limits: Vec<RateLimit> =
+            parse_rate_limits(&scoping, formatted).into_iter().collect();
+
+        assert_eq!(
+            rate_limits,
+            vec![
+                RateLimit {
+                    categories: smallvec![DataCategory::Error],
+                    scope: RateLimitScope::Release("backend@1.0.0: beta, 2".to_owned()),
+                    reason_code: None,
+                    retry_after: rate_limits[0].retry_after,
+                },
+                RateLimit {
+                    categories: DataCategories::new(),
+                    scope: RateLimitScope::User(4711),
+                    reason_code: Some(ReasonCode::new("my_limit")),
+                    retry_after: rate_limits[1].retry_after,
+                },
+            ]
+        );
+    }
+
     macro_rules! envelope {
         ($( $item_type:ident $( :: $attachment_type:ident )? ),*) => {{
             let bytes = "{\"dsn\":\"https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42\"}";
@@ -806,34 +1011,53 @@ mod tests {
 
     #[derive(Debug, Default)]
     struct MockLimiter {
-        denied: Vec<DataCategory>,
-        called: BTreeMap<DataCategory, usize>,
+        denied: Vec<(DataCategory, QuotaUnit)>,
+        called: BTreeMap<(DataCategory, QuotaUnit), usize>,
     }
 
     impl MockLimiter {
-        pub fn deny(mut self, category: DataCategory) -> Self {
-            self.denied.push(category);
+        /// Denies the category in its native unit.
+        pub fn deny(self, category: DataCategory) -> Self {
+            self.deny_unit(category, QuotaUnit::for_category(category))
+        }
+
+        pub fn deny_unit(mut self, category: DataCategory, unit: QuotaUnit) -> Self {
+            self.denied.push((category, unit));
             self
         }
 
         pub fn check(
             &mut self,
             scoping: ItemScoping<'_>,
-            quantity: usize,
+            quantity: Quantity,
         ) -> Result<RateLimits, ()> {
-            let cat = scoping.category;
-            let previous = self.called.insert(cat, quantity);
-            assert!(previous.is_none(), "rate limiter invoked twice for {cat}");
-
             let mut limits = RateLimits::new();
-            if self.denied.contains(&cat) {
-                limits.add(rate_limit(cat));
+
+            for unit in [QuotaUnit::Items, QuotaUnit::Bytes] {
+                let key = (scoping.category, unit);
+                let previous = self.called.insert(key, quantity.get(unit));
+                assert!(previous.is_none(), "rate limiter invoked twice for {key:?}");
+
+                if self.denied.contains(&key) {
+                    limits.add(rate_limit(scoping.category));
+                }
             }
+
             Ok(limits)
         }
 
+        /// Asserts the quantity checked in the native unit of the category.
         pub fn assert_call(&self, category: DataCategory, quantity: Option<usize>) {
-            assert_eq!(self.called.get(&category), quantity.as_ref());
+            self.assert_call_unit(category, QuotaUnit::for_category(category), quantity);
+        }
+
+        pub fn assert_call_unit(
+            &self,
+            category: DataCategory,
+            unit: QuotaUnit,
+            quantity: Option<usize>,
+        ) {
+            assert_eq!(self.called.get(&(category, unit)), quantity.as_ref());
         }
     }
 
@@ -938,7 +1162,13 @@ mod tests {
 
         assert!(limits.is_limited());
         assert_eq!(envelope.len(), 0);
-        assert_eq!(mock.called, BTreeMap::from([(DataCategory::Profile, 2)]));
+        assert_eq!(
+            mock.called,
+            BTreeMap::from([
+                ((DataCategory::Profile, QuotaUnit::Items), 2),
+                ((DataCategory::Profile, QuotaUnit::Bytes), 20),
+            ])
+        );
 
         let outcomes = enforcement
             .get_outcomes(&envelope, &scoping())
@@ -960,7 +1190,13 @@ mod tests {
 
         assert!(limits.is_limited());
         assert_eq!(envelope.len(), 0);
-        assert_eq!(mock.called, BTreeMap::from([(DataCategory::Replay, 2)]));
+        assert_eq!(
+            mock.called,
+            BTreeMap::from([
+                ((DataCategory::Replay, QuotaUnit::Items), 2),
+                ((DataCategory::Replay, QuotaUnit::Bytes), 20),
+            ])
+        );
 
         let outcomes = enforcement
             .get_outcomes(&envelope, &scoping())
@@ -969,6 +1205,51 @@ mod tests {
         assert_eq!(outcomes, vec![(DataCategory::Replay, 2),]);
     }
 
+    /// Limit replays by their size in bytes.
+    #[test]
+    fn test_enforce_limit_replay_bytes() {
+        let mut envelope = envelope![ReplayEvent, ReplayRecording];
+        let config = ProjectConfig::default();
+
+        let mut mock = MockLimiter::default().deny_unit(DataCategory::Replay, QuotaUnit::Bytes);
+        let (enforcement, limits) = EnvelopeLimiter::new(Some(&config), |s, q| mock.check(s, q))
+            .enforce(&mut envelope, &scoping())
+            .unwrap();
+
+        assert!(limits.is_limited());
+        assert_eq!(envelope.len(), 0);
+        mock.assert_call_unit(DataCategory::Replay, QuotaUnit::Bytes, Some(20));
+
+        let outcomes = enforcement
+            .get_outcomes(&envelope, &scoping())
+            .map(|outcome| (outcome.category, outcome.quantity, outcome.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes, vec![(DataCategory::Replay, 2, 20)]);
+    }
+
+    /// Limit attachments by their number of items.
+    #[test]
+    fn test_enforce_limit_attachment_items() {
+        let mut envelope = envelope![Attachment, Attachment];
+        let config = ProjectConfig::default();
+
+        let mut mock = MockLimiter::default().deny_unit(DataCategory::Attachment, QuotaUnit::Items);
+        let (enforcement, limits) = EnvelopeLimiter::new(Some(&config), |s, q| mock.check(s, q))
+            .enforce(&mut envelope, &scoping())
+            .unwrap();
+
+        assert!(limits.is_limited());
+        assert_eq!(envelope.len(), 0);
+        mock.assert_call(DataCategory::Attachment, Some(20));
+        mock.assert_call_unit(DataCategory::Attachment, QuotaUnit::Items, Some(2));
+
+        let outcomes = enforcement
+            .get_outcomes(&envelope, &scoping())
+            .map(|outcome| (outcome.category, outcome.quantity, outcome.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes, vec![(DataCategory::Attachment, 20, 20)]);
+    }
+
     /// Limit monitor checkins.
     #[test]
     fn test_enforce_limit_monitor_checkins() {
@@ -982,7 +1263,13 @@ mod tests {
 
         assert!(limits.is_limited());
         assert_eq!(envelope.len(), 0);
-        assert_eq!(mock.called, BTreeMap::from([(DataCategory::Monitor, 1)]));
+        assert_eq!(
+            mock.called,
+            BTreeMap::from([
+                ((DataCategory::Monitor, QuotaUnit::Items), 1),
+                ((DataCategory::Monitor, QuotaUnit::Bytes), 10),
+            ])
+        );
 
         let outcomes = enforcement
             .get_outcomes(&envelope, &scoping())
@@ -1070,6 +1357,79 @@ mod tests {
         mock.assert_call(DataCategory::Session, Some(2));
     }
 
+    fn release_quota() -> Quota {
+        serde_json::from_value(serde_json::json!({
+            "scope": "release",
+            "scopeId": "backend@1.0.0",
+            "limit": 0,
+            "reasonCode": "noisy_release"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_enforce_limit_release_from_dsc() {
+        let bytes = r#"{"dsn":"https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42","trace":{"trace_id":"89143b0763095bd9c9955e8175d1fb23","public_key":"e12d836b15bb49d7bbf99e64295d995b","release":"backend@1.0.0"}}
+{"type":"event"}
+{}
+"#;
+        let mut envelope = Envelope::parse_bytes(bytes.into()).unwrap();
+        let config = ProjectConfig::default();
+        let quotas = [release_quota()];
+
+        let (enforcement, limits) = EnvelopeLimiter::new(Some(&config), |s, _| {
+            Ok::<_, ()>(RateLimits::new().check_with_quotas(&quotas, s))
+        })
+        .enforce(&mut envelope, &scoping())
+        .unwrap();
+
+        assert!(limits.is_limited());
+        assert!(envelope.is_empty());
+
+        let limit = limits.iter().next().unwrap();
+        assert_eq!(
+            limit.scope,
+            RateLimitScope::Release("backend@1.0.0".to_owned())
+        );
+
+        let outcomes: Vec<_> = enforcement
+            .get_outcomes(&envelope, &scoping())
+            .map(|outcome| outcome.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![Outcome::RateLimited(Some(ReasonCode::new("noisy_release")))]
+        );
+    }
+
+    #[test]
+    fn test_enforce_limit_release_from_event() {
+        let event = Annotated::<Event>::from_json(
+            r#"{"release": "backend@1.0.0", "user": {"email": "user@example.com"}}"#,
+        )
+        .unwrap();
+        let values = event_scope_values(event.value().unwrap());
+        assert_eq!(values.release, Some("backend@1.0.0"));
+        assert_eq!(
+            values.user,
+            Some(ScopeValues::hash_user("user@example.com"))
+        );
+
+        // The envelope does not carry a release, but the event does.
+        let mut envelope = envelope![Event];
+        let config = ProjectConfig::default();
+        let quotas = [release_quota()];
+
+        let mut limiter = EnvelopeLimiter::new(Some(&config), |s, _| {
+            Ok::<_, ()>(RateLimits::new().check_with_quotas(&quotas, s))
+        });
+        limiter.scope_values(values);
+        let (_, limits) = limiter.enforce(&mut envelope, &scoping()).unwrap();
+
+        assert!(limits.is_limited());
+        assert!(envelope.is_empty());
+    }
+
     #[test]
     #[cfg(feature = "processing")]
     fn test_enforce_limit_assumed_event() {
@@ -0,0 +1,623 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use lru::LruCache;
+use parking_lot::Mutex;
+use relay_general::protocol::{Event, Frame, FrameData, RawStacktrace};
+use relay_general::types::{Annotated, Meta, Remark, RemarkType};
+use sourcemap::DecodedMap;
+use url::Url;
+
+/// Number of lines of source context added before and after the resolved line.
+const CONTEXT_LINES: usize = 5;
+
+/// Maximum total size of the source map files held in a [`SourceMapCache`].
+const MAX_CACHE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Rule ID of the remark added to frames resolved with a source map.
+const SOURCE_MAP_REMARK: &str = "sourcemap";
+
+/// Original location of a generated line and column.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    /// Path or URL of the original source file.
+    pub source: &'a str,
+    /// Zero-based line in the original source file.
+    pub line: u32,
+    /// Zero-based column in the original source file.
+    pub column: u32,
+    /// Contents of the original source file, if embedded in the source map.
+    pub source_content: Option<&'a str>,
+}
+
+/// A parsed source map.
+///
+/// Index maps are flattened into a regular source map when parsed. Their sections must embed the
+/// source maps, sections referencing other source maps by URL are not supported.
+#[derive(Debug)]
+pub struct SourceMap {
+    inner: sourcemap::SourceMap,
+}
+
+impl SourceMap {
+    /// Parses a source map from its JSON representation.
+    pub fn parse(bytes: &[u8]) -> Result<Self, sourcemap::Error> {
+        let inner = match sourcemap::decode_slice(bytes)? {
+            DecodedMap::Regular(source_map) => source_map,
+            DecodedMap::Index(index) => index.flatten()?,
+            DecodedMap::Hermes(hermes) => sourcemap::SourceMap::clone(&hermes),
+        };
+
+        Ok(Self { inner })
+    }
+
+    /// Returns the original location of a zero-based line and column in the generated file.
+    pub fn lookup(&self, line: u32, column: u32) -> Option<SourceLocation<'_>> {
+        let token = self.inner.lookup_token(line, column)?;
+
+        // The closest token may be on a previous line, which does not map this position.
+        if token.get_dst_line() != line {
+            return None;
+        }
+
+        Some(SourceLocation {
+            source: token.get_source()?,
+            line: token.get_src_line(),
+            column: token.get_src_col(),
+            source_content: self.inner.get_source_contents(token.get_src_id()),
+        })
+    }
+}
+
+/// Returns `true` if the name can be used as a single directory name.
+fn is_path_component(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+/// A cached source map file.
+#[derive(Debug)]
+struct CacheEntry {
+    modified: Option<SystemTime>,
+    size: u64,
+    source_map: Option<Arc<SourceMap>>,
+}
+
+#[derive(Debug)]
+struct CacheInner {
+    entries: LruCache<PathBuf, CacheEntry>,
+    size: u64,
+}
+
+/// A process-wide cache of parsed source maps.
+///
+/// Source maps are keyed by their path and reloaded when the modification time of the file
+/// changes. The cache is bounded by the total size of the cached files and evicts the least
+/// recently used source maps first.
+#[derive(Debug)]
+pub struct SourceMapCache {
+    inner: Mutex<CacheInner>,
+    max_size: u64,
+}
+
+impl SourceMapCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::with_max_size(MAX_CACHE_SIZE)
+    }
+
+    fn with_max_size(max_size: u64) -> Self {
+        Self {
+            inner: Mutex::new(CacheInner {
+                entries: LruCache::unbounded(),
+                size: 0,
+            }),
+            max_size,
+        }
+    }
+
+    /// Returns the source map at the given path, loading it if it is not cached or has changed.
+    ///
+    /// Returns `None` if the file does not exist or is not a valid source map.
+    fn get(&self, path: &Path) -> Option<Arc<SourceMap>> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                relay_log::error!(
+                    error = &err as &dyn Error,
+                    "failed to read source map at {}",
+                    path.display()
+                );
+                return None;
+            }
+        };
+
+        let key = path.to_owned();
+        let modified = metadata.modified().ok();
+        if let Some(entry) = self.inner.lock().entries.get(&key) {
+            if entry.modified == modified {
+                return entry.source_map.clone();
+            }
+        }
+
+        // Load outside of the lock, so that other workers are not blocked by parsing.
+        let source_map = load_source_map(path).map(Arc::new);
+        let entry = CacheEntry {
+            modified,
+            size: metadata.len(),
+            source_map: source_map.clone(),
+        };
+
+        let mut inner = self.inner.lock();
+        if entry.size <= self.max_size {
+            inner.size += entry.size;
+            if let Some(previous) = inner.entries.put(key, entry) {
+                inner.size -= previous.size;
+            }
+        }
+
+        while inner.size > self.max_size {
+            match inner.entries.pop_lru() {
+                Some((_, evicted)) => inner.size -= evicted.size,
+                None => break,
+            }
+        }
+
+        source_map
+    }
+}
+
+impl Default for SourceMapCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads and parses the source map at the given path.
+fn load_source_map(path: &Path) -> Option<SourceMap> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            relay_log::error!(
+                error = &err as &dyn Error,
+                "failed to read source map at {}",
+                path.display()
+            );
+            return None;
+        }
+    };
+
+    match SourceMap::parse(&bytes) {
+        Ok(source_map) => Some(source_map),
+        Err(err) => {
+            relay_log::debug!(
+                error = &err as &dyn Error,
+                "invalid source map at {}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+/// Resolves minified JavaScript frames with source maps from a local artifact directory.
+///
+/// Source maps are loaded from `<path>/<release>/<dist>/<url path>.map` through a shared
+/// [`SourceMapCache`]. The resolver should be created per event.
+#[derive(Debug)]
+pub struct SourceMapResolver<'a> {
+    cache: &'a SourceMapCache,
+    directory: PathBuf,
+    source_maps: HashMap<PathBuf, Option<Arc<SourceMap>>>,
+}
+
+impl<'a> SourceMapResolver<'a> {
+    /// Creates a resolver for source maps of the given release and dist.
+    ///
+    /// Returns `None` if the release or dist cannot be used as a directory name.
+    pub fn new(
+        cache: &'a SourceMapCache,
+        path: &Path,
+        release: &str,
+        dist: Option<&str>,
+    ) -> Option<Self> {
+        if !is_path_component(release) {
+            return None;
+        }
+
+        let mut directory = path.join(release);
+        if let Some(dist) = dist {
+            if !is_path_component(dist) {
+                return None;
+            }
+            directory.push(dist);
+        }
+
+        Some(Self {
+            cache,
+            directory,
+            source_maps: HashMap::new(),
+        })
+    }
+
+    /// Returns the path of the source map for a minified file.
+    fn source_map_path(&self, abs_path: &str) -> Option<PathBuf> {
+        let url_path = match Url::parse(abs_path) {
+            Ok(url) => url.path().to_owned(),
+            Err(_) => abs_path.to_owned(),
+        };
+
+        let mut path = self.directory.clone();
+        for component in url_path.split('/') {
+            match component {
+                "" | "." => continue,
+                ".." => return None,
+                component => path.push(component),
+            }
+        }
+
+        if path == self.directory {
+            return None;
+        }
+
+        let mut path = path.into_os_string();
+        path.push(".map");
+        Some(path.into())
+    }
+
+    fn source_map(&mut self, abs_path: &str) -> Option<Arc<SourceMap>> {
+        let path = self.source_map_path(abs_path)?;
+        let cache = self.cache;
+        self.source_maps
+            .entry(path)
+            .or_insert_with_key(|path| cache.get(path))
+            .clone()
+    }
+
+    /// Rewrites the location of a frame and returns `true` if it was resolved.
+    fn resolve_frame(&mut self, frame: &mut Frame, meta: &mut Meta) -> bool {
+        let (line, column) = match (frame.lineno.value(), frame.colno.value()) {
+            (Some(&line), Some(&column)) if line > 0 && column > 0 => (line - 1, column - 1),
+            _ => return false,
+        };
+
+        let abs_path = match frame.abs_path.value().or_else(|| frame.filename.value()) {
+            Some(abs_path) => abs_path.as_str().to_owned(),
+            None => return false,
+        };
+
+        let source_map = match self.source_map(&abs_path) {
+            Some(source_map) => source_map,
+            None => return false,
+        };
+
+        let location = match (u32::try_from(line), u32::try_from(column)) {
+            (Ok(line), Ok(column)) => source_map.lookup(line, column),
+            _ => None,
+        };
+        let location = match location {
+            Some(location) => location,
+            None => return false,
+        };
+
+        // Record the minified location and the source map in the frame data, so that the frame
+        // is not resolved again upstream.
+        let data = frame.data.get_or_insert_with(FrameData::default);
+        data.sourcemap = Annotated::new(format!("{abs_path}.map"));
+        data.orig_filename = Annotated::new(abs_path.clone());
+        data.orig_lineno = Annotated::new(line + 1);
+        data.orig_colno = Annotated::new(column + 1);
+
+        frame.abs_path = Annotated::new(location.source.to_owned().into());
+        frame.filename = Annotated::new(location.source.to_owned().into());
+        frame.lineno = Annotated::new(u64::from(location.line) + 1);
+        frame.colno = Annotated::new(u64::from(location.column) + 1);
+
+        let lines: Vec<_> = location
+            .source_content
+            .map(|content| content.lines().collect())
+            .unwrap_or_default();
+        let index = location.line as usize;
+        if let Some(context_line) = lines.get(index) {
+            let context = |lines: &[&str]| {
+                Annotated::new(
+                    lines
+                        .iter()
+                        .map(|line| Annotated::new((*line).to_owned()))
+                        .collect(),
+                )
+            };
+
+            frame.context_line = Annotated::new((*context_line).to_owned());
+            frame.pre_context = context(&lines[index.saturating_sub(CONTEXT_LINES)..index]);
+            frame.post_context =
+                context(&lines[index + 1..lines.len().min(index + 1 + CONTEXT_LINES)]);
+        }
+
+        meta.add_remark(Remark::new(RemarkType::Substituted, SOURCE_MAP_REMARK));
+        true
+    }
+
+    /// Resolves all frames of a stack trace and returns `true` if any frame was resolved.
+    fn resolve_stacktrace(&mut self, stacktrace: &mut RawStacktrace) -> bool {
+        let mut resolved = false;
+        let frames = stacktrace.frames.value_mut().iter_mut().flatten();
+        for Annotated(frame, meta) in frames {
+            if let Some(frame) = frame {
+                resolved |= self.resolve_frame(frame, meta);
+            }
+        }
+        resolved
+    }
+
+    /// Resolves the frames of all exceptions and the stack trace of an event.
+    ///
+    /// The original stack trace of an exception is kept in its `raw_stacktrace`.
+    pub fn resolve_event(&mut self, event: &mut Event) {
+        let exceptions = event
+            .exceptions
+            .value_mut()
+            .as_mut()
+            .and_then(|exceptions| exceptions.values.value_mut().as_mut());
+
+        for exception in exceptions.into_iter().flatten() {
+            let exception = match exception.value_mut() {
+                Some(exception) => exception,
+                None => continue,
+            };
+
+            let stacktrace = match exception.stacktrace.value_mut() {
+                Some(stacktrace) => stacktrace,
+                None => continue,
+            };
+
+            let raw_stacktrace = stacktrace.0.clone();
+            if self.resolve_stacktrace(stacktrace) && exception.raw_stacktrace.value().is_none() {
+                exception.raw_stacktrace = Annotated::new(raw_stacktrace);
+            }
+        }
+
+        if let Some(stacktrace) = event.stacktrace.value_mut() {
+            self.resolve_stacktrace(stacktrace);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_general::types::SerializableAnnotated;
+    use similar_asserts::assert_eq;
+
+    use super::*;
+
+    /// Maps column 0 of the generated file to `src/app.js` and column 9 to the `throwError`
+    /// function on the second line.
+    const SOURCE_MAP: &str = r#"{
+        "version": 3,
+        "file": "app.min.js",
+        "sourceRoot": "webpack:///",
+        "sources": ["src/app.js"],
+        "sourcesContent": ["// app\nfunction throwError() {\n  throw new Error('x');\n}\n"],
+        "names": ["throwError"],
+        "mappings": "AAAA,SACSA"
+    }"#;
+
+    #[test]
+    fn test_lookup() {
+        let source_map = SourceMap::parse(SOURCE_MAP.as_bytes()).unwrap();
+
+        assert_eq!(
+            source_map.lookup(0, 15),
+            Some(SourceLocation {
+                source: "webpack:///src/app.js",
+                line: 1,
+                column: 9,
+                source_content: Some(
+                    "// app\nfunction throwError() {\n  throw new Error('x');\n}\n"
+                ),
+            })
+        );
+        assert_eq!(source_map.lookup(0, 3).unwrap().line, 0);
+        assert_eq!(source_map.lookup(1, 0), None);
+    }
+
+    #[test]
+    fn test_lookup_index_map() {
+        let index_map = format!(
+            r#"{{
+                "version": 3,
+                "file": "bundle.min.js",
+                "sections": [
+                    {{"offset": {{"line": 0, "column": 0}}, "map": {{
+                        "version": 3,
+                        "sources": ["vendor.js"],
+                        "names": [],
+                        "mappings": "AAAA"
+                    }}}},
+                    {{"offset": {{"line": 1, "column": 0}}, "map": {SOURCE_MAP}}}
+                ]
+            }}"#
+        );
+
+        let source_map = SourceMap::parse(index_map.as_bytes()).unwrap();
+        assert_eq!(source_map.lookup(0, 3).unwrap().source, "vendor.js");
+
+        let location = source_map.lookup(1, 15).unwrap();
+        assert_eq!(location.source, "webpack:///src/app.js");
+        assert_eq!((location.line, location.column), (1, 9));
+        assert!(location.source_content.is_some());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(SourceMap::parse(b"{").is_err());
+        assert!(SourceMap::parse(br#"{"version": 3, "sources": [], "mappings": "AA"}"#).is_err());
+
+        // Index maps cannot be flattened if sections reference source maps by URL.
+        let index_map = r#"{
+            "version": 3,
+            "sections": [{"offset": {"line": 0, "column": 0}, "url": "app.min.js.map"}]
+        }"#;
+        assert!(SourceMap::parse(index_map.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_cache() {
+        let directory = tempfile::tempdir().unwrap();
+        let app = directory.path().join("app.min.js.map");
+        let vendor = directory.path().join("vendor.min.js.map");
+        fs::write(&app, SOURCE_MAP).unwrap();
+        fs::write(&vendor, SOURCE_MAP).unwrap();
+
+        let cache = SourceMapCache::with_max_size(SOURCE_MAP.len() as u64);
+        let source_map = cache.get(&app).unwrap();
+        assert!(Arc::ptr_eq(&source_map, &cache.get(&app).unwrap()));
+
+        // Loading another source map evicts the first one to stay within the size limit.
+        cache.get(&vendor).unwrap();
+        assert!(!Arc::ptr_eq(&source_map, &cache.get(&app).unwrap()));
+
+        fs::remove_file(&app).unwrap();
+        assert!(cache.get(&app).is_none());
+    }
+
+    #[test]
+    fn test_resolver_paths() {
+        let cache = SourceMapCache::new();
+        let path = Path::new("/artifacts");
+        assert!(SourceMapResolver::new(&cache, path, "../etc", None).is_none());
+        assert!(SourceMapResolver::new(&cache, path, "1.0", Some("a/b")).is_none());
+
+        let resolver = SourceMapResolver::new(&cache, path, "1.0", Some("web")).unwrap();
+        assert_eq!(
+            resolver.source_map_path("https://example.com/static/app.min.js?v=1"),
+            Some(PathBuf::from("/artifacts/1.0/web/static/app.min.js.map"))
+        );
+        assert_eq!(
+            resolver.source_map_path("app:///main.js"),
+            Some(PathBuf::from("/artifacts/1.0/web/main.js.map"))
+        );
+        assert_eq!(resolver.source_map_path("/static/../../secret.js"), None);
+    }
+
+    #[test]
+    fn test_resolve_event() {
+        let directory = tempfile::tempdir().unwrap();
+        let release_dir = directory.path().join("1.0").join("static");
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(release_dir.join("app.min.js.map"), SOURCE_MAP).unwrap();
+
+        let mut event = Annotated::<Event>::from_json(
+            r#"{
+                "exception": {
+                    "values": [{
+                        "type": "Error",
+                        "stacktrace": {
+                            "frames": [
+                                {
+                                    "abs_path": "https://example.com/static/vendor.min.js",
+                                    "function": "b",
+                                    "lineno": 1,
+                                    "colno": 10
+                                },
+                                {
+                                    "abs_path": "https://example.com/static/app.min.js",
+                                    "function": "a",
+                                    "lineno": 1,
+                                    "colno": 16
+                                }
+                            ]
+                        }
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let cache = SourceMapCache::new();
+        let mut resolver = SourceMapResolver::new(&cache, directory.path(), "1.0", None).unwrap();
+        resolver.resolve_event(event.value_mut().as_mut().unwrap());
+
+        insta::assert_json_snapshot!(SerializableAnnotated(&event.value().unwrap().exceptions), @r###"
+        {
+          "values": [
+            {
+              "type": "Error",
+              "stacktrace": {
+                "frames": [
+                  {
+                    "function": "b",
+                    "abs_path": "https://example.com/static/vendor.min.js",
+                    "lineno": 1,
+                    "colno": 10
+                  },
+                  {
+                    "function": "a",
+                    "filename": "webpack:///src/app.js",
+                    "abs_path": "webpack:///src/app.js",
+                    "lineno": 2,
+                    "colno": 10,
+                    "pre_context": [
+                      "// app"
+                    ],
+                    "context_line": "function throwError() {",
+                    "post_context": [
+                      "  throw new Error('x');",
+                      "}"
+                    ],
+                    "data": {
+                      "sourcemap": "https://example.com/static/app.min.js.map",
+                      "orig_filename": "https://example.com/static/app.min.js",
+                      "orig_lineno": 1,
+                      "orig_colno": 16
+                    }
+                  }
+                ]
+              },
+              "raw_stacktrace": {
+                "frames": [
+                  {
+                    "function": "b",
+                    "abs_path": "https://example.com/static/vendor.min.js",
+                    "lineno": 1,
+                    "colno": 10
+                  },
+                  {
+                    "function": "a",
+                    "abs_path": "https://example.com/static/app.min.js",
+                    "lineno": 1,
+                    "colno": 16
+                  }
+                ]
+              }
+            }
+          ],
+          "_meta": {
+            "values": {
+              "0": {
+                "stacktrace": {
+                  "frames": {
+                    "1": {
+                      "": {
+                        "rem": [
+                          [
+                            "sourcemap",
+                            "s"
+                          ]
+                        ]
+                      }
+                    }
+                  }
+                }
+              }
+            }
+          }
+        }
+        "###);
+    }
+}
@@ -5,6 +5,70 @@ use relay_general::types::{Annotated, RemarkType};
 use crate::metrics_extraction::transactions::extract_http_status_code;
 use crate::statsd::RelayCounters;
 
+/// Names of Sentry SDKs that are reported in metric tags.
+const SDK_NAMES: &[&str] = &[
+    "sentry.cocoa",
+    "sentry.dart",
+    "sentry.dart.flutter",
+    "sentry.dotnet",
+    "sentry.dotnet.unity",
+    "sentry.elixir",
+    "sentry.go",
+    "sentry.java",
+    "sentry.java.android",
+    "sentry.javascript.angular",
+    "sentry.javascript.browser",
+    "sentry.javascript.electron",
+    "sentry.javascript.nextjs",
+    "sentry.javascript.node",
+    "sentry.javascript.react",
+    "sentry.javascript.react-native",
+    "sentry.javascript.remix",
+    "sentry.javascript.svelte",
+    "sentry.javascript.sveltekit",
+    "sentry.javascript.vue",
+    "sentry.native",
+    "sentry.native.android",
+    "sentry.php",
+    "sentry.php.laravel",
+    "sentry.php.symfony",
+    "sentry.python",
+    "sentry.ruby",
+    "sentry.rust",
+];
+
+/// Returns the SDK name to use in metric tags.
+///
+/// SDK names are supplied by clients. To bound the cardinality of metrics, only names of known
+/// Sentry SDKs are returned, and all other SDKs are reported as `"proprietary"`.
+pub fn sdk_name_tag(name: Option<&str>) -> &'static str {
+    name.and_then(|name| SDK_NAMES.iter().find(|known| **known == name))
+        .copied()
+        .unwrap_or("proprietary")
+}
+
+/// Returns the SDK version to use in metric tags.
+///
+/// Versions are reduced to their major and minor components, such as `"7.54"` for `"7.54.1"`.
+/// Versions that do not start with numeric components are reported as `"unknown"`.
+pub fn sdk_version_tag(version: Option<&str>) -> String {
+    let mut components = version.unwrap_or_default().split('.').map(|component| {
+        let end = component
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(component.len());
+        &component[..end]
+    });
+
+    let major = components.next().unwrap_or_default();
+    let minor = components.next().unwrap_or_default();
+
+    match (major.len(), minor.len()) {
+        (1..=4, 1..=4) => format!("{major}.{minor}"),
+        (1..=4, _) => format!("{major}.0"),
+        _ => "unknown".to_owned(),
+    }
+}
+
 /// Log statsd metrics about transaction name modifications.
 ///
 /// We have to look at event & meta before and after the modification is made,
@@ -76,3 +140,27 @@ where
 
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sdk_name_tag() {
+        assert_eq!(sdk_name_tag(Some("sentry.python")), "sentry.python");
+        assert_eq!(sdk_name_tag(Some("sentry.python.random")), "proprietary");
+        assert_eq!(sdk_name_tag(None), "proprietary");
+    }
+
+    #[test]
+    fn test_sdk_version_tag() {
+        assert_eq!(sdk_version_tag(Some("7.54.1")), "7.54");
+        assert_eq!(sdk_version_tag(Some("1.2.3-beta.1")), "1.2");
+        assert_eq!(sdk_version_tag(Some("1.2-rc1")), "1.2");
+        assert_eq!(sdk_version_tag(Some("8")), "8.0");
+        assert_eq!(sdk_version_tag(Some("1.123456789")), "1.0");
+        assert_eq!(sdk_version_tag(Some("123456789.1")), "unknown");
+        assert_eq!(sdk_version_tag(Some("abc")), "unknown");
+        assert_eq!(sdk_version_tag(None), "unknown");
+    }
+}
@@ -1,5 +1,6 @@
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::{env, io};
+use std::{env, fs, io};
 
 use anyhow::{anyhow, bail, Result};
 use clap::ArgMatches;
@@ -9,6 +10,9 @@ use relay_common::Uuid;
 use relay_config::{
     Config, ConfigError, ConfigErrorKind, Credentials, MinimalConfig, OverridableConfig, RelayMode,
 };
+use relay_general::pii::PiiConfig;
+use relay_general::protocol::Event;
+use relay_general::types::Annotated;
 
 use crate::cliapp::make_app;
 use crate::utils::get_theme;
@@ -42,6 +46,8 @@ pub fn execute() -> Result<()> {
         if let Some(matches) = matches.subcommand_matches("init") {
             return init_config(config_path, matches);
         }
+    } else if let Some(matches) = matches.subcommand_matches("pii") {
+        return manage_pii(matches);
     } else if let Some(matches) = matches.subcommand_matches("generate-completions") {
         return generate_completions(matches);
     }
@@ -130,6 +136,27 @@ pub fn manage_credentials(mut config: Config, matches: &ArgMatches) -> Result<()
             println!("Generated new credentials");
             setup::dump_credentials(&config);
         }
+    } else if let Some(matches) = matches.subcommand_matches("rotate") {
+        let Some(mut credentials) = config.credentials().cloned() else {
+            bail!("no stored credentials");
+        };
+        if matches.get_flag("finish") {
+            if !credentials.finish_rotation() {
+                bail!("no key rotation in progress. Run `relay credentials rotate` first.");
+            }
+            config.replace_credentials(Some(credentials))?;
+            println!("Retired the old key pair");
+        } else {
+            if credentials.is_rotating() && !matches.get_flag("overwrite") {
+                bail!("aborting because a key rotation is in progress. Pass --overwrite to force.");
+            }
+            credentials.rotate();
+            config.replace_credentials(Some(credentials))?;
+            println!("Generated next key pair");
+            println!("Trust the next public key in all upstreams, then run:");
+            println!("  relay credentials rotate --finish");
+        }
+        setup::dump_credentials(&config);
     } else if let Some(matches) = matches.subcommand_matches("set") {
         let mut prompted = false;
         let secret_key = match matches.get_one::<String>("secret_key") {
@@ -186,6 +213,8 @@ pub fn manage_credentials(mut config: Config, matches: &ArgMatches) -> Result<()
                     }
                 }
             },
+            next_secret_key: config.credentials().and_then(|x| x.next_secret_key.clone()),
+            next_public_key: config.credentials().and_then(|x| x.next_public_key.clone()),
         }))?;
         if !changed {
             println!("Nothing was changed");
@@ -328,6 +357,39 @@ pub fn init_config<P: AsRef<Path>>(config_path: P, _matches: &ArgMatches) -> Res
     Ok(())
 }
 
+pub fn manage_pii(matches: &ArgMatches) -> Result<()> {
+    if let Some(matches) = matches.subcommand_matches("explain") {
+        explain_pii(matches)
+    } else {
+        unreachable!();
+    }
+}
+
+pub fn explain_pii(matches: &ArgMatches) -> Result<()> {
+    let config_path = matches.get_one::<PathBuf>("pii_config").unwrap();
+    let config_json =
+        fs::read_to_string(config_path).map_err(|e| anyhow!("failed to read PII config: {e}"))?;
+    let config = PiiConfig::from_json(&config_json)
+        .map_err(|e| anyhow!("failed to parse PII config: {e}"))?;
+    config.compiled().force_compile()?;
+
+    let event_path = matches.get_one::<PathBuf>("event").unwrap();
+    let event_json = if event_path.as_os_str() == "-" {
+        let mut buf = String::new();
+        io::stdin().read_to_string(&mut buf)?;
+        buf
+    } else {
+        fs::read_to_string(event_path).map_err(|e| anyhow!("failed to read event: {e}"))?
+    };
+    let event = Annotated::<Event>::from_json(&event_json)
+        .map_err(|e| anyhow!("failed to parse event: {e}"))?;
+
+    let explanations = relay_general::pii::explain_pii(&event, &config)?;
+    println!("{}", serde_json::to_string_pretty(&explanations)?);
+
+    Ok(())
+}
+
 pub fn generate_completions(matches: &ArgMatches) -> Result<()> {
     let shell = match matches.get_one::<Shell>("format") {
         Some(shell) => *shell,
@@ -183,6 +183,30 @@ pub fn make_app() -> Command {
                                 .help("Write credentials to stdout instead of credentials.json"),
                         ),
                 )
+                .subcommand(
+                    Command::new("rotate")
+                        .about("Rotate the key pair of the relay")
+                        .after_help(
+                            "This generates a next key pair and stores it along with the \
+                             current credentials.  While a rotation is in progress, the \
+                             relay signs requests with both keys, so that upstreams accept \
+                             either key.  Once the next public key is trusted by all \
+                             upstreams, pass '--finish' to retire the old key pair.",
+                        )
+                        .arg(
+                            Arg::new("finish")
+                                .long("finish")
+                                .action(ArgAction::SetTrue)
+                                .help("Retire the old key pair and switch to the next key pair"),
+                        )
+                        .arg(
+                            Arg::new("overwrite")
+                                .long("overwrite")
+                                .action(ArgAction::SetTrue)
+                                .conflicts_with("finish")
+                                .help("Replace the next key pair of a rotation in progress"),
+                        ),
+                )
                 .subcommand(
                     Command::new("remove")
                         .about("Remove credentials")
@@ -280,6 +304,39 @@ pub fn make_app() -> Command {
                         ),
                 ),
         )
+        .subcommand(
+            Command::new("pii")
+                .about("Inspect data scrubbing (PII) configs")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("explain")
+                        .about("Explain what a PII config scrubs in an event")
+                        .after_help(
+                            "This applies a PII config to an event without modifying it and \
+                             prints a JSON list of all fields that would be scrubbed.  For \
+                             every field, it lists the path, the matching selector, the rule \
+                             and its redaction method, as well as the value before and after \
+                             scrubbing.",
+                        )
+                        .arg(
+                            Arg::new("pii_config")
+                                .long("pii-config")
+                                .value_name("PATH")
+                                .required(true)
+                                .value_hint(ValueHint::FilePath)
+                                .value_parser(ValueParser::path_buf())
+                                .help("Path to a PII config JSON file."),
+                        )
+                        .arg(
+                            Arg::new("event")
+                                .value_name("EVENT")
+                                .required(true)
+                                .value_hint(ValueHint::FilePath)
+                                .value_parser(ValueParser::path_buf())
+                                .help("Path to an event JSON file, or '-' to read from stdin."),
+                        ),
+                ),
+        )
         .subcommand(
             Command::new("generate-completions")
                 .about("Generate shell completion file")
@@ -45,6 +45,12 @@ pub fn dump_credentials(config: &Config) {
         Some(key) => println!("  public key: {key}"),
         None => println!("  public key: -"),
     };
+    if let Some(key) = config
+        .credentials()
+        .and_then(|c| c.next_public_key.as_ref())
+    {
+        println!("  next public key: {key}");
+    }
 }
 
 /// Initialize the metric system.
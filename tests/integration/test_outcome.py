@@ -1230,6 +1230,7 @@ def test_profile_outcomes(
     ]
     for outcome in outcomes:
         outcome.pop("timestamp")
+        outcome.pop("bytes", None)
 
     assert outcomes == expected_outcomes, outcomes
 
@@ -1302,6 +1303,7 @@ def test_profile_outcomes_metadata_invalid(
     ]
     for outcome in outcomes:
         outcome.pop("timestamp")
+        outcome.pop("bytes", None)
         outcome.pop("event_id", None)
 
     assert outcomes == expected_outcomes, outcomes
@@ -1389,6 +1391,7 @@ def test_profile_outcomes_data_invalid(
     ]
     for outcome in outcomes:
         outcome.pop("timestamp")
+        outcome.pop("bytes", None)
         outcome.pop("event_id", None)
 
     assert outcomes == expected_outcomes, outcomes
@@ -1485,6 +1488,7 @@ def test_profile_outcomes_rate_limited(
     ]
     for outcome in outcomes:
         outcome.pop("timestamp")
+        outcome.pop("bytes", None)
         outcome.pop("event_id", None)
 
     assert outcomes == expected_outcomes, outcomes
@@ -24,6 +24,10 @@ use relay_general::pii::{PiiAttachmentsProcessor, PiiConfig};
 ///
 ///     {"applications": {"$heap_memory": ["@creditcard:remove"]}}
 ///
+/// Remove emails from thread names and crashpad annotations:
+///
+///     {"applications": {"$thread_name || $annotations": ["@email:remove"]}}
+///
 /// For more information on how to scrub IP addresses, user file paths and how to define custom
 /// regexes see <https://getsentry.github.io/relay/pii-config/>
 #[derive(Debug, Parser)]
@@ -39,6 +43,10 @@ struct Cli {
     /// Optional output path. By default, the minidump file is overwritten.
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Only report which streams would be modified without writing the minidump.
+    #[arg(long)]
+    report: bool,
 }
 
 impl Cli {
@@ -79,14 +87,18 @@ impl Cli {
         let processor = PiiAttachmentsProcessor::new(config.compiled());
 
         let mut data = self.load_minidump()?;
-        let changed = processor
-            .scrub_minidump(self.minidump_name(), &mut data)
+        let areas = processor
+            .scrub_minidump_streams(self.minidump_name(), &mut data)
             .map_err(|e| format_err!("{e}"))?; // does not implement std::error::Error
 
-        if changed {
-            self.write_output(&data)?;
-        } else {
+        if areas.is_empty() {
             println!("nothing changed.");
+        } else if self.report {
+            for area in areas {
+                println!("{area}");
+            }
+        } else {
+            self.write_output(&data)?;
         }
 
         Ok(())